@@ -0,0 +1,101 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use legion::{BookStorage, OrderBook, OrderType, Price, PriceLadder, Qty, Side};
+
+const ORDERS: u64 = 10_000;
+const MID_PRICE: Price = 10_000;
+
+/// A small deterministic generator, so every run replays the same workload.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 = self.0.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 33) % bound
+    }
+
+    fn side(&mut self) -> Side {
+        if self.next(2) == 0 {
+            Side::Bid
+        } else {
+            Side::Ask
+        }
+    }
+}
+
+/// Passive limit orders spread over 100 levels on each side.
+fn limit_heavy() -> Vec<OrderType> {
+    let mut rng = Lcg(1);
+    (1..=ORDERS)
+        .map(|id| {
+            let side = rng.side();
+            let offset = (1 + rng.next(100)) as Price;
+            let price = if side == Side::Bid { MID_PRICE - offset } else { MID_PRICE + offset };
+            OrderType::Limit { id, user_id: rng.next(50), side, qty: (1 + rng.next(10)) as Qty, price }
+        })
+        .collect()
+}
+
+/// Passive limit orders, each canceled a few orders after it was entered.
+fn cancel_heavy() -> Vec<OrderType> {
+    let mut orders = Vec::new();
+    for (index, order) in limit_heavy().into_iter().enumerate() {
+        orders.push(order);
+        if index >= 4 {
+            orders.push(OrderType::Cancel { id: index as u64 - 3 });
+        }
+    }
+    orders
+}
+
+/// A deep book swept by market orders taking several levels each.
+fn sweep_heavy() -> Vec<OrderType> {
+    let mut rng = Lcg(2);
+    let mut orders = limit_heavy();
+    orders.extend((ORDERS + 1..=ORDERS + ORDERS / 10).map(|id| OrderType::Market {
+        id,
+        user_id: rng.next(50),
+        side: rng.side(),
+        qty: (50 + rng.next(100)) as Qty,
+    }));
+    orders
+}
+
+fn bench_workload<S: BookStorage>(c: &mut Criterion, name: &str, orders: &[OrderType], book: impl Fn() -> OrderBook<S>) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(orders.len() as u64));
+    group.bench_function("execute", |b| {
+        b.iter_batched(
+            &book,
+            |mut ob| {
+                for order in orders {
+                    black_box(ob.execute(*order));
+                }
+                ob
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("execute_all", |b| {
+        b.iter_batched(&book, |mut ob| black_box(ob.execute_all(orders)), BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+fn tree_book() -> OrderBook {
+    OrderBook::new(ORDERS as usize, 16, false)
+}
+
+fn ladder_book() -> OrderBook<PriceLadder> {
+    let ladder = || PriceLadder::new(MID_PRICE - 1_000, MID_PRICE + 1_000, 1);
+    OrderBook::with_storage(ORDERS as usize, 16, false, ladder(), ladder())
+}
+
+fn workloads(c: &mut Criterion) {
+    for (name, orders) in [("limit_heavy", limit_heavy()), ("cancel_heavy", cancel_heavy()), ("sweep_heavy", sweep_heavy())] {
+        bench_workload(c, &format!("{}/tree", name), &orders, tree_book);
+        bench_workload(c, &format!("{}/ladder", name), &orders, ladder_book);
+    }
+}
+
+criterion_group!(benches, workloads);
+criterion_main!(benches);
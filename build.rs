@@ -0,0 +1,15 @@
+fn main() {
+    // The gRPC service is generated from its protobuf definition with a
+    // vendored `protoc`, so the `grpc` feature needs no system install.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/legion.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/legion.proto"], &["proto"])
+            .expect("failed to compile proto/legion.proto");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
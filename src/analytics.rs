@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use arrow_array::builder::{PrimitiveBuilder, StringBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{ArrayRef, ArrowPrimitiveType, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+
+use crate::models::{wire_qty, BookDepth, BookLevel, Side};
+use crate::tape::TapeEntry;
+
+// Price columns are signed with the `signed-price` feature.
+#[cfg(not(feature = "signed-price"))]
+type PriceType = arrow_array::types::UInt64Type;
+#[cfg(feature = "signed-price")]
+type PriceType = arrow_array::types::Int64Type;
+type PriceBuilder = PrimitiveBuilder<PriceType>;
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Bid => "bid",
+        Side::Ask => "ask",
+    }
+}
+
+/// Convert trades, such as those of [`OrderBook::tape`], into an Arrow record
+/// batch with one row per trade and the columns `sequence`, `timestamp`,
+/// `taker_id`, `maker_id`, `buyer_id`, `seller_id`, `aggressor_side` (`bid`,
+/// `ask`, or null for crosses), `qty` and `price`. Prices are signed with the `signed-price`
+/// feature.
+///
+/// [`OrderBook::tape`]: struct.OrderBook.html#method.tape
+pub fn trades_to_record_batch<'a, I>(trades: I) -> Result<RecordBatch, ArrowError>
+where
+    I: IntoIterator<Item = &'a TapeEntry>,
+{
+    let mut sequence = UInt64Builder::new();
+    let mut timestamp = UInt64Builder::new();
+    let mut taker_id = UInt64Builder::new();
+    let mut maker_id = UInt64Builder::new();
+    let mut buyer_id = UInt64Builder::new();
+    let mut seller_id = UInt64Builder::new();
+    let mut aggressor_side = StringBuilder::new();
+    let mut qty = UInt64Builder::new();
+    let mut price = PriceBuilder::new();
+    for trade in trades {
+        sequence.append_value(trade.sequence);
+        timestamp.append_value(trade.timestamp);
+        taker_id.append_value(trade.taker_id);
+        maker_id.append_value(trade.maker_id);
+        buyer_id.append_value(trade.buyer_id);
+        seller_id.append_value(trade.seller_id);
+        aggressor_side.append_option(trade.aggressor_side.map(side_name));
+        qty.append_value(wire_qty(trade.qty));
+        price.append_value(trade.price);
+    }
+    let schema = Schema::new(vec![
+        Field::new("sequence", DataType::UInt64, false),
+        Field::new("timestamp", DataType::UInt64, false),
+        Field::new("taker_id", DataType::UInt64, false),
+        Field::new("maker_id", DataType::UInt64, false),
+        Field::new("buyer_id", DataType::UInt64, false),
+        Field::new("seller_id", DataType::UInt64, false),
+        Field::new("aggressor_side", DataType::Utf8, true),
+        Field::new("qty", DataType::UInt64, false),
+        Field::new("price", PriceType::DATA_TYPE, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(sequence.finish()),
+        Arc::new(timestamp.finish()),
+        Arc::new(taker_id.finish()),
+        Arc::new(maker_id.finish()),
+        Arc::new(buyer_id.finish()),
+        Arc::new(seller_id.finish()),
+        Arc::new(aggressor_side.finish()),
+        Arc::new(qty.finish()),
+        Arc::new(price.finish()),
+    ];
+    RecordBatch::try_new(Arc::new(schema), columns)
+}
+
+/// Convert depth snapshots, each taken at a caller supplied timestamp, into
+/// an Arrow record batch in long format: one row per price level, with the
+/// columns `timestamp`, `side` (`bid` or `ask`), `level` (0 for the best
+/// price), `price`, `qty`, `cumulative_qty` and `order_count`. Prices are
+/// signed with the `signed-price` feature.
+pub fn depth_to_record_batch<'a, I>(snapshots: I) -> Result<RecordBatch, ArrowError>
+where
+    I: IntoIterator<Item = (u64, &'a BookDepth)>,
+{
+    let mut timestamp = UInt64Builder::new();
+    let mut side = StringBuilder::new();
+    let mut level = UInt32Builder::new();
+    let mut price = PriceBuilder::new();
+    let mut qty = UInt64Builder::new();
+    let mut cumulative_qty = UInt64Builder::new();
+    let mut order_count = UInt32Builder::new();
+    for (time, depth) in snapshots {
+        let sides: [(Side, &Vec<BookLevel>); 2] = [(Side::Ask, &depth.asks), (Side::Bid, &depth.bids)];
+        for (book_side, levels) in sides {
+            for (index, book_level) in levels.iter().enumerate() {
+                timestamp.append_value(time);
+                side.append_value(side_name(book_side));
+                level.append_value(index as u32);
+                price.append_value(book_level.price);
+                qty.append_value(wire_qty(book_level.qty));
+                cumulative_qty.append_value(wire_qty(book_level.cumulative_qty));
+                order_count.append_value(book_level.orders.len() as u32);
+            }
+        }
+    }
+    let schema = Schema::new(vec![
+        Field::new("timestamp", DataType::UInt64, false),
+        Field::new("side", DataType::Utf8, false),
+        Field::new("level", DataType::UInt32, false),
+        Field::new("price", PriceType::DATA_TYPE, false),
+        Field::new("qty", DataType::UInt64, false),
+        Field::new("cumulative_qty", DataType::UInt64, false),
+        Field::new("order_count", DataType::UInt32, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(timestamp.finish()),
+        Arc::new(side.finish()),
+        Arc::new(level.finish()),
+        Arc::new(price.finish()),
+        Arc::new(qty.finish()),
+        Arc::new(cumulative_qty.finish()),
+        Arc::new(order_count.finish()),
+    ];
+    RecordBatch::try_new(Arc::new(schema), columns)
+}
+
+/// Write a record batch to `writer` as a Parquet file, e.g. to load it with
+/// `pandas.read_parquet`.
+#[cfg(feature = "parquet")]
+pub fn write_parquet<W: std::io::Write + Send>(batch: &RecordBatch, writer: W) -> Result<(), parquet::errors::ParquetError> {
+    let mut writer = parquet::arrow::ArrowWriter::try_new(writer, batch.schema(), None)?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use arrow_array::{Array, PrimitiveArray, StringArray, UInt64Array};
+
+    use super::{depth_to_record_batch, trades_to_record_batch, PriceType};
+    use crate::{OrderBook, OrderType, Side};
+
+    #[test]
+    fn trades_and_depth_as_record_batches() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 5, price: 100 });
+        ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 5, price: 101 });
+        ob.execute(OrderType::Market { id: 3, user_id: 2, side: Side::Bid, qty: 7 });
+        ob.execute(OrderType::Cross { id: 4, buyer_id: 1, seller_id: 2, qty: 1, price: 101 });
+
+        let trades = trades_to_record_batch(ob.tape()).unwrap();
+        assert_eq!(trades.num_rows(), 3);
+        let prices = trades.column_by_name("price").unwrap().as_any().downcast_ref::<PrimitiveArray<PriceType>>().unwrap();
+        assert_eq!(prices.values().to_vec(), vec![100, 101, 101]);
+        let sides = trades.column_by_name("aggressor_side").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(sides.value(0), "bid");
+        assert!(sides.is_null(2));
+        let sellers = trades.column_by_name("seller_id").unwrap().as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(sellers.values().to_vec(), vec![1, 1, 2]);
+
+        let depth = ob.depth(5, true);
+        let levels = depth_to_record_batch([(10, &depth), (11, &depth)]).unwrap();
+        assert_eq!(levels.num_rows(), 2);
+        let qtys = levels.column_by_name("qty").unwrap().as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(qtys.values().to_vec(), vec![3, 3]);
+
+        #[cfg(feature = "parquet")]
+        {
+            let mut file = Vec::new();
+            super::write_parquet(&trades, &mut file).unwrap();
+            assert!(file.starts_with(b"PAR1"));
+        }
+    }
+}
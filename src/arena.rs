@@ -1,30 +1,190 @@
-use std::collections::HashMap;
-use std::ops::{Index, IndexMut};
+use alloc::vec::Vec;
+use core::ops::{Index, IndexMut};
 
-use crate::models::LimitOrder;
+use crate::collections::{HashMap, HashSet};
+use crate::models::{ClientOrderId, LimitOrder, OrderId, Price, Qty, SessionId, Side, UserId};
 
+/// The hasher of the maps keyed by order ID. SipHash dominates cancel-heavy
+/// workloads with many live orders, so the `fxhash` feature swaps in the
+/// much cheaper FxHash; order IDs are not attacker-chosen keys.
+#[cfg(feature = "fxhash")]
+type IdHasher = rustc_hash::FxBuildHasher;
+#[cfg(all(not(feature = "fxhash"), feature = "std"))]
+type IdHasher = std::collections::hash_map::RandomState;
+#[cfg(all(not(feature = "fxhash"), not(feature = "std")))]
+type IdHasher = hashbrown::DefaultHashBuilder;
+
+type IdMap<V> = HashMap<OrderId, V, IdHasher>;
+
+/// A stable reference to a resting order, kept in the price queues to reach
+/// the order without looking up its ID. A handle is valid until its order is
+/// deleted, after which its slot may be reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OrderHandle {
+    /// The ID of the order.
+    pub id: OrderId,
+    slot: u32,
+}
+
+/// A resting order with the side of the book it rests on.
+#[derive(Debug)]
+struct Slot {
+    order: LimitOrder,
+    side: Side,
+}
+
+/// A slab of resting orders: orders live in a contiguous vector of slots,
+/// and the slots of deleted orders are reused through a free list.
 #[derive(Debug)]
 pub struct OrderArena {
-    order_map: HashMap<u64, LimitOrder>,
+    slots: Vec<Option<Slot>>,
+    free: Vec<u32>,
+    slot_of: IdMap<u32>,
+    client_ids: HashMap<ClientOrderId, OrderId>,
+    client_id_of: IdMap<ClientOrderId>,
+    sessions: HashMap<SessionId, HashSet<OrderId>>,
+    session_of: IdMap<SessionId>,
+    users: HashMap<UserId, HashSet<OrderId>>,
 }
 
 impl OrderArena {
     pub fn new(capacity: usize) -> Self {
         Self {
-            order_map: HashMap::with_capacity(capacity),
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            slot_of: IdMap::with_capacity_and_hasher(capacity, IdHasher::default()),
+            client_ids: HashMap::new(),
+            client_id_of: IdMap::default(),
+            sessions: HashMap::new(),
+            session_of: IdMap::default(),
+            users: HashMap::new(),
         }
     }
 
+    fn slot(&self, id: u64) -> Option<&Slot> {
+        let slot = *self.slot_of.get(&id)?;
+        self.slots[slot as usize].as_ref()
+    }
+
     pub fn get(&self, id: u64) -> Option<&LimitOrder> {
-        self.order_map.get(&id)
+        self.slot(id).map(|slot| &slot.order)
+    }
+
+    /// Return the number of resting orders.
+    pub fn len(&self) -> usize {
+        self.slot_of.len()
     }
 
-    pub fn insert(&mut self, id: u64, user_id:u64, price: u64, qty: u64) {
-        self.order_map.insert(id, LimitOrder { id, user_id, qty, price });
+    /// Return the side of the book the order rests on.
+    pub fn side(&self, id: u64) -> Option<Side> {
+        self.slot(id).map(|slot| slot.side)
+    }
+
+    pub fn insert(&mut self, id: u64, user_id:u64, side: Side, price: Price, qty: Qty, created_at: u64) -> OrderHandle {
+        let order = Slot { order: LimitOrder { id, user_id, qty, price, created_at }, side };
+        if let Some(&slot) = self.slot_of.get(&id) {
+            if let Some(replaced) = self.slots[slot as usize].replace(order) {
+                self.remove_user_order(replaced.order.user_id, id);
+            }
+            self.users.entry(user_id).or_default().insert(id);
+            return OrderHandle { id, slot };
+        }
+        self.users.entry(user_id).or_default().insert(id);
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot as usize] = Some(order);
+                slot
+            }
+            None => {
+                self.slots.push(Some(order));
+                (self.slots.len() - 1) as u32
+            }
+        };
+        self.slot_of.insert(id, slot);
+        OrderHandle { id, slot }
     }
 
     pub fn delete(&mut self, id: &u64) -> bool {
-        self.order_map.remove(id).map_or(false, |_| true)
+        if let Some(client_order_id) = self.client_id_of.remove(id) {
+            self.client_ids.remove(&client_order_id);
+        }
+        if let Some(session_id) = self.session_of.remove(id) {
+            if let Some(orders) = self.sessions.get_mut(&session_id) {
+                orders.remove(id);
+                if orders.is_empty() {
+                    self.sessions.remove(&session_id);
+                }
+            }
+        }
+        match self.slot_of.remove(id) {
+            Some(slot) => {
+                if let Some(deleted) = self.slots[slot as usize].take() {
+                    self.remove_user_order(deleted.order.user_id, *id);
+                }
+                self.free.push(slot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_client_id(&mut self, id: OrderId, client_order_id: ClientOrderId) {
+        self.client_ids.insert(client_order_id, id);
+        self.client_id_of.insert(id, client_order_id);
+    }
+
+    pub fn client_id(&self, id: OrderId) -> Option<ClientOrderId> {
+        self.client_id_of.get(&id).copied()
+    }
+
+    pub fn session(&self, id: OrderId) -> Option<SessionId> {
+        self.session_of.get(&id).copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.free.clear();
+        self.slot_of.clear();
+        self.client_ids.clear();
+        self.client_id_of.clear();
+        self.sessions.clear();
+        self.session_of.clear();
+        self.users.clear();
+    }
+
+    pub fn set_session(&mut self, id: OrderId, session_id: SessionId) {
+        self.sessions.entry(session_id).or_default().insert(id);
+        self.session_of.insert(id, session_id);
+    }
+
+    pub fn session_orders(&self, session_id: SessionId) -> Vec<OrderId> {
+        let mut orders: Vec<OrderId> = self
+            .sessions
+            .get(&session_id)
+            .map_or_else(Vec::new, |orders| orders.iter().copied().collect());
+        orders.sort_unstable();
+        orders
+    }
+
+    /// Return the IDs of the resting orders of `user_id`, in ID order.
+    pub fn user_orders(&self, user_id: UserId) -> Vec<OrderId> {
+        let mut orders: Vec<OrderId> =
+            self.users.get(&user_id).map_or_else(Vec::new, |orders| orders.iter().copied().collect());
+        orders.sort_unstable();
+        orders
+    }
+
+    fn remove_user_order(&mut self, user_id: UserId, id: OrderId) {
+        if let Some(orders) = self.users.get_mut(&user_id) {
+            orders.remove(&id);
+            if orders.is_empty() {
+                self.users.remove(&user_id);
+            }
+        }
+    }
+
+    pub fn find_by_client_id(&self, client_order_id: ClientOrderId) -> Option<OrderId> {
+        self.client_ids.get(&client_order_id).copied()
     }
 }
 
@@ -33,13 +193,89 @@ impl Index<u64> for OrderArena {
 
     #[inline]
     fn index(&self, id: u64) -> &LimitOrder {
-        &self.order_map.get(&id).unwrap()
+        self.get(id).unwrap()
     }
 }
 
 impl IndexMut<u64> for OrderArena {
     #[inline]
     fn index_mut(&mut self, id: u64) -> &mut LimitOrder {
-        self.order_map.get_mut(&id).unwrap()
+        let slot = self.slot_of[&id];
+        &mut self.slots[slot as usize].as_mut().unwrap().order
+    }
+}
+
+impl Index<OrderHandle> for OrderArena {
+    type Output = LimitOrder;
+
+    #[inline]
+    fn index(&self, handle: OrderHandle) -> &LimitOrder {
+        &self.slots[handle.slot as usize].as_ref().unwrap().order
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OrderArena;
+    use crate::collections::BTreeMap;
+    use crate::models::Qty;
+    use crate::Side;
+
+    #[test]
+    fn slots_are_reused() {
+        let mut arena = OrderArena::new(2);
+        let first = arena.insert(1, 1, Side::Ask, 100, 5, 0);
+        let second = arena.insert(2, 1, Side::Bid, 101, 6, 0);
+        assert!(arena.delete(&1));
+        assert!(!arena.delete(&1));
+        let third = arena.insert(3, 1, Side::Ask, 102, 7, 0);
+        assert_eq!(arena.slots.len(), 2);
+        assert_eq!((arena[third].id, arena[second].qty), (3, 6));
+        assert_ne!(third, first);
+        assert!(arena.get(1).is_none());
+        assert_eq!((arena.side(2), arena.side(3)), (Some(Side::Bid), Some(Side::Ask)));
+    }
+
+    #[test]
+    fn orders_are_indexed_by_user() {
+        let mut arena = OrderArena::new(2);
+        arena.insert(3, 1, Side::Ask, 100, 5, 0);
+        arena.insert(1, 1, Side::Bid, 99, 5, 0);
+        arena.insert(2, 2, Side::Bid, 98, 5, 0);
+        assert_eq!(arena.user_orders(1), vec![1, 3]);
+        arena.insert(3, 2, Side::Ask, 101, 5, 0);
+        arena.delete(&1);
+        assert!(arena.user_orders(1).is_empty());
+        assert_eq!(arena.user_orders(2), vec![2, 3]);
+        arena.clear();
+        assert!(arena.user_orders(2).is_empty());
+    }
+
+    #[test]
+    fn lookups_follow_the_orders_under_churn() {
+        // The maps keyed by order ID must agree with a sorted reference
+        // whatever their hasher.
+        let mut reference = BTreeMap::new();
+        let mut arena = OrderArena::new(16);
+        let mut seed = 3u64;
+        for _ in 0..5000 {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            let id = (seed >> 33) % 500 * 7919;
+            if reference.remove(&id).is_some() {
+                assert!(arena.delete(&id));
+            } else {
+                let (side, user_id) = if seed >> 63 == 0 { (Side::Bid, 1) } else { (Side::Ask, 2) };
+                arena.insert(id, user_id, side, 100, (seed % 9 + 1) as Qty, 0);
+                reference.insert(id, (side, user_id));
+            }
+            assert_eq!(arena.len(), reference.len());
+        }
+        for id in (0..500).map(|id| id * 7919) {
+            assert_eq!(arena.side(id), reference.get(&id).map(|(side, _)| *side));
+        }
+        for user_id in [1, 2] {
+            let orders: Vec<u64> = reference.iter().filter(|(_, order)| order.1 == user_id).map(|(id, _)| *id).collect();
+            assert_eq!(arena.user_orders(user_id), orders);
+        }
     }
 }
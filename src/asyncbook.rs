@@ -0,0 +1,94 @@
+use std::future::Future;
+
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::models::{OrderEvent, OrderType};
+use crate::orderbook::OrderBook;
+use crate::storage::BookStorage;
+
+/// The error returned by [`AsyncOrderBook::execute`] once its matching task
+/// has stopped.
+///
+/// [`AsyncOrderBook::execute`]: struct.AsyncOrderBook.html#method.execute
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("The matching task has stopped")]
+pub struct MatchingTaskStopped;
+
+type Request = (OrderType, oneshot::Sender<OrderEvent>);
+
+/// A cloneable handle to an [`OrderBook`] owned by a single matching task,
+/// for async gateways. Orders are queued on a bounded channel, so callers
+/// wait for room instead of growing the queue without bound when the book
+/// falls behind.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+#[derive(Debug, Clone)]
+pub struct AsyncOrderBook {
+    requests: mpsc::Sender<Request>,
+}
+
+impl AsyncOrderBook {
+    /// Wrap `book`, queueing at most `capacity` orders, and return the handle
+    /// with the matching task. The task must be spawned on a runtime, e.g.
+    /// with `tokio::spawn`; it executes the orders one at a time in arrival
+    /// order, and returns the book once every handle is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new<S: BookStorage>(
+        mut book: OrderBook<S>,
+        capacity: usize,
+    ) -> (Self, impl Future<Output = OrderBook<S>>) {
+        let (requests, mut receiver) = mpsc::channel::<Request>(capacity);
+        let task = async move {
+            while let Some((order, reply)) = receiver.recv().await {
+                // The caller may have stopped waiting; the order is executed
+                // regardless.
+                let _ = reply.send(book.execute(order));
+            }
+            book
+        };
+        (Self { requests }, task)
+    }
+
+    /// Queue `order`, waiting for room if the queue is full, and resolve to
+    /// its event once the matching task has executed it.
+    pub async fn execute(&self, order: OrderType) -> Result<OrderEvent, MatchingTaskStopped> {
+        let (reply, event) = oneshot::channel();
+        self.requests.send((order, reply)).await.map_err(|_| MatchingTaskStopped)?;
+        event.await.map_err(|_| MatchingTaskStopped)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AsyncOrderBook, MatchingTaskStopped};
+    use crate::{OrderBook, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn async_book_executes_in_order() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let (book, task) = AsyncOrderBook::new(OrderBook::default(), 1);
+            let task = tokio::spawn(task);
+            let other = book.clone();
+            let (first, second) = tokio::join!(
+                book.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 }),
+                other.execute(OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: 1 }),
+            );
+            assert_eq!(first, Ok(OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 100 }));
+            assert!(matches!(second, Ok(OrderEvent::Filled { id: 2, filled_qty: 1, .. })));
+
+            drop((book, other));
+            let book = task.await.unwrap();
+            assert_eq!(book.bbo().ask, Some((100, 1)));
+        });
+
+        let (book, task) = AsyncOrderBook::new(OrderBook::default(), 1);
+        drop(task);
+        let event = runtime.block_on(book.execute(OrderType::Cancel { id: 1 }));
+        assert_eq!(event, Err(MatchingTaskStopped));
+    }
+}
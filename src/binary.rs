@@ -0,0 +1,111 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::models::{BookDepth, OrderEvent, OrderType};
+use crate::snapshot::BookSnapshot;
+
+/// The version of the binary format written by [`encode`].
+///
+/// [`encode`]: fn.encode.html
+pub const BINARY_FORMAT_VERSION: u8 = 6;
+
+/// A type with a binary encoding. Every encoded value starts with a two byte
+/// header holding the format version and the type tag, followed by the
+/// value in little endian, fixed width bincode.
+pub trait BinaryMessage: Serialize + DeserializeOwned {
+    /// The tag identifying the type on the wire.
+    const TAG: u8;
+}
+
+impl BinaryMessage for OrderType {
+    const TAG: u8 = 1;
+}
+
+impl BinaryMessage for OrderEvent {
+    const TAG: u8 = 2;
+}
+
+impl BinaryMessage for BookDepth {
+    const TAG: u8 = 3;
+}
+
+impl BinaryMessage for BookSnapshot {
+    const TAG: u8 = 4;
+}
+
+/// Errors returned when decoding a binary message.
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    /// The buffer is too short to hold the header.
+    #[error("Buffer too short for the message header")]
+    Truncated,
+    /// The message was written with an unsupported format version.
+    #[error("Unsupported binary format version {0}")]
+    UnsupportedVersion(u8),
+    /// The message holds another type than the one requested.
+    #[error("Expected message tag {expected}, found {found}")]
+    UnexpectedTag {
+        /// The tag of the requested type.
+        expected: u8,
+        /// The tag found in the header.
+        found: u8,
+    },
+    /// The payload is not a valid encoding of the requested type.
+    #[error("Invalid message payload: {0}")]
+    InvalidPayload(#[from] bincode::Error),
+}
+
+/// Encode a value with its header.
+pub fn encode<T: BinaryMessage>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![BINARY_FORMAT_VERSION, T::TAG];
+    // Writing to a vector only fails for types serde cannot represent.
+    bincode::serialize_into(&mut bytes, value).expect("binary messages are serializable");
+    bytes
+}
+
+/// Decode a value written by [`encode`].
+///
+/// [`encode`]: fn.encode.html
+pub fn decode<T: BinaryMessage>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let [version, tag, payload @ ..] = bytes else {
+        return Err(DecodeError::Truncated);
+    };
+    if *version != BINARY_FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(*version));
+    }
+    if *tag != T::TAG {
+        return Err(DecodeError::UnexpectedTag { expected: T::TAG, found: *tag });
+    }
+    Ok(bincode::deserialize(payload)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, DecodeError};
+    use crate::RejectReason;
+    use crate::{BookDepth, OrderBook, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn binary_round_trip() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        let order = OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: 1 };
+        let event = ob.execute(order);
+
+        let decoded: OrderType = decode(&encode(&order)).unwrap();
+        assert!(matches!(decoded, OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: 1 }));
+        assert_eq!(decode::<OrderEvent>(&encode(&event)).unwrap(), event);
+        let rejected = OrderEvent::CancelRejected { id: 3, message: RejectReason::OrderNotFound };
+        assert_eq!(decode::<OrderEvent>(&encode(&rejected)).unwrap(), rejected);
+        assert_eq!(decode::<BookDepth>(&encode(&ob.depth(5, true))).unwrap(), ob.depth(5, true));
+
+        let mut restored = OrderBook::default();
+        restored.restore(decode(&encode(&ob.snapshot())).unwrap());
+        assert_eq!(restored.depth(5, true), ob.depth(5, true));
+
+        assert!(matches!(decode::<OrderEvent>(&encode(&order)), Err(DecodeError::UnexpectedTag { expected: 2, found: 1 })));
+        assert!(matches!(decode::<OrderEvent>(&[9, 2]), Err(DecodeError::UnsupportedVersion(9))));
+        assert!(matches!(decode::<OrderEvent>(&[1]), Err(DecodeError::Truncated)));
+    }
+}
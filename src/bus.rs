@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use crate::models::{BookDelta, FillMetadata, OrderEvent, Price, Side};
+
+/// The receiving end of one topic of an [`EventBus`]. Its queue is bounded:
+/// messages published while it is full are dropped for this subscriber
+/// only, and counted, so a slow consumer never stalls the matching loop.
+///
+/// [`EventBus`]: struct.EventBus.html
+#[derive(Debug)]
+pub struct Subscription<T> {
+    receiver: Receiver<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> Subscription<T> {
+    /// Block until a message is available and return it, or return `None`
+    /// once the bus is dropped and every queued message was received.
+    pub fn recv(&self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+
+    /// Return a message if one is available, without blocking.
+    pub fn try_recv(&self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Return the number of messages dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct Publisher<T> {
+    sender: SyncSender<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> Publisher<T> {
+    /// Queue a message, returning whether the subscriber is still connected.
+    fn publish(&self, message: T) -> bool {
+        match self.sender.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+fn subscription<T>(capacity: usize) -> (Publisher<T>, Subscription<T>) {
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let dropped = Arc::new(AtomicU64::new(0));
+    (Publisher { sender, dropped: dropped.clone() }, Subscription { receiver, dropped })
+}
+
+/// The latest delta of every level changed since the subscriber last
+/// drained them.
+type ConflatedLevels = Arc<Mutex<BTreeMap<(bool, Price), BookDelta>>>;
+
+/// The receiving end of the book delta topic of an [`EventBus`], either
+/// queued like a [`Subscription`], or conflated, keeping only the latest
+/// delta of each level until it is drained.
+///
+/// [`EventBus`]: struct.EventBus.html
+/// [`Subscription`]: struct.Subscription.html
+#[derive(Debug)]
+pub struct DeltaSubscription {
+    inner: DeltaInner,
+}
+
+#[derive(Debug)]
+enum DeltaInner {
+    Queued(Subscription<BookDelta>),
+    Conflated(ConflatedLevels),
+}
+
+impl DeltaSubscription {
+    /// Return the deltas available, without blocking. Conflated deltas are
+    /// returned asks first, then bids, each in price order.
+    pub fn drain(&self) -> Vec<BookDelta> {
+        match &self.inner {
+            DeltaInner::Queued(subscription) => std::iter::from_fn(|| subscription.try_recv()).collect(),
+            DeltaInner::Conflated(levels) => std::mem::take(&mut *levels.lock().unwrap()).into_values().collect(),
+        }
+    }
+
+    /// Return the number of deltas dropped because the queue was full. A
+    /// conflated subscription never drops deltas.
+    pub fn dropped(&self) -> u64 {
+        match &self.inner {
+            DeltaInner::Queued(subscription) => subscription.dropped(),
+            DeltaInner::Conflated(_) => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum DeltaPublisher {
+    Queued(Publisher<BookDelta>),
+    Conflated(ConflatedLevels),
+}
+
+/// Distributes the output of a book to independent subscribers of order
+/// events, trades and book deltas, so market data consumers are decoupled
+/// from the matching loop. Publish each event as it is returned, with the
+/// deltas from [`OrderBook::take_deltas`]. Subscribers that are dropped are
+/// removed on the next publish.
+///
+/// [`OrderBook::take_deltas`]: struct.OrderBook.html#method.take_deltas
+#[derive(Debug, Default)]
+pub struct EventBus {
+    orders: Vec<Publisher<OrderEvent>>,
+    trades: Vec<Publisher<FillMetadata>>,
+    deltas: Vec<DeltaPublisher>,
+}
+
+impl EventBus {
+    /// Create a bus without subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to every order event, queueing at most `capacity` of them.
+    pub fn subscribe_orders(&mut self, capacity: usize) -> Subscription<OrderEvent> {
+        let (publisher, subscription) = subscription(capacity);
+        self.orders.push(publisher);
+        subscription
+    }
+
+    /// Subscribe to the fills of the order events, queueing at most
+    /// `capacity` of them. Reported crosses have no fills, and are only
+    /// published as order events.
+    pub fn subscribe_trades(&mut self, capacity: usize) -> Subscription<FillMetadata> {
+        let (publisher, subscription) = subscription(capacity);
+        self.trades.push(publisher);
+        subscription
+    }
+
+    /// Subscribe to the book deltas, queueing at most `capacity` of them, or,
+    /// with `conflate` set, keeping only the latest delta of each level.
+    pub fn subscribe_deltas(&mut self, capacity: usize, conflate: bool) -> DeltaSubscription {
+        let (publisher, inner) = if conflate {
+            let levels = ConflatedLevels::default();
+            (DeltaPublisher::Conflated(levels.clone()), DeltaInner::Conflated(levels))
+        } else {
+            let (publisher, subscription) = subscription(capacity);
+            (DeltaPublisher::Queued(publisher), DeltaInner::Queued(subscription))
+        };
+        self.deltas.push(publisher);
+        DeltaSubscription { inner }
+    }
+
+    /// Publish an order event, its fills, and the book deltas it caused.
+    pub fn publish(&mut self, event: &OrderEvent, deltas: &[BookDelta]) {
+        self.orders.retain(|publisher| publisher.publish(event.clone()));
+        if let OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } = event {
+            for fill in fills.iter() {
+                self.trades.retain(|publisher| publisher.publish(*fill));
+            }
+        }
+        if deltas.is_empty() {
+            return;
+        }
+        self.deltas.retain(|publisher| match publisher {
+            DeltaPublisher::Queued(publisher) => deltas.iter().all(|delta| publisher.publish(*delta)),
+            DeltaPublisher::Conflated(levels) => {
+                // The levels are shared with the subscriber only.
+                if Arc::strong_count(levels) < 2 {
+                    return false;
+                }
+                let mut levels = levels.lock().unwrap();
+                for delta in deltas {
+                    levels.insert((delta.side == Side::Bid, delta.price), *delta);
+                }
+                true
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EventBus;
+    use crate::{BookDelta, OrderBook, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn bus_fans_out_topics() {
+        let mut ob = OrderBook::default();
+        ob.track_deltas(true);
+        let mut bus = EventBus::new();
+        let orders = bus.subscribe_orders(2);
+        let trades = bus.subscribe_trades(16);
+        let queued = bus.subscribe_deltas(16, false);
+        let conflated = bus.subscribe_deltas(16, true);
+        let dropped = bus.subscribe_orders(1);
+        drop(dropped);
+
+        for order in [
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 },
+            OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 3, price: 100 },
+            OrderType::Market { id: 3, user_id: 2, side: Side::Bid, qty: 4 },
+        ] {
+            let event = ob.execute(order);
+            bus.publish(&event, &ob.take_deltas());
+        }
+        assert_eq!(bus.orders.len(), 1);
+
+        assert_eq!(orders.try_recv(), Some(OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 100 }));
+        assert_eq!(orders.try_recv(), Some(OrderEvent::Open { id: 2, remaining_qty: 3, resting_price: 100 }));
+        assert_eq!(orders.try_recv(), None);
+        assert_eq!(orders.dropped(), 1);
+
+        let fills: Vec<_> = std::iter::from_fn(|| trades.try_recv()).map(|fill| (fill.maker_id, fill.qty)).collect();
+        assert_eq!(fills, vec![(1, 2), (2, 2)]);
+
+        let qtys = |deltas: Vec<BookDelta>| deltas.into_iter().map(|delta| delta.qty).collect::<Vec<_>>();
+        assert_eq!(qtys(queued.drain()), vec![2, 5, 1]);
+        assert_eq!(qtys(conflated.drain()), vec![1]);
+        assert!(conflated.drain().is_empty());
+    }
+}
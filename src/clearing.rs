@@ -0,0 +1,101 @@
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::sync::mpsc::Sender;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::models::{OrderId, Price, Qty, Side, UserId};
+use crate::risk::abs_notional;
+
+/// A trade as reported for clearing and settlement by an [`OrderBook`],
+/// one record per fill or cross. See [`OrderBook::track_clearing`].
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`OrderBook::track_clearing`]: struct.OrderBook.html#method.track_clearing
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClearingRecord {
+    /// The unique ID of the trade within the book, starting from 1.
+    pub trade_id: u64,
+    /// The position of this record among the clearing records of the book,
+    /// starting from 1, so a downstream system can detect missing records.
+    pub sequence: u64,
+    /// The book clock time at which the trade happened.
+    pub timestamp: u64,
+    /// The ID of the buying order, or of the cross order.
+    pub buy_order_id: OrderId,
+    /// The ID of the selling order, or of the cross order.
+    pub sell_order_id: OrderId,
+    /// User id of the buyer.
+    pub buyer_id: UserId,
+    /// User id of the seller.
+    pub seller_id: UserId,
+    /// The quantity that was traded.
+    pub qty: Qty,
+    /// The price at which the trade happened.
+    pub price: Price,
+    /// The side of the taker order, or `None` for cross trades, which have no
+    /// aggressor.
+    pub aggressor_side: Option<Side>,
+    /// The fee charged to the buyer, negative for a rebate.
+    pub buyer_fee: i128,
+    /// The fee charged to the seller, negative for a rebate.
+    pub seller_fee: i128,
+}
+
+/// The fees charged on trades, in basis points of their notional value (the
+/// absolute price times the quantity). Negative fees are rebates. Both
+/// parties of a cross pay the maker fee, as neither of them took liquidity.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FeeSchedule {
+    /// The fee of the resting order of a fill, in basis points.
+    pub maker_fee_bps: i64,
+    /// The fee of the incoming order of a fill, in basis points.
+    pub taker_fee_bps: i64,
+}
+
+impl FeeSchedule {
+    /// Create a schedule charging `maker_fee_bps` and `taker_fee_bps` basis
+    /// points to the makers and takers of fills.
+    pub fn new(maker_fee_bps: i64, taker_fee_bps: i64) -> Self {
+        Self { maker_fee_bps, taker_fee_bps }
+    }
+
+    /// Return the fee of trading `qty` at `price` at `fee_bps` basis points,
+    /// rounded toward zero.
+    pub(crate) fn fee(fee_bps: i64, price: Price, qty: Qty) -> i128 {
+        let notional = i128::try_from(abs_notional(price, qty)).unwrap_or(i128::MAX);
+        notional.saturating_mul(fee_bps.into()) / 10_000
+    }
+}
+
+/// A destination streaming the clearing records of an order book as trades
+/// happen, e.g. to a settlement system.
+pub trait ClearingSink: Debug + Send {
+    /// Deliver a record. Trades have already happened, so the record cannot
+    /// be refused.
+    fn report(&mut self, record: &ClearingRecord);
+}
+
+/// Send the records over a channel, e.g. to another thread. Records are
+/// dropped once the receiver is gone.
+#[cfg(feature = "std")]
+impl ClearingSink for Sender<ClearingRecord> {
+    fn report(&mut self, record: &ClearingRecord) {
+        let _ = self.send(*record);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FeeSchedule;
+
+    #[test]
+    fn fees_round_toward_zero() {
+        assert_eq!(FeeSchedule::fee(25, 395, 40), 39);
+        assert_eq!(FeeSchedule::fee(-5, 395, 40), -7);
+        assert_eq!(FeeSchedule::fee(0, 395, 40), 0);
+    }
+}
@@ -0,0 +1,136 @@
+use alloc::sync::Arc;
+use core::fmt::Debug;
+#[cfg(not(target_has_atomic = "64"))]
+use core::cell::UnsafeCell;
+#[cfg(not(target_has_atomic = "64"))]
+use core::sync::atomic::AtomicBool;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of timestamps for the order book. Timestamps are opaque `u64`
+/// values; the book only compares them, so any monotonic unit works.
+pub trait Clock: Debug + Send {
+    /// Return the current timestamp.
+    fn now(&self) -> u64;
+}
+
+/// A clock reading the system time as nanoseconds since the Unix epoch.
+///
+/// Not available on `wasm32-unknown-unknown`, where reading the system time
+/// panics; use a [`ManualClock`] there instead.
+///
+/// [`ManualClock`]: struct.ManualClock.html
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    }
+}
+
+/// A clock whose time is set explicitly, for deterministic tests and replays.
+/// Clones share the same time, so a handle can be kept to advance the clock
+/// after it has been given to an order book.
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock {
+    now: Arc<Timestamp>,
+}
+
+impl ManualClock {
+    /// Create a clock starting at the given timestamp.
+    pub fn new(now: u64) -> Self {
+        Self { now: Arc::new(Timestamp::new(now)) }
+    }
+
+    /// Set the current timestamp.
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::Relaxed);
+    }
+
+    /// Move the current timestamp forward by `delta`.
+    pub fn advance(&self, delta: u64) {
+        self.now.fetch_add(delta, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+type Timestamp = AtomicU64;
+
+/// A timestamp shared between threads on targets without 64-bit atomics,
+/// such as 32-bit microcontrollers, guarded by a spin lock.
+#[cfg(not(target_has_atomic = "64"))]
+#[derive(Default)]
+struct Timestamp {
+    locked: AtomicBool,
+    value: UnsafeCell<u64>,
+}
+
+// The value is only accessed while holding the lock.
+#[cfg(not(target_has_atomic = "64"))]
+unsafe impl Sync for Timestamp {}
+
+#[cfg(not(target_has_atomic = "64"))]
+impl Timestamp {
+    fn new(value: u64) -> Self {
+        Self { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut u64) -> R) -> R {
+        while self.locked.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        // Safety: the lock is held, so no other reference to the value exists.
+        let result = f(unsafe { &mut *self.value.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+
+    fn load(&self, _: Ordering) -> u64 {
+        self.update(|value| *value)
+    }
+
+    fn store(&self, now: u64, _: Ordering) {
+        self.update(|value| *value = now);
+    }
+
+    fn fetch_add(&self, delta: u64, _: Ordering) -> u64 {
+        self.update(|value| core::mem::replace(value, value.wrapping_add(delta)))
+    }
+}
+
+#[cfg(not(target_has_atomic = "64"))]
+impl Debug for Timestamp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.load(Ordering::Relaxed).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Clock, ManualClock};
+
+    #[test]
+    fn manual_clock_clones_share_the_time() {
+        let clock = ManualClock::new(5);
+        let handle = clock.clone();
+        handle.advance(10);
+        assert_eq!(clock.now(), 15);
+        clock.set(u64::MAX);
+        handle.advance(2);
+        assert_eq!(handle.now(), 1);
+    }
+}
@@ -0,0 +1,9 @@
+// The collections of the crate: hash maps come from `std` when it is
+// available and from `hashbrown` otherwise, everything else from `alloc`.
+
+pub(crate) use alloc::collections::{btree_map, BTreeMap, BTreeSet, VecDeque};
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{HashMap, HashSet};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use hashbrown::{HashMap, HashSet};
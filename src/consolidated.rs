@@ -0,0 +1,171 @@
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::collections::BTreeMap;
+use crate::models::{Bbo, Price, Qty, Side};
+
+/// One side of a [`NationalBbo`]: the best price across venues, the
+/// quantity resting at it on all of them, and the venues quoting it in venue
+/// order.
+///
+/// [`NationalBbo`]: struct.NationalBbo.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NationalQuote {
+    /// The best price.
+    pub price: Price,
+    /// The aggregate quantity at the best price.
+    pub qty: Qty,
+    /// The venues quoting the best price.
+    pub venues: Vec<String>,
+}
+
+/// The best bid and offer of a symbol across all venues. A side is `None`
+/// when no venue quotes it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NationalBbo {
+    /// The highest bid.
+    pub bid: Option<NationalQuote>,
+    /// The lowest ask.
+    pub ask: Option<NationalQuote>,
+}
+
+/// A consolidated top of book over several venues, e.g. one
+/// [`MatchingEngine`] per venue, keyed by symbol. It is updated one quote at
+/// a time, typically from [`MatchingEngine::take_bbo_updates`], and only the
+/// national best of the updated symbol is recomputed.
+///
+/// [`MatchingEngine`]: struct.MatchingEngine.html
+/// [`MatchingEngine::take_bbo_updates`]: struct.MatchingEngine.html#method.take_bbo_updates
+#[derive(Debug, Default, Clone)]
+pub struct ConsolidatedBbo {
+    quotes: BTreeMap<String, BTreeMap<String, Bbo>>,
+    best: BTreeMap<String, NationalBbo>,
+}
+
+impl ConsolidatedBbo {
+    /// Create an empty view.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the top of book of `symbol` on `venue`, returning whether the
+    /// national best of the symbol changed. An empty `bbo` removes the quote.
+    pub fn update(&mut self, venue: &str, symbol: &str, bbo: Bbo) -> bool {
+        let quotes = self.quotes.entry(symbol.to_string()).or_default();
+        if bbo == Bbo::default() {
+            quotes.remove(venue);
+        } else {
+            quotes.insert(venue.to_string(), bbo);
+        }
+        let best = NationalBbo { bid: national_quote(quotes, Side::Bid), ask: national_quote(quotes, Side::Ask) };
+        if quotes.is_empty() {
+            self.quotes.remove(symbol);
+            return self.best.remove(symbol).is_some();
+        }
+        self.best.insert(symbol.to_string(), best.clone()) != Some(best)
+    }
+
+    /// Apply the updates of one venue, returning the symbols whose national
+    /// best changed.
+    pub fn update_all<I: IntoIterator<Item = (String, Bbo)>>(&mut self, venue: &str, updates: I) -> Vec<String> {
+        updates
+            .into_iter()
+            .filter_map(|(symbol, bbo)| self.update(venue, &symbol, bbo).then_some(symbol))
+            .collect()
+    }
+
+    /// Return the top of book of `symbol` on `venue`.
+    pub fn quote(&self, venue: &str, symbol: &str) -> Option<Bbo> {
+        self.quotes.get(symbol)?.get(venue).copied()
+    }
+
+    /// Return the national best of `symbol`, or `None` when no venue quotes
+    /// it.
+    pub fn national_best(&self, symbol: &str) -> Option<&NationalBbo> {
+        self.best.get(symbol)
+    }
+
+    /// Iterate over the quoted symbols and their national best, in symbol
+    /// order.
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, &NationalBbo)> {
+        self.best.iter().map(|(symbol, best)| (symbol.as_str(), best))
+    }
+}
+
+fn national_quote(quotes: &BTreeMap<String, Bbo>, side: Side) -> Option<NationalQuote> {
+    let mut best: Option<NationalQuote> = None;
+    for (venue, bbo) in quotes {
+        let Some((price, qty)) = (match side {
+            Side::Bid => bbo.bid,
+            Side::Ask => bbo.ask,
+        }) else {
+            continue;
+        };
+        match &mut best {
+            Some(quote) if quote.price == price => {
+                quote.qty += qty;
+                quote.venues.push(venue.clone());
+            }
+            Some(quote) if (side == Side::Bid) == (quote.price > price) => {}
+            _ => best = Some(NationalQuote { price, qty, venues: vec![venue.clone()] }),
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConsolidatedBbo, NationalQuote};
+    use crate::{Bbo, MatchingEngine, OrderBook, OrderType, Price, Qty, Side, SymbolOrder};
+
+    fn limit(id: u64, side: Side, qty: Qty, price: Price) -> SymbolOrder {
+        SymbolOrder { symbol: "AAA".to_string(), order: OrderType::Limit { id, user_id: 1, side, qty, price } }
+    }
+
+    #[test]
+    fn consolidated_bbo_follows_engine_updates() {
+        let mut venues: Vec<MatchingEngine> = (0..2).map(|_| MatchingEngine::new()).collect();
+        for engine in &mut venues {
+            engine.add_book("AAA", OrderBook::default());
+        }
+        let mut view = ConsolidatedBbo::new();
+        let sync = |view: &mut ConsolidatedBbo, venues: &mut [MatchingEngine]| {
+            let mut changed = Vec::new();
+            for (venue, engine) in ["X", "Y"].into_iter().zip(venues) {
+                changed.extend(view.update_all(venue, engine.take_bbo_updates()));
+            }
+            changed
+        };
+        assert!(sync(&mut view, &mut venues).is_empty());
+
+        venues[0].execute_all([limit(1, Side::Bid, 2, 99), limit(2, Side::Ask, 3, 102)]);
+        venues[1].execute_all([limit(1, Side::Bid, 4, 99), limit(2, Side::Ask, 1, 101)]);
+        assert_eq!(sync(&mut view, &mut venues), vec!["AAA".to_string(), "AAA".to_string()]);
+        let best = view.national_best("AAA").unwrap();
+        let quote = |price, qty, venues: &[&str]| NationalQuote { price, qty, venues: venues.iter().map(|v| v.to_string()).collect() };
+        assert_eq!(best.bid, Some(quote(99, 6, &["X", "Y"])));
+        assert_eq!(best.ask, Some(quote(101, 1, &["Y"])));
+        assert_eq!(view.quote("X", "AAA"), Some(Bbo { bid: Some((99, 2)), ask: Some((102, 3)) }));
+
+        // A book touched without a change in its top reports nothing.
+        venues[0].execute(limit(3, Side::Bid, 1, 90));
+        assert!(sync(&mut view, &mut venues).is_empty());
+
+        venues[1].execute(SymbolOrder { symbol: "AAA".to_string(), order: OrderType::Cancel { id: 2 } });
+        assert_eq!(sync(&mut view, &mut venues), vec!["AAA".to_string()]);
+        assert_eq!(view.national_best("AAA").unwrap().ask, Some(quote(102, 3, &["X"])));
+
+        venues[0].remove_book("AAA");
+        venues[1].remove_book("AAA");
+        sync(&mut view, &mut venues);
+        assert_eq!(view.national_best("AAA"), None);
+        assert_eq!(view.symbols().count(), 0);
+    }
+}
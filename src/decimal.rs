@@ -0,0 +1,292 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::cmp::Ordering;
+use core::fmt;
+use core::str::FromStr;
+
+use thiserror::Error;
+
+use crate::models::{wide_qty, Price, Qty};
+
+// The most fractional digits a `Decimal` can carry, the digits of `u128::MAX`.
+const MAX_SCALE: u32 = 38;
+
+/// A decimal number, e.g. a price or quantity as quoted by a venue, kept as
+/// an integer of units and the number of its fractional digits, so that
+/// `12.50` is 1250 units at scale 2.
+///
+/// Decimals parse from and display as plain strings like `-0.25`, without
+/// exponents, and compare by value: `12.5` equals `12.50`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Decimal {
+    negative: bool,
+    units: u128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Create the decimal `units * 10^-scale`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scale` is above 38.
+    pub fn new(units: u128, scale: u32) -> Self {
+        assert!(scale <= MAX_SCALE, "the scale of a decimal is at most {}", MAX_SCALE);
+        Self { negative: false, units, scale }
+    }
+
+    /// The same decimal, with its sign flipped.
+    pub fn negated(self) -> Self {
+        Self { negative: !self.negative, ..self }
+    }
+
+    /// Whether the decimal is below zero.
+    pub fn is_negative(&self) -> bool {
+        self.negative && self.units != 0
+    }
+
+    /// The integer of units of the decimal, without its sign.
+    pub fn units(&self) -> u128 {
+        self.units
+    }
+
+    /// The number of fractional digits of the decimal.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// The units of the decimal at `scale`, rounded with `rounding` if the
+    /// decimal has more fractional digits, and without its sign.
+    fn rescale(&self, scale: u32, rounding: Rounding) -> Result<u128, DecimalError> {
+        if self.scale <= scale {
+            return 10u128
+                .checked_pow(scale - self.scale)
+                .and_then(|factor| self.units.checked_mul(factor))
+                .ok_or(DecimalError::OutOfRange(*self));
+        }
+        let divisor = 10u128.pow(self.scale - scale);
+        let (quotient, remainder) = (self.units / divisor, self.units % divisor);
+        if remainder == 0 {
+            return Ok(quotient);
+        }
+        // Rounding up or down is toward or away from zero depending on the sign.
+        let away_from_zero = match rounding {
+            Rounding::Exact => return Err(DecimalError::Inexact { value: *self, scale }),
+            Rounding::Down => self.is_negative(),
+            Rounding::Up => !self.is_negative(),
+            Rounding::HalfUp => match remainder.cmp(&(divisor - remainder)) {
+                Ordering::Less => false,
+                Ordering::Equal => !self.is_negative(),
+                Ordering::Greater => true,
+            },
+            Rounding::HalfEven => match remainder.cmp(&(divisor - remainder)) {
+                Ordering::Less => false,
+                Ordering::Equal => quotient % 2 == 1,
+                Ordering::Greater => true,
+            },
+        };
+        Ok(if away_from_zero { quotient + 1 } else { quotient })
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        if self.units == 0 && other.units == 0 {
+            return true;
+        }
+        let scale = self.scale.max(other.scale);
+        match (self.rescale(scale, Rounding::Exact), other.rescale(scale, Rounding::Exact)) {
+            (Ok(units), Ok(other_units)) => units == other_units && self.is_negative() == other.is_negative(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Decimal {}
+
+impl FromStr for Decimal {
+    type Err = DecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DecimalError::Invalid(s.to_string());
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        let scale = u32::try_from(fraction.len()).ok().filter(|scale| *scale <= MAX_SCALE).ok_or_else(invalid)?;
+        let mut units: u128 = 0;
+        for digit in integer.chars().chain(fraction.chars()) {
+            let digit = digit.to_digit(10).ok_or_else(invalid)?;
+            units = units.checked_mul(10).and_then(|units| units.checked_add(digit.into())).ok_or_else(invalid)?;
+        }
+        Ok(Self { negative, units, scale })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = format!("{:0>width$}", self.units, width = self.scale as usize + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        let sign = if self.is_negative() { "-" } else { "" };
+        if fraction.is_empty() {
+            write!(f, "{}{}", sign, integer)
+        } else {
+            write!(f, "{}{}.{}", sign, integer, fraction)
+        }
+    }
+}
+
+/// How a [`DecimalScale`] converts decimals with more fractional digits than
+/// its scale.
+///
+/// [`DecimalScale`]: struct.DecimalScale.html
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Refuse the decimal with [`DecimalError::Inexact`].
+    ///
+    /// [`DecimalError::Inexact`]: enum.DecimalError.html#variant.Inexact
+    #[default]
+    Exact,
+    /// Round toward negative infinity, e.g. to keep bids from paying more.
+    Down,
+    /// Round toward positive infinity, e.g. to keep asks from selling for
+    /// less.
+    Up,
+    /// Round to the nearest value, and halfway values up.
+    HalfUp,
+    /// Round to the nearest value, and halfway values to the even one.
+    HalfEven,
+}
+
+/// An error converting a [`Decimal`].
+///
+/// [`Decimal`]: struct.Decimal.html
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DecimalError {
+    /// The string is not a plain decimal number, or has more than 38 digits.
+    #[error("invalid decimal {0:?}")]
+    Invalid(String),
+    /// The decimal has more fractional digits than the scale, and the
+    /// rounding is [`Rounding::Exact`].
+    ///
+    /// [`Rounding::Exact`]: enum.Rounding.html#variant.Exact
+    #[error("{value} has more than {scale} decimals")]
+    Inexact {
+        /// The decimal.
+        value: Decimal,
+        /// The scale it was converted to.
+        scale: u32,
+    },
+    /// The decimal is too large for a price or quantity, or negative, which
+    /// prices may only be with the `signed-price` feature.
+    #[error("{0} is out of range")]
+    OutOfRange(Decimal),
+}
+
+/// The decimal scale of an instrument, converting its decimal prices and
+/// quantities to the integer ticks and units of an [`OrderBook`]: with a
+/// price scale of 2, the price `12.34` is 1234 ticks. See
+/// [`OrderBook::set_decimal_scale`].
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`OrderBook::set_decimal_scale`]: struct.OrderBook.html#method.set_decimal_scale
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DecimalScale {
+    /// The number of fractional digits of a price tick.
+    pub price_scale: u32,
+    /// The number of fractional digits of a quantity unit.
+    pub qty_scale: u32,
+    /// How decimals finer than the scale are converted.
+    pub rounding: Rounding,
+}
+
+impl DecimalScale {
+    /// Create a scale of `price_scale` and `qty_scale` fractional digits,
+    /// refusing finer decimals.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either scale is above 38.
+    pub fn new(price_scale: u32, qty_scale: u32) -> Self {
+        assert!(price_scale.max(qty_scale) <= MAX_SCALE, "the scale of a decimal is at most {}", MAX_SCALE);
+        Self { price_scale, qty_scale, rounding: Rounding::Exact }
+    }
+
+    /// The same scale, converting finer decimals with `rounding`.
+    pub fn with_rounding(self, rounding: Rounding) -> Self {
+        Self { rounding, ..self }
+    }
+
+    /// The price in ticks of the decimal `price`.
+    pub fn price(&self, price: Decimal) -> Result<Price, DecimalError> {
+        let units = price.rescale(self.price_scale, self.rounding)?;
+        let ticks = i128::try_from(units).map(|ticks| if price.is_negative() { -ticks } else { ticks });
+        ticks.ok().and_then(|ticks| Price::try_from(ticks).ok()).ok_or(DecimalError::OutOfRange(price))
+    }
+
+    /// The quantity in units of the decimal `qty`.
+    pub fn qty(&self, qty: Decimal) -> Result<Qty, DecimalError> {
+        let units = qty.rescale(self.qty_scale, self.rounding)?;
+        if qty.is_negative() && units != 0 {
+            return Err(DecimalError::OutOfRange(qty));
+        }
+        Qty::try_from(units).map_err(|_| DecimalError::OutOfRange(qty))
+    }
+
+    /// The decimal price of `ticks`.
+    pub fn price_decimal(&self, ticks: Price) -> Decimal {
+        let ticks = i128::from(ticks);
+        let decimal = Decimal::new(ticks.unsigned_abs(), self.price_scale);
+        if ticks < 0 {
+            decimal.negated()
+        } else {
+            decimal
+        }
+    }
+
+    /// The decimal quantity of `units`.
+    pub fn qty_decimal(&self, units: Qty) -> Decimal {
+        Decimal::new(wide_qty(units), self.qty_scale)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Decimal, DecimalError, DecimalScale, Rounding};
+
+    fn decimal(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn decimals_convert_to_ticks_with_rounding() {
+        assert_eq!(decimal("12.50"), Decimal::new(125, 1));
+        assert_eq!(decimal("-.5").to_string(), "-0.5");
+        assert_eq!(decimal("007").to_string(), "7");
+        assert_eq!("1e3".parse::<Decimal>(), Err(DecimalError::Invalid("1e3".to_string())));
+        assert!(".".parse::<Decimal>().is_err());
+
+        let scale = DecimalScale::new(2, 0);
+        assert_eq!(scale.price(decimal("12.3")), Ok(1230));
+        assert_eq!(scale.price(decimal("12.345")), Err(DecimalError::Inexact { value: decimal("12.345"), scale: 2 }));
+        #[cfg(not(feature = "signed-price"))]
+        assert_eq!(scale.price(decimal("-1")), Err(DecimalError::OutOfRange(decimal("-1"))));
+        #[cfg(feature = "signed-price")]
+        assert_eq!(scale.price(decimal("-1.5")), Ok(-150));
+        assert_eq!(scale.qty(decimal("-1")), Err(DecimalError::OutOfRange(decimal("-1"))));
+        assert_eq!(scale.qty(decimal("3")), Ok(3));
+        assert_eq!(scale.price_decimal(1205).to_string(), "12.05");
+
+        let rounded = |rounding, s| scale.with_rounding(rounding).price(decimal(s));
+        assert_eq!(rounded(Rounding::Down, "12.349"), Ok(1234));
+        assert_eq!(rounded(Rounding::Up, "12.341"), Ok(1235));
+        assert_eq!(rounded(Rounding::HalfUp, "12.345"), Ok(1235));
+        assert_eq!(rounded(Rounding::HalfEven, "12.345"), Ok(1234));
+        assert_eq!(rounded(Rounding::HalfEven, "12.355"), Ok(1236));
+        assert_eq!(rounded(Rounding::Up, "-0.001"), Ok(0));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::collections::{HashSet, VecDeque};
+use crate::models::OrderId;
+
+/// The IDs of the last orders accepted by a book, bounded to a fixed
+/// capacity, used to recognize orders that are sent again.
+#[derive(Debug, Clone)]
+pub(crate) struct DedupWindow {
+    capacity: usize,
+    order: VecDeque<OrderId>,
+    ids: HashSet<OrderId>,
+}
+
+impl DedupWindow {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity, order: VecDeque::new(), ids: HashSet::new() }
+    }
+
+    /// Remember `id`, forgetting the oldest ID once the window is full.
+    pub(crate) fn insert(&mut self, id: OrderId) {
+        if self.capacity == 0 || !self.ids.insert(id) {
+            return;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.order.push_back(id);
+    }
+
+    pub(crate) fn contains(&self, id: OrderId) -> bool {
+        self.ids.contains(&id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DedupWindow;
+
+    #[test]
+    fn dedup_window_forgets_the_oldest_ids() {
+        let mut window = DedupWindow::new(2);
+        window.insert(1);
+        window.insert(2);
+        window.insert(2);
+        assert!(window.contains(1) && window.contains(2));
+        window.insert(3);
+        assert!(!window.contains(1) && window.contains(2) && window.contains(3));
+
+        let mut disabled = DedupWindow::new(0);
+        disabled.insert(1);
+        assert!(!disabled.contains(1));
+    }
+}
@@ -0,0 +1,78 @@
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::sync::mpsc::Sender;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::models::OrderEvent;
+
+/// What happens to orders that would take the book beyond its
+/// [`DepthLimit`].
+///
+/// [`DepthLimit`]: struct.DepthLimit.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DepthPolicy {
+    /// Reject orders that would rest at a new price level, or add a resting
+    /// order, beyond the limit.
+    Reject,
+    /// Accept the orders and cancel the resting orders farthest from the
+    /// touch until the book is within the limit again.
+    EvictFarthest,
+}
+
+/// Bounds on the resting orders of a book, protecting open-ended deployments
+/// from memory exhaustion by order spam. See [`OrderBook::set_depth_limit`].
+///
+/// [`OrderBook::set_depth_limit`]: struct.OrderBook.html#method.set_depth_limit
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DepthLimit {
+    /// The maximum number of price levels on each side, if any.
+    pub max_levels: Option<usize>,
+    /// The maximum number of resting orders on both sides, if any.
+    pub max_orders: Option<usize>,
+    /// What happens to orders beyond the limit.
+    pub policy: DepthPolicy,
+}
+
+impl DepthLimit {
+    /// Limit the number of price levels on each side to `max_levels`.
+    pub fn levels(max_levels: usize, policy: DepthPolicy) -> Self {
+        Self { max_levels: Some(max_levels), max_orders: None, policy }
+    }
+
+    /// Limit the number of resting orders to `max_orders`.
+    pub fn orders(max_orders: usize, policy: DepthPolicy) -> Self {
+        Self { max_levels: None, max_orders: Some(max_orders), policy }
+    }
+
+    /// Return whether the limit bounds the book at all.
+    pub fn is_bounded(&self) -> bool {
+        self.max_levels.is_some() || self.max_orders.is_some()
+    }
+}
+
+/// A destination notified of the orders evicted by a [`DepthLimit`] as they
+/// are evicted, e.g. to tell their owners. See
+/// [`OrderBook::set_eviction_sink`].
+///
+/// [`DepthLimit`]: struct.DepthLimit.html
+/// [`OrderBook::set_eviction_sink`]: struct.OrderBook.html#method.set_eviction_sink
+pub trait EvictionSink: Debug + Send {
+    /// Deliver the [`OrderEvent::Cancelled`] event of an evicted order. The
+    /// order is already off the book.
+    ///
+    /// [`OrderEvent::Cancelled`]: enum.OrderEvent.html#variant.Cancelled
+    fn report(&mut self, event: &OrderEvent);
+}
+
+/// Send the events over a channel, e.g. to another thread. Events are
+/// dropped once the receiver is gone.
+#[cfg(feature = "std")]
+impl EvictionSink for Sender<OrderEvent> {
+    fn report(&mut self, event: &OrderEvent) {
+        let _ = self.send(event.clone());
+    }
+}
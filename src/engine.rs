@@ -0,0 +1,222 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::collections::{BTreeMap, BTreeSet};
+use crate::models::{Bbo, OrderEvent, OrderType, Price};
+use crate::orderbook::OrderBook;
+use crate::rejectmessages::RejectReason;
+use crate::session::TradingPhase;
+use crate::storage::{BookStorage, PriceQueue};
+
+/// An order routed to the book of `symbol` by a [`MatchingEngine`].
+///
+/// [`MatchingEngine`]: struct.MatchingEngine.html
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SymbolOrder {
+    /// The symbol of the instrument.
+    pub symbol: String,
+    /// The order to execute.
+    pub order: OrderType,
+}
+
+/// An event produced by the book of `symbol` in a [`MatchingEngine`].
+///
+/// [`MatchingEngine`]: struct.MatchingEngine.html
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EngineEvent {
+    /// The symbol of the instrument.
+    pub symbol: String,
+    /// The event of the book.
+    pub event: OrderEvent,
+    /// The engine wide trade IDs, one per fill of the event in the same
+    /// order, or one for a reported cross.
+    pub trade_ids: Vec<u64>,
+}
+
+/// A matching engine owning one [`OrderBook`] per instrument symbol. Orders
+/// are routed to the book of their symbol, and trades are numbered across
+/// all books. Each book has a [`TradingPhase`], open unless set otherwise.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`TradingPhase`]: enum.TradingPhase.html
+#[derive(Debug)]
+pub struct MatchingEngine<S = BTreeMap<Price, PriceQueue>> {
+    books: BTreeMap<String, OrderBook<S>>,
+    next_trade_id: u64,
+    // The top of book last reported by `take_bbo_updates`, and the symbols
+    // whose book may have changed since.
+    reported_bbo: BTreeMap<String, Bbo>,
+    touched: BTreeSet<String>,
+}
+
+impl<S> Default for MatchingEngine<S> {
+    fn default() -> Self {
+        Self {
+            books: BTreeMap::new(),
+            next_trade_id: 1,
+            reported_bbo: BTreeMap::new(),
+            touched: BTreeSet::new(),
+        }
+    }
+}
+
+impl<S: BookStorage> MatchingEngine<S> {
+    /// Create an engine without books, numbering trades from 1.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the book of `symbol`, returning the book it replaces, if any.
+    pub fn add_book(&mut self, symbol: impl Into<String>, book: OrderBook<S>) -> Option<OrderBook<S>> {
+        let symbol = symbol.into();
+        self.touched.insert(symbol.clone());
+        self.books.insert(symbol, book)
+    }
+
+    /// Remove and return the book of `symbol`.
+    pub fn remove_book(&mut self, symbol: &str) -> Option<OrderBook<S>> {
+        self.touched.insert(symbol.to_string());
+        self.books.remove(symbol)
+    }
+
+    /// Iterate over the symbols and the top of their books, in symbol order.
+    pub fn tops(&self) -> impl Iterator<Item = (&str, Bbo)> {
+        self.books.iter().map(|(symbol, book)| (symbol.as_str(), book.bbo()))
+    }
+
+    /// Return the symbols whose top of book changed since the last call,
+    /// with their new top, in symbol order. Only the books touched since then
+    /// are compared, so feeding the updates to a [`ConsolidatedBbo`] keeps
+    /// it current without scanning every book. A removed book reports an
+    /// empty top.
+    ///
+    /// [`ConsolidatedBbo`]: struct.ConsolidatedBbo.html
+    pub fn take_bbo_updates(&mut self) -> Vec<(String, Bbo)> {
+        let mut updates = Vec::new();
+        for symbol in core::mem::take(&mut self.touched) {
+            let bbo = self.books.get(&symbol).map(OrderBook::bbo);
+            let previous = match bbo {
+                Some(bbo) => self.reported_bbo.insert(symbol.clone(), bbo),
+                None => self.reported_bbo.remove(&symbol),
+            };
+            let bbo = bbo.unwrap_or_default();
+            if previous.unwrap_or_default() != bbo {
+                updates.push((symbol, bbo));
+            }
+        }
+        updates
+    }
+
+    /// Return the trading phase of the book of `symbol`, or `None` without a
+    /// book.
+    pub fn phase(&self, symbol: &str) -> Option<TradingPhase> {
+        self.books.get(symbol).map(OrderBook::phase)
+    }
+
+    /// Set the trading phase of the book of `symbol`, returning the events
+    /// of the auction it runs, numbered like other trades. Nothing happens
+    /// without a book. See [`OrderBook::set_phase`].
+    ///
+    /// [`OrderBook::set_phase`]: struct.OrderBook.html#method.set_phase
+    pub fn set_phase(&mut self, symbol: &str, phase: TradingPhase) -> Vec<EngineEvent> {
+        let Some(book) = self.books.get_mut(symbol) else {
+            return Vec::new();
+        };
+        let events = book.set_phase(phase);
+        self.touched.insert(symbol.to_string());
+        events.into_iter().map(|event| self.engine_event(symbol.to_string(), event)).collect()
+    }
+
+    /// Return the book of `symbol`.
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook<S>> {
+        self.books.get(symbol)
+    }
+
+    /// Return the book of `symbol` mutably, e.g. to configure it.
+    pub fn book_mut(&mut self, symbol: &str) -> Option<&mut OrderBook<S>> {
+        let book = self.books.get_mut(symbol)?;
+        self.touched.insert(symbol.to_string());
+        Some(book)
+    }
+
+    /// Iterate over the symbols and their books, in symbol order.
+    pub fn books(&self) -> impl Iterator<Item = (&str, &OrderBook<S>)> {
+        self.books.iter().map(|(symbol, book)| (symbol.as_str(), book))
+    }
+
+    /// Execute `order` on the book of `order.symbol`. Orders for a symbol
+    /// without a book are rejected, as are orders the book does not accept in
+    /// its trading phase.
+    pub fn execute(&mut self, order: SymbolOrder) -> EngineEvent {
+        let SymbolOrder { symbol, order } = order;
+        let Some(book) = self.books.get_mut(&symbol) else {
+            let event = OrderEvent::Rejected { id: order.get_id(), message: RejectReason::UnknownSymbol };
+            return EngineEvent { symbol, event, trade_ids: Vec::new() };
+        };
+        let event = book.execute(order);
+        if !self.touched.contains(&symbol) {
+            self.touched.insert(symbol.clone());
+        }
+        self.engine_event(symbol, event)
+    }
+
+    /// Number the trades of `event`, produced by the book of `symbol`.
+    fn engine_event(&mut self, symbol: String, event: OrderEvent) -> EngineEvent {
+        let trades = match &event {
+            OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } => fills.len() as u64,
+            OrderEvent::Traded { .. } => 1,
+            _ => 0,
+        };
+        let trade_ids = (self.next_trade_id..self.next_trade_id + trades).collect();
+        self.next_trade_id += trades;
+        EngineEvent { symbol, event, trade_ids }
+    }
+
+    /// Execute orders in sequence, returning their events in the same order.
+    pub fn execute_all<I: IntoIterator<Item = SymbolOrder>>(&mut self, orders: I) -> Vec<EngineEvent> {
+        orders.into_iter().map(|order| self.execute(order)).collect()
+    }
+
+    /// Return the ID the next trade will be assigned.
+    pub fn next_trade_id(&self) -> u64 {
+        self.next_trade_id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MatchingEngine, SymbolOrder};
+    use crate::RejectReason;
+    use crate::{OrderBook, OrderEvent, OrderType, Side};
+
+    fn order(symbol: &str, order: OrderType) -> SymbolOrder {
+        SymbolOrder { symbol: symbol.to_string(), order }
+    }
+
+    #[test]
+    fn engine_routes_orders_and_numbers_trades() {
+        let mut engine = MatchingEngine::new();
+        engine.add_book("AAA", OrderBook::default());
+        engine.add_book("BBB", OrderBook::default());
+        let events = engine.execute_all([
+            order("AAA", OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 1, price: 100 }),
+            order("AAA", OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 1, price: 101 }),
+            order("BBB", OrderType::Limit { id: 1, user_id: 1, side: Side::Bid, qty: 5, price: 50 }),
+            order("AAA", OrderType::Market { id: 3, user_id: 2, side: Side::Bid, qty: 2 }),
+            order("BBB", OrderType::Cross { id: 2, buyer_id: 1, seller_id: 2, qty: 1, price: 50 }),
+            order("CCC", OrderType::Cancel { id: 7 }),
+        ]);
+        let trade_ids: Vec<_> = events.iter().map(|event| event.trade_ids.clone()).collect();
+        assert_eq!(trade_ids, vec![vec![], vec![], vec![], vec![1, 2], vec![3], vec![]]);
+        assert_eq!(events[5].event, OrderEvent::Rejected { id: 7, message: RejectReason::UnknownSymbol });
+        assert_eq!(engine.book("AAA").unwrap().best_ask(), None);
+        assert_eq!(engine.book("BBB").unwrap().best_bid(), Some(50));
+        assert_eq!(engine.books().map(|(symbol, _)| symbol).collect::<Vec<_>>(), vec!["AAA", "BBB"]);
+        assert_eq!(engine.next_trade_id(), 4);
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::engine::EngineEvent;
+use crate::models::{BookDelta, FillMetadata, OrderEvent};
+use crate::utils::TaggedFields;
+
+/// A message of the market data feed written by a [`FeedPublisher`], as
+/// JSON.
+///
+/// [`FeedPublisher`]: struct.FeedPublisher.html
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedMessage {
+    /// A fill.
+    Trade {
+        /// The symbol of the instrument.
+        symbol: String,
+        /// The position of this message in the feed of the symbol, starting
+        /// from 1.
+        sequence: u64,
+        /// The fill.
+        fill: FillMetadata,
+    },
+    /// A change of a price level.
+    Delta {
+        /// The symbol of the instrument.
+        symbol: String,
+        /// The position of this message in the feed of the symbol, starting
+        /// from 1.
+        sequence: u64,
+        /// The change.
+        delta: BookDelta,
+    },
+}
+
+impl<'de> Deserialize<'de> for FeedMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut message = TaggedFields::deserialize(deserializer)?;
+        match message.kind() {
+            "trade" => Ok(Self::Trade { symbol: message.take("symbol")?, sequence: message.take("sequence")?, fill: message.take("fill")? }),
+            "delta" => Ok(Self::Delta { symbol: message.take("symbol")?, sequence: message.take("sequence")?, delta: message.take("delta")? }),
+            _ => Err(message.unknown_variant(&["trade", "delta"])),
+        }
+    }
+}
+
+/// A destination for feed messages, such as a Kafka or NATS client.
+pub trait FeedSink: Debug + Send {
+    /// Send `payload` to `topic`. Messages with the same `key`, the symbol,
+    /// must be delivered in order, e.g. by keeping them on one partition.
+    fn send(&mut self, topic: &str, key: &str, payload: &[u8]) -> io::Result<()>;
+}
+
+/// A feed kept in memory as `(topic, key, message)` records. Clones share the
+/// same records, so a handle can be kept to read them after the feed has
+/// been given to a publisher.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFeed {
+    records: Arc<Mutex<Vec<(String, String, FeedMessage)>>>,
+}
+
+impl MemoryFeed {
+    /// Create an empty feed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return a copy of the records sent so far.
+    pub fn records(&self) -> Vec<(String, String, FeedMessage)> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+impl FeedSink for MemoryFeed {
+    fn send(&mut self, topic: &str, key: &str, payload: &[u8]) -> io::Result<()> {
+        let message = serde_json::from_slice(payload)?;
+        self.records.lock().unwrap().push((topic.to_string(), key.to_string(), message));
+        Ok(())
+    }
+}
+
+/// Serializes trades and book deltas and sends them to a [`FeedSink`],
+/// available with the `feed` feature. Trades and deltas go to their own
+/// topic, keyed by symbol, and are numbered per symbol so consumers can
+/// detect gaps.
+///
+/// [`FeedSink`]: trait.FeedSink.html
+#[derive(Debug)]
+pub struct FeedPublisher {
+    sink: Box<dyn FeedSink>,
+    trades_topic: String,
+    deltas_topic: String,
+    sequences: HashMap<String, u64>,
+}
+
+impl FeedPublisher {
+    /// Create a publisher sending trades to `trades_topic` and deltas to
+    /// `deltas_topic`.
+    pub fn new(sink: Box<dyn FeedSink>, trades_topic: impl Into<String>, deltas_topic: impl Into<String>) -> Self {
+        Self { sink, trades_topic: trades_topic.into(), deltas_topic: deltas_topic.into(), sequences: HashMap::new() }
+    }
+
+    /// Publish the fills of `event`, an event of the book of `symbol`.
+    pub fn publish_event(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        if let OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } = event {
+            for fill in fills.iter() {
+                let sequence = self.next_sequence(symbol);
+                let message = FeedMessage::Trade { symbol: symbol.to_string(), sequence, fill: *fill };
+                let topic = self.trades_topic.clone();
+                self.send(&topic, symbol, &message)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Publish the fills of an event of a [`MatchingEngine`].
+    ///
+    /// [`MatchingEngine`]: struct.MatchingEngine.html
+    pub fn publish_engine_event(&mut self, event: &EngineEvent) -> io::Result<()> {
+        self.publish_event(&event.symbol, &event.event)
+    }
+
+    /// Publish the deltas of the book of `symbol`, such as those returned by
+    /// [`OrderBook::take_deltas`].
+    ///
+    /// [`OrderBook::take_deltas`]: struct.OrderBook.html#method.take_deltas
+    pub fn publish_deltas(&mut self, symbol: &str, deltas: &[BookDelta]) -> io::Result<()> {
+        for delta in deltas {
+            let sequence = self.next_sequence(symbol);
+            let message = FeedMessage::Delta { symbol: symbol.to_string(), sequence, delta: *delta };
+            let topic = self.deltas_topic.clone();
+            self.send(&topic, symbol, &message)?;
+        }
+        Ok(())
+    }
+
+    fn next_sequence(&mut self, symbol: &str) -> u64 {
+        let sequence = self.sequences.entry(symbol.to_string()).or_insert(0);
+        *sequence += 1;
+        *sequence
+    }
+
+    fn send(&mut self, topic: &str, key: &str, message: &FeedMessage) -> io::Result<()> {
+        let payload = serde_json::to_vec(message)?;
+        self.sink.send(topic, key, &payload)
+    }
+}
+
+/// A [`FeedSink`] publishing to a NATS server over its text protocol.
+/// Messages go to the subject `<topic>.<key>`, so consumers can subscribe to
+/// one symbol or, with `<topic>.*`, to all of them.
+///
+/// [`FeedSink`]: trait.FeedSink.html
+#[derive(Debug)]
+pub struct NatsFeed {
+    stream: TcpStream,
+}
+
+impl NatsFeed {
+    /// Connect to the NATS server at `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n")?;
+        Ok(Self { stream })
+    }
+
+    /// Answer the pings of the server, which closes connections that do not.
+    fn answer_pings(&mut self) -> io::Result<()> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0; 512];
+        let mut pings = 0;
+        let read = loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => break Err(io::Error::new(ErrorKind::ConnectionAborted, "the NATS server closed the connection")),
+                Ok(read) => pings += buffer[..read].windows(6).filter(|line| line == b"PING\r\n").count(),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(error) => break Err(error),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        read?;
+        for _ in 0..pings {
+            self.stream.write_all(b"PONG\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl FeedSink for NatsFeed {
+    fn send(&mut self, topic: &str, key: &str, payload: &[u8]) -> io::Result<()> {
+        self.answer_pings()?;
+        let mut message = format!("PUB {}.{} {}\r\n", topic, key, payload.len()).into_bytes();
+        message.extend_from_slice(payload);
+        message.extend_from_slice(b"\r\n");
+        self.stream.write_all(&message)
+    }
+}
+
+/// A [`FeedSink`] producing to Kafka, available with the `kafka` feature.
+/// Records are keyed by symbol, so each symbol stays on one partition.
+///
+/// [`FeedSink`]: trait.FeedSink.html
+#[cfg(feature = "kafka")]
+pub struct KafkaFeed {
+    producer: rdkafka::producer::BaseProducer,
+}
+
+#[cfg(feature = "kafka")]
+impl Debug for KafkaFeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaFeed").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaFeed {
+    /// Create a producer for the comma-separated `brokers`.
+    pub fn new(brokers: &str) -> io::Result<Self> {
+        let producer = rdkafka::ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("enable.idempotence", "true")
+            .create()
+            .map_err(io::Error::other)?;
+        Ok(Self { producer })
+    }
+
+    /// Wait up to `timeout` for the queued records to be delivered.
+    pub fn flush(&mut self, timeout: std::time::Duration) -> io::Result<()> {
+        rdkafka::producer::Producer::flush(&self.producer, timeout).map_err(io::Error::other)
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl FeedSink for KafkaFeed {
+    fn send(&mut self, topic: &str, key: &str, payload: &[u8]) -> io::Result<()> {
+        let record = rdkafka::producer::BaseRecord::to(topic).key(key).payload(payload);
+        self.producer.send(record).map_err(|(error, _)| io::Error::other(error))?;
+        // Serve the delivery callbacks, so the queue does not fill up.
+        self.producer.poll(std::time::Duration::ZERO);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    use super::{FeedMessage, FeedPublisher, FeedSink, MemoryFeed, NatsFeed};
+    use crate::{OrderBook, OrderType, Side};
+
+    #[test]
+    #[cfg(feature = "wide-qty")]
+    fn wide_quantities_round_trip_through_feed_messages() {
+        let qty = u64::MAX as crate::Qty + 1;
+        let delta = crate::BookDelta { side: Side::Ask, price: 100, qty, reason: crate::DeltaReason::Add };
+        let message = FeedMessage::Delta { symbol: "AAA".to_string(), sequence: 1, delta };
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(matches!(serde_json::from_str(&json).unwrap(), FeedMessage::Delta { delta: parsed, .. } if parsed == delta));
+    }
+
+    #[test]
+    fn feed_publishes_trades_and_deltas_by_symbol() {
+        let feed = MemoryFeed::new();
+        let mut publisher = FeedPublisher::new(Box::new(feed.clone()), "trades", "deltas");
+        let mut ob = OrderBook::default();
+        ob.track_deltas(true);
+        for order in [
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 },
+            OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: 1 },
+        ] {
+            let event = ob.execute(order);
+            publisher.publish_event("AAA", &event).unwrap();
+            publisher.publish_deltas("AAA", &ob.take_deltas()).unwrap();
+        }
+        publisher.publish_deltas("BBB", &ob.take_deltas()).unwrap();
+
+        let records: Vec<_> = feed
+            .records()
+            .into_iter()
+            .map(|(topic, key, message)| match message {
+                FeedMessage::Trade { sequence, fill, .. } => (topic, key, sequence, fill.qty),
+                FeedMessage::Delta { sequence, delta, .. } => (topic, key, sequence, delta.qty),
+            })
+            .collect();
+        let record = |topic: &str, sequence, qty| (topic.to_string(), "AAA".to_string(), sequence, qty);
+        assert_eq!(records, vec![record("deltas", 1, 2), record("trades", 2, 1), record("deltas", 3, 1)]);
+    }
+
+    #[test]
+    fn nats_feed_speaks_the_text_protocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"INFO {}\r\nPING\r\n").unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut lines = Vec::new();
+            for _ in 0..4 {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                lines.push(line);
+            }
+            let mut rest = String::new();
+            reader.read_to_string(&mut rest).unwrap();
+            (lines, rest)
+        });
+
+        let mut feed = NatsFeed::connect(addr).unwrap();
+        // Let the server's ping arrive before publishing.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        feed.send("trades", "AAA", b"{}").unwrap();
+        drop(feed);
+
+        let (lines, rest) = server.join().unwrap();
+        assert!(lines[0].starts_with("CONNECT "));
+        assert_eq!(lines[1..], ["PONG\r\n", "PUB trades.AAA 2\r\n", "{}\r\n"]);
+        assert!(rest.is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::models::{notional, Notional, OrderEvent, OrderId, OrderType, Price, Qty, Side, UserId};
+
+const SOH: char = '\u{1}';
+
+/// Errors returned when converting a FIX message into an order.
+#[derive(Debug, Error, PartialEq)]
+pub enum FixError {
+    /// A field is not of the form `tag=value`.
+    #[error("Malformed field `{0}`")]
+    MalformedField(String),
+    /// The checksum (10) does not match the message.
+    #[error("Invalid checksum: expected {expected:03}, found {found}")]
+    InvalidChecksum {
+        /// The checksum computed over the message.
+        expected: u8,
+        /// The checksum found in the message.
+        found: String,
+    },
+    /// A field required by the message type is missing.
+    #[error("Missing required field {0}")]
+    MissingField(u32),
+    /// A field holds an unsupported value.
+    #[error("Invalid value `{value}` for field {tag}")]
+    InvalidField {
+        /// The tag of the field.
+        tag: u32,
+        /// The value found.
+        value: String,
+    },
+    /// The message type (35) is neither NewOrderSingle nor
+    /// OrderCancelRequest.
+    #[error("Unsupported message type `{0}`")]
+    UnsupportedMessage(String),
+    /// The ClOrdID (11) is already used by an order of the adapter.
+    #[error("Duplicate ClOrdID `{0}`")]
+    DuplicateClOrdId(String),
+    /// The OrigClOrdID (41) of a cancel does not refer to an open order.
+    #[error("Unknown OrigClOrdID `{0}`")]
+    UnknownOrder(String),
+}
+
+/// The fields of a FIX message, in the order they were received.
+struct FixFields {
+    fields: Vec<(u32, String)>,
+}
+
+impl FixFields {
+    fn parse(message: &str) -> Result<Self, FixError> {
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        for field in message.split(SOH).filter(|field| !field.is_empty()) {
+            let (tag, value) = field.split_once('=').ok_or_else(|| FixError::MalformedField(field.to_string()))?;
+            let tag: u32 = tag.parse().map_err(|_| FixError::MalformedField(field.to_string()))?;
+            if tag == 10 {
+                let expected = checksum(&message[..offset]);
+                if value.parse() != Ok(expected) {
+                    return Err(FixError::InvalidChecksum { expected, found: value.to_string() });
+                }
+            }
+            offset += field.len() + 1;
+            fields.push((tag, value.to_string()));
+        }
+        Ok(Self { fields })
+    }
+
+    fn get(&self, tag: u32) -> Option<&str> {
+        self.fields.iter().find(|(field, _)| *field == tag).map(|(_, value)| value.as_str())
+    }
+
+    fn required(&self, tag: u32) -> Result<&str, FixError> {
+        self.get(tag).ok_or(FixError::MissingField(tag))
+    }
+
+    fn number<T: FromStr>(&self, tag: u32) -> Result<T, FixError> {
+        let value = self.required(tag)?;
+        value.parse().map_err(|_| FixError::InvalidField { tag, value: value.to_string() })
+    }
+}
+
+fn checksum(bytes: &str) -> u8 {
+    bytes.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+/// The state of an order entered through the adapter.
+#[derive(Debug, Clone)]
+struct FixOrder {
+    cl_ord_id: String,
+    symbol: Option<String>,
+    side: Side,
+    order_qty: Qty,
+    cum_qty: Qty,
+    notional: Notional,
+    /// Whether a remaining quantity rests on the book after the order
+    /// partially fills.
+    rests: bool,
+    /// The ClOrdID of a pending cancel request.
+    cancel_cl_ord_id: Option<String>,
+}
+
+/// Converts FIX 4.4 NewOrderSingle (`D`) and OrderCancelRequest (`F`)
+/// messages into orders, and renders the events of those orders as
+/// ExecutionReports (`8`) and OrderCancelRejects (`9`).
+///
+/// The adapter assigns increasing order IDs to the orders it converts and
+/// keeps their ClOrdIDs, which are echoed back on every report. Fills of
+/// resting orders entered through the adapter are reported to them as well,
+/// from the fill metadata of the taker's event. Feed every event of the book
+/// to [`execution_reports`] in order, so the adapter can track the state of
+/// its orders.
+///
+/// [`execution_reports`]: #method.execution_reports
+#[derive(Debug, Clone)]
+pub struct FixAdapter {
+    sender_comp_id: String,
+    target_comp_id: String,
+    next_order_id: OrderId,
+    next_seq_num: u64,
+    next_exec_id: u64,
+    orders: HashMap<OrderId, FixOrder>,
+    cl_ord_ids: HashMap<String, OrderId>,
+}
+
+impl FixAdapter {
+    /// Create an adapter sending reports as `sender_comp_id` to
+    /// `target_comp_id`. Order IDs are assigned starting from 1.
+    pub fn new(sender_comp_id: &str, target_comp_id: &str) -> Self {
+        Self {
+            sender_comp_id: sender_comp_id.to_string(),
+            target_comp_id: target_comp_id.to_string(),
+            next_order_id: 1,
+            next_seq_num: 1,
+            next_exec_id: 1,
+            orders: HashMap::new(),
+            cl_ord_ids: HashMap::new(),
+        }
+    }
+
+    /// Set the ID assigned to the next order, to share a book with other
+    /// order sources. IDs must keep increasing for the book to accept them.
+    pub fn set_next_order_id(&mut self, id: OrderId) {
+        self.next_order_id = id;
+    }
+
+    /// Return the order ID assigned to an open order entered with
+    /// `cl_ord_id`.
+    pub fn order_id(&self, cl_ord_id: &str) -> Option<OrderId> {
+        self.cl_ord_ids.get(cl_ord_id).copied()
+    }
+
+    /// Convert a NewOrderSingle or OrderCancelRequest into an order to
+    /// execute. Fields are separated by SOH, and the checksum is verified when
+    /// present.
+    ///
+    /// NewOrderSingle reads ClOrdID (11), Account (1) as the user ID, Side
+    /// (54, `1` buy or `2` sell), OrderQty (38), OrdType (40, `1` market or
+    /// `2` limit), Price (44), TimeInForce (59, `0` day, `1` good till
+    /// cancel, `3` IOC, `4` FOK or `6` good till date), ExpireTime (126) as
+    /// the book clock time for good till date orders, ExecInst (18, `6` for
+    /// post-only) and Symbol (55), which is echoed on the reports.
+    /// OrderCancelRequest reads ClOrdID (11) and OrigClOrdID (41).
+    pub fn parse_order(&mut self, message: &str) -> Result<OrderType, FixError> {
+        let fields = FixFields::parse(message)?;
+        match fields.required(35)? {
+            "D" => self.new_order_single(&fields),
+            "F" => {
+                let cl_ord_id = fields.required(11)?;
+                let orig_cl_ord_id = fields.required(41)?;
+                let id = self.order_id(orig_cl_ord_id).ok_or_else(|| FixError::UnknownOrder(orig_cl_ord_id.to_string()))?;
+                if let Some(order) = self.orders.get_mut(&id) {
+                    order.cancel_cl_ord_id = Some(cl_ord_id.to_string());
+                }
+                Ok(OrderType::Cancel { id })
+            }
+            other => Err(FixError::UnsupportedMessage(other.to_string())),
+        }
+    }
+
+    fn new_order_single(&mut self, fields: &FixFields) -> Result<OrderType, FixError> {
+        let cl_ord_id = fields.required(11)?;
+        if self.cl_ord_ids.contains_key(cl_ord_id) {
+            return Err(FixError::DuplicateClOrdId(cl_ord_id.to_string()));
+        }
+        let invalid = |tag: u32| FixError::InvalidField { tag, value: fields.get(tag).unwrap_or_default().to_string() };
+        let user_id: UserId = fields.number(1)?;
+        let side = match fields.required(54)? {
+            "1" => Side::Bid,
+            "2" => Side::Ask,
+            _ => return Err(invalid(54)),
+        };
+        let qty: Qty = fields.number(38)?;
+        let id = self.next_order_id;
+        let order = match fields.required(40)? {
+            "1" => OrderType::Market { id, user_id, side, qty },
+            "2" => {
+                let price = fields.number(44)?;
+                let post_only = fields.get(18).is_some_and(|inst| inst.split(' ').any(|inst| inst == "6"));
+                match (fields.get(59).unwrap_or("0"), post_only) {
+                    ("0" | "1", false) => OrderType::Limit { id, user_id, side, qty, price },
+                    ("0" | "1", true) => OrderType::Postonly { id, user_id, side, qty, price },
+                    ("3", false) => OrderType::IOC { id, user_id, side, qty, price },
+                    ("4", false) => OrderType::FOK { id, user_id, side, qty, price },
+                    ("6", false) => OrderType::GTT { id, user_id, side, qty, price, expires_at: fields.number(126)? },
+                    (_, false) => return Err(invalid(59)),
+                    (_, true) => return Err(invalid(18)),
+                }
+            }
+            _ => return Err(invalid(40)),
+        };
+        self.next_order_id += 1;
+        let rests = matches!(order, OrderType::Limit { .. } | OrderType::Postonly { .. } | OrderType::GTT { .. });
+        self.orders.insert(
+            id,
+            FixOrder {
+                cl_ord_id: cl_ord_id.to_string(),
+                symbol: fields.get(55).map(str::to_string),
+                side,
+                order_qty: qty,
+                cum_qty: 0,
+                notional: 0,
+                rests,
+                cancel_cl_ord_id: None,
+            },
+        );
+        self.cl_ord_ids.insert(cl_ord_id.to_string(), id);
+        Ok(order)
+    }
+
+    /// Render the reports for an event of the book. Events of orders that
+    /// were not entered through the adapter only produce reports for the
+    /// adapter's resting orders they filled.
+    pub fn execution_reports(&mut self, event: &OrderEvent) -> Vec<String> {
+        let mut reports = Vec::new();
+        match event {
+            OrderEvent::Open { id, .. } => reports.extend(self.execution_report(*id, "0", "0", Vec::new())),
+            // The reports of the order were sent when it was first executed.
+            OrderEvent::Duplicate { .. } => {}
+            OrderEvent::Cancelled { id } => {
+                // A requested cancel is reported under the ClOrdID of the
+                // request, referring to the original order.
+                let cancel_cl_ord_id = self.orders.get_mut(id).and_then(|order| order.cancel_cl_ord_id.take());
+                if let (Some(cl_ord_id), Some(order)) = (cancel_cl_ord_id, self.orders.get_mut(id)) {
+                    let orig_cl_ord_id = std::mem::replace(&mut order.cl_ord_id, cl_ord_id);
+                    reports.extend(self.execution_report(*id, "4", "4", vec![(41, orig_cl_ord_id.clone())]));
+                    self.cl_ord_ids.remove(&orig_cl_ord_id);
+                } else {
+                    reports.extend(self.execution_report(*id, "4", "4", Vec::new()));
+                }
+                self.close(*id);
+            }
+            OrderEvent::AdminCancelled { id, reason } => {
+                reports.extend(self.execution_report(*id, "4", "4", vec![(58, reason.clone())]));
+                self.close(*id);
+            }
+            OrderEvent::Expired { id } => {
+                reports.extend(self.execution_report(*id, "C", "C", Vec::new()));
+                self.close(*id);
+            }
+            OrderEvent::Reduced { id, qty } => {
+                if let Some(order) = self.orders.get_mut(id) {
+                    order.order_qty = order.cum_qty + qty;
+                    let status = if order.cum_qty > 0 { "1" } else { "0" };
+                    reports.extend(self.execution_report(*id, "5", status, Vec::new()));
+                }
+            }
+            OrderEvent::Rejected { id, message } => {
+                reports.extend(self.execution_report(*id, "8", "8", vec![(58, message.to_string())]));
+                self.close(*id);
+            }
+            OrderEvent::CancelRejected { id, message } => {
+                if let Some(order) = self.orders.get_mut(id) {
+                    let fields = vec![
+                        (37, id.to_string()),
+                        (11, order.cancel_cl_ord_id.take().unwrap_or_default()),
+                        (41, order.cl_ord_id.clone()),
+                        (39, if order.cum_qty > 0 { "1" } else { "0" }.to_string()),
+                        (434, "1".to_string()),
+                        (102, "1".to_string()),
+                        (58, message.to_string()),
+                    ];
+                    reports.push(self.render("9", fields));
+                }
+            }
+            OrderEvent::PartiallyFilled { id, fills, .. } | OrderEvent::Filled { id, fills, .. } => {
+                for fill in fills {
+                    reports.extend(self.fill(*id, fill.qty, fill.price, None));
+                    reports.extend(self.fill(fill.maker_id, fill.qty, fill.price, Some(fill.maker_remaining_qty)));
+                }
+                // The rest of an order that does not rest on the book is
+                // canceled.
+                if self.orders.get(id).is_some_and(|order| !order.rests) {
+                    reports.extend(self.execution_report(*id, "4", "4", Vec::new()));
+                    self.close(*id);
+                }
+            }
+            OrderEvent::Traded { .. } => {}
+        }
+        reports
+    }
+
+    /// Apply a fill to an order and render its trade report.
+    fn fill(&mut self, id: OrderId, qty: Qty, price: Price, leaves_qty: Option<Qty>) -> Option<String> {
+        let order = self.orders.get_mut(&id)?;
+        order.cum_qty += qty;
+        order.notional += notional(price, qty);
+        if let Some(leaves_qty) = leaves_qty {
+            order.order_qty = order.cum_qty + leaves_qty;
+        }
+        let status = if order.cum_qty >= order.order_qty { "2" } else { "1" };
+        let report = self.execution_report(id, "F", status, vec![(32, qty.to_string()), (31, price.to_string())]);
+        if status == "2" {
+            self.close(id);
+        }
+        report
+    }
+
+    /// Render an execution report for an order of the adapter.
+    fn execution_report(&mut self, id: OrderId, exec_type: &str, status: &str, extra: Vec<(u32, String)>) -> Option<String> {
+        let order = self.orders.get(&id)?;
+        let terminal = matches!(status, "2" | "4" | "8" | "C");
+        let leaves_qty = if terminal { 0 } else { order.order_qty.saturating_sub(order.cum_qty) };
+        let avg_px = order.notional.checked_div(notional(1, order.cum_qty)).unwrap_or(0);
+        let mut fields = vec![
+            (37, id.to_string()),
+            (11, order.cl_ord_id.clone()),
+            (17, self.next_exec_id.to_string()),
+            (150, exec_type.to_string()),
+            (39, status.to_string()),
+        ];
+        if let Some(symbol) = &order.symbol {
+            fields.push((55, symbol.clone()));
+        }
+        fields.push((54, if order.side == Side::Bid { "1" } else { "2" }.to_string()));
+        fields.push((38, order.order_qty.to_string()));
+        fields.push((151, leaves_qty.to_string()));
+        fields.push((14, order.cum_qty.to_string()));
+        fields.push((6, avg_px.to_string()));
+        fields.extend(extra);
+        self.next_exec_id += 1;
+        Some(self.render("8", fields))
+    }
+
+    /// Render a message with its header, body length and checksum.
+    fn render(&mut self, msg_type: &str, fields: Vec<(u32, String)>) -> String {
+        let mut body = format!(
+            "35={}{}49={}{}56={}{}34={}{}",
+            msg_type, SOH, self.sender_comp_id, SOH, self.target_comp_id, SOH, self.next_seq_num, SOH
+        );
+        self.next_seq_num += 1;
+        for (tag, value) in fields {
+            body.push_str(&format!("{}={}{}", tag, value, SOH));
+        }
+        let message = format!("8=FIX.4.4{}9={}{}{}", SOH, body.len(), SOH, body);
+        format!("{}10={:03}{}", message, checksum(&message), SOH)
+    }
+
+    fn close(&mut self, id: OrderId) {
+        if let Some(order) = self.orders.remove(&id) {
+            self.cl_ord_ids.remove(&order.cl_ord_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FixAdapter, FixError, FixFields};
+    use crate::{OrderBook, OrderType, Side};
+
+    fn message(fields: &str) -> String {
+        fields.replace('|', "\u{1}")
+    }
+
+    fn field(report: &str, tag: u32) -> Option<String> {
+        FixFields::parse(report).unwrap().get(tag).map(str::to_string)
+    }
+
+    #[test]
+    fn fix_orders_and_execution_reports() {
+        let mut ob = OrderBook::default();
+        let mut fix = FixAdapter::new("BOOK", "CLIENT");
+
+        let order = fix.parse_order(&message("8=FIX.4.4|35=D|11=sell-1|1=7|55=ABC|54=2|38=5|40=2|44=100|59=0|")).unwrap();
+        assert!(matches!(order, OrderType::Limit { id: 1, user_id: 7, side: Side::Ask, qty: 5, price: 100 }));
+        let reports = fix.execution_reports(&ob.execute(order));
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].starts_with(&message("8=FIX.4.4|9=")));
+        assert!(reports[0].contains(&message("|35=8|49=BOOK|56=CLIENT|34=1|37=1|11=sell-1|17=1|150=0|39=0|55=ABC|54=2|38=5|151=5|14=0|6=0|")));
+        // Reports carry a valid checksum.
+        assert!(FixFields::parse(&reports[0]).is_ok());
+
+        let order = fix.parse_order(&message("35=D|11=buy-1|1=8|54=1|38=2|40=1|")).unwrap();
+        let reports = fix.execution_reports(&ob.execute(order));
+        assert_eq!(reports.len(), 2);
+        assert_eq!(field(&reports[0], 11).as_deref(), Some("buy-1"));
+        assert_eq!(field(&reports[0], 39).as_deref(), Some("2"));
+        assert_eq!(field(&reports[1], 11).as_deref(), Some("sell-1"));
+        assert_eq!(field(&reports[1], 39).as_deref(), Some("1"));
+        assert_eq!(field(&reports[1], 151).as_deref(), Some("3"));
+        assert_eq!(field(&reports[1], 31).as_deref(), Some("100"));
+
+        let order = fix.parse_order(&message("35=F|11=cancel-1|41=sell-1|54=2|")).unwrap();
+        assert!(matches!(order, OrderType::Cancel { id: 1 }));
+        let reports = fix.execution_reports(&ob.execute(order));
+        assert_eq!(field(&reports[0], 11).as_deref(), Some("cancel-1"));
+        assert_eq!(field(&reports[0], 41).as_deref(), Some("sell-1"));
+        assert_eq!(field(&reports[0], 150).as_deref(), Some("4"));
+        assert_eq!(fix.order_id("sell-1"), None);
+
+        assert_eq!(fix.parse_order(&message("35=F|11=cancel-2|41=sell-1|")).unwrap_err(), FixError::UnknownOrder("sell-1".to_string()));
+        assert_eq!(fix.parse_order(&message("35=D|11=x|1=1|54=3|38=1|40=1|")).unwrap_err(), FixError::InvalidField { tag: 54, value: "3".to_string() });
+        assert_eq!(fix.parse_order(&message("35=D|11=x|1=1|54=1|40=1|")).unwrap_err(), FixError::MissingField(38));
+        assert!(matches!(fix.parse_order(&message("35=D|10=000|")), Err(FixError::InvalidChecksum { .. })));
+        assert_eq!(fix.parse_order(&message("35=A|")).unwrap_err(), FixError::UnsupportedMessage("A".to_string()));
+    }
+}
@@ -0,0 +1,47 @@
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::sync::mpsc::Sender;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::models::OrderId;
+
+/// A range of order IDs skipped by the externally sequenced orders of an
+/// [`OrderBook`], which a replication consumer can request again. The book
+/// rejects the missing orders if they arrive late, so gaps are only closed
+/// downstream. See [`OrderBook::track_gaps`].
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`OrderBook::track_gaps`]: struct.OrderBook.html#method.track_gaps
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SequenceGap {
+    /// The first missing order ID.
+    pub first: OrderId,
+    /// The last missing order ID, inclusive.
+    pub last: OrderId,
+}
+
+impl SequenceGap {
+    /// Return whether `id` is one of the missing order IDs.
+    pub fn contains(&self, id: OrderId) -> bool {
+        (self.first..=self.last).contains(&id)
+    }
+}
+
+/// A destination notified of sequence gaps as they are found, e.g. to send a
+/// resend request upstream.
+pub trait GapSink: Debug + Send {
+    /// Deliver a gap. The order following it has already been accepted.
+    fn report(&mut self, gap: SequenceGap);
+}
+
+/// Send the gaps over a channel, e.g. to another thread. Gaps are dropped
+/// once the receiver is gone.
+#[cfg(feature = "std")]
+impl GapSink for Sender<SequenceGap> {
+    fn report(&mut self, gap: SequenceGap) {
+        let _ = self.send(gap);
+    }
+}
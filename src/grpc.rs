@@ -0,0 +1,268 @@
+use std::pin::Pin;
+use std::thread;
+
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+use crate::models::{price_from_wire, qty_from_wire, wire_price, wire_qty, BookDepth, BookLevel, FillMetadata, OrderEvent, OrderType, Side};
+use crate::orderbook::OrderBook;
+use crate::storage::BookStorage;
+
+/// The messages and service generated from `proto/legion.proto`, available
+/// with the `grpc` feature.
+#[allow(missing_docs, clippy::all)]
+pub mod proto {
+    tonic::include_proto!("legion");
+}
+
+use proto::matching_service_server::{MatchingService, MatchingServiceServer};
+
+// Trades not yet sent to a slow stream are dropped beyond this many.
+const TRADE_BUFFER: usize = 1024;
+const COMMAND_BUFFER: usize = 1024;
+
+enum Command {
+    Execute(OrderType, oneshot::Sender<OrderEvent>),
+    Depth(usize, oneshot::Sender<BookDepth>),
+}
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// A gRPC [`MatchingService`] around an [`OrderBook`], available with the
+/// `grpc` feature, so the book can be driven from other languages. The book
+/// runs on its own thread; serve the service with
+/// `tonic::transport::Server::builder().add_service(service.into_server())`.
+///
+/// [`MatchingService`]: proto/matching_service_server/trait.MatchingService.html
+/// [`OrderBook`]: struct.OrderBook.html
+#[derive(Debug, Clone)]
+pub struct BookService {
+    commands: mpsc::Sender<Command>,
+    trades: broadcast::Sender<proto::Trade>,
+    changes: watch::Receiver<u64>,
+}
+
+impl BookService {
+    /// Move `book` to a new thread and serve it.
+    pub fn new<S: BookStorage + Send + 'static>(mut book: OrderBook<S>) -> Self {
+        let (commands, mut receiver) = mpsc::channel(COMMAND_BUFFER);
+        let (trades, _) = broadcast::channel(TRADE_BUFFER);
+        let (change_sender, changes) = watch::channel(0);
+        let trade_sender = trades.clone();
+        thread::spawn(move || {
+            while let Some(command) = receiver.blocking_recv() {
+                match command {
+                    Command::Execute(order, reply) => {
+                        let event = book.execute(order);
+                        if let OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } = &event {
+                            for fill in fills.iter() {
+                                // Sending only fails without streams.
+                                let _ = trade_sender.send(trade(fill));
+                            }
+                        }
+                        change_sender.send_modify(|version| *version += 1);
+                        let _ = reply.send(event);
+                    }
+                    Command::Depth(levels, reply) => {
+                        let _ = reply.send(book.depth(levels, false));
+                    }
+                }
+            }
+        });
+        Self { commands, trades, changes }
+    }
+
+    /// Wrap the service for a tonic server.
+    pub fn into_server(self) -> MatchingServiceServer<Self> {
+        MatchingServiceServer::new(self)
+    }
+
+    async fn execute(&self, order: OrderType) -> Result<proto::OrderEventReply, Status> {
+        let (reply, event) = oneshot::channel();
+        self.commands.send(Command::Execute(order, reply)).await.map_err(|_| stopped())?;
+        Ok(event_reply(event.await.map_err(|_| stopped())?))
+    }
+
+    async fn depth(commands: &mpsc::Sender<Command>, levels: u32) -> Result<proto::Depth, Status> {
+        let (reply, depth) = oneshot::channel();
+        commands.send(Command::Depth(levels as usize, reply)).await.map_err(|_| stopped())?;
+        let depth = depth.await.map_err(|_| stopped())?;
+        let levels = |levels: Vec<BookLevel>| {
+            levels.into_iter().map(|level| proto::Level { price: wire_price(level.price), qty: wire_qty(level.qty) }).collect()
+        };
+        Ok(proto::Depth { asks: levels(depth.asks), bids: levels(depth.bids) })
+    }
+}
+
+fn stopped() -> Status {
+    Status::unavailable("the book has stopped")
+}
+
+fn side(side: Side) -> proto::Side {
+    match side {
+        Side::Bid => proto::Side::Bid,
+        Side::Ask => proto::Side::Ask,
+    }
+}
+
+fn trade(fill: &FillMetadata) -> proto::Trade {
+    proto::Trade {
+        taker_id: fill.taker_id,
+        maker_id: fill.maker_id,
+        maker_user_id: fill.maker_user_id,
+        maker_client_order_id: fill.maker_client_order_id,
+        qty: wire_qty(fill.qty),
+        price: wire_price(fill.price),
+        taker_side: side(fill.taker_side) as i32,
+        total_fill: fill.total_fill,
+        maker_remaining_qty: wire_qty(fill.maker_remaining_qty),
+        trade_id: fill.trade_id,
+    }
+}
+
+/// Convert a submitted order, refusing unknown enum values and quantities
+/// that do not fit the book quantity type.
+fn order(request: proto::SubmitOrderRequest) -> Result<OrderType, Status> {
+    let proto::SubmitOrderRequest { id, user_id, qty, price, expires_at, .. } = request;
+    let side = match proto::Side::try_from(request.side) {
+        Ok(proto::Side::Bid) => Side::Bid,
+        Ok(proto::Side::Ask) => Side::Ask,
+        Err(_) => return Err(Status::invalid_argument("unknown side")),
+    };
+    let qty = qty_from_wire(qty).ok_or_else(|| Status::invalid_argument("quantity out of range"))?;
+    let price = price_from_wire(price);
+    let kind = proto::OrderKind::try_from(request.kind).map_err(|_| Status::invalid_argument("unknown order kind"))?;
+    Ok(match kind {
+        proto::OrderKind::Limit => OrderType::Limit { id, user_id, side, qty, price },
+        proto::OrderKind::Market => OrderType::Market { id, user_id, side, qty },
+        proto::OrderKind::Ioc => OrderType::IOC { id, user_id, side, qty, price },
+        proto::OrderKind::Fok => OrderType::FOK { id, user_id, side, qty, price },
+        proto::OrderKind::PostOnly => OrderType::Postonly { id, user_id, side, qty, price },
+        proto::OrderKind::PostOnlySlide => OrderType::PostonlySlide { id, user_id, side, qty, price },
+        proto::OrderKind::GoodTillTime => OrderType::GTT { id, user_id, side, qty, price, expires_at },
+    })
+}
+
+fn event_reply(event: OrderEvent) -> proto::OrderEventReply {
+    use proto::EventKind;
+    let reply = |id, kind: EventKind| proto::OrderEventReply { id, kind: kind as i32, ..Default::default() };
+    match event {
+        OrderEvent::Rejected { id, message } => proto::OrderEventReply { message: message.to_string(), ..reply(id, EventKind::Rejected) },
+        OrderEvent::Open { id, remaining_qty, resting_price } => proto::OrderEventReply {
+            remaining_qty: wire_qty(remaining_qty),
+            resting_price: Some(wire_price(resting_price)),
+            ..reply(id, EventKind::Open)
+        },
+        OrderEvent::Duplicate { id } => reply(id, EventKind::Duplicate),
+        OrderEvent::Cancelled { id } => reply(id, EventKind::Cancelled),
+        OrderEvent::AdminCancelled { id, reason } => proto::OrderEventReply { message: reason, ..reply(id, EventKind::AdminCancelled) },
+        OrderEvent::CancelRejected { id, message } => {
+            proto::OrderEventReply { message: message.to_string(), ..reply(id, EventKind::CancelRejected) }
+        }
+        OrderEvent::Expired { id } => reply(id, EventKind::Expired),
+        OrderEvent::Reduced { id, qty } => proto::OrderEventReply { qty: wire_qty(qty), ..reply(id, EventKind::Reduced) },
+        OrderEvent::PartiallyFilled { id, filled_qty, remaining_qty, resting_price, fills } => proto::OrderEventReply {
+            qty: wire_qty(filled_qty),
+            remaining_qty: wire_qty(remaining_qty),
+            resting_price: resting_price.map(wire_price),
+            fills: fills.iter().map(trade).collect(),
+            ..reply(id, EventKind::PartiallyFilled)
+        },
+        OrderEvent::Filled { id, filled_qty, fills, .. } => proto::OrderEventReply {
+            qty: wire_qty(filled_qty),
+            fills: fills.iter().map(trade).collect(),
+            ..reply(id, EventKind::Filled)
+        },
+        OrderEvent::Traded { id, qty, .. } => proto::OrderEventReply { qty: wire_qty(qty), ..reply(id, EventKind::Traded) },
+    }
+}
+
+#[tonic::async_trait]
+impl MatchingService for BookService {
+    async fn submit_order(&self, request: Request<proto::SubmitOrderRequest>) -> Result<Response<proto::OrderEventReply>, Status> {
+        let order = order(request.into_inner())?;
+        self.execute(order).await.map(Response::new)
+    }
+
+    async fn cancel_order(&self, request: Request<proto::CancelOrderRequest>) -> Result<Response<proto::OrderEventReply>, Status> {
+        self.execute(OrderType::Cancel { id: request.into_inner().id }).await.map(Response::new)
+    }
+
+    async fn get_depth(&self, request: Request<proto::DepthRequest>) -> Result<Response<proto::Depth>, Status> {
+        Self::depth(&self.commands, request.into_inner().levels).await.map(Response::new)
+    }
+
+    type StreamTradesStream = ResponseStream<proto::Trade>;
+
+    async fn stream_trades(&self, _: Request<proto::StreamTradesRequest>) -> Result<Response<Self::StreamTradesStream>, Status> {
+        // Trades a lagging stream missed are skipped.
+        let stream = BroadcastStream::new(self.trades.subscribe()).filter_map(|trade| trade.ok().map(Ok));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type StreamDepthStream = ResponseStream<proto::Depth>;
+
+    async fn stream_depth(&self, request: Request<proto::DepthRequest>) -> Result<Response<Self::StreamDepthStream>, Status> {
+        let levels = request.into_inner().levels;
+        let (sender, receiver) = mpsc::channel(1);
+        let commands = self.commands.clone();
+        let mut changes = self.changes.clone();
+        tokio::spawn(async move {
+            loop {
+                changes.mark_unchanged();
+                let depth = Self::depth(&commands, levels).await;
+                if sender.send(depth).await.is_err() || changes.changed().await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_stream::StreamExt;
+    use tonic::Request;
+
+    use super::proto::matching_service_server::MatchingService;
+    use super::proto::{CancelOrderRequest, DepthRequest, EventKind, OrderKind, Side, StreamTradesRequest, SubmitOrderRequest};
+    use super::BookService;
+    use crate::OrderBook;
+
+    #[test]
+    fn grpc_service_drives_the_book() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let service = BookService::new(OrderBook::default());
+            let mut trades = service.stream_trades(Request::new(StreamTradesRequest {})).await.unwrap().into_inner();
+            let mut depth = service.stream_depth(Request::new(DepthRequest { levels: 5 })).await.unwrap().into_inner();
+            assert!(depth.next().await.unwrap().unwrap().asks.is_empty());
+
+            let submit = |id, side: Side, kind: OrderKind, qty, price| {
+                let request = SubmitOrderRequest { id, user_id: id, side: side as i32, kind: kind as i32, qty, price, expires_at: 0 };
+                service.submit_order(Request::new(request))
+            };
+            let reply = submit(1, Side::Ask, OrderKind::Limit, 3, 100).await.unwrap().into_inner();
+            assert_eq!((reply.id, reply.kind), (1, EventKind::Open as i32));
+            assert_eq!(depth.next().await.unwrap().unwrap().asks[0].qty, 3);
+
+            let reply = submit(2, Side::Bid, OrderKind::Market, 1, 0).await.unwrap().into_inner();
+            assert_eq!((reply.kind, reply.qty, reply.fills.len()), (EventKind::Filled as i32, 1, 1));
+            let trade = trades.next().await.unwrap().unwrap();
+            assert_eq!((trade.maker_id, trade.qty, trade.price), (1, 1, 100));
+
+            let reply = service.cancel_order(Request::new(CancelOrderRequest { id: 1 })).await.unwrap().into_inner();
+            assert_eq!(reply.kind, EventKind::Cancelled as i32);
+            let reply = service.cancel_order(Request::new(CancelOrderRequest { id: 1 })).await.unwrap().into_inner();
+            assert_eq!((reply.kind, reply.message.as_str()), (EventKind::CancelRejected as i32, "ORDER_NOT_FOUND"));
+            let book = service.get_depth(Request::new(DepthRequest { levels: 5 })).await.unwrap().into_inner();
+            assert!(book.asks.is_empty());
+
+            let request = SubmitOrderRequest { id: 3, side: 7, ..Default::default() };
+            assert!(service.submit_order(Request::new(request)).await.is_err());
+        });
+    }
+}
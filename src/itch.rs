@@ -0,0 +1,227 @@
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::collections::HashMap;
+use crate::models::{wire_qty, OrderEvent, OrderId, OrderUpdate, Price, Qty, Side};
+
+/// A message of an ITCH 5.0 style market data feed. Every message carries
+/// the feed sequence number, as a MoldUDP64 session would, and the book clock
+/// time at which it was generated.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ItchMessage {
+    /// An order was added to the book (`A`).
+    AddOrder {
+        /// The feed sequence number.
+        sequence: u64,
+        /// The book clock time.
+        timestamp: u64,
+        /// The order reference number.
+        order_ref: OrderId,
+        /// The side of the order.
+        side: Side,
+        /// The displayed quantity.
+        shares: Qty,
+        /// The limit price.
+        price: Price,
+    },
+    /// A resting order was executed in whole or in part (`E`).
+    OrderExecuted {
+        /// The feed sequence number.
+        sequence: u64,
+        /// The book clock time.
+        timestamp: u64,
+        /// The order reference number.
+        order_ref: OrderId,
+        /// The executed quantity.
+        executed_shares: Qty,
+        /// The number identifying the match, shared by its trade messages.
+        match_number: u64,
+    },
+    /// A resting order was reduced without trading (`X`).
+    OrderCancel {
+        /// The feed sequence number.
+        sequence: u64,
+        /// The book clock time.
+        timestamp: u64,
+        /// The order reference number.
+        order_ref: OrderId,
+        /// The canceled quantity.
+        cancelled_shares: Qty,
+    },
+    /// A resting order was removed from the book (`D`).
+    OrderDelete {
+        /// The feed sequence number.
+        sequence: u64,
+        /// The book clock time.
+        timestamp: u64,
+        /// The order reference number.
+        order_ref: OrderId,
+    },
+    /// A trade that did not execute a displayed order, i.e. a reported
+    /// cross (`P`).
+    Trade {
+        /// The feed sequence number.
+        sequence: u64,
+        /// The book clock time.
+        timestamp: u64,
+        /// The ID of the cross order.
+        order_ref: OrderId,
+        /// The traded quantity.
+        shares: Qty,
+        /// The traded price.
+        price: Price,
+        /// The number identifying the match.
+        match_number: u64,
+    },
+}
+
+impl ItchMessage {
+    /// Return the ITCH message type character.
+    pub fn message_type(&self) -> u8 {
+        match self {
+            ItchMessage::AddOrder { .. } => b'A',
+            ItchMessage::OrderExecuted { .. } => b'E',
+            ItchMessage::OrderCancel { .. } => b'X',
+            ItchMessage::OrderDelete { .. } => b'D',
+            ItchMessage::Trade { .. } => b'P',
+        }
+    }
+
+    /// Encode the message in the ITCH layout: the message type, a zero stock
+    /// locate and tracking number, a 48-bit timestamp and the message fields,
+    /// all big endian. Unlike ITCH, quantities and prices are 64-bit wide and
+    /// the stock symbol is omitted.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![self.message_type(), 0, 0, 0, 0];
+        let (timestamp, fields) = match *self {
+            ItchMessage::AddOrder { timestamp, order_ref, side, shares, price, .. } => {
+                let side = if side == Side::Bid { b'B' } else { b'S' };
+                (timestamp, [&order_ref.to_be_bytes()[..], &[side], &wire_qty(shares).to_be_bytes(), &price.to_be_bytes()].concat())
+            }
+            ItchMessage::OrderExecuted { timestamp, order_ref, executed_shares, match_number, .. } => {
+                (timestamp, [order_ref.to_be_bytes(), wire_qty(executed_shares).to_be_bytes(), match_number.to_be_bytes()].concat())
+            }
+            ItchMessage::OrderCancel { timestamp, order_ref, cancelled_shares, .. } => {
+                (timestamp, [order_ref.to_be_bytes(), wire_qty(cancelled_shares).to_be_bytes()].concat())
+            }
+            ItchMessage::OrderDelete { timestamp, order_ref, .. } => (timestamp, order_ref.to_be_bytes().to_vec()),
+            ItchMessage::Trade { timestamp, order_ref, shares, price, match_number, .. } => (
+                timestamp,
+                [order_ref.to_be_bytes(), wire_qty(shares).to_be_bytes(), price.to_be_bytes(), match_number.to_be_bytes()].concat(),
+            ),
+        };
+        bytes.extend_from_slice(&timestamp.to_be_bytes()[2..]);
+        bytes.extend_from_slice(&fields);
+        bytes
+    }
+}
+
+/// Translates the changes of an order book into ITCH style messages.
+///
+/// Feed it the [`OrderUpdate`]s taken from a book tracking order updates, and
+/// the [`OrderEvent::Traded`] events of reported crosses, in the order they
+/// happened. The generator tracks the resting quantity of every order to
+/// report reductions as canceled quantities.
+///
+/// [`OrderUpdate`]: enum.OrderUpdate.html
+/// [`OrderEvent::Traded`]: enum.OrderEvent.html#variant.Traded
+#[derive(Debug, Default, Clone)]
+pub struct ItchGenerator {
+    next_sequence: u64,
+    next_match: u64,
+    resting: HashMap<OrderId, Qty>,
+}
+
+impl ItchGenerator {
+    /// Create a generator starting at sequence and match number 1.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn sequence(&mut self) -> u64 {
+        self.next_sequence += 1;
+        self.next_sequence
+    }
+
+    fn match_number(&mut self) -> u64 {
+        self.next_match += 1;
+        self.next_match
+    }
+
+    /// Translate a change of a resting order that happened at `timestamp`.
+    pub fn order_update(&mut self, update: &OrderUpdate, timestamp: u64) -> ItchMessage {
+        let sequence = self.sequence();
+        match *update {
+            OrderUpdate::Add { id, side, price, qty } => {
+                self.resting.insert(id, qty);
+                ItchMessage::AddOrder { sequence, timestamp, order_ref: id, side, shares: qty, price }
+            }
+            OrderUpdate::Modify { id, qty, .. } => {
+                let previous = self.resting.insert(id, qty).unwrap_or(qty);
+                ItchMessage::OrderCancel { sequence, timestamp, order_ref: id, cancelled_shares: previous.saturating_sub(qty) }
+            }
+            OrderUpdate::Delete { id, .. } => {
+                self.resting.remove(&id);
+                ItchMessage::OrderDelete { sequence, timestamp, order_ref: id }
+            }
+            OrderUpdate::Execute { id, qty, remaining_qty, .. } => {
+                if remaining_qty == 0 {
+                    self.resting.remove(&id);
+                } else {
+                    self.resting.insert(id, remaining_qty);
+                }
+                let match_number = self.match_number();
+                ItchMessage::OrderExecuted { sequence, timestamp, order_ref: id, executed_shares: qty, match_number }
+            }
+        }
+    }
+
+    /// Translate a reported cross, returning `None` for any other event.
+    pub fn cross(&mut self, event: &OrderEvent, timestamp: u64) -> Option<ItchMessage> {
+        let OrderEvent::Traded { id, qty, price, .. } = *event else {
+            return None;
+        };
+        let sequence = self.sequence();
+        let match_number = self.match_number();
+        Some(ItchMessage::Trade { sequence, timestamp, order_ref: id, shares: qty, price, match_number })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ItchGenerator, ItchMessage};
+    use crate::{OrderBook, OrderType, Side};
+
+    #[test]
+    fn book_changes_as_itch_messages() {
+        let mut ob = OrderBook::default();
+        ob.track_order_updates(true);
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 5, price: 100 });
+        ob.execute(OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: 2 });
+        ob.execute(OrderType::Reduce { id: 1, qty_delta: 1 });
+        ob.execute(OrderType::Cancel { id: 1 });
+        let cross = ob.execute(OrderType::Cross { id: 3, buyer_id: 1, seller_id: 2, qty: 4, price: 99 });
+
+        let mut itch = ItchGenerator::new();
+        let mut messages: Vec<ItchMessage> = ob.take_order_updates().iter().map(|update| itch.order_update(update, 7)).collect();
+        messages.extend(itch.cross(&cross, 8));
+        assert_eq!(
+            messages,
+            vec![
+                ItchMessage::AddOrder { sequence: 1, timestamp: 7, order_ref: 1, side: Side::Ask, shares: 5, price: 100 },
+                ItchMessage::OrderExecuted { sequence: 2, timestamp: 7, order_ref: 1, executed_shares: 2, match_number: 1 },
+                ItchMessage::OrderCancel { sequence: 3, timestamp: 7, order_ref: 1, cancelled_shares: 1 },
+                ItchMessage::OrderDelete { sequence: 4, timestamp: 7, order_ref: 1 },
+                ItchMessage::Trade { sequence: 5, timestamp: 8, order_ref: 3, shares: 4, price: 99, match_number: 2 },
+            ]
+        );
+        let bytes = messages[3].encode();
+        assert_eq!(bytes, [&b"D\0\0\0\0"[..], &[0, 0, 0, 0, 0, 7], &1u64.to_be_bytes()].concat());
+        assert_eq!(messages[0].encode().len(), 11 + 25);
+    }
+}
@@ -0,0 +1,175 @@
+#[cfg(feature = "std")]
+use std::fmt::Debug;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(all(feature = "std", feature = "serde"))]
+use std::io::{BufRead, Write};
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use thiserror::Error;
+
+#[cfg(all(feature = "std", feature = "serde"))]
+use crate::jsonl::read_json_lines;
+use crate::depthlimit::DepthLimit;
+use crate::models::{ClientOrderId, OrderType, SessionId};
+use crate::rfq::RfqAction;
+use crate::session::TradingPhase;
+
+/// An order, a step of the request for quote flow, a change of trading phase
+/// or a change of depth limit accepted by an [`OrderBook`], as appended to
+/// its journal before being applied. Each entry holds one of `order`, `rfq`,
+/// `phase` and `depth_limit`.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct JournalEntry {
+    /// The position of this entry in the journal, starting from 1.
+    pub sequence: u64,
+    /// The book clock time at which the order was accepted.
+    pub timestamp: u64,
+    /// The accepted order. Orders removed by administrative calls, such as
+    /// expiries and session cancels, are journaled as cancel orders.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub order: Option<OrderType>,
+    /// The accepted request for quote, quote or request execution.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub rfq: Option<RfqAction>,
+    /// The new trading phase of the book. Leaving a call phase runs its
+    /// auction.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub phase: Option<TradingPhase>,
+    /// The new depth limit of the book, evicting the orders beyond it. A
+    /// limit without bounds is journaled when the limit is removed.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub depth_limit: Option<DepthLimit>,
+    /// The client order ID the order was executed with, if any.
+    pub client_order_id: Option<ClientOrderId>,
+    /// The session the order was executed in, if any.
+    pub session_id: Option<SessionId>,
+}
+
+/// A destination for journal entries.
+#[cfg(feature = "std")]
+pub trait JournalSink: Debug + Send {
+    /// Durably append an entry. When it fails, the order is not applied.
+    fn append(&mut self, entry: &JournalEntry) -> io::Result<()>;
+}
+
+/// A journal kept in memory. Clones share the same entries, so a handle can be
+/// kept to read them after the journal has been given to an order book.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone)]
+pub struct MemoryJournal {
+    entries: Arc<Mutex<Vec<JournalEntry>>>,
+}
+
+#[cfg(feature = "std")]
+impl MemoryJournal {
+    /// Create an empty journal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return a copy of the entries appended so far.
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+#[cfg(feature = "std")]
+impl JournalSink for MemoryJournal {
+    fn append(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        self.entries.lock().unwrap().push(entry.clone());
+        Ok(())
+    }
+}
+
+/// A journal writing one JSON entry per line to a writer, such as a file, and
+/// flushing it after every entry. It can be read back with [`read_journal`].
+///
+/// [`read_journal`]: fn.read_journal.html
+#[cfg(all(feature = "std", feature = "serde"))]
+#[derive(Debug)]
+pub struct WriterJournal<W> {
+    writer: W,
+}
+
+#[cfg(all(feature = "std", feature = "serde"))]
+impl<W: Write> WriterJournal<W> {
+    /// Create a journal appending to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(all(feature = "std", feature = "serde"))]
+impl<W: Write + Debug + Send> JournalSink for WriterJournal<W> {
+    fn append(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, entry)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+/// Errors returned while reading a journal.
+#[cfg(feature = "std")]
+#[derive(Debug, Error)]
+pub enum JournalError {
+    /// The journal could not be read.
+    #[error("Failed to read the journal: {0}")]
+    Io(#[from] io::Error),
+    /// A line of the journal is not a valid entry.
+    #[error("Invalid journal entry on line {line}: {message}")]
+    InvalidEntry {
+        /// The line number, starting from 1.
+        line: usize,
+        /// A description of the problem.
+        message: String,
+    },
+}
+
+/// Read the entries written by a [`WriterJournal`], skipping blank lines.
+///
+/// [`WriterJournal`]: struct.WriterJournal.html
+#[cfg(all(feature = "std", feature = "serde"))]
+pub fn read_journal<R: BufRead>(reader: R) -> impl Iterator<Item = Result<JournalEntry, JournalError>> {
+    read_json_lines(reader)
+}
+
+#[cfg(all(test, feature = "std", feature = "serde"))]
+mod test {
+    use super::{read_journal, JournalError, JournalSink, WriterJournal};
+    use crate::{OrderType, Side};
+
+    #[test]
+    fn writer_journal_round_trip() {
+        let mut journal = WriterJournal::new(Vec::new());
+        let order = OrderType::Limit { id: 1, user_id: 2, side: Side::Ask, qty: 3, price: 4 };
+        let entry = super::JournalEntry {
+            sequence: 1,
+            timestamp: 5,
+            order: Some(order),
+            client_order_id: Some(6),
+            ..Default::default()
+        };
+        journal.append(&entry).unwrap();
+        let mut bytes = journal.into_inner();
+        bytes.extend_from_slice(b"\nnot json\n");
+        let entries: Vec<_> = read_journal(bytes.as_slice()).collect();
+        assert_eq!(entries.len(), 2);
+        let read = entries[0].as_ref().unwrap();
+        assert_eq!((read.sequence, read.timestamp, read.client_order_id), (1, 5, Some(6)));
+        assert!(matches!(read.order, Some(OrderType::Limit { id: 1, price: 4, .. })));
+        assert!(matches!(entries[1], Err(JournalError::InvalidEntry { line: 3, .. })));
+    }
+}
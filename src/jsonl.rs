@@ -0,0 +1,110 @@
+use std::io::{self, BufRead, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::journal::JournalError;
+use crate::models::{OrderEvent, OrderType, Qty};
+
+/// Read one JSON value per line, skipping blank lines.
+pub(crate) fn read_json_lines<T: DeserializeOwned, R: BufRead>(reader: R) -> impl Iterator<Item = Result<T, JournalError>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(index, line)| {
+            serde_json::from_str(&line?).map_err(|error| JournalError::InvalidEntry {
+                line: index + 1,
+                message: error.to_string(),
+            })
+        })
+}
+
+/// Read orders written one JSON object per line, such as
+/// `{"Limit":{"id":1,"user_id":1,"side":"Ask","qty":5,"price":100}}`,
+/// skipping blank lines.
+pub fn read_orders_jsonl<R: BufRead>(reader: R) -> impl Iterator<Item = Result<OrderType, JournalError>> {
+    read_json_lines(reader)
+}
+
+/// Write a value, such as an order or an [`OrderEvent`], as a line of JSON.
+///
+/// [`OrderEvent`]: enum.OrderEvent.html
+pub fn write_jsonl<T: Serialize, W: Write>(writer: &mut W, value: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")
+}
+
+/// Aggregate statistics of the orders executed by
+/// [`OrderBook::load_jsonl`].
+///
+/// [`OrderBook::load_jsonl`]: struct.OrderBook.html#method.load_jsonl
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct LoadStats {
+    /// The number of orders executed.
+    pub orders: usize,
+    /// The number of orders that rested on the book without trading.
+    pub opened: usize,
+    /// The number of orders that were filled completely.
+    pub filled: usize,
+    /// The number of orders that were filled partially.
+    pub partially_filled: usize,
+    /// The number of orders and cancels that were rejected.
+    pub rejected: usize,
+    /// The number of orders that were canceled or reduced.
+    pub cancelled: usize,
+    /// The number of orders ignored as duplicates of executed orders.
+    pub duplicates: usize,
+    /// The number of fills and reported crosses.
+    pub trades: usize,
+    /// The total quantity traded.
+    pub traded_qty: Qty,
+}
+
+impl LoadStats {
+    pub(crate) fn record(&mut self, event: &OrderEvent) {
+        self.orders += 1;
+        match event {
+            OrderEvent::Open { .. } => self.opened += 1,
+            OrderEvent::Duplicate { .. } => self.duplicates += 1,
+            OrderEvent::Rejected { .. } | OrderEvent::CancelRejected { .. } => self.rejected += 1,
+            OrderEvent::Cancelled { .. }
+            | OrderEvent::AdminCancelled { .. }
+            | OrderEvent::Expired { .. }
+            | OrderEvent::Reduced { .. } => self.cancelled += 1,
+            OrderEvent::PartiallyFilled { filled_qty, fills, .. } | OrderEvent::Filled { filled_qty, fills, .. } => {
+                if matches!(event, OrderEvent::Filled { .. }) {
+                    self.filled += 1;
+                } else {
+                    self.partially_filled += 1;
+                }
+                self.trades += fills.len();
+                self.traded_qty += filled_qty;
+            }
+            OrderEvent::Traded { qty, .. } => {
+                self.trades += 1;
+                self.traded_qty += qty;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_orders_jsonl, write_jsonl};
+    use crate::journal::JournalError;
+    use crate::{OrderType, Side};
+
+    #[test]
+    fn orders_jsonl_round_trip() {
+        let mut bytes = Vec::new();
+        write_jsonl(&mut bytes, &OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 5, price: 100 }).unwrap();
+        write_jsonl(&mut bytes, &OrderType::Cancel { id: 1 }).unwrap();
+        bytes.extend_from_slice(b"\n{\"Cancel\":{}}\n");
+        let orders: Vec<_> = read_orders_jsonl(bytes.as_slice()).collect();
+        assert_eq!(orders.len(), 3);
+        assert!(matches!(orders[0], Ok(OrderType::Limit { id: 1, qty: 5, price: 100, .. })));
+        assert!(matches!(orders[1], Ok(OrderType::Cancel { id: 1 })));
+        assert!(matches!(orders[2], Err(JournalError::InvalidEntry { line: 4, .. })));
+    }
+}
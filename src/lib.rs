@@ -2,13 +2,13 @@
 //! order book instance with default parameters, and send orders for execution:
 //!
 //! ```rust
-//! use legion::{FillMetadata, OrderBook, OrderEvent, OrderType, Side };
+//! use legion::{fills, FillMetadata, OrderBook, OrderEvent, OrderType, Side };
 //!
 //! let mut ob = OrderBook::default();
 //! let event = ob.execute(OrderType::Market { id: 0, user_id: 1, qty: 1, side: Side::Bid });
 //!
 //! let event = ob.execute(OrderType::Limit { id: 1, user_id: 1, price: 120, qty: 3, side: Side::Ask });
-//! assert_eq!(event, OrderEvent::Open { id: 1 });
+//! assert_eq!(event, OrderEvent::Open { id: 1, remaining_qty: 3, resting_price: 120 });
 //!
 //! let event = ob.execute(OrderType::Market { id: 2, user_id: 1, qty: 4, side: Side::Bid });
 //! assert_eq!(
@@ -16,14 +16,20 @@
 //!     OrderEvent::PartiallyFilled {
 //!         id: 2,
 //!         filled_qty: 3,
-//!         fills: vec![
+//!         remaining_qty: 0,
+//!         resting_price: None,
+//!         fills: fills![
 //!             FillMetadata {
+//!                 trade_id: 1,
 //!                 taker_id: 2,
 //!                 maker_id: 1,
+//!                 maker_user_id: 1,
+//!                 maker_client_order_id: None,
 //!                 qty: 3,
 //!                 price: 120,
 //!                 taker_side: Side::Bid,
 //!                 total_fill: true,
+//!                 maker_remaining_qty: 0,
 //!             }
 //!         ],
 //!     },
@@ -31,23 +37,166 @@
 //! ```
 //!
 //! Legion only deals in integer price points and quantities. Prices and
-//! quantities are represented as unsigned 64-bit integers. If the traded
-//! instrument supports fractional prices and quantities, the conversion needs to
-//! be handled by the user. At this time, Legion does not support negative prices.
+//! quantities are represented as unsigned 64-bit integers; enabling the
+//! `wide-qty` feature widens [`Qty`] to 128 bits for instruments whose
+//! quantities do not fit, and the `narrow-qty` feature narrows it to 32 bits
+//! to shrink small books. Wire protocols keep their 64-bit fields and saturate
+//! quantities that do not fit on encode and decode. The `signed-price` feature
+//! makes [`Price`] a signed 64-bit integer, for instruments such as spreads and
+//! commodity futures that trade below zero; fixed-width wire formats then carry
+//! prices in two's complement. If the traded instrument supports fractional
+//! prices and quantities, a [`DecimalScale`] converts them to and from
+//! integers.
+//!
+//! The matching core builds for `no_std` targets with `alloc` when the default
+//! `std` feature is disabled. Journals, replays from readers, the binary and
+//! JSON lines formats, the system clock and the WebAssembly bindings need
+//! `std`, as do the transport features such as `stream`, `server` and `grpc`.
+//! `cargo check --target thumbv7em-none-eabihf --no-default-features` checks
+//! the `no_std` build on a bare metal target.
+//!
+//! The default features also include `serde`, for serializing the models and
+//! the JSON formats, `strum`, for parsing sides, reject messages and text
+//! orders, `binary`, for the fixed width binary format, and `wasm`, for the
+//! WebAssembly bindings. Consumers of the matching core alone can disable
+//! them to leave the serialization and WebAssembly dependencies out. The
+//! WebAssembly module itself is built from the `legion-wasm` crate in the
+//! `wasm` directory, e.g. with `wasm-pack build wasm --target web`.
+//!
+//! Events, depth and snapshots kept across releases of the crate are best
+//! stored with `encode_versioned`, which tags them with a schema version that
+//! later releases still decode.
 
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 #![warn(missing_docs, missing_debug_implementations, rustdoc::broken_intra_doc_links)]
 
+extern crate alloc;
+
+#[cfg(feature = "arrow")]
+mod analytics;
 mod arena;
+#[cfg(feature = "async")]
+mod asyncbook;
+#[cfg(feature = "binary")]
+mod binary;
+#[cfg(feature = "stream")]
+mod bus;
+mod clearing;
+mod clock;
+mod collections;
+mod consolidated;
+mod decimal;
+mod dedup;
+mod depthlimit;
+mod engine;
+#[cfg(feature = "feed")]
+mod feed;
+#[cfg(feature = "fix")]
+mod fix;
+mod gaps;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod itch;
+mod journal;
+#[cfg(all(feature = "std", feature = "serde"))]
+mod jsonl;
 mod models;
 mod orderbook;
+mod ouch;
 mod utils;
+#[cfg(feature = "wasm")]
 mod wasm;
+mod ratelimit;
 mod rejectmessages;
+#[cfg(all(feature = "std", feature = "serde"))]
+mod recovery;
+mod rfq;
+mod risk;
+mod router;
+#[cfg(feature = "serde")]
+mod schema;
+#[cfg(feature = "server")]
+mod server;
+mod session;
+#[cfg(feature = "shm")]
+mod shm;
+#[cfg(feature = "threaded")]
+mod sharded;
+mod spread;
+mod snapshot;
+mod stats;
+mod storage;
+#[cfg(feature = "stream")]
+mod stream;
+mod tape;
+#[cfg(feature = "threaded")]
+mod threaded;
 mod orderbook_test;
 
+#[cfg(feature = "arrow")]
+pub use analytics::{depth_to_record_batch, trades_to_record_batch};
+#[cfg(feature = "parquet")]
+pub use analytics::write_parquet;
+pub use arena::OrderHandle;
+#[cfg(feature = "async")]
+pub use asyncbook::{AsyncOrderBook, MatchingTaskStopped};
+#[cfg(feature = "stream")]
+pub use bus::{DeltaSubscription, EventBus, Subscription};
+#[cfg(feature = "binary")]
+pub use binary::{decode, encode, BinaryMessage, DecodeError, BINARY_FORMAT_VERSION};
+pub use clearing::{ClearingRecord, ClearingSink, FeeSchedule};
+pub use clock::{Clock, ManualClock};
+#[cfg(feature = "std")]
+pub use clock::SystemClock;
+pub use consolidated::{ConsolidatedBbo, NationalBbo, NationalQuote};
+pub use decimal::{Decimal, DecimalError, DecimalScale, Rounding};
+pub use depthlimit::{DepthLimit, DepthPolicy, EvictionSink};
+pub use engine::{EngineEvent, MatchingEngine, SymbolOrder};
+#[cfg(feature = "feed")]
+pub use feed::{FeedMessage, FeedPublisher, FeedSink, MemoryFeed, NatsFeed};
+#[cfg(feature = "kafka")]
+pub use feed::KafkaFeed;
+#[cfg(feature = "fix")]
+pub use fix::{FixAdapter, FixError};
+pub use gaps::{GapSink, SequenceGap};
+#[cfg(feature = "grpc")]
+pub use grpc::{proto, BookService};
+pub use itch::{ItchGenerator, ItchMessage};
+pub use journal::JournalEntry;
+#[cfg(feature = "std")]
+pub use journal::{JournalError, JournalSink, MemoryJournal};
+#[cfg(all(feature = "std", feature = "serde"))]
+pub use journal::{read_journal, WriterJournal};
+#[cfg(all(feature = "std", feature = "serde"))]
+pub use jsonl::{read_orders_jsonl, write_jsonl, LoadStats};
 pub use models::{
-    BookDepth, BookLevel, FillMetadata, OrderEvent, OrderType, Side, Trade,
+    Bbo, BookDelta, BookDepth, BookLevel, ClientOrderEvent, DeltaReason, FillMetadata, FillRecord, Fills, LimitOrder, Notional, OrderEvent, OrderParseError, OrderType, OrderUpdate, Position, Price, Qty, Side, StampedEvent, Trade, UnpricedOrder,
 };
-pub use rejectmessages::LIQUIDITY_NOT_AVAILABLE;
-pub use orderbook::OrderBook;
+pub use rejectmessages::{RejectReason, LIQUIDITY_NOT_AVAILABLE};
+pub use orderbook::{ExecuteError, OrderBook};
+pub use ouch::{decode_ouch, encode_ouch_event, OuchError, OuchRequest};
+pub use ratelimit::RateLimiter;
+#[cfg(all(feature = "std", feature = "serde"))]
+pub use recovery::Recovery;
+pub use rfq::{Rfq, RfqAction, RfqError, RfqQuote, RfqState};
+pub use risk::RiskLimits;
+pub use router::{RouteOrder, RouteReport, SmartRouter};
+#[cfg(feature = "serde")]
+pub use schema::{decode_versioned, encode_versioned, SchemaError, Versioned, SCHEMA_VERSION};
+#[cfg(feature = "threaded")]
+pub use sharded::{ShardEvent, ShardedEngine};
+#[cfg(feature = "server")]
+pub use server::{ClientMessage, ExchangeServer, ServerMessage, StreamKind};
+#[cfg(feature = "shm")]
+pub use shm::{ShmPublisher, ShmReader, ShmSnapshot};
+pub use session::{SessionEvent, SessionSchedule, SessionScheduler, TradingPhase};
+pub use snapshot::{BookSnapshot, LevelDiff, SnapshotDiff};
+pub use spread::{execute_spread, implied_spread, SpreadEvent, SpreadOrder};
+pub use stats::{BookMetrics, Candle, CandleInterval, StatsWindow};
+pub use storage::{BookStorage, LadderLevels, PriceLadder, PriceQueue};
+pub use tape::TapeEntry;
+#[cfg(feature = "stream")]
+pub use stream::DepthReceiver;
+#[cfg(feature = "threaded")]
+pub use threaded::{BookCommand, BookResponse, BookThread};
 
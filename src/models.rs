@@ -1,22 +1,31 @@
-use std::{str::FromStr};
-use serde::{Serialize, Deserialize};
+use alloc::string::String;
+#[cfg(feature = "strum")]
+use alloc::string::ToString;
+use alloc::vec::Vec;
+#[cfg(feature = "strum")]
+use core::str::FromStr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "strum")]
 use strum_macros::{EnumString, FromRepr};
+use crate::rejectmessages::RejectReason;
 
 /// An order book side.
-#[derive(Debug, Copy, Clone, PartialEq, EnumString, FromRepr, Default, Serialize, Deserialize)]
-#[strum(serialize_all = "kebab_case")]
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "strum", derive(EnumString, FromRepr), strum(serialize_all = "kebab_case"))]
 #[repr(u8)]
 pub enum Side {
     /// The bid (or buy) side.
     #[default]
-    #[strum(serialize = "bid", serialize = "BID", serialize = "Bid")]
+    #[cfg_attr(feature = "strum", strum(serialize = "bid", serialize = "BID", serialize = "Bid"))]
     Bid,
     /// The ask (or sell) side.
-    #[strum(serialize = "ask", serialize = "ASK", serialize = "Ask")]
+    #[cfg_attr(feature = "strum", strum(serialize = "ask", serialize = "ASK", serialize = "Ask"))]
     Ask,
 }
 
-impl std::ops::Not for Side {
+impl core::ops::Not for Side {
     type Output = Side;
 
     fn not(self) -> Self::Output {
@@ -28,13 +37,149 @@ impl std::ops::Not for Side {
 }
 
 
+/// The price of an order, in ticks, unsigned unless the `signed-price`
+/// feature makes it signed, e.g. for spreads and commodity futures trading
+/// below zero.
+#[cfg(not(feature = "signed-price"))]
 pub type Price = u64;
+/// The price of an order, in signed ticks with the `signed-price` feature.
+#[cfg(feature = "signed-price")]
+pub type Price = i64;
+/// The quantity of an order, 64-bit wide unless the `wide-qty` feature makes
+/// it 128-bit wide, e.g. to hold 18-decimal crypto quantities, or the
+/// `narrow-qty` feature makes it 32-bit wide.
+#[cfg(not(any(feature = "wide-qty", feature = "narrow-qty")))]
 pub type Qty = u64;
+/// The quantity of an order, 128-bit wide with the `wide-qty` feature.
+#[cfg(feature = "wide-qty")]
+pub type Qty = u128;
+/// The quantity of an order, 32-bit wide with the `narrow-qty` feature, to
+/// shrink the resting orders of embedded and WebAssembly books. `wide-qty`
+/// takes precedence when both are enabled.
+#[cfg(all(feature = "narrow-qty", not(feature = "wide-qty")))]
+pub type Qty = u32;
+
+/// Narrow `qty` to the 64-bit quantity fields of fixed-width formats.
+#[cfg(not(any(feature = "wide-qty", feature = "narrow-qty")))]
+pub(crate) fn wire_qty(qty: Qty) -> u64 {
+    qty
+}
+
+/// Widen `qty` to the 64-bit quantity fields of fixed-width formats.
+#[cfg(all(feature = "narrow-qty", not(feature = "wide-qty")))]
+pub(crate) fn wire_qty(qty: Qty) -> u64 {
+    qty.into()
+}
+
+/// Narrow `qty` to the 64-bit quantity fields of fixed-width formats,
+/// saturating at `u64::MAX`.
+#[cfg(feature = "wide-qty")]
+pub(crate) fn wire_qty(qty: Qty) -> u64 {
+    u64::try_from(qty).unwrap_or(u64::MAX)
+}
+
+/// Convert the 64-bit quantity field of a fixed-width format, or return
+/// `None` if it does not fit `Qty`.
+#[cfg(not(feature = "wide-qty"))]
+pub(crate) fn qty_from_wire(qty: u64) -> Option<Qty> {
+    Qty::try_from(qty).ok()
+}
+
+/// Convert the 64-bit quantity field of a fixed-width format.
+#[cfg(feature = "wide-qty")]
+pub(crate) fn qty_from_wire(qty: u64) -> Option<Qty> {
+    Some(qty.into())
+}
+
+/// Convert a 64-bit quantity the book reported itself, saturating at
+/// `Qty::MAX`. Use [`qty_from_wire`] for quantities of incoming orders.
+#[cfg(feature = "shm")]
+pub(crate) fn saturating_qty_from_wire(qty: u64) -> Qty {
+    qty_from_wire(qty).unwrap_or(Qty::MAX)
+}
+
+/// Widen `qty` for accumulations, such as volumes and notionals.
+// The cast is only needed while quantities are 64-bit wide.
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn wide_qty(qty: Qty) -> u128 {
+    qty as u128
+}
+
+/// Convert `price` to the 64-bit price fields of fixed-width formats.
+#[cfg(not(feature = "signed-price"))]
+pub(crate) fn wire_price(price: Price) -> u64 {
+    price
+}
+
+/// Convert `price` to the 64-bit price fields of fixed-width formats, in
+/// two's complement, so that negative prices read back with
+/// [`price_from_wire`].
+#[cfg(feature = "signed-price")]
+pub(crate) fn wire_price(price: Price) -> u64 {
+    price as u64
+}
+
+/// Convert the 64-bit price field of a fixed-width format.
+#[cfg(not(feature = "signed-price"))]
+pub(crate) fn price_from_wire(price: u64) -> Price {
+    price
+}
+
+/// Convert the 64-bit price field of a fixed-width format, in two's
+/// complement.
+#[cfg(feature = "signed-price")]
+pub(crate) fn price_from_wire(price: u64) -> Price {
+    price as Price
+}
+
+/// Return the number of price points from `low` up to `high`, if `high` is
+/// not below `low`.
+// The difference of two signed prices may only fit unsigned, and the cast is
+// only needed while prices are signed.
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn price_distance(low: Price, high: Price) -> Option<u64> {
+    (high >= low).then(|| high.wrapping_sub(low) as u64)
+}
+
+/// Return `price` times `qty` in price units, saturating at the bounds of
+/// [`Price`].
+#[cfg(not(feature = "signed-price"))]
+pub(crate) fn price_times(price: Price, qty: Qty) -> Price {
+    price.saturating_mul(wire_qty(qty))
+}
+
+/// Return `price` times `qty` in price units, saturating at the bounds of
+/// [`Price`].
+#[cfg(feature = "signed-price")]
+pub(crate) fn price_times(price: Price, qty: Qty) -> Price {
+    price.saturating_mul(Price::try_from(wire_qty(qty)).unwrap_or(Price::MAX))
+}
+
+/// The sum of price times quantity over fills, unsigned unless the
+/// `signed-price` feature makes prices signed.
+#[cfg(not(feature = "signed-price"))]
+pub type Notional = u128;
+/// The sum of price times quantity over fills, signed with the
+/// `signed-price` feature.
+#[cfg(feature = "signed-price")]
+pub type Notional = i128;
+
+/// Return the notional value of `qty` at `price`, saturating at the bounds
+/// of [`Notional`].
+///
+/// [`Notional`]: type.Notional.html
+pub(crate) fn notional(price: Price, qty: Qty) -> Notional {
+    let qty = Notional::try_from(wide_qty(qty)).unwrap_or(Notional::MAX);
+    Notional::from(price).saturating_mul(qty)
+}
 pub type OrderId = u64;
 pub type UserId = u64;
+pub type ClientOrderId = u64;
+pub type SessionId = u64;
 
 /// An order to be executed by the order book.
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OrderType {
     /// A market order, which is either filled immediately (even partially), or
     /// canceled.
@@ -132,6 +277,50 @@ pub enum OrderType {
         /// The unique ID of the order to be canceled.
         id: OrderId,
     },
+    /// A good-till-time order, which behaves like a limit order but is removed
+    /// from the order book by [`OrderBook::expire_orders`] once the book clock
+    /// reaches `expires_at`.
+    ///
+    /// [`OrderBook::expire_orders`]: struct.OrderBook.html#method.expire_orders
+    GTT {
+        /// The unique ID of this order.
+        id: OrderId,
+        /// User id for this order
+        user_id: UserId,
+        /// The order side. It will be matched against the resting orders on the
+        /// other side of the order book.
+        side: Side,
+        /// The order quantity.
+        qty: Qty,
+        /// The limit price. The order book will only match this order with
+        /// other orders at this price or better.
+        price: Price,
+        /// The timestamp at which the resting order expires.
+        expires_at: u64,
+    },
+    /// A reduce order, which decrements the quantity of a resting order without
+    /// changing its price or queue priority. The order is removed when its
+    /// remaining quantity reaches zero.
+    Reduce {
+        /// The unique ID of the order to be reduced.
+        id: OrderId,
+        /// The quantity to remove from the order.
+        qty_delta: Qty,
+    },
+    /// A pre-negotiated cross (block trade) between two parties. It is
+    /// recorded as a trade but never interacts with the resting orders.
+    Cross {
+        /// The unique ID of this order.
+        id: OrderId,
+        /// User id of the buying party.
+        buyer_id: UserId,
+        /// User id of the selling party.
+        seller_id: UserId,
+        /// The agreed quantity.
+        qty: Qty,
+        /// The agreed price.
+        price: Price,
+    },
 }
 
 impl OrderType {
@@ -141,10 +330,46 @@ impl OrderType {
             OrderType::Market { id, user_id: _, side:_, qty:_ } => *id,
             OrderType::Limit { id,user_id:_, side:_, qty:_, price:_ } => *id,
             OrderType::Cancel { id } => *id,
+            OrderType::Reduce { id, qty_delta:_ } => *id,
+            OrderType::GTT { id, user_id:_, side:_, qty:_, price:_, expires_at:_ } => *id,
             OrderType::IOC { user_id:_, id, side:_, qty:_, price:_ } => *id,
             OrderType::FOK { user_id:_, id, side:_, qty:_, price:_ } => *id,
             OrderType::Postonly { id, user_id:_, side:_, qty:_, price:_ } => *id,
             OrderType::PostonlySlide { id, user_id:_, side:_, qty:_, price:_ } => *id,
+            OrderType::Cross { id, buyer_id:_, seller_id:_, qty:_, price:_ } => *id,
+        }
+    }
+
+    /// Return the same order with the ID `new_id`. Cancels and reduces refer
+    /// to the order they act on by its ID, so the ID is changed for them too.
+    pub fn with_id(self, new_id: OrderId) -> Self {
+        let mut order = self;
+        match &mut order {
+            OrderType::Market { id, .. }
+            | OrderType::Limit { id, .. }
+            | OrderType::IOC { id, .. }
+            | OrderType::FOK { id, .. }
+            | OrderType::Postonly { id, .. }
+            | OrderType::PostonlySlide { id, .. }
+            | OrderType::Cancel { id }
+            | OrderType::GTT { id, .. }
+            | OrderType::Reduce { id, .. }
+            | OrderType::Cross { id, .. } => *id = new_id,
+        }
+        order
+    }
+
+    /// Return the user submitting this order, when the order carries one.
+    pub fn get_user_id(&self) -> Option<UserId> {
+        match self {
+            OrderType::Market { user_id, .. }
+            | OrderType::Limit { user_id, .. }
+            | OrderType::IOC { user_id, .. }
+            | OrderType::FOK { user_id, .. }
+            | OrderType::Postonly { user_id, .. }
+            | OrderType::PostonlySlide { user_id, .. }
+            | OrderType::GTT { user_id, .. } => Some(*user_id),
+            OrderType::Cancel { .. } | OrderType::Reduce { .. } | OrderType::Cross { .. } => None,
         }
     }
 
@@ -154,144 +379,317 @@ impl OrderType {
             OrderType::Market { id:_,user_id:_,  side:_, qty:_ } => "market",
             OrderType::Limit { id:_,user_id:_,  side:_, qty:_, price:_ } => "limit",
             OrderType::Cancel { id:_ } => "cancel",
+            OrderType::Reduce { id:_, qty_delta:_ } => "reduce",
+            OrderType::GTT { id:_, user_id:_, side:_, qty:_, price:_, expires_at:_ } => "gtt",
             OrderType::IOC { id:_, user_id:_,  side:_, qty:_, price:_ } => "ioc",
             OrderType::FOK { id:_, user_id:_,  side:_, qty:_, price:_ } => "fok",
             OrderType::Postonly { id:_, user_id:_, side:_, qty:_, price:_ }  => "postonly",
             OrderType::PostonlySlide { id:_, user_id:_, side:_, qty:_, price:_ } => "postonlyslide",
+            OrderType::Cross { id:_, buyer_id:_, seller_id:_, qty:_, price:_ } => "cross",
         }
     }
 }
 
+/// Constructors naming the quantity and the price of an order apart, so they
+/// cannot be mixed up:
+///
+/// ```rust
+/// use legion::{OrderType, Side};
+///
+/// let order = OrderType::limit(1, 7, Side::Bid, 10).at(395).post_only();
+/// assert!(matches!(order, OrderType::Postonly { id: 1, user_id: 7, qty: 10, price: 395, .. }));
+/// ```
+impl OrderType {
+    /// A market order of `qty`.
+    pub fn market(id: OrderId, user_id: UserId, side: Side, qty: Qty) -> Self {
+        OrderType::Market { id, user_id, side, qty }
+    }
+
+    /// A limit order of `qty`, priced with [`UnpricedOrder::at`]. The limit
+    /// order can then be turned into another priced order, e.g. with
+    /// [`ioc`](#method.ioc).
+    ///
+    /// [`UnpricedOrder::at`]: struct.UnpricedOrder.html#method.at
+    pub fn limit(id: OrderId, user_id: UserId, side: Side, qty: Qty) -> UnpricedOrder {
+        UnpricedOrder { order: OrderType::Limit { id, user_id, side, qty, price: 0 } }
+    }
+
+    /// A cross of `qty` between `buyer_id` and `seller_id`, priced with
+    /// [`UnpricedOrder::at`].
+    ///
+    /// [`UnpricedOrder::at`]: struct.UnpricedOrder.html#method.at
+    pub fn cross(id: OrderId, buyer_id: UserId, seller_id: UserId, qty: Qty) -> UnpricedOrder {
+        UnpricedOrder { order: OrderType::Cross { id, buyer_id, seller_id, qty, price: 0 } }
+    }
+
+    /// A cancel of the resting order `id`.
+    pub fn cancel(id: OrderId) -> Self {
+        OrderType::Cancel { id }
+    }
+
+    /// A reduce of the resting order `id` by `qty_delta`.
+    pub fn reduce(id: OrderId, qty_delta: Qty) -> Self {
+        OrderType::Reduce { id, qty_delta }
+    }
+
+    /// The same order as a post-only order. Orders without a limit price,
+    /// and crosses, are returned unchanged, as by the other conversions.
+    pub fn post_only(self) -> Self {
+        self.priced().map_or(self, |(id, user_id, side, qty, price)| OrderType::Postonly { id, user_id, side, qty, price })
+    }
+
+    /// The same order as a sliding post-only order.
+    pub fn post_only_slide(self) -> Self {
+        self.priced().map_or(self, |(id, user_id, side, qty, price)| OrderType::PostonlySlide { id, user_id, side, qty, price })
+    }
+
+    /// The same order as an immediate-or-cancel order.
+    pub fn ioc(self) -> Self {
+        self.priced().map_or(self, |(id, user_id, side, qty, price)| OrderType::IOC { id, user_id, side, qty, price })
+    }
+
+    /// The same order as a fill-or-kill order.
+    pub fn fok(self) -> Self {
+        self.priced().map_or(self, |(id, user_id, side, qty, price)| OrderType::FOK { id, user_id, side, qty, price })
+    }
+
+    /// The same order as a good-till-time order expiring at `expires_at`.
+    pub fn good_till(self, expires_at: u64) -> Self {
+        self.priced()
+            .map_or(self, |(id, user_id, side, qty, price)| OrderType::GTT { id, user_id, side, qty, price, expires_at })
+    }
+
+    /// Return the fields of an order with a limit price.
+    fn priced(&self) -> Option<(OrderId, UserId, Side, Qty, Price)> {
+        match *self {
+            OrderType::Limit { id, user_id, side, qty, price }
+            | OrderType::IOC { id, user_id, side, qty, price }
+            | OrderType::FOK { id, user_id, side, qty, price }
+            | OrderType::Postonly { id, user_id, side, qty, price }
+            | OrderType::PostonlySlide { id, user_id, side, qty, price }
+            | OrderType::GTT { id, user_id, side, qty, price, .. } => Some((id, user_id, side, qty, price)),
+            OrderType::Market { .. } | OrderType::Cancel { .. } | OrderType::Reduce { .. } | OrderType::Cross { .. } => None,
+        }
+    }
+}
+
+/// An order waiting for its price, as built by [`OrderType::limit`] and
+/// [`OrderType::cross`].
+///
+/// [`OrderType::limit`]: enum.OrderType.html#method.limit
+/// [`OrderType::cross`]: enum.OrderType.html#method.cross
+#[derive(Debug, Copy, Clone)]
+#[must_use = "an unpriced order does nothing until priced with `at`"]
+pub struct UnpricedOrder {
+    order: OrderType,
+}
+
+impl UnpricedOrder {
+    /// The order at `price`.
+    pub fn at(self, price: Price) -> OrderType {
+        let mut order = self.order;
+        if let OrderType::Limit { price: limit, .. } | OrderType::Cross { price: limit, .. } = &mut order {
+            *limit = price;
+        }
+        order
+    }
+}
+
 use thiserror::Error;
 
 
-#[derive(Debug, Clone, Copy, PartialEq, Error)]
+/// Errors returned when parsing an [`OrderType`] from text.
+///
+/// [`OrderType`]: enum.OrderType.html
+#[derive(Debug, Clone, PartialEq, Error)]
 pub enum OrderParseError {
-    #[error("Invalid fields count for order type")]
-    InvalidFieldSize,
-    #[error("Invalid order type")]
-    InvalidOrderType,
-    #[error("Invalid Integer")]
-    InvalidInteger,
-    #[error("Invalid Side")]
-    InvalidSide
+    /// The line holds no order.
+    #[error("Empty order line")]
+    Empty,
+    /// The order type is not one of the known types.
+    #[error("Unknown order type `{0}`")]
+    UnknownOrderType(String),
+    /// The line does not hold exactly the fields of its order type.
+    #[error("Expected {expected} fields for a {order_type} order, found {found}")]
+    FieldCount {
+        /// The order type named by the line.
+        order_type: &'static str,
+        /// The number of fields of the order type, besides the type itself.
+        expected: usize,
+        /// The number of fields found, besides the type itself.
+        found: usize,
+    },
+    /// A field is not an unsigned integer.
+    #[error("Invalid integer `{value}` for {field}")]
+    InvalidInteger {
+        /// The name of the field.
+        field: &'static str,
+        /// The value found.
+        value: String,
+    },
+    /// The side is neither `bid` nor `ask`.
+    #[error("Invalid side `{0}`")]
+    InvalidSide(String),
+    /// The line is not a valid JSON order.
+    #[error("Invalid JSON order: {0}")]
+    InvalidJson(String),
+}
+
+impl core::fmt::Display for Side {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Side::Bid => "bid",
+            Side::Ask => "ask",
+        })
+    }
 }
 
+/// Orders are written as a line of comma separated fields, starting with the
+/// order type, as produced by `to_string`:
+///
+/// | Order type | Line |
+/// |------------|------|
+/// | Market | `market,id,user_id,side,qty` |
+/// | Limit, IOC, FOK, post-only and sliding post-only | `limit,id,user_id,side,qty,price` (or `ioc`, `fok`, `postonly`, `postonlyslide`) |
+/// | Good-till-time | `gtt,id,user_id,side,qty,price,expires_at` |
+/// | Cancel | `cancel,id` |
+/// | Reduce | `reduce,id,qty_delta` |
+/// | Cross | `cross,id,buyer_id,seller_id,qty,price` |
+///
+/// The side is `bid` or `ask`, and whitespace around fields is ignored.
+/// Every order type takes exactly its fields. With the `serde` feature, a
+/// line starting with `{` is read as the JSON form of the order instead, e.g.
+/// `{"Cancel":{"id":1}}`.
+///
+/// For compatibility, the previous layout, which starts with the order ID and
+/// names the order type in the second (cancel and reduce) or third field, is
+/// still accepted. Parsing needs the `strum` feature.
+#[cfg(feature = "strum")]
 impl FromStr for OrderType {
-    type Err=OrderParseError;
+    type Err = OrderParseError;
+
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let fields = s.split(",").collect::<Vec<&str>>();
-        let total_fields = fields.len();
-        if  total_fields < 2 {
-            return Err(OrderParseError::InvalidFieldSize)
+        let line = s.trim();
+        #[cfg(feature = "serde")]
+        if line.starts_with('{') {
+            return serde_json::from_str(line).map_err(|error| OrderParseError::InvalidJson(error.to_string()));
         }
-        let order_type_index = if s.to_lowercase().contains("cancel") { 1 } else { 2 };
-        let ordertype = fields[order_type_index];
-        match ordertype {
-            "market" => {
-                if total_fields < 5 {
-                    return Err(OrderParseError::InvalidFieldSize)
-                }
-                Ok(OrderType::Market { 
-                    id: fields[0].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    user_id: fields[1].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    side: Side::from_str(fields[3]).map_err(|_| OrderParseError::InvalidSide)?, 
-                    qty: fields[4].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)? , 
-                })
-            },
-            "limit" => {
-                if total_fields < 6 {
-                    return Err(OrderParseError::InvalidFieldSize)
-                }
-                Ok(OrderType::Limit { 
-                    id: fields[0].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    user_id: fields[1].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    side: Side::from_str(fields[3]).map_err(|_| OrderParseError::InvalidSide)?, 
-                    qty: fields[4].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    price: fields[5].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?, 
-                })
-            },
-            "ioc" => {
-                if total_fields < 6 {
-                    return Err(OrderParseError::InvalidFieldSize)
-                }
-                Ok(OrderType::IOC { 
-                    id: fields[0].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    user_id: fields[1].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    side: Side::from_str(fields[3]).map_err(|_| OrderParseError::InvalidSide)?, 
-                    qty: fields[4].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    price: fields[5].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?, 
-                })
-            },
-            "fok" => {
-                if total_fields < 6 {
-                    return Err(OrderParseError::InvalidFieldSize)
-                }
-                Ok(OrderType::FOK { 
-                    id: fields[0].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    user_id: fields[1].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    side: Side::from_str(fields[3]).map_err(|_| OrderParseError::InvalidSide)?, 
-                    qty: fields[4].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    price: fields[5].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?, 
-                })
-            },
-            "postonly" => {
-                if total_fields < 6 {
-                    return Err(OrderParseError::InvalidFieldSize)
-                }
-                Ok(OrderType::Postonly { 
-                    id: fields[0].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    user_id: fields[1].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    side: Side::from_str(fields[3]).map_err(|_| OrderParseError::InvalidSide)?, 
-                    qty: fields[4].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    price: fields[5].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?, 
-                })
-            },
+        if line.is_empty() {
+            return Err(OrderParseError::Empty);
+        }
+        let mut fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields[0].parse::<u64>().is_ok() && fields.len() > 1 {
+            let type_index = match fields[1].to_ascii_lowercase().as_str() {
+                "cancel" | "reduce" => 1,
+                _ => 2.min(fields.len() - 1),
+            };
+            let order_type = fields.remove(type_index);
+            fields.insert(0, order_type);
+        }
+
+        let (order_type, names): (&'static str, &[&'static str]) = match fields[0].to_ascii_lowercase().as_str() {
+            "market" => ("market", &["id", "user_id", "side", "qty"]),
+            "limit" => ("limit", &["id", "user_id", "side", "qty", "price"]),
+            "ioc" => ("ioc", &["id", "user_id", "side", "qty", "price"]),
+            "fok" => ("fok", &["id", "user_id", "side", "qty", "price"]),
+            "postonly" => ("postonly", &["id", "user_id", "side", "qty", "price"]),
+            "postonlyslide" => ("postonlyslide", &["id", "user_id", "side", "qty", "price"]),
+            "gtt" => ("gtt", &["id", "user_id", "side", "qty", "price", "expires_at"]),
+            "cancel" => ("cancel", &["id"]),
+            "reduce" => ("reduce", &["id", "qty_delta"]),
+            "cross" => ("cross", &["id", "buyer_id", "seller_id", "qty", "price"]),
+            _ => return Err(OrderParseError::UnknownOrderType(fields[0].to_string())),
+        };
+        let values = &fields[1..];
+        if values.len() != names.len() {
+            return Err(OrderParseError::FieldCount { order_type, expected: names.len(), found: values.len() });
+        }
+        fn field<T: FromStr>(values: &[&str], names: &[&'static str], index: usize) -> Result<T, OrderParseError> {
+            values[index].parse::<T>().map_err(|_| OrderParseError::InvalidInteger {
+                field: names[index],
+                value: values[index].to_string(),
+            })
+        }
+        let int = |index: usize| field::<u64>(values, names, index);
+        let qty = |index: usize| field::<Qty>(values, names, index);
+        let price = |index: usize| field::<Price>(values, names, index);
+        let side = || Side::from_str(values[2]).map_err(|_| OrderParseError::InvalidSide(values[2].to_string()));
+
+        let order = match order_type {
+            "market" => OrderType::Market { id: int(0)?, user_id: int(1)?, side: side()?, qty: qty(3)? },
+            "limit" => OrderType::Limit { id: int(0)?, user_id: int(1)?, side: side()?, qty: qty(3)?, price: price(4)? },
+            "ioc" => OrderType::IOC { id: int(0)?, user_id: int(1)?, side: side()?, qty: qty(3)?, price: price(4)? },
+            "fok" => OrderType::FOK { id: int(0)?, user_id: int(1)?, side: side()?, qty: qty(3)?, price: price(4)? },
+            "postonly" => OrderType::Postonly { id: int(0)?, user_id: int(1)?, side: side()?, qty: qty(3)?, price: price(4)? },
             "postonlyslide" => {
-                if total_fields < 6 {
-                    return Err(OrderParseError::InvalidFieldSize)
-                }
-                Ok(OrderType::PostonlySlide { 
-                    id: fields[0].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    user_id: fields[1].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    side: Side::from_str(fields[3]).map_err(|_| OrderParseError::InvalidSide)?, 
-                    qty: fields[4].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?,
-                    price: fields[5].parse::<u64>().map_err(|_| OrderParseError::InvalidInteger)?, 
-                })
-            },
-            "cancel" => {
-                if total_fields < 2 {
-                    return Err(OrderParseError::InvalidFieldSize)
-                }
-                Ok(OrderType::Cancel { 
-                    id: fields[0].parse::<u64>().unwrap()
-                })
-            },
-            _ => {
-                return Err(OrderParseError::InvalidOrderType) 
+                OrderType::PostonlySlide { id: int(0)?, user_id: int(1)?, side: side()?, qty: qty(3)?, price: price(4)? }
             }
+            "gtt" => OrderType::GTT {
+                id: int(0)?,
+                user_id: int(1)?,
+                side: side()?,
+                qty: qty(3)?,
+                price: price(4)?,
+                expires_at: int(5)?,
+            },
+            "cancel" => OrderType::Cancel { id: int(0)? },
+            "reduce" => OrderType::Reduce { id: int(0)?, qty_delta: qty(1)? },
+            _ => OrderType::Cross { id: int(0)?, buyer_id: int(1)?, seller_id: int(2)?, qty: qty(3)?, price: price(4)? },
+        };
+        Ok(order)
+    }
+}
 
+/// Writes the order in the line format read by `from_str`.
+impl core::fmt::Display for OrderType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let order_type = self.get_type();
+        match self {
+            OrderType::Market { id, user_id, side, qty } => write!(f, "{},{},{},{},{}", order_type, id, user_id, side, qty),
+            OrderType::Limit { id, user_id, side, qty, price }
+            | OrderType::IOC { id, user_id, side, qty, price }
+            | OrderType::FOK { id, user_id, side, qty, price }
+            | OrderType::Postonly { id, user_id, side, qty, price }
+            | OrderType::PostonlySlide { id, user_id, side, qty, price } => {
+                write!(f, "{},{},{},{},{},{}", order_type, id, user_id, side, qty, price)
+            }
+            OrderType::GTT { id, user_id, side, qty, price, expires_at } => {
+                write!(f, "{},{},{},{},{},{},{}", order_type, id, user_id, side, qty, price, expires_at)
+            }
+            OrderType::Cancel { id } => write!(f, "{},{}", order_type, id),
+            OrderType::Reduce { id, qty_delta } => write!(f, "{},{},{}", order_type, id, qty_delta),
+            OrderType::Cross { id, buyer_id, seller_id, qty, price } => {
+                write!(f, "{},{},{},{},{},{}", order_type, id, buyer_id, seller_id, qty, price)
+            }
         }
-    }   
+    }
 }
 
 
 /// An event resulting from the execution of an order.
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum OrderEvent {
     /// Indicating that the corresponding order was not filled. It is only sent
     /// in response to market orders.
     Rejected {
         /// The ID of the order this event is referring to.
         id: OrderId,
-        /// Reject message
-        message: &'static str
+        /// The reason of the rejection.
+        message: RejectReason,
     },
     /// Indicating that the corresponding order is open on the order book. It
     /// is only send in response to limit orders.
     Open {
         /// The ID of the order this event is referring to.
         id: OrderId,
+        /// The quantity resting on the order book.
+        #[cfg_attr(feature = "serde", serde(default))]
+        remaining_qty: Qty,
+        /// The price the order rests at, which differs from the order price
+        /// for sliding post-only orders.
+        #[cfg_attr(feature = "serde", serde(default))]
+        resting_price: Price,
     },
     /// Indicating that the corresponding order was removed from the order book.
     /// It is only sent in response to cancel orders.
@@ -299,6 +697,37 @@ pub enum OrderEvent {
         /// The ID of the order this event is referring to.
         id: OrderId,
     },
+    /// Indicating that the corresponding order was forcibly removed from the
+    /// order book by an administrator. It is only sent in response to
+    /// administrative deletes.
+    AdminCancelled {
+        /// The ID of the order this event is referring to.
+        id: OrderId,
+        /// The reason given for the removal, kept for audit purposes.
+        reason: String,
+    },
+    /// Indicating that a cancel order was rejected because the order it refers
+    /// to is not resting on the order book.
+    CancelRejected {
+        /// The ID of the order this event is referring to.
+        id: OrderId,
+        /// The reason of the rejection.
+        message: RejectReason,
+    },
+    /// Indicating that the corresponding order was removed from the order book
+    /// because it reached its expiry time.
+    Expired {
+        /// The ID of the order this event is referring to.
+        id: OrderId,
+    },
+    /// Indicating that the quantity of the corresponding resting order was
+    /// reduced. It is only sent in response to reduce orders.
+    Reduced {
+        /// The ID of the order this event is referring to.
+        id: OrderId,
+        /// The quantity left resting on the order book.
+        qty: Qty,
+    },
     /// Indicating that the corresponding order was only partially filled. It is
     /// sent in response to market or limit orders.
     PartiallyFilled {
@@ -306,8 +735,16 @@ pub enum OrderEvent {
         id: OrderId,
         /// The filled quantity.
         filled_qty: Qty,
+        /// The quantity left resting on the order book, zero when the rest
+        /// of the order was canceled, as for market and IOC orders.
+        #[cfg_attr(feature = "serde", serde(default))]
+        remaining_qty: Qty,
+        /// The price the rest of the order rests at, or `None` when it was
+        /// canceled.
+        #[cfg_attr(feature = "serde", serde(default))]
+        resting_price: Option<Price>,
         /// A vector with information on the order fills.
-        fills: Vec<FillMetadata>,
+        fills: Fills,
     },
     /// Indicating that the corresponding order was filled completely. It is
     /// sent in response to market or limit orders.
@@ -316,35 +753,368 @@ pub enum OrderEvent {
         id: OrderId,
         /// The filled quantity.
         filled_qty: Qty,
+        /// The quantity left resting on the order book, always zero for a
+        /// filled order.
+        #[cfg_attr(feature = "serde", serde(default))]
+        remaining_qty: Qty,
         /// A vector with information on the order fills.
-        fills: Vec<FillMetadata>,
+        fills: Fills,
+    },
+    /// Indicating that a pre-negotiated trade was reported. It is only sent in
+    /// response to cross orders.
+    Traded {
+        /// The ID of the order this event is referring to.
+        id: OrderId,
+        /// User id of the buying party.
+        buyer_id: UserId,
+        /// User id of the selling party.
+        seller_id: UserId,
+        /// The traded quantity.
+        qty: Qty,
+        /// The traded price.
+        price: Price,
+    },
+    /// Indicating that the corresponding order was already executed and is
+    /// ignored. It is only sent for orders sent again within the
+    /// deduplication window of the order book.
+    Duplicate {
+        /// The ID of the order this event is referring to.
+        id: OrderId,
     },
 }
 
+/// Writes the event as a single human-readable line, e.g.
+/// `order 1 partially filled 10 (2 fills), 5@100 resting`. The alternate form
+/// (`{:#}`) lists every fill on a line of its own below it.
+impl core::fmt::Display for OrderEvent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OrderEvent::Rejected { id, message } => write!(f, "order {} rejected: {}", id, message),
+            OrderEvent::Open { id, remaining_qty, resting_price } => {
+                write!(f, "order {} open, {}@{} resting", id, remaining_qty, resting_price)
+            }
+            OrderEvent::Cancelled { id } => write!(f, "order {} cancelled", id),
+            OrderEvent::AdminCancelled { id, reason } => write!(f, "order {} cancelled by admin: {}", id, reason),
+            OrderEvent::CancelRejected { id, message } => write!(f, "cancel of order {} rejected: {}", id, message),
+            OrderEvent::Expired { id } => write!(f, "order {} expired", id),
+            OrderEvent::Reduced { id, qty } => write!(f, "order {} reduced, {} resting", id, qty),
+            OrderEvent::PartiallyFilled { id, filled_qty, remaining_qty, resting_price, fills } => {
+                write!(f, "order {} partially filled {} ({}), ", id, filled_qty, FillCount(fills.len()))?;
+                match resting_price {
+                    Some(price) => write!(f, "{}@{} resting", remaining_qty, price)?,
+                    None => f.write_str("rest cancelled")?,
+                }
+                write_fills(f, fills)
+            }
+            OrderEvent::Filled { id, filled_qty, fills, .. } => {
+                write!(f, "order {} filled {} ({})", id, filled_qty, FillCount(fills.len()))?;
+                write_fills(f, fills)
+            }
+            OrderEvent::Traded { id, buyer_id, seller_id, qty, price } => {
+                write!(f, "order {} traded {}@{}, buyer {}, seller {}", id, qty, price, buyer_id, seller_id)
+            }
+            OrderEvent::Duplicate { id } => write!(f, "order {} duplicate, ignored", id),
+        }
+    }
+}
+
+/// A number of fills, written as `1 fill` or `2 fills`.
+struct FillCount(usize);
+
+impl core::fmt::Display for FillCount {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} fill{}", self.0, if self.0 == 1 { "" } else { "s" })
+    }
+}
+
+/// Write the fills of an event on lines of their own in the alternate form.
+fn write_fills(f: &mut core::fmt::Formatter<'_>, fills: &[FillMetadata]) -> core::fmt::Result {
+    if f.alternate() {
+        for fill in fills {
+            write!(f, "\n  {}", fill)?;
+        }
+    }
+    Ok(())
+}
+
+/// An [`OrderEvent`] echoed back together with the client order ID of the
+/// order it refers to.
+///
+/// [`OrderEvent`]: enum.OrderEvent.html
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClientOrderEvent {
+    /// The client order ID supplied with the order.
+    pub client_order_id: ClientOrderId,
+    /// The resulting event.
+    pub event: OrderEvent,
+}
+
+/// An [`OrderEvent`] together with the time the order was executed at, which
+/// is also the time of its fills and the creation time of its resting order.
+///
+/// [`OrderEvent`]: enum.OrderEvent.html
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StampedEvent {
+    /// The time the order was executed at.
+    pub timestamp: u64,
+    /// The resulting event.
+    pub event: OrderEvent,
+}
+
+/// The fills of an execution. With the `smallvec` feature, up to two fills,
+/// the common case, are stored inline instead of on the heap.
+#[cfg(feature = "smallvec")]
+pub type Fills = smallvec::SmallVec<[FillMetadata; 2]>;
+
+/// The fills of an execution. With the `smallvec` feature, up to two fills,
+/// the common case, are stored inline instead of on the heap.
+#[cfg(not(feature = "smallvec"))]
+pub type Fills = Vec<FillMetadata>;
+
+/// Build a [`Fills`] collection from a list of fills, like `vec!`, whether or
+/// not the `smallvec` feature is enabled.
+///
+/// [`Fills`]: type.Fills.html
+#[macro_export]
+macro_rules! fills {
+    ($($fill:expr),* $(,)?) => {
+        <$crate::Fills as ::core::iter::FromIterator<$crate::FillMetadata>>::from_iter([$($fill),*])
+    };
+}
+
 /// Information on a single order fill. When an order is matched with multiple
 /// resting orders, it generates multiple `FillMetadata` values.
-#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FillMetadata {
-    /// The ID of the order that triggered the fill (taker).
+    /// The unique ID of the trade within the order book, increasing with
+    /// every fill and reported cross. Unlike order IDs, it identifies the
+    /// fill itself.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trade_id: u64,
+    /// The ID of the order that triggered the fill (taker). Read from
+    /// `order_1` as well, its name in earlier releases.
+    #[cfg_attr(feature = "serde", serde(alias = "order_1"))]
     pub taker_id: OrderId,
-    /// The ID of the matching order.
+    /// The ID of the matching order (maker). Read from `order_2` as well, its
+    /// name in earlier releases.
+    #[cfg_attr(feature = "serde", serde(alias = "order_2"))]
     pub maker_id: OrderId,
+    /// User id of the matching order.
+    pub maker_user_id: UserId,
+    /// The client order ID of the matching order, if it was placed with
+    /// one through [`OrderBook::execute_with_client_id`].
+    ///
+    /// [`OrderBook::execute_with_client_id`]: struct.OrderBook.html#method.execute_with_client_id
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub maker_client_order_id: Option<ClientOrderId>,
     /// The quantity that was traded.
     pub qty: Qty,
     /// The price at which the trade happened.
     pub price: Price,
-    /// The side of the taker order (order 1)
+    /// The side of the taker order.
     pub taker_side: Side,
     /// Whether this order was a total (true) or partial (false) fill of the
     /// maker order.
     pub total_fill: bool,
+    /// The quantity of the matching order still resting after this fill.
+    pub maker_remaining_qty: Qty,
+}
+
+/// Writes the fill as a single human-readable line, e.g.
+/// `trade 7: 10@100, taker 3 (bid), maker 2 (user 5, 0 left)`.
+impl core::fmt::Display for FillMetadata {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "trade {}: {}@{}, taker {} ({}), maker {} (user {}, {} left)",
+            self.trade_id,
+            self.qty,
+            self.price,
+            self.taker_id,
+            self.taker_side,
+            self.maker_id,
+            self.maker_user_id,
+            self.maker_remaining_qty
+        )
+    }
+}
+
+/// A fill recorded in the history of a single order. Both the taker and the
+/// maker of a fill get their own record.
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FillRecord {
+    /// The ID of the order on the other side of the fill.
+    pub counterparty_id: OrderId,
+    /// The quantity that was traded.
+    pub qty: Qty,
+    /// The price at which the trade happened.
+    pub price: Price,
+    /// The sequence number of the fill within the order book.
+    pub sequence: u64,
+}
+
+/// The position of a user, accumulated over the fills and crosses the user
+/// took part in while position tracking was active.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Position {
+    /// The quantity bought.
+    pub bought_qty: Qty,
+    /// The quantity sold.
+    pub sold_qty: Qty,
+    /// The notional value of the quantity bought.
+    pub bought_notional: Notional,
+    /// The notional value of the quantity sold.
+    pub sold_notional: Notional,
+}
+
+impl Position {
+    /// Return the net position, positive when long and negative when short,
+    /// saturating at the bounds of `i128`.
+    pub fn net(&self) -> i128 {
+        let (bought, sold) = (wide_qty(self.bought_qty), wide_qty(self.sold_qty));
+        if bought >= sold {
+            i128::try_from(bought - sold).unwrap_or(i128::MAX)
+        } else {
+            i128::try_from(sold - bought).map_or(i128::MIN, |short| -short)
+        }
+    }
+
+    /// Return the quantity traded, bought and sold alike.
+    pub fn volume(&self) -> Qty {
+        self.bought_qty.saturating_add(self.sold_qty)
+    }
+
+    /// Account for buying (on the bid side) or selling `qty` at `price`.
+    pub(crate) fn record(&mut self, side: Side, qty: Qty, price: Price) {
+        let (position_qty, position_notional) = match side {
+            Side::Bid => (&mut self.bought_qty, &mut self.bought_notional),
+            Side::Ask => (&mut self.sold_qty, &mut self.sold_notional),
+        };
+        *position_qty = position_qty.saturating_add(qty);
+        *position_notional = position_notional.saturating_add(notional(price, qty));
+    }
+}
+
+/// The reason a price level changed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DeltaReason {
+    /// An order was added to the level.
+    Add,
+    /// An order was removed from the level without trading.
+    Cancel,
+    /// An order at the level was (partially) filled.
+    Fill,
+    /// The quantity of an order at the level was changed in place.
+    Modify,
+}
+
+/// An incremental update of a single price level. Applying the updates in
+/// order to a copy of the book depth keeps it synchronized.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BookDelta {
+    /// The side of the level.
+    pub side: Side,
+    /// The price point of the level.
+    pub price: Price,
+    /// The new total quantity at the level. Zero means the level is gone.
+    pub qty: Qty,
+    /// The reason of the last change of the level.
+    pub reason: DeltaReason,
+}
+
+/// A change of a single resting order, as reported by the market-by-order
+/// (level 3) feed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OrderUpdate {
+    /// An order was added to the order book.
+    Add {
+        /// The ID of the order.
+        id: OrderId,
+        /// The side of the order.
+        side: Side,
+        /// The price of the order.
+        price: Price,
+        /// The resting quantity of the order.
+        qty: Qty,
+    },
+    /// The quantity of an order was changed in place.
+    Modify {
+        /// The ID of the order.
+        id: OrderId,
+        /// The side of the order.
+        side: Side,
+        /// The price of the order.
+        price: Price,
+        /// The new resting quantity of the order.
+        qty: Qty,
+    },
+    /// An order was removed from the order book without trading.
+    Delete {
+        /// The ID of the order.
+        id: OrderId,
+        /// The side of the order.
+        side: Side,
+        /// The price of the order.
+        price: Price,
+    },
+    /// A resting order was executed against an incoming order.
+    Execute {
+        /// The ID of the order.
+        id: OrderId,
+        /// The side of the order.
+        side: Side,
+        /// The price of the order.
+        price: Price,
+        /// The executed quantity.
+        qty: Qty,
+        /// The quantity still resting after the execution.
+        remaining_qty: Qty,
+    },
+}
+
+impl OrderUpdate {
+    pub(crate) fn side(&self) -> Side {
+        match self {
+            OrderUpdate::Add { side, .. }
+            | OrderUpdate::Modify { side, .. }
+            | OrderUpdate::Delete { side, .. }
+            | OrderUpdate::Execute { side, .. } => *side,
+        }
+    }
+
+    pub(crate) fn price(&self) -> Price {
+        match self {
+            OrderUpdate::Add { price, .. }
+            | OrderUpdate::Modify { price, .. }
+            | OrderUpdate::Delete { price, .. }
+            | OrderUpdate::Execute { price, .. } => *price,
+        }
+    }
+
+    pub(crate) fn reason(&self) -> DeltaReason {
+        match self {
+            OrderUpdate::Add { .. } => DeltaReason::Add,
+            OrderUpdate::Modify { .. } => DeltaReason::Modify,
+            OrderUpdate::Delete { .. } => DeltaReason::Cancel,
+            OrderUpdate::Execute { .. } => DeltaReason::Fill,
+        }
+    }
 }
 
 /// A snapshot of the order book up to a certain depth level. Multiple orders at
 /// the same price points are merged into a single [`BookLevel`] struct.
 ///
 /// [`BookLevel`]: /struct.BookLevel.html
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BookDepth {
     /// The requested level. This field will always contain the level that was
     /// requested, even if some or all levels are empty.
@@ -357,18 +1127,78 @@ pub struct BookDepth {
 
 /// A single level in the order book. This struct is used both for the bid and
 /// ask side.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BookLevel {
     /// The price point this level represents.
     pub price: Price,
     /// The total quantity of all orders resting at the specified price point.
     pub qty: Qty,
+    /// The total quantity resting from the top of the book up to and
+    /// including this level.
+    pub cumulative_qty: Qty,
+    /// The total notional (price times quantity) resting from the top of the
+    /// book up to and including this level, saturating at the bounds of
+    /// [`Price`].
+    ///
+    /// [`Price`]: type.Price.html
+    pub cumulative_notional: Price,
     /// Orders at this level.
     pub orders: Vec<LimitOrder>
 }
 
+/// Writes the depth as a single line of `qty@price` levels, best first, e.g.
+/// `bids 10@100 5@99 | asks 3@101`. The alternate form (`{:#}`) renders a
+/// price ladder instead, one level per line with the asks on top, highest
+/// first, so that the spread sits in the middle:
+///
+/// ```text
+/// side        price          qty   cumulative
+/// ask           101            3            3
+/// bid           100           10           10
+/// bid            99            5           15
+/// ```
+impl core::fmt::Display for BookDepth {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if f.alternate() {
+            write!(f, "{:<4} {:>12} {:>12} {:>12}", "side", "price", "qty", "cumulative")?;
+            let asks = self.asks.iter().rev().map(|level| (Side::Ask, level));
+            for (side, level) in asks.chain(self.bids.iter().map(|level| (Side::Bid, level))) {
+                write!(f, "\n{:<4} {:>12} {:>12} {:>12}", side, level.price, level.qty, level.cumulative_qty)?;
+            }
+            return Ok(());
+        }
+        for (name, levels) in [("bids", &self.bids), ("asks", &self.asks)] {
+            if name == "asks" {
+                f.write_str(" | ")?;
+            }
+            f.write_str(name)?;
+            if levels.is_empty() {
+                f.write_str(" -")?;
+            }
+            for level in levels {
+                write!(f, " {}@{}", level.qty, level.price)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The best bid and offer of the order book, with the aggregate quantity
+/// resting at each price as `(price, qty)` pairs. A side is `None` when it is
+/// empty.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Bbo {
+    /// The highest bid price and its quantity.
+    pub bid: Option<(Price, Qty)>,
+    /// The lowest ask price and its quantity.
+    pub ask: Option<(Price, Qty)>,
+}
+
 /// A trade that happened as part of the matching process.
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Trade {
     /// The total quantity transacted as part of this trade.
     pub total_qty: Qty,
@@ -381,21 +1211,174 @@ pub struct Trade {
     pub last_qty: Qty,
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// An order resting on the book.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct LimitOrder {
+    /// User id for this order
     pub user_id: UserId,
+    /// The unique ID of this order.
     pub id: OrderId,
+    /// The quantity left to fill.
     pub qty: Qty,
+    /// The limit price.
     pub price: Price,
+    /// The book clock time at which the order started resting.
+    pub created_at: u64,
 }
 
 #[cfg(test)]
 mod test {
-    use super::Side;
+    #[cfg(feature = "strum")]
+    use std::str::FromStr;
+
+    use crate::rejectmessages::RejectReason;
+
+    #[cfg(feature = "strum")]
+    use super::OrderParseError;
+    use super::{BookDepth, BookLevel, FillMetadata, OrderEvent, OrderType, Side};
 
     #[test]
     fn side_negation() {
         assert_eq!(!Side::Ask, Side::Bid);
         assert_eq!(!Side::Bid, Side::Ask);
     }
+
+    #[test]
+    #[cfg(feature = "strum")]
+    fn order_text_round_trip() {
+        let orders = [
+            OrderType::Market { id: 1, user_id: 2, side: Side::Bid, qty: 3 },
+            OrderType::Limit { id: 1, user_id: 2, side: Side::Ask, qty: 3, price: 4 },
+            OrderType::PostonlySlide { id: 1, user_id: 2, side: Side::Bid, qty: 3, price: 4 },
+            OrderType::GTT { id: 1, user_id: 2, side: Side::Ask, qty: 3, price: 4, expires_at: 5 },
+            OrderType::Cancel { id: 1 },
+            OrderType::Reduce { id: 1, qty_delta: 2 },
+            OrderType::Cross { id: 1, buyer_id: 2, seller_id: 3, qty: 4, price: 5 },
+        ];
+        for order in orders {
+            let text = order.to_string();
+            assert_eq!(OrderType::from_str(&text).unwrap().to_string(), text);
+        }
+        assert_eq!(orders[1].to_string(), "limit,1,2,ask,3,4");
+        assert_eq!(orders[6].to_string(), "cross,1,2,3,4,5");
+
+        assert!(matches!(OrderType::from_str(" LIMIT, 1, 2, Bid, 3, 4 "), Ok(OrderType::Limit { side: Side::Bid, price: 4, .. })));
+        #[cfg(feature = "serde")]
+        {
+            assert!(matches!(OrderType::from_str(r#"{"Cancel":{"id":7}}"#), Ok(OrderType::Cancel { id: 7 })));
+        }
+        // The previous layout is still accepted.
+        assert!(matches!(OrderType::from_str("1,2,limit,ask,3,4"), Ok(OrderType::Limit { id: 1, user_id: 2, qty: 3, .. })));
+        assert!(matches!(OrderType::from_str("1,cancel"), Ok(OrderType::Cancel { id: 1 })));
+        assert!(matches!(OrderType::from_str("1,reduce,2"), Ok(OrderType::Reduce { id: 1, qty_delta: 2 })));
+
+        assert_eq!(OrderType::from_str(" ").unwrap_err(), OrderParseError::Empty);
+        assert_eq!(OrderType::from_str("stop,1").unwrap_err(), OrderParseError::UnknownOrderType("stop".to_string()));
+        assert_eq!(
+            OrderType::from_str("cancel,x").unwrap_err(),
+            OrderParseError::InvalidInteger { field: "id", value: "x".to_string() }
+        );
+        assert_eq!(
+            OrderType::from_str("limit,1,2,ask,3,4,5").unwrap_err(),
+            OrderParseError::FieldCount { order_type: "limit", expected: 5, found: 6 }
+        );
+        assert_eq!(OrderType::from_str("market,1,2,up,3").unwrap_err(), OrderParseError::InvalidSide("up".to_string()));
+        #[cfg(feature = "serde")]
+        {
+            assert!(matches!(OrderType::from_str("{}"), Err(OrderParseError::InvalidJson(_))));
+        }
+    }
+
+    #[test]
+    fn orders_built_with_named_qty_and_price() {
+        let limit = OrderType::limit(1, 2, Side::Ask, 3).at(4);
+        assert_eq!(limit.to_string(), "limit,1,2,ask,3,4");
+        assert_eq!(limit.ioc().to_string(), "ioc,1,2,ask,3,4");
+        assert_eq!(limit.fok().to_string(), "fok,1,2,ask,3,4");
+        assert_eq!(limit.post_only().post_only_slide().to_string(), "postonlyslide,1,2,ask,3,4");
+        assert_eq!(limit.good_till(5).to_string(), "gtt,1,2,ask,3,4,5");
+        assert_eq!(OrderType::market(1, 2, Side::Bid, 3).ioc().to_string(), "market,1,2,bid,3");
+        assert_eq!(OrderType::cross(1, 2, 3, 4).at(5).to_string(), "cross,1,2,3,4,5");
+        assert_eq!(OrderType::cancel(1).to_string(), "cancel,1");
+        assert_eq!(OrderType::reduce(1, 2).to_string(), "reduce,1,2");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn fills_read_their_former_field_names() {
+        let fill = FillMetadata {
+            trade_id: 1, taker_id: 2, maker_id: 3, maker_user_id: 4, maker_client_order_id: None, qty: 5, price: 6, taker_side: Side::Bid, total_fill: true, maker_remaining_qty: 0,
+        };
+        let json = serde_json::to_string(&fill).unwrap();
+        assert!(json.contains(r#""taker_id":2,"maker_id":3"#));
+        let former = json.replace("taker_id", "order_1").replace("maker_id", "order_2");
+        assert_eq!(serde_json::from_str::<FillMetadata>(&former).unwrap(), fill);
+    }
+
+    #[test]
+    fn fills_match_whether_inline_or_spilled() {
+        use super::{Price, Qty};
+        use crate::OrderBook;
+
+        // Three fills spill past the inline capacity of the `smallvec`
+        // feature.
+        for makers in 1..=3 {
+            let mut ob = OrderBook::default();
+            for id in 1..=makers {
+                ob.execute(OrderType::Limit { id, user_id: 1, side: Side::Ask, qty: 2, price: 100 + id as Price });
+            }
+            let OrderEvent::Filled { fills, filled_qty, .. } = ob.execute(OrderType::Market { id: 9, user_id: 2, side: Side::Bid, qty: 2 * makers as Qty }) else {
+                panic!("expected a fill");
+            };
+            assert_eq!(filled_qty, 2 * makers as Qty);
+            let summary: Vec<_> = fills.iter().map(|fill| (fill.maker_id, fill.qty, fill.price, fill.total_fill)).collect();
+            assert_eq!(summary, (1..=makers).map(|id| (id, 2, 100 + id as Price, true)).collect::<Vec<_>>());
+            #[cfg(feature = "serde")]
+            {
+                use super::Fills;
+
+                let json = serde_json::to_string(&fills).unwrap();
+                assert_eq!(json, serde_json::to_string(&fills.to_vec()).unwrap());
+                assert_eq!(serde_json::from_str::<Fills>(&json).unwrap(), fills);
+            }
+        }
+    }
+
+    #[test]
+    fn events_and_depth_display_as_readable_lines() {
+        let fill = FillMetadata {
+            trade_id: 7, taker_id: 3, maker_id: 2, maker_user_id: 5, maker_client_order_id: None, qty: 10, price: 100, taker_side: Side::Bid, total_fill: true, maker_remaining_qty: 0,
+        };
+        assert_eq!(fill.to_string(), "trade 7: 10@100, taker 3 (bid), maker 2 (user 5, 0 left)");
+
+        let partial = OrderEvent::PartiallyFilled { id: 3, filled_qty: 10, remaining_qty: 5, resting_price: Some(100), fills: fills![fill] };
+        assert_eq!(partial.to_string(), "order 3 partially filled 10 (1 fill), 5@100 resting");
+        assert_eq!(format!("{:#}", partial), format!("{}\n  {}", partial, fill));
+        let rejected = OrderEvent::Rejected { id: 1, message: RejectReason::OrderNotFound };
+        assert_eq!(rejected.to_string(), format!("order 1 rejected: {}", RejectReason::OrderNotFound));
+        assert_eq!(OrderEvent::Open { id: 4, remaining_qty: 2, resting_price: 99 }.to_string(), "order 4 open, 2@99 resting");
+
+        let level = |price, qty, cumulative_qty| BookLevel { price, qty, cumulative_qty, cumulative_notional: 0, orders: vec![] };
+        let depth = BookDepth { levels: 2, asks: vec![level(101, 3, 3)], bids: vec![level(100, 10, 10), level(99, 5, 15)] };
+        assert_eq!(depth.to_string(), "bids 10@100 5@99 | asks 3@101");
+        assert_eq!(BookDepth { levels: 1, asks: vec![], bids: vec![] }.to_string(), "bids - | asks -");
+        let ladder: Vec<String> = format!("{:#}", depth).lines().map(|line| line.split_whitespace().collect::<Vec<_>>().join(" ")).collect();
+        assert_eq!(ladder, ["side price qty cumulative", "ask 101 3 3", "bid 100 10 10", "bid 99 5 15"]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "narrow-qty", not(feature = "wide-qty")))]
+    fn narrow_quantities_are_refused_from_the_wire() {
+        use super::{qty_from_wire, wire_qty, Qty};
+        use crate::OrderBook;
+
+        assert_eq!(qty_from_wire(u64::from(Qty::MAX) + 1), None);
+        assert_eq!(qty_from_wire(u64::from(Qty::MAX)), Some(Qty::MAX));
+        assert_eq!(wire_qty(Qty::MAX), u64::from(u32::MAX));
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: Qty::MAX, price: 100 });
+        let event = ob.execute(OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: Qty::MAX });
+        assert!(matches!(event, OrderEvent::Filled { filled_qty: Qty::MAX, .. }));
+    }
 }
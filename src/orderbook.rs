@@ -1,29 +1,193 @@
-use std::collections::BTreeMap;
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::RangeBounds;
+#[cfg(all(feature = "std", feature = "serde"))]
+use std::io::BufRead;
 
-use crate::rejectmessages::{LIQUIDITY_NOT_AVAILABLE, self};
+use thiserror::Error;
+
+use crate::rejectmessages::RejectReason;
 use crate::arena::OrderArena;
+use crate::clearing::{ClearingRecord, ClearingSink, FeeSchedule};
+use crate::clock::{Clock, ManualClock};
+use crate::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::decimal::DecimalScale;
+use crate::dedup::DedupWindow;
+use crate::depthlimit::{DepthLimit, DepthPolicy, EvictionSink};
+use crate::gaps::{GapSink, SequenceGap};
+use crate::ratelimit::RateLimiter;
+use crate::journal::JournalEntry;
+#[cfg(feature = "std")]
+use crate::journal::JournalSink;
+#[cfg(all(feature = "std", feature = "serde"))]
+use crate::journal::{read_journal, JournalError};
+#[cfg(all(feature = "std", feature = "serde"))]
+use crate::jsonl::{read_orders_jsonl, LoadStats};
+use crate::snapshot::BookSnapshot;
+use crate::storage::{BookStorage, PriceQueue, QueuePool};
+use crate::tape::{TapeEntry, TradeTape};
+#[cfg(feature = "stream")]
+use crate::stream::{depth_channel, DepthPublisher, DepthReceiver};
+use crate::stats::{BookMetrics, Candle, CandleAggregator, CandleInterval, StatsWindow, TradeWindow};
+use crate::rfq::{Rfq, RfqAction, RfqError, RfqQuote};
+use crate::risk::{abs_notional, sweep_notional, RiskLimits};
+use crate::session::TradingPhase;
 use crate::models::{
-    BookDepth, BookLevel, FillMetadata, OrderEvent, OrderType, Side, Trade, OrderId, Qty, Price, UserId,
+    notional, price_times, Bbo, BookDelta, BookDepth, BookLevel, ClientOrderEvent, ClientOrderId, DeltaReason, Notional, FillMetadata, FillRecord, Fills, LimitOrder, OrderEvent, OrderType, OrderUpdate, Position, Side, StampedEvent, Trade, OrderId, Qty, Price, UserId, SessionId,
 };
 
 const DEFAULT_ARENA_CAPACITY: usize = 10_000;
 const DEFAULT_QUEUE_CAPACITY: usize = 10;
+const DEFAULT_QUEUE_POOL_SIZE: usize = 64;
+const DEFAULT_STATS_WINDOW: usize = 1_000;
+const DEFAULT_TAPE_CAPACITY: usize = 1_000;
+
+/// A validation failure of an order given to [`OrderBook::try_execute`],
+/// found before the order reaches the book. Unlike the trading outcomes
+/// reported as [`OrderEvent`]s, it points at a faulty order stream.
+///
+/// [`OrderBook::try_execute`]: struct.OrderBook.html#method.try_execute
+/// [`OrderEvent`]: enum.OrderEvent.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ExecuteError {
+    /// The order ID is the ID of an order resting on the book.
+    #[error("Order id {id} is already resting on the book")]
+    DuplicateOrderId {
+        /// The ID of the order.
+        id: OrderId,
+    },
+    /// The order ID is not greater than the ID of the last order.
+    #[error("Order id {id} is not greater than the last order id {last_id}")]
+    InvalidSequence {
+        /// The ID of the order.
+        id: OrderId,
+        /// The ID of the last sequenced order.
+        last_id: OrderId,
+    },
+    /// The order would rest at a price the book storage cannot hold, e.g.
+    /// off its tick grid.
+    #[error("Order {id} cannot rest at price {price}")]
+    InvalidPrice {
+        /// The ID of the order.
+        id: OrderId,
+        /// The price of the order.
+        price: Price,
+    },
+    /// The order would overflow the resting quantity of its price level.
+    #[error("Order {id} would overflow the quantity resting at price {price}")]
+    QuantityOverflow {
+        /// The ID of the order.
+        id: OrderId,
+        /// The price of the order.
+        price: Price,
+    },
+    /// The order could not be appended to the journal of the book.
+    #[error("Order {id} could not be appended to the journal")]
+    JournalUnavailable {
+        /// The ID of the order.
+        id: OrderId,
+    },
+}
+
+impl ExecuteError {
+    /// Return the ID of the failed order.
+    pub fn id(&self) -> OrderId {
+        match *self {
+            ExecuteError::DuplicateOrderId { id }
+            | ExecuteError::InvalidSequence { id, .. }
+            | ExecuteError::InvalidPrice { id, .. }
+            | ExecuteError::QuantityOverflow { id, .. }
+            | ExecuteError::JournalUnavailable { id } => id,
+        }
+    }
+
+    /// Return the reason of the rejection [`OrderBook::execute`] reports for
+    /// the failure.
+    ///
+    /// [`OrderBook::execute`]: struct.OrderBook.html#method.execute
+    pub fn reason(&self) -> RejectReason {
+        match self {
+            ExecuteError::DuplicateOrderId { .. } => RejectReason::DuplicateOrderId,
+            ExecuteError::InvalidSequence { .. } => RejectReason::InvalidSequence,
+            ExecuteError::InvalidPrice { .. } => RejectReason::PriceOutOfRange,
+            ExecuteError::QuantityOverflow { .. } => RejectReason::QuantityOverflow,
+            ExecuteError::JournalUnavailable { .. } => RejectReason::JournalUnavailable,
+        }
+    }
+
+    fn into_event(self) -> OrderEvent {
+        OrderEvent::Rejected { id: self.id(), message: self.reason() }
+    }
+}
 
 /// An order book that executes orders serially through the [`execute`] method.
 ///
+/// The price levels are kept in a [`BookStorage`], a `BTreeMap` unless
+/// another storage is given to [`with_storage`].
+///
 /// [`execute`]: #method.execute
+/// [`BookStorage`]: trait.BookStorage.html
+/// [`with_storage`]: #method.with_storage
 #[derive(Debug)]
-pub struct OrderBook {
+pub struct OrderBook<S = BTreeMap<Price, PriceQueue>> {
     last_processed_order_id: u64,
+    last_seen_order_id: u64,
     last_trade: Option<Trade>,
     traded_volume: Qty,
     min_ask: Price,
     max_bid: Price,
-    asks: BTreeMap<Price, Vec<OrderId>>,
-    bids: BTreeMap<Price, Vec<OrderId>>,
+    asks: S,
+    bids: S,
+    ask_sizes: BTreeMap<Price, Qty>,
+    bid_sizes: BTreeMap<Price, Qty>,
+    bbo: Bbo,
     arena: OrderArena,
-    default_queue_capacity: usize,
+    queue_pool: QueuePool,
+    in_batch: bool,
     track_stats: bool,
+    trade_window: TradeWindow,
+    candles: Option<CandleAggregator>,
+    tape: Option<TradeTape>,
+    #[cfg(feature = "std")]
+    journal: Option<Box<dyn JournalSink>>,
+    journal_sequence: u64,
+    #[cfg(feature = "stream")]
+    depth_stream: Option<DepthPublisher>,
+    validate_crosses: bool,
+    rfqs: HashMap<OrderId, Rfq>,
+    clock: Box<dyn Clock>,
+    expiries: BTreeSet<(u64, OrderId)>,
+    track_fills: bool,
+    fill_sequence: u64,
+    fill_history: HashMap<OrderId, Vec<FillRecord>>,
+    track_positions: bool,
+    positions: HashMap<UserId, Position>,
+    last_trade_id: u64,
+    track_clearing: bool,
+    clearing_sequence: u64,
+    clearing_records: Vec<ClearingRecord>,
+    clearing_sink: Option<Box<dyn ClearingSink>>,
+    fee_schedule: FeeSchedule,
+    rate_limiter: Option<RateLimiter>,
+    dedup_window: Option<DedupWindow>,
+    phase: TradingPhase,
+    track_gaps: bool,
+    gaps: Vec<SequenceGap>,
+    gap_sink: Option<Box<dyn GapSink>>,
+    risk_limits: HashMap<UserId, RiskLimits>,
+    track_deltas: bool,
+    touched_levels: Vec<(Side, Price, DeltaReason)>,
+    track_order_updates: bool,
+    order_updates: Vec<OrderUpdate>,
+    metrics: BookMetrics,
+    metrics_clock: Option<Box<dyn Clock>>,
+    depth_limit: Option<DepthLimit>,
+    track_evictions: bool,
+    evicted: Vec<OrderEvent>,
+    eviction_sink: Option<Box<dyn EvictionSink>>,
+    decimal_scale: DecimalScale,
 }
 
 impl Default for OrderBook {
@@ -53,48 +217,203 @@ impl OrderBook {
         arena_capacity: usize,
         queue_capacity: usize,
         track_stats: bool,
+    ) -> Self {
+        Self::with_storage(arena_capacity, queue_capacity, track_stats, BTreeMap::new(), BTreeMap::new())
+    }
+}
+
+impl<S: BookStorage> OrderBook<S> {
+    /// Create an instance like [`new`], keeping the price levels of each side
+    /// in the given storage, e.g. a [`PriceLadder`] for an instrument with a
+    /// bounded price range. Orders that would rest at a price the storage
+    /// does not accept are rejected.
+    ///
+    /// [`new`]: #method.new
+    /// [`PriceLadder`]: struct.PriceLadder.html
+    pub fn with_storage(
+        arena_capacity: usize,
+        queue_capacity: usize,
+        track_stats: bool,
+        asks: S,
+        bids: S,
     ) -> Self {
         Self {
             last_processed_order_id: 0,
+            last_seen_order_id: 0,
             last_trade: None,
             traded_volume: 0,
-            min_ask: std::u64::MAX,
-            max_bid: 0u64,
-            asks: BTreeMap::new(),
-            bids: BTreeMap::new(),
+            min_ask: Price::MAX,
+            max_bid: Price::MIN,
+            asks,
+            bids,
+            ask_sizes: BTreeMap::new(),
+            bid_sizes: BTreeMap::new(),
+            bbo: Bbo::default(),
             arena: OrderArena::new(arena_capacity),
-            default_queue_capacity: queue_capacity,
+            queue_pool: QueuePool::new(queue_capacity, DEFAULT_QUEUE_POOL_SIZE),
+            in_batch: false,
             track_stats,
+            trade_window: TradeWindow::new(DEFAULT_STATS_WINDOW),
+            candles: None,
+            tape: Some(TradeTape::new(DEFAULT_TAPE_CAPACITY)),
+            #[cfg(feature = "std")]
+            journal: None,
+            journal_sequence: 0,
+            #[cfg(feature = "stream")]
+            depth_stream: None,
+            validate_crosses: false,
+            rfqs: HashMap::new(),
+            clock: Box::new(ManualClock::default()),
+            expiries: BTreeSet::new(),
+            track_fills: false,
+            fill_sequence: 0,
+            fill_history: HashMap::new(),
+            track_positions: false,
+            positions: HashMap::new(),
+            last_trade_id: 0,
+            track_clearing: false,
+            clearing_sequence: 0,
+            clearing_records: Vec::new(),
+            clearing_sink: None,
+            fee_schedule: FeeSchedule::default(),
+            rate_limiter: None,
+            dedup_window: None,
+            phase: TradingPhase::Open,
+            track_gaps: false,
+            gaps: Vec::new(),
+            gap_sink: None,
+            risk_limits: HashMap::new(),
+            track_deltas: false,
+            touched_levels: Vec::new(),
+            track_order_updates: false,
+            order_updates: Vec::new(),
+            metrics: BookMetrics::default(),
+            metrics_clock: None,
+            depth_limit: None,
+            track_evictions: false,
+            evicted: Vec::new(),
+            eviction_sink: None,
+            decimal_scale: DecimalScale::default(),
         }
     }
 
+    /// The resting ask levels in ascending price order. Levels are removed as
+    /// soon as their last order leaves the book, so only non-empty levels are
+    /// listed.
     #[doc(hidden)]
     pub fn _asks(&self) -> Vec<(Price, Vec<OrderId>)> {
-        self.asks.clone().into_iter().collect()
+        self.asks.levels().map(|(price, queue)| (price, queue.iter().map(|handle| handle.id).collect())).collect()
     }
 
+    /// The resting bid levels in ascending price order, listing only
+    /// non-empty levels like [`_asks`](#method._asks).
     #[doc(hidden)]
     pub fn _bids(&self) -> Vec<(Price, Vec<OrderId>)> {
-        self.bids.clone().into_iter().collect()
+        self.bids.levels().map(|(price, queue)| (price, queue.iter().map(|handle| handle.id).collect())).collect()
+    }
+
+    /// Iterate over the ask price levels, best (lowest) price first, yielding
+    /// the price and the ids of the orders resting there in time priority.
+    pub fn iter_asks(&self) -> impl Iterator<Item = (Price, impl ExactSizeIterator<Item = OrderId> + '_)> {
+        self.asks.levels().filter(|(_, queue)| !queue.is_empty()).map(|(price, queue)| (price, queue.iter().map(|handle| handle.id)))
+    }
+
+    /// Iterate over the bid price levels, best (highest) price first, yielding
+    /// the price and the ids of the orders resting there in time priority.
+    pub fn iter_bids(&self) -> impl Iterator<Item = (Price, impl ExactSizeIterator<Item = OrderId> + '_)> {
+        self.bids.levels().rev().filter(|(_, queue)| !queue.is_empty()).map(|(price, queue)| (price, queue.iter().map(|handle| handle.id)))
+    }
+
+    /// Iterate over the ask price levels, best (lowest) price first, yielding
+    /// the price and the aggregate quantity resting there.
+    pub fn iter_ask_qtys(&self) -> impl Iterator<Item = (Price, Qty)> + '_ {
+        self.ask_sizes.iter().map(|(price, qty)| (*price, *qty))
+    }
+
+    /// Iterate over the bid price levels, best (highest) price first, yielding
+    /// the price and the aggregate quantity resting there.
+    pub fn iter_bid_qtys(&self) -> impl Iterator<Item = (Price, Qty)> + '_ {
+        self.bid_sizes.iter().rev().map(|(price, qty)| (*price, *qty))
     }
 
     /// Return the lowest ask price, if present.
     #[inline(always)]
+    pub fn best_ask(&self) -> Option<Price> {
+        (self.min_ask != Price::MAX).then_some(self.min_ask)
+    }
+
+    /// Return the highest bid price, if present.
+    #[inline(always)]
+    pub fn best_bid(&self) -> Option<Price> {
+        (self.max_bid != Price::MIN).then_some(self.max_bid)
+    }
+
+    /// Return the difference of the lowest ask and highest bid, if both are
+    /// present.
+    #[inline(always)]
+    pub fn bid_ask_spread(&self) -> Option<Price> {
+        Some(self.best_ask()? - self.best_bid()?)
+    }
+
+    /// Return the lowest ask price, or `Price::MAX` if there are no asks.
+    #[deprecated(note = "use `best_ask`, which returns `None` on an empty side")]
+    #[inline(always)]
     pub fn min_ask(&self) -> Price {
         self.min_ask
     }
 
-    /// Return the highest bid price, if present.
+    /// Return the highest bid price, or `Price::MIN` if there are no bids:
+    /// `0` unless prices are signed.
+    #[deprecated(note = "use `best_bid`, which returns `None` on an empty side")]
     #[inline(always)]
     pub fn max_bid(&self) -> Price {
         self.max_bid
     }
 
-    /// Return the difference of the lowest ask and highest bid, if both are
-    /// present.
+    /// Return the difference of the lowest ask and highest bid, computed with
+    /// the sentinel values of [`min_ask`] and [`max_bid`] for empty sides.
+    ///
+    /// [`min_ask`]: #method.min_ask
+    /// [`max_bid`]: #method.max_bid
+    #[deprecated(note = "use `bid_ask_spread`, which returns `None` unless both sides are present")]
     #[inline(always)]
-    pub fn spread(&self) -> Price { 
-        self.min_ask - self.max_bid
+    pub fn spread(&self) -> Price {
+        self.min_ask.saturating_sub(self.max_bid)
+    }
+
+    /// Return the best bid and offer with their aggregate quantities. It is
+    /// kept up to date as orders are added, matched and canceled, so it does
+    /// not walk the book.
+    #[inline(always)]
+    pub fn bbo(&self) -> Bbo {
+        self.bbo
+    }
+
+    /// Return the midpoint of the best bid and ask prices, if both are
+    /// present.
+    pub fn mid_price(&self) -> Option<f64> {
+        let (bid, _) = self.best_level(Side::Bid)?;
+        let (ask, _) = self.best_level(Side::Ask)?;
+        Some((bid as f64 + ask as f64) / 2.0)
+    }
+
+    /// Return the average of the best bid and ask prices weighted by their own
+    /// aggregate quantities, if both are present. It leans towards the side
+    /// with more resting quantity.
+    pub fn weighted_mid(&self) -> Option<f64> {
+        let (bid, bid_qty) = self.best_level(Side::Bid)?;
+        let (ask, ask_qty) = self.best_level(Side::Ask)?;
+        Some((bid as f64 * bid_qty as f64 + ask as f64 * ask_qty as f64) / (bid_qty as f64 + ask_qty as f64))
+    }
+
+    /// Return the microprice, i.e. the average of the best bid and ask prices
+    /// weighted by the aggregate quantity of the opposite side, if both are
+    /// present. It leans towards the side with less resting quantity, which
+    /// is the one more likely to be traded through.
+    pub fn microprice(&self) -> Option<f64> {
+        let (bid, bid_qty) = self.best_level(Side::Bid)?;
+        let (ask, ask_qty) = self.best_level(Side::Ask)?;
+        Some((bid as f64 * ask_qty as f64 + ask as f64 * bid_qty as f64) / (bid_qty as f64 + ask_qty as f64))
     }
 
     /// Return the last sequence processed
@@ -113,12 +432,87 @@ impl OrderBook {
     }
 
     /// Return the total traded volume for all the trades that occurred while
-    /// the stats tracking was active.
+    /// the stats tracking was active, saturating at `Qty::MAX`.
     #[inline(always)]
     pub fn traded_volume(&self) -> Qty {
         self.traded_volume
     }
 
+    /// Return the volume weighted average price of the trades within `window`
+    /// that occurred while the stats tracking was active, if any.
+    pub fn vwap(&self, window: StatsWindow) -> Option<f64> {
+        self.trade_window.vwap(window, self.clock.now())
+    }
+
+    /// Return the time weighted average price of the trades within `window`
+    /// that occurred while the stats tracking was active, if any. Each price is
+    /// weighted by the clock time it remained the last traded price.
+    pub fn twap(&self, window: StatsWindow) -> Option<f64> {
+        self.trade_window.twap(window, self.clock.now())
+    }
+
+    /// Set the number of recent trades kept for the rolling statistics (see
+    /// [`vwap`] and [`twap`]). Defaults to 1,000.
+    ///
+    /// [`vwap`]: #method.vwap
+    /// [`twap`]: #method.twap
+    pub fn set_stats_window(&mut self, capacity: usize) {
+        self.trade_window.set_capacity(capacity);
+    }
+
+    /// Enable candle aggregation with the given bucket width, or disable it
+    /// with `None`. Candles are built from the trades that occur while the
+    /// stats tracking is active (see [`take_candles`]). Changing the interval
+    /// discards the candles aggregated so far.
+    ///
+    /// [`take_candles`]: #method.take_candles
+    pub fn set_candle_interval(&mut self, interval: Option<CandleInterval>) {
+        self.candles = interval.map(CandleAggregator::new);
+    }
+
+    /// Return the finished candles aggregated since the previous call, oldest
+    /// first.
+    pub fn take_candles(&mut self) -> Vec<Candle> {
+        self.candles.as_mut().map_or_else(Vec::new, |candles| candles.take_finished())
+    }
+
+    /// Return the candle still being aggregated, if any.
+    pub fn current_candle(&self) -> Option<Candle> {
+        self.candles.as_ref().and_then(|candles| candles.current())
+    }
+
+    /// Set the number of queues of emptied price levels kept for reuse by new
+    /// levels, which avoids reallocating them when prices oscillate. Defaults
+    /// to 64.
+    pub fn set_queue_pool_size(&mut self, size: usize) {
+        self.queue_pool.set_max_queues(size);
+    }
+
+    /// Enable the trade tape keeping the last `capacity` trades, or disable it
+    /// with `None`. The tape keeps the last 1,000 trades by default. Changing
+    /// the capacity discards the recorded trades.
+    pub fn set_tape_capacity(&mut self, capacity: Option<usize>) {
+        self.tape = capacity.map(TradeTape::new);
+    }
+
+    /// Iterate over the trades on the tape, oldest first.
+    pub fn tape(&self) -> impl DoubleEndedIterator<Item = &TapeEntry> {
+        self.tape.iter().flat_map(|tape| tape.iter())
+    }
+
+    /// Iterate over the last `n` trades on the tape, most recent first, e.g.
+    /// to render a time and sales panel.
+    pub fn recent_trades(&self, n: usize) -> impl Iterator<Item = &TapeEntry> {
+        self.tape().rev().take(n)
+    }
+
+    /// Return up to `limit` trades from the tape whose sequence is greater
+    /// than `after`, oldest first. Pass the sequence of the last trade of a
+    /// page to fetch the next one.
+    pub fn tape_page(&self, after: u64, limit: usize) -> Vec<TapeEntry> {
+        self.tape.as_ref().map_or_else(Vec::new, |tape| tape.page(after, limit))
+    }
+
     /// Return the order book depth as a [`BookDepth`] struct, up to the
     /// specified level. Bids and offers at the same price level are merged in a
     /// single [`BookLevel`] struct.
@@ -126,945 +520,3707 @@ impl OrderBook {
     /// [`BookDepth`]: struct.BookDepth.html
     /// [`BookLevel`]: struct.BookLevel.html
     pub fn depth(&self, levels: usize, include_orders: bool) -> BookDepth {
-        let mut asks: Vec<BookLevel> = Vec::with_capacity(levels);
-        let mut bids: Vec<BookLevel> = Vec::with_capacity(levels);
-        
-        for (index, (ask_price, queue)) in self.asks.iter().enumerate() {
-            if index >= levels {
-                break;
-            }
-            let mut qty = 0;
-            for idx in queue {
-                qty += self.arena[*idx].qty;
-            }
-            if qty > 0 {
-                asks.push(BookLevel {
-                    price: *ask_price,
-                    qty,
-                    orders: if include_orders { queue.iter().map(|order_id| self.arena[*order_id].clone()).collect() } else { vec![]}
-                });
-            }
-        }
-
-        for (index, (bid_price, queue)) in self.bids.iter().rev().enumerate() {
-            if index >= levels {
-                break;
-            }
-            let mut qty = 0;
-            for idx in queue {
-                qty += self.arena[*idx].qty;
-            }
-            if qty > 0 {
-                bids.push(BookLevel {
-                    price: *bid_price,
-                    qty,
-                    orders: if include_orders { queue.iter().map(|order_id| self.arena[*order_id].clone()).collect() } else { vec![]}
-                });
-            }
-        }
-
+        let mut asks = Vec::with_capacity(levels.min(self.ask_sizes.len()));
+        let mut bids = Vec::with_capacity(levels.min(self.bid_sizes.len()));
+        self.depth_into(&mut asks, &mut bids, levels, include_orders);
         BookDepth { levels, asks, bids }
     }
 
-    /// Toggle the stats tracking on or off, depending on the `track` parameter.
-    pub fn track_stats(&mut self, track: bool) {
-        self.track_stats = track;
+    /// Render the top `levels` levels of each side into `asks` and `bids`,
+    /// like [`depth`], overwriting their content. The levels already in the
+    /// buffers are updated in place, order lists included, so publishers
+    /// calling it repeatedly with the same buffers stop allocating once the
+    /// buffers have grown.
+    ///
+    /// [`depth`]: #method.depth
+    pub fn depth_into(&self, asks: &mut Vec<BookLevel>, bids: &mut Vec<BookLevel>, levels: usize, include_orders: bool) {
+        self.depth_side(Side::Ask, self.ask_sizes.iter(), levels, include_orders, asks);
+        self.depth_side(Side::Bid, self.bid_sizes.iter().rev(), levels, include_orders, bids);
     }
 
-    /// Execute an order, returning immediately an event indicating the result.
-    pub fn execute(&mut self, event: OrderType) -> OrderEvent {
-        let order_id = event.get_id();
-        let order_type = event.get_type();
-        
-        // Having order id sequence to only increase is very important which helps in optimizing the order search during cancel.
-        // and helps reconstructing the btreemaps orders from the hashmap 
-        if order_type != "cancel" {
-            if self.last_processed_order_id >=  order_id {
-                return OrderEvent::Rejected { id: order_id, message: rejectmessages::INVALID_ORDER_NUMBER }
+    /// Write at most `levels` book levels from the level sizes of `side`
+    /// yielded best price first into `out`, accumulating quantity and
+    /// notional from the first one. The queues are only visited to list the
+    /// orders.
+    fn depth_side<'a>(
+        &self,
+        side: Side,
+        sizes: impl Iterator<Item = (&'a Price, &'a Qty)>,
+        levels: usize,
+        include_orders: bool,
+        out: &mut Vec<BookLevel>,
+    ) {
+        let queues = if side == Side::Bid { &self.bids } else { &self.asks };
+        let (mut cumulative_qty, mut cumulative_notional): (Qty, Price) = (0, 0);
+        let mut written = 0;
+        for (price, qty) in sizes.take(levels) {
+            cumulative_qty = cumulative_qty.saturating_add(*qty);
+            cumulative_notional = cumulative_notional.saturating_add(price_times(*price, *qty));
+            if written == out.len() {
+                out.push(BookLevel { price: 0, qty: 0, cumulative_qty: 0, cumulative_notional: 0, orders: vec![] });
+            }
+            let level = &mut out[written];
+            level.price = *price;
+            level.qty = *qty;
+            level.cumulative_qty = cumulative_qty;
+            level.cumulative_notional = cumulative_notional;
+            level.orders.clear();
+            if let Some(queue) = queues.get(*price).filter(|_| include_orders) {
+                level.orders.extend(queue.iter().map(|handle| self.arena[*handle].clone()));
             }
-            self.last_processed_order_id = order_id;
+            written += 1;
         }
+        out.truncate(written);
+    }
 
-        let event = self._execute(event);
-        if !self.track_stats {
-            return event;
-        }
+    /// Open a stream publishing a snapshot of the top `levels` levels after
+    /// every call that can change the book, starting with the current state.
+    /// With `conflate` set, snapshots the receiver has not picked up yet are
+    /// replaced by newer ones instead of queueing up. Opening a stream closes
+    /// the previous one, and the stream stops when the receiver is dropped.
+    #[cfg(feature = "stream")]
+    pub fn stream_depth(&mut self, levels: usize, conflate: bool) -> DepthReceiver {
+        let (publisher, receiver) = depth_channel(levels, conflate);
+        self.depth_stream = Some(publisher);
+        self.publish_depth();
+        receiver
+    }
 
-        match event.clone() {
-            OrderEvent::Filled {
-                id: _,
-                filled_qty,
-                fills,
-            } => {
-                self.traded_volume += filled_qty;
-                // If we are here, fills is not empty, so it's safe to unwrap it
-                let last_fill = fills.last().unwrap();
-                self.last_trade = Some(Trade {
-                    total_qty: filled_qty,
-                    avg_price: fills
-                        .iter()
-                        .map(|fm| fm.price * fm.qty)
-                        .sum::<u64>() as f64
-                        / (filled_qty as f64),
-                    last_qty: last_fill.qty,
-                    last_price: last_fill.price,
-                });
-            }
-            OrderEvent::PartiallyFilled {
-                id: _,
-                filled_qty,
-                fills,
-            } => {
-                self.traded_volume += filled_qty;
-                // If we are here, fills is not empty, so it's safe to unwrap it
-                let last_fill = fills.last().unwrap();
-                self.last_trade = Some(Trade {
-                    total_qty: filled_qty,
-                    avg_price: fills
-                        .iter()
-                        .map(|fm| fm.price * fm.qty)
-                        .sum::<u64>() as f64
-                        / (filled_qty as f64),
-                    last_qty: last_fill.qty,
-                    last_price: last_fill.price,
-                });
+    /// Close the depth stream, if any.
+    #[cfg(feature = "stream")]
+    pub fn stop_depth_stream(&mut self) {
+        self.depth_stream = None;
+    }
+
+    fn publish_depth(&mut self) {
+        #[cfg(feature = "stream")]
+        if let Some(publisher) = self.depth_stream.as_ref().filter(|_| !self.in_batch) {
+            if !publisher.publish(self.depth(publisher.levels(), false)) {
+                self.depth_stream = None;
             }
-            _ => {}
         }
-        event
     }
 
-    fn _execute(&mut self, event: OrderType) -> OrderEvent {
-        match event {
-            OrderType::Market { id, user_id:_, side, qty } => {
-                let (fills, partial, filled_qty) = self.market(id, side, qty);
-                if fills.is_empty() {
-                    OrderEvent::Rejected { id, message: LIQUIDITY_NOT_AVAILABLE  }
-                } else if partial {
-                    OrderEvent::PartiallyFilled {
-                        id,
-                        filled_qty,
-                        fills,
-                    }
-                } else {
-                    OrderEvent::Filled {
-                        id,
-                        filled_qty,
-                        fills,
-                    }
-                }
-            }
-            OrderType::Limit { id, user_id, side, qty, price,} => {
-                let (fills, partial, filled_qty) =
-                    self.limit(id, user_id, side, qty, price);
-                if fills.is_empty() {
-                    OrderEvent::Open { id }
-                } else if partial {
-                    OrderEvent::PartiallyFilled {
-                        id,
-                        filled_qty,
-                        fills,
-                    }
-                } else {
-                    OrderEvent::Filled {
-                        id,
-                        filled_qty,
-                        fills,
-                    }
-                }
-            }
-            OrderType::Cancel { id } => {
-                self.cancel(id);
-                OrderEvent::Cancelled { id }
-            }
-            OrderType::IOC { id, user_id, side, qty, price } => {
-                let (fills, partial, filled_qty) = self.ioc(id, user_id, side, qty, price);
-                if fills.is_empty() {
-                    OrderEvent::Cancelled { id }
-                } else if partial {
-                    OrderEvent::PartiallyFilled {
-                        id,
-                        filled_qty,
-                        fills,
-                    }
-                } else {
-                    OrderEvent::Filled {
-                        id,
-                        filled_qty,
-                        fills,
-                    }
-                }
-            },
-            OrderType::FOK { id, user_id, side, qty, price } =>  {
-                let (fills, partial, filled_qty) = self.ioc(id, user_id, side, qty, price);
-                if fills.is_empty() || partial {
-                    OrderEvent::Cancelled { id }
-                } else {
-                    OrderEvent::Filled {
-                        id,
-                        filled_qty,
-                        fills,
-                    }
-                }
-            }
-            OrderType::Postonly { id, user_id, side, qty, price } => {
-                let (_fills, _partial, filled_qty) = self.postonly(id, user_id, side, qty, price);
-                if filled_qty == 0 {
-                    OrderEvent::Open { id }
-                } else {
-                    OrderEvent::Cancelled { id }
-                }
-            },
-            
-            OrderType::PostonlySlide { id, user_id, side, qty, price } => {
-                let (_fills, _partial, filled_qty) = self.postonlyslide(id, user_id, side, qty, price);
-                if filled_qty == 0 {
-                    OrderEvent::Open { id }
-                } else {
-                    OrderEvent::Cancelled { id }
-                }
+    /// Capture the resting orders, sequence and stats of the book in a
+    /// [`BookSnapshot`], which can be persisted and loaded back with
+    /// [`restore`].
+    ///
+    /// [`BookSnapshot`]: struct.BookSnapshot.html
+    /// [`restore`]: #method.restore
+    pub fn snapshot(&self) -> BookSnapshot {
+        let orders = |levels: &mut dyn Iterator<Item = (Price, &PriceQueue)>| -> Vec<LimitOrder> {
+            levels.flat_map(|(_, queue)| queue.iter().map(|handle| self.arena[*handle].clone())).collect()
+        };
+        let asks = orders(&mut self.asks.levels());
+        let bids = orders(&mut self.bids.levels().rev());
+        let resting = || asks.iter().chain(bids.iter()).map(|order| order.id);
+        BookSnapshot {
+            last_sequence: self.last_processed_order_id,
+            journal_sequence: self.journal_sequence,
+            last_trade_id: self.last_trade_id,
+            expiries: self.expiries.iter().copied().filter(|(_, id)| self.arena.get(*id).is_some()).collect(),
+            client_order_ids: resting().filter_map(|id| Some((id, self.arena.client_id(id)?))).collect(),
+            sessions: resting().filter_map(|id| Some((id, self.arena.session(id)?))).collect(),
+            last_trade: self.last_trade,
+            traded_volume: self.traded_volume,
+            rfqs: {
+                let mut rfqs: Vec<Rfq> = self.rfqs.values().cloned().collect();
+                rfqs.sort_unstable_by_key(|rfq| rfq.id);
+                rfqs
             },
+            phase: self.phase,
+            asks,
+            bids,
         }
     }
 
-    fn cancel(&mut self, id: OrderId) -> bool {
-        if let Some(order) = self.arena.get(id) {
-            if let Some(ref mut queue) = self.asks.get_mut(&order.price) {
-                if let Some(i) = queue.iter().position(|i| *i == id) {
-                    queue.remove(i);
-                }
-                if queue.len() == 0 { self.asks.remove(&order.price); }
-            }
-            if let Some(ref mut queue) = self.bids.get_mut(&order.price) {
-                if let Some(i) = queue.iter().position(|i| *i == id) {
-                    queue.remove(i);
-                }
-                if queue.len() == 0 { self.bids.remove(&order.price); }
+    /// Return a deterministic hash of the book state: the resting orders in
+    /// priority order, their expiries, client order IDs and sessions, the
+    /// processed sequences, the open requests for quote, the trading phase
+    /// and the traded volume. Two replicas applying the
+    /// same journal return the same hash, so they can be compared without
+    /// exchanging their state. See [`BookSnapshot::state_hash`].
+    ///
+    /// [`BookSnapshot::state_hash`]: struct.BookSnapshot.html#method.state_hash
+    #[cfg(feature = "binary")]
+    pub fn state_hash(&self) -> u64 {
+        self.snapshot().state_hash()
+    }
+
+    /// Replace the state of the book, including its trading phase, with the
+    /// content of `snapshot`, keeping the book configuration (clock, rate
+    /// limiter and tracking toggles).
+    /// Pending deltas and order updates are discarded.
+    ///
+    /// Panics if an order of the snapshot rests at a price the storage of
+    /// the book does not accept.
+    pub fn restore(&mut self, snapshot: BookSnapshot) {
+        self.asks.clear();
+        self.bids.clear();
+        self.ask_sizes.clear();
+        self.bid_sizes.clear();
+        self.arena.clear();
+        self.touched_levels.clear();
+        self.order_updates.clear();
+        for (side, orders) in [(Side::Ask, snapshot.asks), (Side::Bid, snapshot.bids)] {
+            let (levels, sizes) = match side {
+                Side::Ask => (&mut self.asks, &mut self.ask_sizes),
+                Side::Bid => (&mut self.bids, &mut self.bid_sizes),
+            };
+            for order in orders {
+                let handle = self.arena.insert(order.id, order.user_id, side, order.price, order.qty, order.created_at);
+                levels.get_or_insert_with(order.price, || self.queue_pool.take()).push_back(handle);
+                *sizes.entry(order.price).or_default() += order.qty;
             }
         }
+        for (id, client_order_id) in snapshot.client_order_ids {
+            self.arena.set_client_id(id, client_order_id);
+        }
+        for (id, session_id) in snapshot.sessions {
+            self.arena.set_session(id, session_id);
+        }
+        self.expiries = snapshot.expiries.into_iter().collect();
+        self.rfqs = snapshot.rfqs.into_iter().map(|rfq| (rfq.id, rfq)).collect();
+        self.phase = snapshot.phase;
+        self.last_processed_order_id = snapshot.last_sequence;
+        self.last_seen_order_id = snapshot.last_sequence;
+        self.journal_sequence = snapshot.journal_sequence;
+        self.last_trade_id = snapshot.last_trade_id;
+        self.last_trade = snapshot.last_trade;
+        self.traded_volume = snapshot.traded_volume;
         self.update_min_ask();
         self.update_max_bid();
-        self.arena.delete(&id)
+        self.refresh_bbo(Side::Ask);
+        self.refresh_bbo(Side::Bid);
+        self.publish_depth();
     }
 
-    fn finalize_execution(&mut self, fills: &Vec<FillMetadata>) {
-        fills.iter().for_each(|fill| {
-            let maker_id = fill.maker_id;
-            let maker_side = !fill.taker_side;
-            let qty = fill.qty;
-            let remove_maker_order = fill.total_fill;
-            let levels = if maker_side == Side::Bid { &mut self.bids } else { &mut self.asks };  
-            let entry = levels.entry(fill.price).or_insert(Vec::with_capacity(self.default_queue_capacity));
-            let index = entry.binary_search(&maker_id);
-            if remove_maker_order {
-                if let Ok(index) = index {
-                    entry.remove(index);
-                }
-                self.arena.delete(&maker_id);
-            } else { 
-                self.arena[maker_id].qty -= qty;                
-            }
-            if  entry.len() == 0 { levels.remove(&fill.price); }
-        });
-        self.update_max_bid();
-        self.update_min_ask();
+    /// Return a CRC32 checksum of the top `levels` price levels of each side,
+    /// in the style of the Kraken and OKX market data feeds, so a client
+    /// mirroring the book can verify it is in sync. The checksummed string
+    /// concatenates the decimal price and quantity of each ask level, best
+    /// first, followed by those of each bid level, best first.
+    pub fn checksum(&self, levels: usize) -> u32 {
+        let asks = self.ask_sizes.iter().take(levels);
+        let bids = self.bid_sizes.iter().rev().take(levels);
+        let mut hasher = crc32fast::Hasher::new();
+        for (price, qty) in asks.chain(bids) {
+            hasher.update(price.to_string().as_bytes());
+            hasher.update(qty.to_string().as_bytes());
+        }
+        hasher.finalize()
     }
 
-    fn market(
-        &mut self,
-        id: OrderId,
-        side: Side,
-        qty: u64,
-    ) -> (Vec<FillMetadata>, bool, u64) {
-        let mut fills = Vec::new();
+    /// Return the resting quantity an order on `side` with the given limit
+    /// price could be matched against, i.e. the total quantity on the
+    /// opposite side at prices at or better than `limit_price`, saturating at
+    /// `Qty::MAX`.
+    pub fn available_qty(&self, side: Side, limit_price: Price) -> Qty {
+        match side {
+            Side::Bid => self.ask_sizes.range(..=limit_price).map(|(_, qty)| *qty).fold(0, Qty::saturating_add),
+            Side::Ask => self.bid_sizes.range(limit_price..).map(|(_, qty)| *qty).fold(0, Qty::saturating_add),
+        }
+    }
 
-        let remaining_qty = match side {
-            Side::Bid => self.match_with_asks(id, qty, &mut fills, None),
-            Side::Ask => self.match_with_bids(id, qty, &mut fills, None),
-        };
-        self.finalize_execution(&fills);
-        let partial = remaining_qty > 0;
-        (fills, partial, qty - remaining_qty)
+    /// Toggle the stats tracking on or off, depending on the `track` parameter.
+    pub fn track_stats(&mut self, track: bool) {
+        self.track_stats = track;
     }
-    
 
-    fn postonlyslide(&mut self, id: u64, user_id: u64, side: Side, qty: u64, price: u64) -> (Vec<FillMetadata>, bool, u64) {
-        let partial= false;
-        let remaining_qty;
-        let fills: Vec<FillMetadata> = Vec::new();
+    /// Replace the clock used to timestamp resting orders and to expire
+    /// good-till-time orders. The default clock is a [`ManualClock`] stuck at
+    /// zero, which keeps the book deterministic until a clock is provided.
+    ///
+    /// [`ManualClock`]: struct.ManualClock.html
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
 
-        match side {
-            Side::Bid => {
-                let insert_price = if price < self.min_ask {
-                    price
-                } else {
-                    self.min_ask - 1u64
-                };
-                let queue_capacity = self.default_queue_capacity;
-                self.arena.insert(id, user_id, insert_price, qty);
-                self.bids
-                    .entry(insert_price)
-                    .or_insert_with(|| Vec::with_capacity(queue_capacity))
-                    .push(id);
-                if insert_price > self.max_bid {
-                    self.max_bid = price;
-                }
-                remaining_qty = qty
-            }
-            Side::Ask => {
-                let insert_price = if price > self.max_bid {
-                    price
-                } else {
-                    self.max_bid + 1u64
-                };
-                let queue_capacity = self.default_queue_capacity;
-                self.arena.insert(id, user_id, insert_price, qty);
-                self.asks
-                    .entry(insert_price)
-                    .or_insert_with(|| Vec::with_capacity(queue_capacity))
-                    .push(id);
-                if insert_price < self.min_ask {
-                    self.min_ask = price;
-                }
-                remaining_qty = qty
-            }
-        }
-        (fills, partial, qty - remaining_qty)
+    /// Return the side and the state of the resting order `id`.
+    pub fn resting_order(&self, id: OrderId) -> Option<(Side, &LimitOrder)> {
+        Some((self.arena.side(id)?, self.arena.get(id)?))
     }
 
-    fn postonly(&mut self, id: u64, user_id: u64, side: Side, qty: u64, price: u64) -> (Vec<FillMetadata>, bool, u64) {
-        let partial= false;
-        let remaining_qty;
-        let fills: Vec<FillMetadata> = Vec::new();
+    /// Return the side and the state of every resting order of `user_id`, in
+    /// order ID order.
+    pub fn open_orders(&self, user_id: UserId) -> Vec<(Side, &LimitOrder)> {
+        self.arena.user_orders(user_id).into_iter().filter_map(|id| self.resting_order(id)).collect()
+    }
 
-        match side {
-            Side::Bid => {
-                let filled = if price < self.min_ask {
-                    let queue_capacity = self.default_queue_capacity;
-                    self.arena.insert(id, user_id, price, qty);
-                    self.bids
-                        .entry(price)
-                        .or_insert_with(|| Vec::with_capacity(queue_capacity))
-                        .push(id);
-                    if price > self.max_bid {
-                        self.max_bid = price;
-                    }
-                    0u64
-                } else {
-                    1u64
-                };
-                remaining_qty = qty - filled
+    /// Return the time elapsed since the resting order `id` entered the
+    /// order book, according to the book clock.
+    pub fn order_age(&self, id: OrderId) -> Option<u64> {
+        self.arena
+            .get(id)
+            .map(|order| self.clock.now().saturating_sub(order.created_at))
+    }
+
+    /// Remove every good-till-time order whose expiry time has been reached
+    /// according to the book clock, returning an [`OrderEvent::Expired`] event
+    /// for each of them in expiry order.
+    ///
+    /// [`OrderEvent::Expired`]: enum.OrderEvent.html#variant.Expired
+    pub fn expire_orders(&mut self) -> Vec<OrderEvent> {
+        let now = self.clock.now();
+        let mut events = Vec::new();
+        while let Some(&(expires_at, id)) = self.expiries.first() {
+            if expires_at > now {
+                break;
             }
-            Side::Ask => {
-                let filled = if price > self.max_bid {
-                    let queue_capacity = self.default_queue_capacity;
-                    self.arena.insert(id, user_id, price, qty);
-                    self.asks
-                        .entry(price)
-                        .or_insert_with(|| Vec::with_capacity(queue_capacity))
-                        .push(id);
-                    if price < self.min_ask {
-                        self.min_ask = price;
-                    }
-                    0u64
-                } else {
-                    1u64
-                };
-                remaining_qty = qty - filled
+            self.expiries.pop_first();
+            // Orders filled or canceled before expiring leave stale entries.
+            if self.journaled_cancel(id) {
+                events.push(OrderEvent::Expired { id });
             }
         }
-        (fills, partial, qty - remaining_qty)
+        self.publish_depth();
+        events
     }
 
-    fn ioc(
-        &mut self,
-        id: OrderId,
-        _user_id: UserId,
-        side: Side,
-        qty: u64,
-        price: u64,
-    ) -> (Vec<FillMetadata>, bool, u64) {
-        let partial;
-        let remaining_qty;
-        let mut fills: Vec<FillMetadata> = Vec::new();
+    /// Toggle the per-order fill history on or off, depending on the `track`
+    /// parameter (see [`fills_for`]).
+    ///
+    /// [`fills_for`]: #method.fills_for
+    pub fn track_fills(&mut self, track: bool) {
+        self.track_fills = track;
+    }
 
-        match side {
-            Side::Bid => {
-                remaining_qty = self.match_with_asks(id, qty, &mut fills, Some(price));
-                partial = remaining_qty > 0;
-                if !partial {
-                    self.finalize_execution(&fills);
-                }
-            }
-            Side::Ask => {
-                remaining_qty = self.match_with_bids(id, qty, &mut fills, Some(price));
-                partial = remaining_qty > 0;
-                if !partial {
-                    self.finalize_execution(&fills);
-                }
-            }
-        }
+    /// Return the fills recorded for the order `id` while the fill history
+    /// was active, in the order they happened.
+    pub fn fills_for(&self, id: OrderId) -> &[FillRecord] {
+        self.fill_history.get(&id).map_or(&[], |fills| fills.as_slice())
+    }
 
-        (fills, partial, qty - remaining_qty)
+    /// Toggle the per-user position tracking on or off, depending on the
+    /// `track` parameter (see [`position`]). Positions are kept when tracking
+    /// is turned off, and resume from there when it is turned back on.
+    ///
+    /// [`position`]: #method.position
+    pub fn track_positions(&mut self, track: bool) {
+        self.track_positions = track;
     }
 
-    fn limit(
-        &mut self,
-        id: OrderId,
-        user_id: UserId,
-        side: Side,
-        qty: u64,
-        price: u64,
-    ) -> (Vec<FillMetadata>, bool, u64) {
-        let mut partial = false;
-        let remaining_qty;
-        let mut fills: Vec<FillMetadata> = Vec::new();
+    /// Return the position of `user_id`, accumulated while position tracking
+    /// was active, or a flat position if the user has not traded.
+    pub fn position(&self, user_id: UserId) -> Position {
+        self.positions.get(&user_id).copied().unwrap_or_default()
+    }
 
-        match side {
-            Side::Bid => {
-                remaining_qty = self.match_with_asks(id, qty, &mut fills, Some(price));
-                self.finalize_execution(&fills);
-                if remaining_qty > 0 {
-                    partial = true;
-                    let queue_capacity = self.default_queue_capacity;
-                    //mutation
-                    self.arena.insert(id, user_id, price, remaining_qty);
-                    self.bids
-                        .entry(price)
-                        .or_insert_with(|| Vec::with_capacity(queue_capacity))
-                        .push(id);
-                    if price > self.max_bid {
-                        self.max_bid = price;
-                    }
-                }
-            }
-            Side::Ask => {
-                remaining_qty = self.match_with_bids(id, qty, &mut fills, Some(price));
-                self.finalize_execution(&fills);
-                if remaining_qty > 0 {
-                    partial = true;
-                    self.arena.insert(id, user_id, price, remaining_qty);
-                    let queue_capacity = self.default_queue_capacity;
-                    self.asks
-                        .entry(price)
-                        .or_insert_with(|| Vec::with_capacity(queue_capacity))
-                        .push(id);
-                    if price < self.min_ask {
-                        self.min_ask = price;
-                    }
-                }
-            }
+    /// Toggle the buffering of clearing records on or off, depending on the
+    /// `track` parameter (see [`take_clearing_records`]). Records are
+    /// streamed to the sink given to [`set_clearing_sink`] either way.
+    ///
+    /// [`take_clearing_records`]: #method.take_clearing_records
+    /// [`set_clearing_sink`]: #method.set_clearing_sink
+    pub fn track_clearing(&mut self, track: bool) {
+        self.track_clearing = track;
+        if !track {
+            self.clearing_records.clear();
         }
+    }
 
-        (fills, partial, qty - remaining_qty)
+    /// Return and clear the clearing records buffered since the last call,
+    /// one per trade in the order the trades happened.
+    pub fn take_clearing_records(&mut self) -> Vec<ClearingRecord> {
+        core::mem::take(&mut self.clearing_records)
     }
 
-    fn match_with_asks(
-        &self,
-        id: OrderId,
-        qty: u64,
-        fills: &mut Vec<FillMetadata>,
-        limit_price: Option<u64>,
-    ) -> u64 {
-        let mut remaining_qty = qty;
-        // let mut update_bid_ask = false;
-        for (ask_price, queue) in self.asks.iter() {
-            if queue.is_empty() {
-                continue;
-            }
-            // if (update_bid_ask || self.min_ask == u64::MAX) && !queue.is_empty() {
-            //     self.min_ask = *ask_price;
-            //     update_bid_ask = false;
-            // }
-            if let Some(lp) = limit_price {
-                if lp < *ask_price {
-                    break;
-                }
-            }
-            if remaining_qty == 0 {
-                break;
-            }
-            let filled_qty = Self::simulate_queue_fills(
-                &self.arena,
-                queue,
-                remaining_qty,
-                id,
-                Side::Bid,
-                fills,
-            );
-            // if queue.is_empty() {
-            //     update_bid_ask = true;
-            // }
-            remaining_qty -= filled_qty;
-        }
+    /// Install or remove (with `None`) the sink every clearing record is
+    /// streamed to as the trade happens.
+    pub fn set_clearing_sink(&mut self, sink: Option<Box<dyn ClearingSink>>) {
+        self.clearing_sink = sink;
+    }
 
-        // self.update_min_ask();
-        remaining_qty
+    /// Set the fees charged in the clearing records. Trades are free by
+    /// default.
+    pub fn set_fee_schedule(&mut self, schedule: FeeSchedule) {
+        self.fee_schedule = schedule;
     }
 
-    fn match_with_bids(
-        &self,
-        id: OrderId,
-        qty: Qty,
-        fills: &mut Vec<FillMetadata>,
-        limit_price: Option<Price>,
-    ) -> u64 {
-        let mut remaining_qty = qty;
-        // let mut update_bid_ask = false;
-        for (bid_price, queue) in self.bids.iter().rev() {
-            if queue.is_empty() {
-                continue;
-            }
-            // if (update_bid_ask || self.max_bid == 0) && !queue.is_empty() {
-            //     self.max_bid = *bid_price;
-            //     update_bid_ask = false;
-            // }
-            if let Some(lp) = limit_price {
-                if lp > *bid_price {
-                    break;
-                }
-            }
-            if remaining_qty == 0 {
-                break;
-            }
-            let filled_qty = Self::simulate_queue_fills(
-                &self.arena,
-                queue,
-                remaining_qty,
-                id,
-                Side::Ask,
-                fills,
-            );
-            // if queue.is_empty() {
-            //     update_bid_ask = true;
-            // }
-            remaining_qty -= filled_qty;
-        }
+    /// Return the fees charged in the clearing records.
+    pub fn fee_schedule(&self) -> FeeSchedule {
+        self.fee_schedule
+    }
 
-        // self.update_max_bid();
-        remaining_qty
+    /// Install or remove (with `None`) the per-user rate limiter consulted by
+    /// [`execute`]. Rate limiting is disabled by default. Cancels and reduces
+    /// count against the owner of the referenced order. The limiter measures
+    /// time with its own clock, not the book clock.
+    ///
+    /// [`execute`]: #method.execute
+    pub fn set_rate_limiter(&mut self, limiter: Option<RateLimiter>) {
+        self.rate_limiter = limiter;
     }
 
-    fn update_min_ask(&mut self) {
-        let mut cur_asks = self.asks.iter().filter(|(_, q)| !q.is_empty());
-        self.min_ask = cur_asks.next().map(|(p, _)| *p).unwrap_or(u64::MAX);
+    /// Remember the IDs of the last `capacity` accepted orders, or stop with
+    /// `None`, the default. An order sent again with one of these IDs, e.g.
+    /// after a gateway retry, is acknowledged with an
+    /// [`OrderEvent::Duplicate`] instead of being rejected as out of
+    /// sequence, so upstream can deliver orders at least once. Changing the
+    /// capacity forgets the remembered IDs.
+    ///
+    /// [`OrderEvent::Duplicate`]: enum.OrderEvent.html#variant.Duplicate
+    pub fn set_dedup_window(&mut self, capacity: Option<usize>) {
+        self.dedup_window = capacity.map(DedupWindow::new);
     }
 
-    fn update_max_bid(&mut self) {
-        let mut cur_bids =
-            self.bids.iter().rev().filter(|(_, q)| !q.is_empty());
-        self.max_bid = cur_bids.next().map(|(p, _)| *p).unwrap_or(0u64);
+    /// Return the trading phase of the book, open unless set otherwise.
+    pub fn phase(&self) -> TradingPhase {
+        self.phase
     }
 
-    fn simulate_queue_fills(
-        arena: &OrderArena,
-        opposite_orders: &Vec<OrderId>,
-        remaining_qty: u64,
-        id: u64,
-        side: Side,
-        fills: &mut Vec<FillMetadata>,
-    ) -> u64 {
-        let mut qty_to_fill = remaining_qty;
-        let mut filled_qty = 0;
-        
-        for (_, head_order_id) in opposite_orders.iter().enumerate() {
-            if qty_to_fill == 0 {
+    /// Set the trading phase of the book, returning the events of the
+    /// auction run when the book leaves a call phase. While the book is
+    /// closed, orders other than cancels and reduces are rejected. During
+    /// the pre-open and closing call phases, limit orders rest without being
+    /// matched, even when they cross, and other orders that would trade are
+    /// rejected. Requests for quote and quotes follow the same rules, and
+    /// requests are only executed while the book is open. Resting orders are
+    /// kept across phases.
+    ///
+    /// The change is journaled, and replays apply it. If the journal cannot
+    /// be appended to, the phase is left unchanged.
+    ///
+    /// The auction matches the crossed orders at a single price, the one
+    /// trading the most quantity. Ties go to the price leaving the smallest
+    /// imbalance between the bid and ask quantities at that price, then to
+    /// the price closest to the last trade recorded by the stats tracking,
+    /// then to the lowest price. The crossing bids are reported as the
+    /// takers, in price and time priority, with one event each; their
+    /// remaining quantity keeps resting.
+    pub fn set_phase(&mut self, phase: TradingPhase) -> Vec<OrderEvent> {
+        if phase == self.phase || !self.journal_phase(phase) {
+            return Vec::new();
+        }
+        self.apply_phase(phase)
+    }
+
+    /// Change to the journaled phase `phase`, running the auction of the
+    /// phase the book leaves, if any.
+    fn apply_phase(&mut self, phase: TradingPhase) -> Vec<OrderEvent> {
+        let previous = core::mem::replace(&mut self.phase, phase);
+        if !previous.is_call() {
+            return Vec::new();
+        }
+        let events = self.uncross();
+        self.publish_depth();
+        events
+    }
+
+    /// Match the crossed orders of the book at the auction price, returning
+    /// the event of each crossing bid that traded.
+    fn uncross(&mut self) -> Vec<OrderEvent> {
+        let mut events = Vec::new();
+        let Some(auction_price) = self.auction_price() else {
+            return events;
+        };
+        loop {
+            let best_bid = self.bids.levels().rev().find_map(|(_, queue)| queue.front().copied());
+            let Some(handle) = best_bid else {
+                break;
+            };
+            let bid = self.arena[handle].clone();
+            if self.best_ask().is_none_or(|ask| ask > bid.price) {
                 break;
             }
-            let head_order = &arena[*head_order_id];
-            let traded_price = head_order.price;
-            let available_qty = head_order.qty;
-            if available_qty == 0 {
-                continue;
+            let mut fills = Fills::new();
+            let remaining_qty = self.match_with_asks(bid.id, bid.qty, &mut fills, Some(bid.price));
+            self.finalize_execution(&fills);
+            let filled_qty = bid.qty - remaining_qty;
+            // The bid rests on the book, so its fills are applied to it as
+            // they are to makers.
+            self.finalize_execution(&[FillMetadata {
+                trade_id: 0,
+                taker_id: bid.id,
+                maker_id: bid.id,
+                maker_user_id: bid.user_id,
+                maker_client_order_id: None,
+                qty: filled_qty,
+                price: bid.price,
+                taker_side: Side::Ask,
+                total_fill: remaining_qty == 0,
+                maker_remaining_qty: remaining_qty,
+            }]);
+            for fill in fills.iter_mut() {
+                fill.price = auction_price;
             }
-            let traded_quantity: u64;
-            let filled;
+            let mut event = self.fill_event(bid.id, filled_qty, fills, remaining_qty > 0);
+            self.assign_trade_ids(&mut event);
+            self.record_stats(&event);
+            self.record_fills(&event);
+            self.record_positions(Some(bid.user_id), &event);
+            self.record_clearing(Some(bid.user_id), &event);
+            self.record_tape(Some(bid.user_id), &event);
+            events.push(event);
+        }
+        events
+    }
 
-            if qty_to_fill >= available_qty {
-                traded_quantity = available_qty;
-                qty_to_fill -= available_qty;
-                filled = true;
-            } else {
-                traded_quantity = qty_to_fill;
-                qty_to_fill = 0;
-                filled = false;
+    /// Return the price of the auction uncrossing the book, or `None` when
+    /// the book is not crossed. See [`set_phase`].
+    ///
+    /// [`set_phase`]: #method.set_phase
+    fn auction_price(&self) -> Option<Price> {
+        // Matching the levels best first finds the last pair of levels that
+        // trades. Every price between them trades the most quantity, and no
+        // other price does.
+        let mut bids = self.bid_sizes.iter().rev().map(|(price, qty)| (*price, *qty));
+        let mut asks = self.ask_sizes.iter().map(|(price, qty)| (*price, *qty));
+        let (mut bid, mut ask) = (bids.next()?, asks.next()?);
+        if bid.0 < ask.0 {
+            return None;
+        }
+        let (mut low, mut high) = (ask.0, bid.0);
+        while bid.0 >= ask.0 {
+            (low, high) = (ask.0, bid.0);
+            let qty = bid.1.min(ask.1);
+            bid.1 -= qty;
+            ask.1 -= qty;
+            if bid.1 == 0 {
+                let Some(next) = bids.next() else { break };
+                bid = next;
+            }
+            if ask.1 == 0 {
+                let Some(next) = asks.next() else { break };
+                ask = next;
             }
-            let fill = FillMetadata {
-                taker_id: id,
-                maker_id: head_order.id,
-                qty: traded_quantity,
-                price: traded_price,
-                taker_side: side,
-                total_fill: filled,
-            };
-            fills.push(fill);
-            filled_qty += traded_quantity;
         }
-        filled_qty
+        let reference = self.last_trade.map(|trade| trade.last_price);
+        let candidates = self.bid_sizes.range(low..=high).chain(self.ask_sizes.range(low..=high)).map(|(price, _)| *price);
+        candidates.min_by_key(|&price| {
+            let demand = self.bid_sizes.range(price..).map(|(_, qty)| *qty).fold(0, Qty::saturating_add);
+            let supply = self.ask_sizes.range(..=price).map(|(_, qty)| *qty).fold(0, Qty::saturating_add);
+            (demand.abs_diff(supply), reference.map(|reference| reference.abs_diff(price)), price)
+        })
     }
 
-   
-}
+    /// Toggle the recording of sequence gaps on or off, depending on the
+    /// `track` parameter (see [`gaps`]). A gap is found when the ID of an
+    /// order skips values after the highest ID received so far, including
+    /// orders rejected before reaching the book; the order is still accepted.
+    /// Gaps are reported to the sink given to [`set_gap_sink`] either way.
+    ///
+    /// Gaps only serve downstream consumers, e.g. a replica requesting the
+    /// missing orders from its source: the book itself never closes them.
+    /// An order later arriving with a missing ID is still below the highest
+    /// ID received and is rejected with `InvalidSequence`, and a recorded gap
+    /// stays recorded until it is taken.
+    ///
+    /// [`gaps`]: #method.gaps
+    /// [`set_gap_sink`]: #method.set_gap_sink
+    pub fn track_gaps(&mut self, track: bool) {
+        self.track_gaps = track;
+        if !track {
+            self.gaps.clear();
+        }
+    }
 
-#[cfg(test)]
-mod test {
-    use crate::{
-        BookDepth, BookLevel, FillMetadata, OrderBook, OrderEvent, OrderType,
-        Side, Trade, rejectmessages::LIQUIDITY_NOT_AVAILABLE, models::LimitOrder,
-    };
-    use std::collections::BTreeMap;
+    /// Return the sequence gaps recorded since tracking started or the last
+    /// call to [`take_gaps`], oldest first.
+    ///
+    /// [`take_gaps`]: #method.take_gaps
+    pub fn gaps(&self) -> &[SequenceGap] {
+        &self.gaps
+    }
 
-    const DEFAULT_QUEUE_SIZE: usize = 10;
-    const BID_ASK_COMBINATIONS: [(Side, Side); 2] =
-        [(Side::Bid, Side::Ask), (Side::Ask, Side::Bid)];
+    /// Return and clear the recorded sequence gaps, e.g. once they have been
+    /// requested again.
+    pub fn take_gaps(&mut self) -> Vec<SequenceGap> {
+        core::mem::take(&mut self.gaps)
+    }
 
-    // In general, floating point values cannot be compared for equality. That's
-    // why we don't derive PartialEq in lobster::models, but we do it here for
-    // our tests in some very specific cases.
-    impl PartialEq for Trade {
-        fn eq(&self, other: &Self) -> bool {
-            self.total_qty == other.total_qty
-                && (self.avg_price - other.avg_price).abs() < 1.0e-6
-                && self.last_qty == other.last_qty
-                && self.last_price == other.last_price
+    /// Install or remove (with `None`) the sink every sequence gap is
+    /// reported to as it is found.
+    pub fn set_gap_sink(&mut self, sink: Option<Box<dyn GapSink>>) {
+        self.gap_sink = sink;
+    }
+
+    /// Note the receipt of the sequenced order `id`, recording the IDs it
+    /// skips. IDs before the first order of the book are not missing.
+    fn record_gap(&mut self, id: OrderId) {
+        let last_seen = self.last_seen_order_id;
+        self.last_seen_order_id = last_seen.max(id);
+        if last_seen == 0 || id <= last_seen + 1 {
+            return;
+        }
+        let gap = SequenceGap { first: last_seen + 1, last: id - 1 };
+        if let Some(sink) = self.gap_sink.as_mut() {
+            sink.report(gap);
+        }
+        if self.track_gaps {
+            self.gaps.push(gap);
         }
     }
 
-    fn init_ob(events: Vec<OrderType>) -> (OrderBook, Vec<OrderEvent>) {
-        let mut ob = OrderBook::default();
-        ob.track_stats(true);
-        let mut results = Vec::new();
-        for e in events {
-            results.push(ob.execute(e));
+    /// Set or remove (with `None`) the pre-trade risk limits of `user_id`
+    /// consulted by [`execute`]. Users have no limits by default. New limits
+    /// apply to the next orders of the user; resting orders are left alone,
+    /// even when they are beyond the limits.
+    ///
+    /// [`execute`]: #method.execute
+    pub fn set_risk_limits(&mut self, user_id: UserId, limits: Option<RiskLimits>) {
+        match limits {
+            Some(limits) => self.risk_limits.insert(user_id, limits),
+            None => self.risk_limits.remove(&user_id),
+        };
+    }
+
+    /// Return the pre-trade risk limits of `user_id`, if any.
+    pub fn risk_limits(&self, user_id: UserId) -> Option<RiskLimits> {
+        self.risk_limits.get(&user_id).copied()
+    }
+
+    /// Return the reason to reject `order` under the risk limits of its
+    /// user, if any.
+    fn check_risk_limits(&self, order: &OrderType) -> Option<RejectReason> {
+        let user_id = order.get_user_id()?;
+        let limits = self.risk_limits.get(&user_id)?;
+        let (qty, notional) = match *order {
+            OrderType::Market { side, qty, .. } => (qty, self.sweep_notional(side, qty)),
+            OrderType::Limit { qty, price, .. }
+            | OrderType::IOC { qty, price, .. }
+            | OrderType::FOK { qty, price, .. }
+            | OrderType::Postonly { qty, price, .. }
+            | OrderType::PostonlySlide { qty, price, .. }
+            | OrderType::GTT { qty, price, .. } => (qty, abs_notional(price, qty)),
+            OrderType::Cancel { .. } | OrderType::Reduce { .. } | OrderType::Cross { .. } => return None,
+        };
+        let open = self.resting_level(order).map(|_| {
+            let orders = self.arena.user_orders(user_id);
+            let open_notional = orders
+                .iter()
+                .filter_map(|id| self.arena.get(*id))
+                .fold(0u128, |sum, order| sum.saturating_add(abs_notional(order.price, order.qty)));
+            (orders.len(), open_notional)
+        });
+        limits.check(qty, notional, open)
+    }
+
+    /// Return the notional a market order of `qty` on `side` would trade,
+    /// sweeping the opposite side of the book.
+    fn sweep_notional(&self, side: Side, qty: Qty) -> u128 {
+        match side {
+            Side::Bid => sweep_notional(self.iter_ask_qtys(), qty),
+            Side::Ask => sweep_notional(self.iter_bid_qtys(), qty),
         }
-        (ob, results)
     }
 
-    fn _init_book(orders: Vec<(u64, u64)>) -> BTreeMap<u64, Vec<u64>> {
-        let mut bk = BTreeMap::new();
-        for (p, i) in orders {
-            bk.entry(p)
-                .or_insert_with(|| Vec::with_capacity(DEFAULT_QUEUE_SIZE))
-                .push(i);
+    /// Install or remove (with `None`) bounds on the resting orders. Orders
+    /// that would cross the book are never rejected by the limit; when their
+    /// remainder takes the book beyond it, the farthest orders are evicted
+    /// whatever the policy. Orders beyond a new limit are evicted right
+    /// away. The change of limit is journaled, but evicted orders are
+    /// canceled without being journaled, since replaying the journal applies
+    /// the same limits and evicts them again. Their [`OrderEvent::Cancelled`]
+    /// events are reported to the sink given to [`set_eviction_sink`], and
+    /// buffered for [`take_evicted`] while [`track_evictions`] is on.
+    ///
+    /// Returns `false`, leaving the limit unchanged, if the journal fails.
+    ///
+    /// [`OrderEvent::Cancelled`]: enum.OrderEvent.html#variant.Cancelled
+    /// [`set_eviction_sink`]: #method.set_eviction_sink
+    /// [`take_evicted`]: #method.take_evicted
+    /// [`track_evictions`]: #method.track_evictions
+    pub fn set_depth_limit(&mut self, limit: Option<DepthLimit>) -> bool {
+        let journaled = limit.unwrap_or(DepthLimit { max_levels: None, max_orders: None, policy: DepthPolicy::Reject });
+        if !self.append_journal(|sequence, timestamp| JournalEntry { sequence, timestamp, depth_limit: Some(journaled), ..Default::default() }) {
+            return false;
         }
-        bk
+        self.apply_depth_limit(limit);
+        true
     }
 
-    fn init_book(orders: Vec<(u64, u64)>) -> Vec<(u64, Vec<u64>)> {
-        _init_book(orders).into_iter().collect()
+    fn apply_depth_limit(&mut self, limit: Option<DepthLimit>) {
+        self.depth_limit = limit.filter(DepthLimit::is_bounded);
+        self.evict_beyond_depth_limit();
+        self.publish_depth();
+    }
+
+    /// Set the decimal scale of the instrument, converting its decimal prices
+    /// and quantities to the ticks and units of the book. The book itself only
+    /// deals in integers; the default scale has no fractional digits.
+    pub fn set_decimal_scale(&mut self, scale: DecimalScale) {
+        self.decimal_scale = scale;
+    }
+
+    /// The decimal scale of the instrument, see [`set_decimal_scale`].
+    ///
+    /// [`set_decimal_scale`]: #method.set_decimal_scale
+    pub fn decimal_scale(&self) -> DecimalScale {
+        self.decimal_scale
     }
 
-    fn init_book_holes(
-        orders: Vec<(u64, u64)>,
-        holes: Vec<u64>,
-    ) -> Vec<(u64, Vec<u64>)> {
-        let mut bk = _init_book(orders);
-        for h in holes {
-            bk.insert(h, Vec::new());
+    /// Toggle the buffering of the events of evicted orders on or off,
+    /// depending on the `track` parameter (see [`take_evicted`]). Events are
+    /// reported to the sink given to [`set_eviction_sink`] either way.
+    ///
+    /// [`take_evicted`]: #method.take_evicted
+    /// [`set_eviction_sink`]: #method.set_eviction_sink
+    pub fn track_evictions(&mut self, track: bool) {
+        self.track_evictions = track;
+        if !track {
+            self.evicted.clear();
         }
-        bk.into_iter().collect()
     }
 
-    #[test]
-    fn empty_book() {
-        let (ob, results) = init_ob(Vec::new());
-        assert_eq!(results, Vec::new());
-        assert_eq!(ob.min_ask(), u64::MAX);
-        assert_eq!(ob.max_bid(), 0u64);
-        assert_eq!(ob._asks(), Vec::new());
-        assert_eq!(ob._bids(), Vec::new());
-        assert_eq!(ob.spread(), u64::MAX);
-        assert_eq!(ob.traded_volume(), 0);
-        assert_eq!(
-            ob.depth(2, false),
-            BookDepth {
-                levels: 2,
-                asks: Vec::new(),
-                bids: Vec::new()
-            }
-        );
-        assert_eq!(ob.last_trade(), None);
+    /// Return and clear the events of the orders evicted by the depth limit
+    /// since the last call, in eviction order.
+    pub fn take_evicted(&mut self) -> Vec<OrderEvent> {
+        core::mem::take(&mut self.evicted)
     }
 
-    #[test]
-    fn one_resting_order() {
-        for (bid_ask, _) in &BID_ASK_COMBINATIONS {
-            let (ob, results) = init_ob(vec![OrderType::Limit {
-                id: 1,
-                user_id: 1,
-                side: *bid_ask,
-                qty: 12,
-                price: 395,
-            }]);
-            assert_eq!(results, vec![OrderEvent::Open { id: 1 }]);
-            if *bid_ask == Side::Bid {
-                assert_eq!(ob.min_ask(), u64::MAX);
-                assert_eq!(ob.max_bid(), 395);
-                assert_eq!(ob._asks(), Vec::new());
-                assert_eq!(ob._bids(), init_book(vec![(395, 1)]));
-                assert_eq!(ob.spread(), u64::MAX - 395);
-                assert_eq!(ob.traded_volume(), 0);
-                assert_eq!(
-                    ob.depth(3, false),
-                    BookDepth {
-                        levels: 3,
-                        asks: Vec::new(),
-                        bids: vec![BookLevel {
-                            price: 395,
-                            qty: 12,
-                            orders: vec![]
-                        }],
+    /// Install or remove (with `None`) the sink the events of evicted orders
+    /// are reported to as they are evicted.
+    pub fn set_eviction_sink(&mut self, sink: Option<Box<dyn EvictionSink>>) {
+        self.eviction_sink = sink;
+    }
+
+    /// Return whether an order resting at `price` on `side` without trading
+    /// is rejected by the depth limit.
+    fn exceeds_depth_limit(&self, side: Side, price: Price) -> bool {
+        let Some(limit) = self.depth_limit.filter(|limit| limit.policy == DepthPolicy::Reject) else {
+            return false;
+        };
+        let (sizes, crosses) = match side {
+            Side::Bid => (&self.bid_sizes, price >= self.min_ask),
+            Side::Ask => (&self.ask_sizes, price <= self.max_bid),
+        };
+        !crosses
+            && (limit.max_levels.is_some_and(|max| !sizes.contains_key(&price) && sizes.len() >= max)
+                || limit.max_orders.is_some_and(|max| self.arena.len() >= max))
+    }
+
+    /// Cancel the resting orders farthest from the touch until the book is
+    /// within the depth limit: whole levels beyond the level limit, then the
+    /// lowest priority order of the farthest level beyond the order limit.
+    fn evict_beyond_depth_limit(&mut self) {
+        let Some(limit) = self.depth_limit else {
+            return;
+        };
+        if let Some(max_levels) = limit.max_levels {
+            for side in [Side::Bid, Side::Ask] {
+                loop {
+                    let (sizes, levels) = match side {
+                        Side::Bid => (&self.bid_sizes, &self.bids),
+                        Side::Ask => (&self.ask_sizes, &self.asks),
+                    };
+                    let farthest = if side == Side::Bid { sizes.keys().next() } else { sizes.keys().next_back() };
+                    let Some(&price) = farthest.filter(|_| sizes.len() > max_levels) else {
+                        break;
+                    };
+                    let ids: Vec<OrderId> = levels.get(price).map_or_else(Vec::new, |queue| queue.iter().map(|handle| handle.id).collect());
+                    if ids.is_empty() {
+                        break;
                     }
-                );
-                assert_eq!(ob.last_trade(), None);
-            } else {
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0u64);
-                assert_eq!(ob._asks(), init_book(vec![(395, 1)]));
-                assert_eq!(ob._bids(), Vec::new());
-                assert_eq!(ob.spread(), 395);
-                assert_eq!(ob.traded_volume(), 0);
-                assert_eq!(
-                    ob.depth(4, false),
-                    BookDepth {
-                        levels: 4,
-                        asks: vec![BookLevel {
-                            price: 395,
-                            qty: 12,
-                            orders: vec![]
-                        }],
-                        bids: Vec::new()
+                    for id in ids {
+                        self.evict(id);
                     }
-                );
-                assert_eq!(ob.last_trade(), None);
+                }
+            }
+        }
+        if let Some(max_orders) = limit.max_orders {
+            while self.arena.len() > max_orders {
+                let far_ask = self.ask_sizes.keys().next_back().map(|price| (price.saturating_sub(self.min_ask), Side::Ask, *price));
+                let far_bid = self.bid_sizes.keys().next().map(|price| (self.max_bid.saturating_sub(*price), Side::Bid, *price));
+                let Some((_, side, price)) = far_ask.into_iter().chain(far_bid).max_by_key(|(distance, _, _)| *distance) else {
+                    break;
+                };
+                let levels = if side == Side::Bid { &self.bids } else { &self.asks };
+                match levels.get(price).and_then(|queue| queue.back()) {
+                    Some(handle) => self.evict(handle.id),
+                    None => break,
+                }
             }
         }
     }
 
-    #[test]
-    fn two_resting_orders() {
-        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *ask_bid,
-                    qty: 2,
-                    price: 398,
-                },
-            ]);
-            if *bid_ask == Side::Bid {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Open { id: 2 }
-                    ]
-                );
-                assert_eq!(ob.min_ask(), 398);
-                assert_eq!(ob.max_bid(), 395);
-                assert_eq!(ob._asks(), init_book(vec![(398, 2)]));
-                assert_eq!(ob._bids(), init_book(vec![(395, 1)]));
-                assert_eq!(ob.spread(), 3);
-                assert_eq!(ob.traded_volume(), 0);
-                assert_eq!(
-                    ob.depth(4, false),
-                    BookDepth {
-                        levels: 4,
-                        asks: vec![BookLevel { price: 398, qty: 2, orders: vec![] }],
-                        bids: vec![BookLevel {
-                            price: 395,
-                            qty: 12,
-                            orders: vec![]
-                        }],
-                    }
-                );
-                assert_eq!(ob.last_trade(), None);
-            } else {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Filled {
-                            id: 2,
-                            filled_qty: 2,
-                            fills: vec![FillMetadata {
-                                taker_id: 2,
-                                maker_id: 1,
-                                qty: 2,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: false,
-                            }],
-                        }
-                    ]
-                );
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0u64);
-                assert_eq!(ob._asks(), init_book(vec![(395, 1)]));
-                assert_eq!(ob._bids(), init_book(vec![]));
-                assert_eq!(ob.spread(), 395);
-                assert_eq!(ob.traded_volume(), 2);
-                assert_eq!(
-                    ob.depth(4, false),
-                    BookDepth {
-                        levels: 4,
-                        asks: vec![BookLevel {
-                            price: 395,
-                            qty: 10,
-                            orders: vec![]
-                        }],
-                        bids: Vec::new(),
-                    }
-                );
-                assert_eq!(
-                    ob.last_trade(),
-                    Some(Trade {
-                        total_qty: 2,
-                        avg_price: 395.0,
-                        last_qty: 2,
-                        last_price: 395,
-                    })
-                );
+    fn evict(&mut self, id: OrderId) {
+        if !self.cancel(id) {
+            return;
+        }
+        let event = OrderEvent::Cancelled { id };
+        if let Some(sink) = self.eviction_sink.as_mut() {
+            sink.report(&event);
+        }
+        if self.track_evictions {
+            self.evicted.push(event);
+        }
+    }
+
+    /// Toggle the recording of incremental depth updates on or off, depending
+    /// on the `track` parameter (see [`take_deltas`]).
+    ///
+    /// [`take_deltas`]: #method.take_deltas
+    pub fn track_deltas(&mut self, track: bool) {
+        self.track_deltas = track;
+        if !track {
+            self.touched_levels.clear();
+        }
+    }
+
+    /// Return the price levels changed since the previous call, each with its
+    /// new aggregate quantity (zero when the level is gone) and the reason of
+    /// its last change. A level changed several times is reported once.
+    pub fn take_deltas(&mut self) -> Vec<BookDelta> {
+        let mut deltas: Vec<BookDelta> = Vec::new();
+        let mut positions: HashMap<(bool, Price), usize> = HashMap::new();
+        for (side, price, reason) in core::mem::take(&mut self.touched_levels) {
+            match positions.get(&(side == Side::Bid, price)) {
+                Some(&position) => deltas[position].reason = reason,
+                None => {
+                    positions.insert((side == Side::Bid, price), deltas.len());
+                    let qty = self.level_qty(side, price);
+                    deltas.push(BookDelta { side, price, qty, reason });
+                }
             }
         }
+        deltas
     }
 
-    #[test]
-    fn two_resting_orders_merged() {
-        for (bid_ask, _) in &BID_ASK_COMBINATIONS {
-            let (ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *bid_ask,
-                    qty: 2,
-                    price: 395,
-                },
-            ]);
-            assert_eq!(
-                results,
-                vec![
-                    OrderEvent::Open { id: 1 },
-                    OrderEvent::Open { id: 2 }
-                ]
-            );
-            if *bid_ask == Side::Bid {
-                assert_eq!(ob.min_ask(), u64::MAX);
-                assert_eq!(ob.max_bid(), 395);
-                assert_eq!(ob._asks(), Vec::new());
-                assert_eq!(
-                    ob._bids(),
-                    init_book(vec![(395, 1), (395, 2)])
-                );
-                assert_eq!(ob.spread(), u64::MAX-395);
-                assert_eq!(ob.traded_volume(), 0);
-                assert_eq!(
-                    ob.depth(3, false),
-                    BookDepth {
-                        levels: 3,
-                        asks: Vec::new(),
-                        bids: vec![BookLevel {
-                            price: 395,
-                            qty: 14,
-                            orders: vec![]
-                        }],
-                    }
-                );
-                assert_eq!(ob.last_trade(), None);
-            } else {
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0u64);
-                assert_eq!(
-                    ob._asks(),
-                    init_book(vec![(395, 1), (395, 2)])
-                );
-                assert_eq!(ob._bids(), Vec::new());
-                assert_eq!(ob.spread(), 395);
+    /// Toggle the recording of the market-by-order feed on or off, depending
+    /// on the `track` parameter (see [`take_order_updates`]).
+    ///
+    /// [`take_order_updates`]: #method.take_order_updates
+    pub fn track_order_updates(&mut self, track: bool) {
+        self.track_order_updates = track;
+        if !track {
+            self.order_updates.clear();
+        }
+    }
+
+    /// Return every change of an individual resting order since the previous
+    /// call, in the order they happened. Incoming orders that never rest on
+    /// the book only show up through the updates of the makers they hit.
+    pub fn take_order_updates(&mut self) -> Vec<OrderUpdate> {
+        core::mem::take(&mut self.order_updates)
+    }
+
+    /// Install or remove (with `None`) the clock timing each execution for
+    /// the [`metrics`]. Use a [`SystemClock`] to measure nanoseconds per
+    /// order; executions are not timed by default.
+    ///
+    /// [`metrics`]: #method.metrics
+    /// [`SystemClock`]: struct.SystemClock.html
+    pub fn set_metrics_clock(&mut self, clock: Option<Box<dyn Clock>>) {
+        self.metrics_clock = clock;
+    }
+
+    /// Return the throughput counters accumulated since the book was created
+    /// or the counters were reset.
+    pub fn metrics(&self) -> BookMetrics {
+        self.metrics
+    }
+
+    /// Reset the throughput counters.
+    pub fn reset_metrics(&mut self) {
+        self.metrics = BookMetrics::default();
+    }
+
+    /// Toggle the price validation of reported crosses on or off, depending on
+    /// the `validate` parameter. When enabled, a cross is rejected unless its
+    /// price lies within the current best bid and offer (inclusive).
+    pub fn validate_crosses(&mut self, validate: bool) {
+        self.validate_crosses = validate;
+    }
+
+    /// Execute an order, returning immediately an event indicating the result.
+    pub fn execute(&mut self, event: OrderType) -> OrderEvent {
+        self.execute_order(event, None, None).unwrap_or_else(ExecuteError::into_event)
+    }
+
+    /// Execute an order under an ID assigned by the book, the one following
+    /// the last processed order ID, and return the assigned ID with the
+    /// event. The ID the order carries is ignored, except for cancels and
+    /// reduces, which keep the ID of the order they act on. Assigned IDs are
+    /// journaled like any other, so replicas replaying the journal see the
+    /// same IDs, and orders with external IDs can still be executed in
+    /// between as long as they keep increasing. An order rejected before
+    /// reaching the book does not use up its ID.
+    pub fn execute_auto(&mut self, order: OrderType) -> (OrderId, OrderEvent) {
+        let order = match order {
+            OrderType::Cancel { .. } | OrderType::Reduce { .. } => order,
+            _ => order.with_id(self.last_processed_order_id + 1),
+        };
+        (order.get_id(), self.execute(order))
+    }
+
+    /// Execute an order at `timestamp`, or at the time of the book clock if
+    /// `None`, returning the event stamped with that time. The book clock is
+    /// left alone; the order is journaled, its fills are taped and its
+    /// remainder rests as if the clock read `timestamp`, so replaying the
+    /// journal gives the same times.
+    pub fn execute_at(&mut self, order: OrderType, timestamp: Option<u64>) -> StampedEvent {
+        let timestamp = timestamp.unwrap_or_else(|| self.clock.now());
+        let clock = core::mem::replace(&mut self.clock, Box::new(ManualClock::new(timestamp)));
+        let event = self.execute(order);
+        self.clock = clock;
+        StampedEvent { timestamp, event }
+    }
+
+    /// Execute an order like [`execute`], but return validation failures,
+    /// which `execute` reports as rejections, as an [`ExecuteError`]. Trading
+    /// outcomes, such as a market order finding no liquidity, are still
+    /// events.
+    ///
+    /// [`execute`]: #method.execute
+    /// [`ExecuteError`]: enum.ExecuteError.html
+    pub fn try_execute(&mut self, order: OrderType) -> Result<OrderEvent, ExecuteError> {
+        self.execute_order(order, None, None)
+    }
+
+    /// Execute a burst of orders in sequence, returning their events in the
+    /// same order. Levels emptied by the batch are only pruned once it
+    /// completes, the best prices are taken from the cached BBO meanwhile,
+    /// and a streamed depth is published once at the end, which cuts the
+    /// bookkeeping of replay and backfill workloads. The events are the same
+    /// as executing the orders one by one.
+    pub fn execute_all(&mut self, orders: &[OrderType]) -> Vec<OrderEvent> {
+        self.in_batch = true;
+        let events = orders
+            .iter()
+            .map(|order| self.execute_order(*order, None, None).unwrap_or_else(ExecuteError::into_event))
+            .collect();
+        self.in_batch = false;
+        self.prune_empty_levels();
+        self.publish_depth();
+        events
+    }
+
+    /// Drop the price levels left empty by a batch and refresh the best
+    /// prices from the remaining ones.
+    fn prune_empty_levels(&mut self) {
+        self.asks.remove_empty();
+        self.bids.remove_empty();
+        self.update_min_ask();
+        self.update_max_bid();
+    }
+
+    fn execute_order(
+        &mut self,
+        event: OrderType,
+        client_order_id: Option<ClientOrderId>,
+        session_id: Option<SessionId>,
+    ) -> Result<OrderEvent, ExecuteError> {
+        let started = self.metrics_clock.as_ref().map(|clock| clock.now());
+        let event = self.process_order(event, client_order_id, session_id);
+        self.record_metrics(started, event.as_ref().ok());
+        event
+    }
+
+    fn record_metrics(&mut self, started: Option<u64>, event: Option<&OrderEvent>) {
+        self.metrics.orders_processed += 1;
+        if let Some(OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. }) = event {
+            self.metrics.fills_generated += fills.len() as u64;
+        }
+        if let (Some(started), Some(clock)) = (started, self.metrics_clock.as_ref()) {
+            self.metrics.timed_orders += 1;
+            self.metrics.busy_time += clock.now().saturating_sub(started);
+        }
+    }
+
+    fn process_order(
+        &mut self,
+        event: OrderType,
+        client_order_id: Option<ClientOrderId>,
+        session_id: Option<SessionId>,
+    ) -> Result<OrderEvent, ExecuteError> {
+        if let Some(event) = self.admit(&event)? {
+            return Ok(event);
+        }
+        if !self.journal(event, client_order_id, session_id) {
+            return Err(ExecuteError::JournalUnavailable { id: event.get_id() });
+        }
+        Ok(self.apply(event, client_order_id, session_id))
+    }
+
+    /// Run the checks an order must pass before it is journaled: sequencing,
+    /// price and depth limits, rate limits and risk limits. Return the event
+    /// ending the order early, if any.
+    fn admit(&mut self, event: &OrderType) -> Result<Option<OrderEvent>, ExecuteError> {
+        let event = *event;
+        let order_id = event.get_id();
+        let phase_reason = match self.phase {
+            TradingPhase::Closed if !matches!(event, OrderType::Cancel { .. } | OrderType::Reduce { .. }) => {
+                Some(RejectReason::MarketClosed)
+            }
+            phase if phase.is_call() && self.would_trade(&event) && !matches!(event, OrderType::Limit { .. } | OrderType::GTT { .. }) => {
+                Some(RejectReason::WouldTradeBeforeOpen)
+            }
+            _ => None,
+        };
+        if let Some(message) = phase_reason {
+            return Ok(Some(OrderEvent::Rejected { id: order_id, message }));
+        }
+        let order_type = event.get_type();
+        let sequenced = order_type != "cancel" && order_type != "reduce";
+        
+        // Having order id sequence to only increase is very important which helps in optimizing the order search during cancel.
+        // and helps reconstructing the btreemaps orders from the hashmap 
+        if sequenced {
+            // Acknowledge orders sent again, e.g. by a retrying gateway,
+            // without executing them twice.
+            if self.dedup_window.as_ref().is_some_and(|window| window.contains(order_id)) {
+                return Ok(Some(OrderEvent::Duplicate { id: order_id }));
+            }
+            // Report collisions with live orders explicitly, so they are not
+            // mistaken for a sequencing problem.
+            if self.arena.get(order_id).is_some() {
+                return Err(ExecuteError::DuplicateOrderId { id: order_id });
+            }
+            if self.last_processed_order_id >=  order_id {
+                return Err(ExecuteError::InvalidSequence { id: order_id, last_id: self.last_processed_order_id });
+            }
+            self.record_gap(order_id);
+        }
+
+        if let Some((side, price, qty)) = self.resting_level(&event) {
+            let levels = if side == Side::Bid { &self.bids } else { &self.asks };
+            if !levels.accepts(price) {
+                return Err(ExecuteError::InvalidPrice { id: order_id, price });
+            }
+            if self.level_qty(side, price).checked_add(qty).is_none() {
+                return Err(ExecuteError::QuantityOverflow { id: order_id, price });
+            }
+            if self.exceeds_depth_limit(side, price) {
+                return Ok(Some(OrderEvent::Rejected { id: order_id, message: RejectReason::DepthLimitExceeded }));
+            }
+        }
+
+        if let Some(limiter) = self.rate_limiter.as_mut() {
+            let user_id = event
+                .get_user_id()
+                .or_else(|| self.arena.get(order_id).map(|order| order.user_id));
+            if let Some(user_id) = user_id {
+                if !limiter.allow(user_id) {
+                    return Ok(Some(OrderEvent::Rejected { id: order_id, message: RejectReason::RateLimitExceeded }));
+                }
+            }
+        }
+
+        if let Some(reason) = self.check_risk_limits(&event) {
+            return Ok(Some(OrderEvent::Rejected { id: order_id, message: reason }));
+        }
+        Ok(None)
+    }
+
+    /// Run the checks of [`admit`] that apply to a request for quote or a
+    /// quote with the ID `id` from `user_id`, which neither rest on the book
+    /// nor trade against it: the closed phase, deduplication, sequencing,
+    /// rate limits and the per order risk limits. The notional is only known
+    /// for quotes, at `price`.
+    ///
+    /// [`admit`]: #method.admit
+    fn admit_rfq(&mut self, id: OrderId, user_id: UserId, qty: Qty, price: Option<Price>) -> Result<(), RfqError> {
+        if self.phase == TradingPhase::Closed {
+            return Err(RfqError::Rejected(RejectReason::MarketClosed));
+        }
+        if qty == 0 {
+            return Err(RfqError::InvalidQuantity);
+        }
+        if self.dedup_window.as_ref().is_some_and(|window| window.contains(id)) {
+            return Err(RfqError::Duplicate);
+        }
+        if self.last_processed_order_id >= id {
+            return Err(RfqError::InvalidOrderNumber);
+        }
+        self.record_gap(id);
+        if let Some(limiter) = self.rate_limiter.as_mut() {
+            if !limiter.allow(user_id) {
+                return Err(RfqError::Rejected(RejectReason::RateLimitExceeded));
+            }
+        }
+        let notional = price.map_or(0, |price| abs_notional(price, qty));
+        if let Some(reason) = self.risk_limits.get(&user_id).and_then(|limits| limits.check(qty, notional, None)) {
+            return Err(RfqError::Rejected(reason));
+        }
+        Ok(())
+    }
+
+    /// Record `id` as the last processed ID of the sequence, and remember it
+    /// to acknowledge it as a duplicate if it is sent again.
+    fn mark_processed(&mut self, id: OrderId) {
+        self.last_processed_order_id = id;
+        if let Some(window) = self.dedup_window.as_mut() {
+            window.insert(id);
+        }
+    }
+
+    /// Apply an admitted and journaled order to the book.
+    fn apply(&mut self, event: OrderType, client_order_id: Option<ClientOrderId>, session_id: Option<SessionId>) -> OrderEvent {
+        let order_id = event.get_id();
+        if event.get_type() != "cancel" && event.get_type() != "reduce" {
+            self.mark_processed(order_id);
+        }
+
+        let user_id = event.get_user_id();
+        let mut event = self._execute(event);
+        self.assign_trade_ids(&mut event);
+        self.evict_beyond_depth_limit();
+        if self.arena.get(order_id).is_some() {
+            if let Some(client_order_id) = client_order_id {
+                if self.arena.client_id(order_id).is_none() {
+                    self.arena.set_client_id(order_id, client_order_id);
+                }
+            }
+            if let Some(session_id) = session_id {
+                self.arena.set_session(order_id, session_id);
+            }
+        }
+        self.record_stats(&event);
+        self.record_fills(&event);
+        self.record_positions(user_id, &event);
+        self.record_clearing(user_id, &event);
+        self.record_tape(user_id, &event);
+        self.publish_depth();
+        event
+    }
+
+    /// Return whether `order` could trade against the resting orders. Market
+    /// orders and reported crosses always trade.
+    fn would_trade(&self, order: &OrderType) -> bool {
+        match *order {
+            OrderType::Market { .. } | OrderType::Cross { .. } => true,
+            OrderType::Limit { side, price, .. }
+            | OrderType::IOC { side, price, .. }
+            | OrderType::FOK { side, price, .. }
+            | OrderType::GTT { side, price, .. } => match side {
+                Side::Bid => self.best_ask().is_some_and(|ask| price >= ask),
+                Side::Ask => self.best_bid().is_some_and(|bid| price <= bid),
+            },
+            OrderType::Postonly { .. } | OrderType::PostonlySlide { .. } | OrderType::Cancel { .. } | OrderType::Reduce { .. } => false,
+        }
+    }
+
+    /// Run the checks of [`execute`] on a spread leg, without executing it.
+    /// Return the reason the leg would be rejected, if any.
+    ///
+    /// [`execute`]: #method.execute
+    pub(crate) fn admit_leg(&mut self, order: &OrderType) -> Option<RejectReason> {
+        match self.admit(order) {
+            Ok(None) => None,
+            Ok(Some(OrderEvent::Rejected { message, .. })) => Some(message),
+            Ok(Some(_)) => Some(RejectReason::InvalidSequence),
+            Err(error) => Some(error.reason()),
+        }
+    }
+
+    /// Append an admitted spread leg to the journal, if any. Return whether
+    /// the leg may be applied.
+    pub(crate) fn journal_leg(&mut self, order: OrderType) -> bool {
+        self.journal(order, None, None)
+    }
+
+    /// Execute an admitted and journaled spread leg.
+    pub(crate) fn apply_leg(&mut self, order: OrderType) -> OrderEvent {
+        let started = self.metrics_clock.as_ref().map(|clock| clock.now());
+        let event = self.apply(order, None, None);
+        self.record_metrics(started, Some(&event));
+        event
+    }
+
+    /// Append an order to the journal, if any, ahead of applying it. Return
+    /// whether the order may be applied.
+    fn journal(
+        &mut self,
+        order: OrderType,
+        client_order_id: Option<ClientOrderId>,
+        session_id: Option<SessionId>,
+    ) -> bool {
+        self.append_journal(|sequence, timestamp| JournalEntry {
+            sequence,
+            timestamp,
+            order: Some(order),
+            client_order_id,
+            session_id,
+            ..Default::default()
+        })
+    }
+
+    /// Append a step of the request for quote flow to the journal, if any,
+    /// ahead of applying it. Return whether the step may be applied.
+    fn journal_rfq(&mut self, action: impl FnOnce() -> RfqAction) -> bool {
+        self.append_journal(|sequence, timestamp| JournalEntry { sequence, timestamp, rfq: Some(action()), ..Default::default() })
+    }
+
+    /// Append a change to `phase` to the journal, if any, ahead of applying
+    /// it. Return whether the change may be applied.
+    fn journal_phase(&mut self, phase: TradingPhase) -> bool {
+        self.append_journal(|sequence, timestamp| JournalEntry { sequence, timestamp, phase: Some(phase), ..Default::default() })
+    }
+
+    /// Append the entry built by `entry` from its sequence and timestamp to
+    /// the journal, if any. The entry is only built when there is a journal.
+    /// Return whether the entry may be applied.
+    #[cfg(feature = "std")]
+    fn append_journal(&mut self, entry: impl FnOnce(u64, u64) -> JournalEntry) -> bool {
+        let Some(journal) = self.journal.as_mut() else {
+            return true;
+        };
+        let entry = entry(self.journal_sequence + 1, self.clock.now());
+        if journal.append(&entry).is_err() {
+            return false;
+        }
+        self.journal_sequence = entry.sequence;
+        true
+    }
+
+    /// Without `std` a book has no journal, so every entry may be applied.
+    #[cfg(not(feature = "std"))]
+    fn append_journal(&mut self, _entry: impl FnOnce(u64, u64) -> JournalEntry) -> bool {
+        true
+    }
+
+    /// Cancel the resting order `id` on behalf of an administrative call,
+    /// journaling it as a cancel order.
+    fn journaled_cancel(&mut self, id: OrderId) -> bool {
+        self.arena.get(id).is_some() && self.journal(OrderType::Cancel { id }, None, None) && self.cancel(id)
+    }
+
+    /// Install or remove (with `None`) the journal every accepted order,
+    /// request for quote step and phase change is appended to before being
+    /// applied. If appending fails, the order is rejected; orders removed by
+    /// administrative calls are then left resting. The journal can be
+    /// replayed with [`replay`].
+    ///
+    /// [`replay`]: #method.replay
+    #[cfg(feature = "std")]
+    pub fn set_journal(&mut self, journal: Option<Box<dyn JournalSink>>) {
+        self.journal = journal;
+    }
+
+    /// Return the ID of the last trade of the book, or 0 before the first
+    /// trade. See [`FillMetadata::trade_id`].
+    ///
+    /// [`FillMetadata::trade_id`]: struct.FillMetadata.html#structfield.trade_id
+    pub fn last_trade_id(&self) -> u64 {
+        self.last_trade_id
+    }
+
+    /// Return the sequence of the last entry appended to the journal.
+    pub fn journal_sequence(&self) -> u64 {
+        self.journal_sequence
+    }
+
+    /// Apply the journal entries read from `reader`, as written by a
+    /// [`WriterJournal`], returning the number of entries applied. Stops at
+    /// the first entry that cannot be read.
+    ///
+    /// [`WriterJournal`]: struct.WriterJournal.html
+    #[cfg(all(feature = "std", feature = "serde"))]
+    pub fn replay<R: BufRead>(&mut self, reader: R) -> Result<usize, JournalError> {
+        let mut entries = Vec::new();
+        for entry in read_journal(reader) {
+            entries.push(entry?);
+        }
+        Ok(self.replay_entries(entries))
+    }
+
+    /// Execute the orders read from `reader`, one JSON object per line as
+    /// read by [`read_orders_jsonl`], and return aggregate statistics of their
+    /// events. Stops at the first line that cannot be read, leaving the
+    /// orders before it executed.
+    ///
+    /// [`read_orders_jsonl`]: fn.read_orders_jsonl.html
+    #[cfg(all(feature = "std", feature = "serde"))]
+    pub fn load_jsonl<R: BufRead>(&mut self, reader: R) -> Result<LoadStats, JournalError> {
+        let mut stats = LoadStats::default();
+        for order in read_orders_jsonl(reader) {
+            stats.record(&self.execute(order?));
+        }
+        Ok(stats)
+    }
+
+    /// Apply journal entries to the book, returning the number of entries
+    /// applied. Each order runs at the clock time it was accepted, without
+    /// rate limiting nor journaling, so replaying a journal on an empty book
+    /// configured like the original one rebuilds it identically. Risk limits
+    /// are not checked either, as they may have changed since, and the trades
+    /// are not reported for clearing again, though they take up trade IDs,
+    /// nor are evictions reported again.
+    /// Requests for quote, quotes and request executions are replayed the
+    /// same way. The entries run in the open phase until a journaled phase
+    /// change, which is applied with its auction; without one, the book
+    /// keeps its phase. Journaled depth limits are applied as they come,
+    /// evicting the same orders again.
+    pub fn replay_entries<I: IntoIterator<Item = JournalEntry>>(&mut self, entries: I) -> usize {
+        let clock = ManualClock::default();
+        let previous_clock = core::mem::replace(&mut self.clock, Box::new(clock.clone()));
+        #[cfg(feature = "std")]
+        let journal = self.journal.take();
+        let rate_limiter = self.rate_limiter.take();
+        let risk_limits = core::mem::take(&mut self.risk_limits);
+        let mut phase = Some(core::mem::replace(&mut self.phase, TradingPhase::Open));
+        let clearing_sink = self.clearing_sink.take();
+        let track_clearing = core::mem::replace(&mut self.track_clearing, false);
+        let eviction_sink = self.eviction_sink.take();
+        let track_evictions = core::mem::replace(&mut self.track_evictions, false);
+        let mut applied = 0;
+        for entry in entries {
+            clock.set(entry.timestamp);
+            if let Some(next) = entry.phase.filter(|next| *next != self.phase) {
+                self.apply_phase(next);
+            }
+            phase = phase.filter(|_| entry.phase.is_none());
+            if let Some(limit) = entry.depth_limit {
+                self.apply_depth_limit(Some(limit));
+            }
+            if let Some(order) = entry.order {
+                let _ = self.execute_order(order, entry.client_order_id, entry.session_id);
+            }
+            match entry.rfq {
+                Some(RfqAction::Request { id, user_id, side, qty, makers, deadline }) => {
+                    let _ = self.request_quote(id, user_id, side, qty, makers, deadline);
+                }
+                Some(RfqAction::Quote { rfq_id, id, maker_id, qty, price, now }) => {
+                    let _ = self.submit_quote(rfq_id, id, maker_id, qty, price, now);
+                }
+                Some(RfqAction::Execute { rfq_id }) => {
+                    let _ = self.execute_rfq(rfq_id);
+                }
+                None => {}
+            }
+            self.journal_sequence = entry.sequence;
+            applied += 1;
+        }
+        self.clock = previous_clock;
+        #[cfg(feature = "std")]
+        {
+            self.journal = journal;
+        }
+        self.rate_limiter = rate_limiter;
+        self.risk_limits = risk_limits;
+        if let Some(phase) = phase {
+            self.phase = phase;
+        }
+        self.clearing_sink = clearing_sink;
+        self.track_clearing = track_clearing;
+        self.eviction_sink = eviction_sink;
+        self.track_evictions = track_evictions;
+        applied
+    }
+
+    /// Execute an order carrying the client order ID `client_order_id`. The
+    /// client order ID is echoed back with the resulting event and, while the
+    /// order rests on the book, it can be used with [`cancel_by_client_id`].
+    /// Client order IDs must be unique among resting orders.
+    ///
+    /// [`cancel_by_client_id`]: #method.cancel_by_client_id
+    pub fn execute_with_client_id(
+        &mut self,
+        order: OrderType,
+        client_order_id: ClientOrderId,
+    ) -> ClientOrderEvent {
+        let id = order.get_id();
+        let event = if self.arena.find_by_client_id(client_order_id).is_some() {
+            OrderEvent::Rejected { id, message: RejectReason::DuplicateClientOrderId }
+        } else {
+            self.execute_order(order, Some(client_order_id), None).unwrap_or_else(ExecuteError::into_event)
+        };
+        ClientOrderEvent { client_order_id, event }
+    }
+
+    /// Cancel the resting order carrying the client order ID
+    /// `client_order_id`, returning the event of the cancel, which carries
+    /// the order ID. Return `None`, without executing anything, when no
+    /// resting order carries it.
+    pub fn cancel_by_client_id(&mut self, client_order_id: ClientOrderId) -> Option<ClientOrderEvent> {
+        let id = self.arena.find_by_client_id(client_order_id)?;
+        Some(ClientOrderEvent {
+            client_order_id,
+            event: self.execute(OrderType::Cancel { id }),
+        })
+    }
+
+    /// Execute an order on behalf of the session `session_id`. If the order
+    /// rests on the book, it is pulled by [`cancel_session`] for that session.
+    ///
+    /// [`cancel_session`]: #method.cancel_session
+    pub fn execute_in_session(&mut self, order: OrderType, session_id: SessionId) -> OrderEvent {
+        self.execute_order(order, None, Some(session_id)).unwrap_or_else(ExecuteError::into_event)
+    }
+
+    /// Cancel every resting order placed in the session `session_id`, e.g.
+    /// when the session disconnects. Return a [`OrderEvent::Cancelled`] event
+    /// per order, in order ID order.
+    ///
+    /// [`OrderEvent::Cancelled`]: enum.OrderEvent.html#variant.Cancelled
+    pub fn cancel_session(&mut self, session_id: SessionId) -> Vec<OrderEvent> {
+        let events = self
+            .arena
+            .session_orders(session_id)
+            .into_iter()
+            .filter(|id| self.journaled_cancel(*id))
+            .map(|id| OrderEvent::Cancelled { id })
+            .collect();
+        self.publish_depth();
+        events
+    }
+
+    /// Return the client order ID of the resting order `id`, if it has one.
+    pub fn client_order_id(&self, id: OrderId) -> Option<ClientOrderId> {
+        self.arena.client_id(id)
+    }
+
+    /// Forcibly remove the resting order `id`, e.g. for regulatory or error
+    /// correction reasons. Unlike a user cancel, the resulting
+    /// [`OrderEvent::AdminCancelled`] event carries the given `reason`.
+    ///
+    /// [`OrderEvent::AdminCancelled`]: enum.OrderEvent.html#variant.AdminCancelled
+    pub fn admin_cancel(&mut self, id: OrderId, reason: &str) -> OrderEvent {
+        let event = if self.journaled_cancel(id) {
+            OrderEvent::AdminCancelled { id, reason: reason.to_string() }
+        } else {
+            OrderEvent::CancelRejected { id, message: RejectReason::OrderNotFound }
+        };
+        self.publish_depth();
+        event
+    }
+
+    /// Cancel every resting order on `side` (or on both sides when `None`)
+    /// whose price lies within `price_range`, optionally restricted to the
+    /// orders of `user_id`. Return the IDs of the canceled orders.
+    pub fn cancel_where<R>(
+        &mut self,
+        side: Option<Side>,
+        price_range: R,
+        user_id: Option<UserId>,
+    ) -> Vec<OrderId>
+    where
+        R: RangeBounds<Price> + Clone,
+    {
+        let mut matched = Vec::new();
+        for level_side in [Side::Bid, Side::Ask] {
+            if side.is_some_and(|side| side != level_side) {
+                continue;
+            }
+            let levels = if level_side == Side::Bid { &self.bids } else { &self.asks };
+            for (_, queue) in levels.range(price_range.clone()) {
+                matched.extend(
+                    queue
+                        .iter()
+                        .filter(|handle| user_id.is_none_or(|user_id| self.arena[**handle].user_id == user_id))
+                        .map(|handle| handle.id),
+                );
+            }
+        }
+        let canceled = matched.into_iter().filter(|id| self.journaled_cancel(*id)).collect();
+        self.publish_depth();
+        canceled
+    }
+
+    /// Open a request for quote on behalf of the taker `user_id`. Only the
+    /// users listed in `makers` can respond, and only until `deadline`. The
+    /// request id takes part in the same increasing sequence as order ids,
+    /// and goes through the same deduplication, rate limits and order
+    /// quantity risk limit as orders. Once admitted, the request is journaled
+    /// and held until it is executed by [`execute_rfq`] or
+    /// [`execute_expired_rfqs`].
+    ///
+    /// [`execute_rfq`]: #method.execute_rfq
+    /// [`execute_expired_rfqs`]: #method.execute_expired_rfqs
+    pub fn request_quote(
+        &mut self,
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        qty: Qty,
+        makers: Vec<UserId>,
+        deadline: u64,
+    ) -> Result<(), RfqError> {
+        self.admit_rfq(id, user_id, qty, None)?;
+        if !self.journal_rfq(|| RfqAction::Request { id, user_id, side, qty, makers: makers.clone(), deadline }) {
+            return Err(RfqError::Rejected(RejectReason::JournalUnavailable));
+        }
+        self.mark_processed(id);
+        self.rfqs.insert(id, Rfq::new(id, user_id, side, qty, makers, deadline));
+        Ok(())
+    }
+
+    /// Submit a maker quote with the unique ID `id` for the request `rfq_id`
+    /// at time `now`. Like requests, quotes go through the checks of orders,
+    /// with the risk limits of the maker, and are journaled. Quotes for a zero
+    /// quantity are refused.
+    pub fn submit_quote(
+        &mut self,
+        rfq_id: OrderId,
+        id: OrderId,
+        maker_id: UserId,
+        qty: Qty,
+        price: Price,
+        now: u64,
+    ) -> Result<(), RfqError> {
+        let quote = RfqQuote { id, maker_id, qty, price };
+        self.rfqs.get(&rfq_id).ok_or(RfqError::UnknownRfq)?.check_quote(&quote, now)?;
+        self.admit_rfq(id, maker_id, qty, Some(price))?;
+        if !self.journal_rfq(|| RfqAction::Quote { rfq_id, id, maker_id, qty, price, now }) {
+            return Err(RfqError::Rejected(RejectReason::JournalUnavailable));
+        }
+        let rfq = self.rfqs.get_mut(&rfq_id).ok_or(RfqError::UnknownRfq)?;
+        rfq.add_quote(quote, now)?;
+        self.mark_processed(id);
+        Ok(())
+    }
+
+    /// Close the request `rfq_id` and execute it against the received quotes,
+    /// best price first. The resting orders of the book are not touched. The
+    /// execution is journaled, then the closed request is removed from the
+    /// book. Like orders that would trade, requests are only executed while
+    /// the book is open; they are kept open otherwise.
+    pub fn execute_rfq(&mut self, rfq_id: OrderId) -> Result<OrderEvent, RfqError> {
+        if !self.rfqs.contains_key(&rfq_id) {
+            return Err(RfqError::UnknownRfq);
+        }
+        match self.phase {
+            TradingPhase::Closed => return Err(RfqError::Rejected(RejectReason::MarketClosed)),
+            TradingPhase::PreOpen | TradingPhase::PreClose => {
+                return Err(RfqError::Rejected(RejectReason::WouldTradeBeforeOpen))
+            }
+            TradingPhase::Open => {}
+        }
+        if !self.journal_rfq(|| RfqAction::Execute { rfq_id }) {
+            return Err(RfqError::Rejected(RejectReason::JournalUnavailable));
+        }
+        let rfq = self.rfqs.remove(&rfq_id).ok_or(RfqError::UnknownRfq)?;
+        let mut fills = Fills::new();
+        let mut remaining_qty = rfq.qty;
+        for quote in rfq.ranked_quotes() {
+            if remaining_qty == 0 {
+                break;
+            }
+            let qty = remaining_qty.min(quote.qty);
+            remaining_qty -= qty;
+            fills.push(FillMetadata {
+                trade_id: 0,
+                taker_id: rfq.id,
+                maker_id: quote.id,
+                maker_user_id: quote.maker_id,
+                maker_client_order_id: None,
+                qty,
+                price: quote.price,
+                taker_side: rfq.side,
+                total_fill: qty == quote.qty,
+                maker_remaining_qty: quote.qty - qty,
+            });
+        }
+        let filled_qty = rfq.qty - remaining_qty;
+        let mut event = if fills.is_empty() {
+            OrderEvent::Rejected { id: rfq_id, message: RejectReason::LiquidityNotAvailable }
+        } else if remaining_qty > 0 {
+            OrderEvent::PartiallyFilled { id: rfq_id, filled_qty, remaining_qty: 0, resting_price: None, fills }
+        } else {
+            OrderEvent::Filled { id: rfq_id, filled_qty, remaining_qty: 0, fills }
+        };
+        let user_id = rfq.user_id;
+        self.assign_trade_ids(&mut event);
+        self.record_stats(&event);
+        self.record_fills(&event);
+        self.record_positions(Some(user_id), &event);
+        self.record_clearing(Some(user_id), &event);
+        self.record_tape(Some(user_id), &event);
+        Ok(event)
+    }
+
+    /// Execute every request for quote whose deadline is before `now`, as
+    /// [`execute_rfq`] does, returning their events in request ID order.
+    /// Call it regularly so that requests abandoned by their takers do not
+    /// accumulate. Outside of the open phase, the requests are kept for a
+    /// later call.
+    ///
+    /// [`execute_rfq`]: #method.execute_rfq
+    pub fn execute_expired_rfqs(&mut self, now: u64) -> Vec<OrderEvent> {
+        let mut expired: Vec<OrderId> = self.rfqs.values().filter(|rfq| rfq.deadline < now).map(|rfq| rfq.id).collect();
+        expired.sort_unstable();
+        expired.into_iter().filter_map(|id| self.execute_rfq(id).ok()).collect()
+    }
+
+    /// Return the open request for quote with the given id, if present.
+    pub fn rfq(&self, id: OrderId) -> Option<&Rfq> {
+        self.rfqs.get(&id)
+    }
+
+    /// Apply a change of the resting orders to the level sizes and feed it to
+    /// the enabled update trackers. Modifications and deletions must be
+    /// recorded while the arena still holds the previous order quantity.
+    fn record(&mut self, update: OrderUpdate) {
+        let sizes = if update.side() == Side::Bid { &mut self.bid_sizes } else { &mut self.ask_sizes };
+        match update {
+            OrderUpdate::Add { price, qty, .. } => *sizes.entry(price).or_default() += qty,
+            OrderUpdate::Modify { id, price, qty, .. } => {
+                Self::shrink_level(sizes, price, self.arena[id].qty);
+                *sizes.entry(price).or_default() += qty;
+            }
+            OrderUpdate::Delete { id, price, .. } => Self::shrink_level(sizes, price, self.arena[id].qty),
+            OrderUpdate::Execute { price, qty, .. } => Self::shrink_level(sizes, price, qty),
+        }
+        self.refresh_bbo(update.side());
+        if self.track_deltas {
+            self.touched_levels.push((update.side(), update.price(), update.reason()));
+        }
+        if self.track_order_updates {
+            self.order_updates.push(update);
+        }
+    }
+
+    fn record_add(&mut self, side: Side, id: OrderId) {
+        let order = &self.arena[id];
+        let (price, qty) = (order.price, order.qty);
+        self.record(OrderUpdate::Add { id, side, price, qty });
+    }
+
+    fn shrink_level(sizes: &mut BTreeMap<Price, Qty>, price: Price, qty: Qty) {
+        if let Some(size) = sizes.get_mut(&price) {
+            *size -= qty;
+            if *size == 0 {
+                sizes.remove(&price);
+            }
+        }
+    }
+
+    fn level_qty(&self, side: Side, price: Price) -> Qty {
+        let sizes = if side == Side::Bid { &self.bid_sizes } else { &self.ask_sizes };
+        sizes.get(&price).copied().unwrap_or(0)
+    }
+
+    /// Return the best price and its aggregate quantity on `side`, if any.
+    fn best_level(&self, side: Side) -> Option<(Price, Qty)> {
+        match side {
+            Side::Bid => self.bbo.bid,
+            Side::Ask => self.bbo.ask,
+        }
+    }
+
+    fn refresh_bbo(&mut self, side: Side) {
+        match side {
+            Side::Bid => self.bbo.bid = self.bid_sizes.last_key_value().map(|(price, qty)| (*price, *qty)),
+            Side::Ask => self.bbo.ask = self.ask_sizes.first_key_value().map(|(price, qty)| (*price, *qty)),
+        }
+    }
+
+    fn record_fills(&mut self, event: &OrderEvent) {
+        if !self.track_fills {
+            return;
+        }
+        let fills = match event {
+            OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } => fills,
+            _ => return,
+        };
+        for fill in fills {
+            self.fill_sequence += 1;
+            let record = FillRecord {
+                counterparty_id: fill.maker_id,
+                qty: fill.qty,
+                price: fill.price,
+                sequence: self.fill_sequence,
+            };
+            self.fill_history.entry(fill.taker_id).or_default().push(record);
+            self.fill_history
+                .entry(fill.maker_id)
+                .or_default()
+                .push(FillRecord { counterparty_id: fill.taker_id, ..record });
+        }
+    }
+
+    /// Account the fills of `event`, an event of an order of
+    /// `taker_user_id`, or its cross, in the positions of both parties.
+    fn record_positions(&mut self, taker_user_id: Option<UserId>, event: &OrderEvent) {
+        if !self.track_positions {
+            return;
+        }
+        match event {
+            OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } => {
+                for fill in fills {
+                    if let Some(taker_user_id) = taker_user_id {
+                        self.positions.entry(taker_user_id).or_default().record(fill.taker_side, fill.qty, fill.price);
+                    }
+                    self.positions.entry(fill.maker_user_id).or_default().record(!fill.taker_side, fill.qty, fill.price);
+                }
+            }
+            OrderEvent::Traded { buyer_id, seller_id, qty, price, .. } => {
+                self.positions.entry(*buyer_id).or_default().record(Side::Bid, *qty, *price);
+                self.positions.entry(*seller_id).or_default().record(Side::Ask, *qty, *price);
+            }
+            _ => {}
+        }
+    }
+
+    /// Assign the next trade IDs to the fills of `event`, or to its cross.
+    fn assign_trade_ids(&mut self, event: &mut OrderEvent) {
+        match event {
+            OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } => {
+                for fill in fills.iter_mut() {
+                    self.last_trade_id += 1;
+                    fill.trade_id = self.last_trade_id;
+                }
+            }
+            OrderEvent::Traded { .. } => self.last_trade_id += 1,
+            _ => {}
+        }
+    }
+
+    /// Report the fills of `event`, an event of an order of `taker_user_id`,
+    /// or its cross for clearing.
+    fn record_clearing(&mut self, taker_user_id: Option<UserId>, event: &OrderEvent) {
+        let fees = self.fee_schedule;
+        let timestamp = self.clock.now();
+        let mut records = Vec::new();
+        match event {
+            OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } => {
+                for fill in fills {
+                    let taker = (fill.taker_id, taker_user_id.unwrap_or_default(), fees.taker_fee_bps);
+                    let maker = (fill.maker_id, fill.maker_user_id, fees.maker_fee_bps);
+                    let ((buy_order_id, buyer_id, buyer_fee_bps), (sell_order_id, seller_id, seller_fee_bps)) =
+                        if fill.taker_side == Side::Bid { (taker, maker) } else { (maker, taker) };
+                    records.push(ClearingRecord {
+                        trade_id: fill.trade_id,
+                        sequence: 0,
+                        timestamp,
+                        buy_order_id,
+                        sell_order_id,
+                        buyer_id,
+                        seller_id,
+                        qty: fill.qty,
+                        price: fill.price,
+                        aggressor_side: Some(fill.taker_side),
+                        buyer_fee: FeeSchedule::fee(buyer_fee_bps, fill.price, fill.qty),
+                        seller_fee: FeeSchedule::fee(seller_fee_bps, fill.price, fill.qty),
+                    });
+                }
+            }
+            OrderEvent::Traded { id, buyer_id, seller_id, qty, price } => {
+                let fee = FeeSchedule::fee(fees.maker_fee_bps, *price, *qty);
+                // A cross is the last trade of the book.
+                records.push(ClearingRecord {
+                    trade_id: self.last_trade_id,
+                    sequence: 0,
+                    timestamp,
+                    buy_order_id: *id,
+                    sell_order_id: *id,
+                    buyer_id: *buyer_id,
+                    seller_id: *seller_id,
+                    qty: *qty,
+                    price: *price,
+                    aggressor_side: None,
+                    buyer_fee: fee,
+                    seller_fee: fee,
+                });
+            }
+            _ => {}
+        }
+        if !self.track_clearing && self.clearing_sink.is_none() {
+            return;
+        }
+        for mut record in records {
+            self.clearing_sequence += 1;
+            record.sequence = self.clearing_sequence;
+            if let Some(sink) = self.clearing_sink.as_mut() {
+                sink.report(&record);
+            }
+            if self.track_clearing {
+                self.clearing_records.push(record);
+            }
+        }
+    }
+
+    fn record_tape(&mut self, taker_user_id: Option<UserId>, event: &OrderEvent) {
+        let Some(tape) = self.tape.as_mut() else {
+            return;
+        };
+        let timestamp = self.clock.now();
+        match event {
+            OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } => {
+                for fill in fills {
+                    let (taker, maker) = (taker_user_id.unwrap_or_default(), fill.maker_user_id);
+                    let (buyer_id, seller_id) = if fill.taker_side == Side::Bid { (taker, maker) } else { (maker, taker) };
+                    tape.push(TapeEntry {
+                        sequence: 0,
+                        trade_id: fill.trade_id,
+                        timestamp,
+                        taker_id: fill.taker_id,
+                        maker_id: fill.maker_id,
+                        buyer_id,
+                        seller_id,
+                        aggressor_side: Some(fill.taker_side),
+                        qty: fill.qty,
+                        price: fill.price,
+                    });
+                }
+            }
+            OrderEvent::Traded { id, buyer_id, seller_id, qty, price } => tape.push(TapeEntry {
+                sequence: 0,
+                trade_id: self.last_trade_id,
+                timestamp,
+                taker_id: *id,
+                maker_id: *id,
+                buyer_id: *buyer_id,
+                seller_id: *seller_id,
+                aggressor_side: None,
+                qty: *qty,
+                price: *price,
+            }),
+            _ => {}
+        }
+    }
+
+    fn record_stats(&mut self, event: &OrderEvent) {
+        if !self.track_stats {
+            return;
+        }
+
+        match event.clone() {
+            OrderEvent::Filled {
+                filled_qty,
+                fills,
+                ..
+            } => {
+                self.traded_volume = self.traded_volume.saturating_add(filled_qty);
+                // If we are here, fills is not empty, so it's safe to unwrap it
+                let last_fill = fills.last().unwrap();
+                self.last_trade = Some(Trade {
+                    total_qty: filled_qty,
+                    avg_price: fills
+                        .iter()
+                        .map(|fm| notional(fm.price, fm.qty))
+                        .fold(0, Notional::saturating_add) as f64
+                        / (filled_qty as f64),
+                    last_qty: last_fill.qty,
+                    last_price: last_fill.price,
+                });
+            }
+            OrderEvent::PartiallyFilled {
+                filled_qty,
+                fills,
+                ..
+            } => {
+                self.traded_volume = self.traded_volume.saturating_add(filled_qty);
+                // If we are here, fills is not empty, so it's safe to unwrap it
+                let last_fill = fills.last().unwrap();
+                self.last_trade = Some(Trade {
+                    total_qty: filled_qty,
+                    avg_price: fills
+                        .iter()
+                        .map(|fm| notional(fm.price, fm.qty))
+                        .fold(0, Notional::saturating_add) as f64
+                        / (filled_qty as f64),
+                    last_qty: last_fill.qty,
+                    last_price: last_fill.price,
+                });
+            }
+            OrderEvent::Traded {
+                id: _,
+                buyer_id: _,
+                seller_id: _,
+                qty,
+                price,
+            } => {
+                self.traded_volume = self.traded_volume.saturating_add(qty);
+                self.last_trade = Some(Trade {
+                    total_qty: qty,
+                    avg_price: price as f64,
+                    last_qty: qty,
+                    last_price: price,
+                });
+            }
+            _ => {}
+        }
+
+        match event {
+            OrderEvent::Filled { id, fills, .. } | OrderEvent::PartiallyFilled { id, fills, .. } => {
+                for fill in fills {
+                    self.record_print(*id, fill.price, fill.qty);
+                }
+            }
+            OrderEvent::Traded { id, qty, price, .. } => self.record_print(*id, *price, *qty),
+            _ => {}
+        }
+    }
+
+    fn record_print(&mut self, id: OrderId, price: Price, qty: Qty) {
+        let now = self.clock.now();
+        self.trade_window.push(price, qty, now);
+        if let Some(candles) = self.candles.as_mut() {
+            candles.push(id, now, price, qty);
+        }
+    }
+
+    fn _execute(&mut self, event: OrderType) -> OrderEvent {
+        match event {
+            OrderType::Market { id, user_id:_, side, qty } => {
+                let (fills, partial, filled_qty) = self.market(id, side, qty);
+                if fills.is_empty() {
+                    OrderEvent::Rejected { id, message: RejectReason::LiquidityNotAvailable  }
+                } else {
+                    self.fill_event(id, filled_qty, fills, partial)
+                }
+            }
+            OrderType::Limit { id, user_id, side, qty, price,} => {
+                let (fills, partial, filled_qty) =
+                    self.limit(id, user_id, side, qty, price);
+                if fills.is_empty() {
+                    self.open_event(id, price)
+                } else {
+                    self.fill_event(id, filled_qty, fills, partial)
+                }
+            }
+            OrderType::GTT { id, user_id, side, qty, price, expires_at } => {
+                let event = self._execute(OrderType::Limit { id, user_id, side, qty, price });
+                if self.arena.get(id).is_some() {
+                    self.expiries.insert((expires_at, id));
+                }
+                event
+            }
+            OrderType::Cancel { id } => {
+                if self.cancel(id) {
+                    OrderEvent::Cancelled { id }
+                } else {
+                    OrderEvent::CancelRejected { id, message: RejectReason::OrderNotFound }
+                }
+            }
+            OrderType::Reduce { id, qty_delta } => {
+                match self.arena.get(id).map(|order| order.qty) {
+                    Some(qty) if qty > qty_delta => {
+                        let price = self.arena[id].price;
+                        let side = self.arena.side(id).unwrap_or(Side::Ask);
+                        self.record(OrderUpdate::Modify { id, side, price, qty: qty - qty_delta });
+                        self.arena[id].qty -= qty_delta;
+                        OrderEvent::Reduced { id, qty: qty - qty_delta }
+                    }
+                    Some(_) => {
+                        self.cancel(id);
+                        OrderEvent::Cancelled { id }
+                    }
+                    None => OrderEvent::CancelRejected { id, message: RejectReason::OrderNotFound },
+                }
+            }
+            OrderType::IOC { id, user_id, side, qty, price } => {
+                let (fills, partial, filled_qty) = self.ioc(id, user_id, side, qty, price);
+                if fills.is_empty() {
+                    OrderEvent::Cancelled { id }
+                } else {
+                    self.fill_event(id, filled_qty, fills, partial)
+                }
+            },
+            OrderType::FOK { id, user_id, side, qty, price } =>  {
+                // The level sizes tell whether the order can be filled
+                // without walking the queues.
+                if self.available_qty(side, price) < qty {
+                    OrderEvent::Cancelled { id }
+                } else {
+                    let (fills, partial, filled_qty) = self.ioc(id, user_id, side, qty, price);
+                    debug_assert!(!partial, "FOK order {} filled partially", id);
+                    if fills.is_empty() {
+                        OrderEvent::Cancelled { id }
+                    } else {
+                        self.fill_event(id, filled_qty, fills, false)
+                    }
+                }
+            }
+            OrderType::Postonly { id, user_id, side, qty, price } => {
+                let (_fills, _partial, filled_qty) = self.postonly(id, user_id, side, qty, price);
+                if filled_qty == 0 {
+                    self.open_event(id, price)
+                } else {
+                    OrderEvent::Cancelled { id }
+                }
+            },
+            
+            OrderType::PostonlySlide { id, user_id, side, qty, price } => {
+                let (_fills, _partial, filled_qty) = self.postonlyslide(id, user_id, side, qty, price);
+                if filled_qty == 0 {
+                    self.open_event(id, price)
+                } else {
+                    OrderEvent::Cancelled { id }
+                }
+            },
+            OrderType::Cross { id, buyer_id, seller_id, qty, price } => {
+                if self.validate_crosses && (price < self.max_bid || price > self.min_ask) {
+                    OrderEvent::Rejected { id, message: RejectReason::CrossPriceOutsideBbo }
+                } else {
+                    OrderEvent::Traded { id, buyer_id, seller_id, qty, price }
+                }
+            },
+        }
+    }
+
+    /// Return the event of the order `id` opened at `price`, with the
+    /// quantity and price it rests at.
+    fn open_event(&self, id: OrderId, price: Price) -> OrderEvent {
+        let (remaining_qty, resting_price) = self.arena.get(id).map_or((0, price), |order| (order.qty, order.price));
+        OrderEvent::Open { id, remaining_qty, resting_price }
+    }
+
+    /// Return the event of the order `id` after it traded `fills`, with the
+    /// quantity and price the rest of a `partial` fill rests at, if any.
+    fn fill_event(&self, id: OrderId, filled_qty: Qty, fills: Fills, partial: bool) -> OrderEvent {
+        if !partial {
+            return OrderEvent::Filled { id, filled_qty, remaining_qty: 0, fills };
+        }
+        let resting = self.arena.get(id);
+        OrderEvent::PartiallyFilled {
+            id,
+            filled_qty,
+            remaining_qty: resting.map_or(0, |order| order.qty),
+            resting_price: resting.map(|order| order.price),
+            fills,
+        }
+    }
+
+    fn cancel(&mut self, id: OrderId) -> bool {
+        if let (Some(order), Some(side)) = (self.arena.get(id), self.arena.side(id)) {
+            let price = order.price;
+            let levels = if side == Side::Bid { &mut self.bids } else { &mut self.asks };
+            let (mut removed, mut emptied) = (false, false);
+            if let Some(queue) = levels.get_mut(price) {
+                // Order IDs only increase, so queues are sorted by ID unless
+                // they were restored out of order.
+                let index = queue
+                    .binary_search_by_key(&id, |handle| handle.id)
+                    .ok()
+                    .or_else(|| queue.iter().position(|handle| handle.id == id));
+                if let Some(index) = index {
+                    queue.remove(index);
+                    removed = true;
+                }
+                emptied = queue.is_empty();
+                if emptied && !self.in_batch {
+                    if let Some(queue) = levels.remove(price) {
+                        self.queue_pool.recycle(queue);
+                    }
+                }
+            }
+            if removed {
+                self.record(OrderUpdate::Delete { id, side, price });
+            }
+            // The best prices only move when the best level is emptied.
+            let touch = if side == Side::Bid { self.max_bid } else { self.min_ask };
+            if emptied && price == touch {
+                self.refresh_best_price(side);
+            }
+        }
+        self.arena.delete(&id)
+    }
+
+    fn finalize_execution(&mut self, fills: &[FillMetadata]) {
+        let in_batch = self.in_batch;
+        let mut emptied = false;
+        fills.iter().for_each(|fill| {
+            let maker_id = fill.maker_id;
+            let levels = if !fill.taker_side == Side::Bid { &mut self.bids } else { &mut self.asks };
+            if fill.total_fill {
+                // Fills consume each level from the front of its queue, so a
+                // filled maker is the head of its queue.
+                if let Some(queue) = levels.get_mut(fill.price) {
+                    if queue.front().is_some_and(|handle| handle.id == maker_id) {
+                        queue.pop_front();
+                    } else if let Some(index) = queue.iter().position(|handle| handle.id == maker_id) {
+                        queue.remove(index);
+                    }
+                    if queue.is_empty() {
+                        emptied = true;
+                        if !in_batch {
+                            if let Some(queue) = levels.remove(fill.price) {
+                                self.queue_pool.recycle(queue);
+                            }
+                        }
+                    }
+                }
+                self.arena.delete(&maker_id);
+            } else {
+                self.arena[maker_id].qty -= fill.qty;
+            }
+        });
+        for fill in fills {
+            let remaining_qty = self.arena.get(fill.maker_id).map_or(0, |order| order.qty);
+            self.record(OrderUpdate::Execute {
+                id: fill.maker_id,
+                side: !fill.taker_side,
+                price: fill.price,
+                qty: fill.qty,
+                remaining_qty,
+            });
+        }
+        // Fills walk the levels from the touch, so the best level is emptied
+        // whenever a level is.
+        if let Some(fill) = fills.first().filter(|_| emptied) {
+            self.refresh_best_price(!fill.taker_side);
+        }
+    }
+
+    fn market(
+        &mut self,
+        id: OrderId,
+        side: Side,
+        qty: Qty,
+    ) -> (Fills, bool, Qty) {
+        let mut fills = Fills::new();
+
+        let remaining_qty = match side {
+            Side::Bid => self.match_with_asks(id, qty, &mut fills, None),
+            Side::Ask => self.match_with_bids(id, qty, &mut fills, None),
+        };
+        self.finalize_execution(&fills);
+        let partial = remaining_qty > 0;
+        (fills, partial, qty - remaining_qty)
+    }
+    
+
+    /// Return the price a sliding post-only order rests at: its own price,
+    /// or one tick away from the opposite best price if it would cross.
+    fn slide_price(&self, side: Side, price: Price) -> Price {
+        match side {
+            Side::Bid if price >= self.min_ask => self.min_ask - self.bids.tick_size(),
+            Side::Ask if price <= self.max_bid => self.max_bid + self.asks.tick_size(),
+            _ => price,
+        }
+    }
+
+    /// Return the side and price an order would rest at, for the orders that
+    /// can rest on the book.
+    fn resting_level(&self, order: &OrderType) -> Option<(Side, Price, Qty)> {
+        match *order {
+            OrderType::Limit { side, price, qty, .. }
+            | OrderType::Postonly { side, price, qty, .. }
+            | OrderType::GTT { side, price, qty, .. } => Some((side, price, qty)),
+            OrderType::PostonlySlide { side, price, qty, .. } => Some((side, self.slide_price(side, price), qty)),
+            _ => None,
+        }
+    }
+
+    fn postonlyslide(&mut self, id: u64, user_id: u64, side: Side, qty: Qty, price: Price) -> (Fills, bool, Qty) {
+        let partial= false;
+        let remaining_qty;
+        let fills = Fills::new();
+
+        match side {
+            Side::Bid => {
+                let insert_price = self.slide_price(side, price);
+                let handle = self.arena.insert(id, user_id, side, insert_price, qty, self.clock.now());
+                self.bids
+                    .get_or_insert_with(insert_price, || self.queue_pool.take())
+                    .push_back(handle);
+                self.record_add(Side::Bid, id);
+                if insert_price > self.max_bid {
+                    self.max_bid = insert_price;
+                }
+                remaining_qty = qty
+            }
+            Side::Ask => {
+                let insert_price = self.slide_price(side, price);
+                let handle = self.arena.insert(id, user_id, side, insert_price, qty, self.clock.now());
+                self.asks
+                    .get_or_insert_with(insert_price, || self.queue_pool.take())
+                    .push_back(handle);
+                self.record_add(Side::Ask, id);
+                if insert_price < self.min_ask {
+                    self.min_ask = insert_price;
+                }
+                remaining_qty = qty
+            }
+        }
+        (fills, partial, qty - remaining_qty)
+    }
+
+    fn postonly(&mut self, id: u64, user_id: u64, side: Side, qty: Qty, price: Price) -> (Fills, bool, Qty) {
+        let partial= false;
+        let remaining_qty;
+        let fills = Fills::new();
+
+        match side {
+            Side::Bid => {
+                let filled = if price < self.min_ask {
+                    let handle = self.arena.insert(id, user_id, side, price, qty, self.clock.now());
+                    self.bids
+                        .get_or_insert_with(price, || self.queue_pool.take())
+                        .push_back(handle);
+                    self.record_add(Side::Bid, id);
+                    if price > self.max_bid {
+                        self.max_bid = price;
+                    }
+                    0
+                } else {
+                    1
+                };
+                remaining_qty = qty - filled
+            }
+            Side::Ask => {
+                let filled = if price > self.max_bid {
+                    let handle = self.arena.insert(id, user_id, side, price, qty, self.clock.now());
+                    self.asks
+                        .get_or_insert_with(price, || self.queue_pool.take())
+                        .push_back(handle);
+                    self.record_add(Side::Ask, id);
+                    if price < self.min_ask {
+                        self.min_ask = price;
+                    }
+                    0
+                } else {
+                    1
+                };
+                remaining_qty = qty - filled
+            }
+        }
+        (fills, partial, qty - remaining_qty)
+    }
+
+    fn ioc(
+        &mut self,
+        id: OrderId,
+        _user_id: UserId,
+        side: Side,
+        qty: Qty,
+        price: Price,
+    ) -> (Fills, bool, Qty) {
+        let mut fills = Fills::new();
+        // The remainder is canceled, but the fills happened and are applied
+        // to the makers like any other.
+        let remaining_qty = match side {
+            Side::Bid => self.match_with_asks(id, qty, &mut fills, Some(price)),
+            Side::Ask => self.match_with_bids(id, qty, &mut fills, Some(price)),
+        };
+        self.finalize_execution(&fills);
+        (fills, remaining_qty > 0, qty - remaining_qty)
+    }
+
+    fn limit(
+        &mut self,
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        qty: Qty,
+        price: Price,
+    ) -> (Fills, bool, Qty) {
+        let mut partial = false;
+        let remaining_qty;
+        let mut fills = Fills::new();
+        // Orders are collected for an auction during a call phase.
+        let matching = !self.phase.is_call();
+
+        match side {
+            Side::Bid => {
+                remaining_qty = if matching { self.match_with_asks(id, qty, &mut fills, Some(price)) } else { qty };
+                self.finalize_execution(&fills);
+                if remaining_qty > 0 {
+                    partial = true;
+                    //mutation
+                    let handle = self.arena.insert(id, user_id, side, price, remaining_qty, self.clock.now());
+                    self.bids
+                        .get_or_insert_with(price, || self.queue_pool.take())
+                        .push_back(handle);
+                    self.record_add(Side::Bid, id);
+                    if price > self.max_bid {
+                        self.max_bid = price;
+                    }
+                }
+            }
+            Side::Ask => {
+                remaining_qty = if matching { self.match_with_bids(id, qty, &mut fills, Some(price)) } else { qty };
+                self.finalize_execution(&fills);
+                if remaining_qty > 0 {
+                    partial = true;
+                    let handle = self.arena.insert(id, user_id, side, price, remaining_qty, self.clock.now());
+                    self.asks
+                        .get_or_insert_with(price, || self.queue_pool.take())
+                        .push_back(handle);
+                    self.record_add(Side::Ask, id);
+                    if price < self.min_ask {
+                        self.min_ask = price;
+                    }
+                }
+            }
+        }
+
+        (fills, partial, qty - remaining_qty)
+    }
+
+    /// Simulate matching an order of `qty` on `side` against the resting
+    /// orders without mutating the book. Return the fills that would happen
+    /// and the quantity left unfilled.
+    pub(crate) fn simulate(
+        &self,
+        id: OrderId,
+        side: Side,
+        qty: Qty,
+        limit_price: Option<Price>,
+    ) -> (Fills, Qty) {
+        let mut fills = Fills::new();
+        let remaining_qty = match side {
+            Side::Bid => self.match_with_asks(id, qty, &mut fills, limit_price),
+            Side::Ask => self.match_with_bids(id, qty, &mut fills, limit_price),
+        };
+        (fills, remaining_qty)
+    }
+
+    fn match_with_asks(
+        &self,
+        id: OrderId,
+        qty: Qty,
+        fills: &mut Fills,
+        limit_price: Option<Price>,
+    ) -> Qty {
+        let mut remaining_qty = qty;
+        // let mut update_bid_ask = false;
+        for (ask_price, queue) in self.asks.levels() {
+            if queue.is_empty() {
+                continue;
+            }
+            // if (update_bid_ask || self.min_ask == u64::MAX) && !queue.is_empty() {
+            //     self.min_ask = *ask_price;
+            //     update_bid_ask = false;
+            // }
+            if let Some(lp) = limit_price {
+                if lp < ask_price {
+                    break;
+                }
+            }
+            if remaining_qty == 0 {
+                break;
+            }
+            let filled_qty = Self::simulate_queue_fills(
+                &self.arena,
+                queue,
+                remaining_qty,
+                id,
+                Side::Bid,
+                fills,
+            );
+            // if queue.is_empty() {
+            //     update_bid_ask = true;
+            // }
+            remaining_qty -= filled_qty;
+        }
+
+        // self.update_min_ask();
+        remaining_qty
+    }
+
+    fn match_with_bids(
+        &self,
+        id: OrderId,
+        qty: Qty,
+        fills: &mut Fills,
+        limit_price: Option<Price>,
+    ) -> Qty {
+        let mut remaining_qty = qty;
+        // let mut update_bid_ask = false;
+        for (bid_price, queue) in self.bids.levels().rev() {
+            if queue.is_empty() {
+                continue;
+            }
+            // if (update_bid_ask || self.max_bid == 0) && !queue.is_empty() {
+            //     self.max_bid = *bid_price;
+            //     update_bid_ask = false;
+            // }
+            if let Some(lp) = limit_price {
+                if lp > bid_price {
+                    break;
+                }
+            }
+            if remaining_qty == 0 {
+                break;
+            }
+            let filled_qty = Self::simulate_queue_fills(
+                &self.arena,
+                queue,
+                remaining_qty,
+                id,
+                Side::Ask,
+                fills,
+            );
+            // if queue.is_empty() {
+            //     update_bid_ask = true;
+            // }
+            remaining_qty -= filled_qty;
+        }
+
+        // self.update_max_bid();
+        remaining_qty
+    }
+
+    /// Refresh the best price of `side` after its best level was emptied.
+    /// Within a batch, emptied levels are kept until the end, so the price is
+    /// read from the cached BBO, which is maintained from the level sizes.
+    fn refresh_best_price(&mut self, side: Side) {
+        match (side, self.in_batch) {
+            (Side::Ask, true) => self.min_ask = self.bbo.ask.map_or(Price::MAX, |(price, _)| price),
+            (Side::Bid, true) => self.max_bid = self.bbo.bid.map_or(Price::MIN, |(price, _)| price),
+            (Side::Ask, false) => self.update_min_ask(),
+            (Side::Bid, false) => self.update_max_bid(),
+        }
+    }
+
+    fn update_min_ask(&mut self) {
+        self.min_ask = self.asks.lowest().unwrap_or(Price::MAX);
+    }
+
+    fn update_max_bid(&mut self) {
+        self.max_bid = self.bids.highest().unwrap_or(Price::MIN);
+    }
+
+    fn simulate_queue_fills(
+        arena: &OrderArena,
+        opposite_orders: &PriceQueue,
+        remaining_qty: Qty,
+        id: u64,
+        side: Side,
+        fills: &mut Fills,
+    ) -> Qty {
+        let mut qty_to_fill = remaining_qty;
+        let mut filled_qty = 0;
+        
+        for head_handle in opposite_orders {
+            if qty_to_fill == 0 {
+                break;
+            }
+            let head_order = &arena[*head_handle];
+            let traded_price = head_order.price;
+            let available_qty = head_order.qty;
+            if available_qty == 0 {
+                continue;
+            }
+            let traded_quantity: Qty;
+            let filled;
+
+            if qty_to_fill >= available_qty {
+                traded_quantity = available_qty;
+                qty_to_fill -= available_qty;
+                filled = true;
+            } else {
+                traded_quantity = qty_to_fill;
+                qty_to_fill = 0;
+                filled = false;
+            }
+            let fill = FillMetadata {
+                trade_id: 0,
+                taker_id: id,
+                maker_id: head_order.id,
+                maker_user_id: head_order.user_id,
+                maker_client_order_id: arena.client_id(head_order.id),
+                qty: traded_quantity,
+                price: traded_price,
+                taker_side: side,
+                total_fill: filled,
+                maker_remaining_qty: head_order.qty - traded_quantity,
+            };
+            fills.push(fill);
+            filled_qty += traded_quantity;
+        }
+        filled_qty
+    }
+
+   
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fills, BookDepth, BookLevel, FillMetadata, OrderBook, OrderEvent, OrderType,
+        Price, Qty, Side, Trade, rejectmessages::RejectReason, models::LimitOrder,
+        ExecuteError, RfqError, RfqState, ManualClock, FillRecord, ClientOrderEvent,
+        RateLimiter, Bbo, BookDelta, DeltaReason, OrderUpdate, TapeEntry,
+        PriceLadder, BookMetrics,
+        DepthLimit, DepthPolicy, RiskLimits, Position,
+    };
+    #[cfg(feature = "std")]
+    use crate::{ClearingRecord, FeeSchedule, JournalEntry, JournalSink, MemoryJournal, SequenceGap, StampedEvent};
+    #[cfg(all(feature = "std", feature = "serde"))]
+    use crate::WriterJournal;
+    #[cfg(feature = "std")]
+    use crate::TradingPhase;
+    use std::collections::BTreeMap;
+
+    const DEFAULT_QUEUE_SIZE: usize = 10;
+    const BID_ASK_COMBINATIONS: [(Side, Side); 2] =
+        [(Side::Bid, Side::Ask), (Side::Ask, Side::Bid)];
+
+    // In general, floating point values cannot be compared for equality. That's
+    // why we don't derive PartialEq in lobster::models, but we do it here for
+    // our tests in some very specific cases.
+    impl PartialEq for Trade {
+        fn eq(&self, other: &Self) -> bool {
+            self.total_qty == other.total_qty
+                && (self.avg_price - other.avg_price).abs() < 1.0e-6
+                && self.last_qty == other.last_qty
+                && self.last_price == other.last_price
+        }
+    }
+
+    fn init_ob(events: Vec<OrderType>) -> (OrderBook, Vec<OrderEvent>) {
+        let mut ob = OrderBook::default();
+        ob.track_stats(true);
+        let mut results = Vec::new();
+        for e in events {
+            results.push(ob.execute(e));
+        }
+        (ob, results)
+    }
+
+    fn _init_book(orders: Vec<(Price, u64)>) -> BTreeMap<Price, Vec<u64>> {
+        let mut bk = BTreeMap::new();
+        for (p, i) in orders {
+            bk.entry(p)
+                .or_insert_with(|| Vec::with_capacity(DEFAULT_QUEUE_SIZE))
+                .push(i);
+        }
+        bk
+    }
+
+    fn init_book(orders: Vec<(Price, u64)>) -> Vec<(Price, Vec<u64>)> {
+        _init_book(orders).into_iter().collect()
+    }
+
+    /// Return a deterministic generator of numbers below a bound, to script
+    /// long order sequences.
+    fn scripted(mut seed: u64) -> impl FnMut(u64) -> u64 {
+        move |bound| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) % bound
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn empty_book() {
+        let (ob, results) = init_ob(Vec::new());
+        assert_eq!(results, Vec::new());
+        assert_eq!(ob.min_ask(), Price::MAX);
+        assert_eq!(ob.best_ask(), None);
+        assert_eq!(ob.max_bid(), Price::MIN);
+        assert_eq!(ob.best_bid(), None);
+        assert_eq!(ob._asks(), Vec::new());
+        assert_eq!(ob._bids(), Vec::new());
+        assert_eq!(ob.spread(), Price::MAX);
+        assert_eq!(ob.bid_ask_spread(), None);
+        assert_eq!(ob.traded_volume(), 0);
+        assert_eq!(
+            ob.depth(2, false),
+            BookDepth {
+                levels: 2,
+                asks: Vec::new(),
+                bids: Vec::new()
+            }
+        );
+        assert_eq!(ob.last_trade(), None);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn one_resting_order() {
+        for (bid_ask, _) in &BID_ASK_COMBINATIONS {
+            let (ob, results) = init_ob(vec![OrderType::Limit {
+                id: 1,
+                user_id: 1,
+                side: *bid_ask,
+                qty: 12,
+                price: 395,
+            }]);
+            assert_eq!(results, vec![OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 }]);
+            if *bid_ask == Side::Bid {
+                assert_eq!(ob.min_ask(), Price::MAX);
+                assert_eq!(ob.best_ask(), None);
+                assert_eq!(ob.max_bid(), 395);
+                assert_eq!(ob.best_bid(), Some(395));
+                assert_eq!(ob._asks(), Vec::new());
+                assert_eq!(ob._bids(), init_book(vec![(395, 1)]));
+                assert_eq!(ob.spread(), Price::MAX - 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+                assert_eq!(ob.traded_volume(), 0);
+                assert_eq!(
+                    ob.depth(3, false),
+                    BookDepth {
+                        levels: 3,
+                        asks: Vec::new(),
+                        bids: vec![BookLevel {
+                            price: 395,
+                            qty: 12,
+                            cumulative_qty: 12,
+                            cumulative_notional: 4740,
+                            orders: vec![]
+                        }],
+                    }
+                );
+                assert_eq!(ob.last_trade(), None);
+            } else {
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(ob._asks(), init_book(vec![(395, 1)]));
+                assert_eq!(ob._bids(), Vec::new());
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+                assert_eq!(ob.traded_volume(), 0);
+                assert_eq!(
+                    ob.depth(4, false),
+                    BookDepth {
+                        levels: 4,
+                        asks: vec![BookLevel {
+                            price: 395,
+                            qty: 12,
+                            cumulative_qty: 12,
+                            cumulative_notional: 4740,
+                            orders: vec![]
+                        }],
+                        bids: Vec::new()
+                    }
+                );
+                assert_eq!(ob.last_trade(), None);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn two_resting_orders() {
+        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
+            let (ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *ask_bid,
+                    qty: 2,
+                    price: 398,
+                },
+            ]);
+            if *bid_ask == Side::Bid {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(ob.min_ask(), 398);
+                assert_eq!(ob.best_ask(), Some(398));
+                assert_eq!(ob.max_bid(), 395);
+                assert_eq!(ob.best_bid(), Some(395));
+                assert_eq!(ob._asks(), init_book(vec![(398, 2)]));
+                assert_eq!(ob._bids(), init_book(vec![(395, 1)]));
+                assert_eq!(ob.spread(), 3);
+                assert_eq!(ob.bid_ask_spread(), Some(3));
+                assert_eq!(ob.traded_volume(), 0);
+                assert_eq!(
+                    ob.depth(4, false),
+                    BookDepth {
+                        levels: 4,
+                        asks: vec![BookLevel { price: 398, qty: 2, cumulative_qty: 2, cumulative_notional: 796, orders: vec![] }],
+                        bids: vec![BookLevel {
+                            price: 395,
+                            qty: 12,
+                            cumulative_qty: 12,
+                            cumulative_notional: 4740,
+                            orders: vec![]
+                        }],
+                    }
+                );
+                assert_eq!(ob.last_trade(), None);
+            } else {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Filled {
+                            id: 2,
+                            filled_qty: 2,
+                            remaining_qty: 0,
+                            fills: fills![FillMetadata {
+                                trade_id: 1,
+                                taker_id: 2,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: false,
+                                maker_remaining_qty: 10,
+                            }],
+                        }
+                    ]
+                );
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(ob._asks(), init_book(vec![(395, 1)]));
+                assert_eq!(ob._bids(), init_book(vec![]));
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+                assert_eq!(ob.traded_volume(), 2);
+                assert_eq!(
+                    ob.depth(4, false),
+                    BookDepth {
+                        levels: 4,
+                        asks: vec![BookLevel {
+                            price: 395,
+                            qty: 10,
+                            cumulative_qty: 10,
+                            cumulative_notional: 3950,
+                            orders: vec![]
+                        }],
+                        bids: Vec::new(),
+                    }
+                );
+                assert_eq!(
+                    ob.last_trade(),
+                    Some(Trade {
+                        total_qty: 2,
+                        avg_price: 395.0,
+                        last_qty: 2,
+                        last_price: 395,
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn two_resting_orders_merged() {
+        for (bid_ask, _) in &BID_ASK_COMBINATIONS {
+            let (ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *bid_ask,
+                    qty: 2,
+                    price: 395,
+                },
+            ]);
+            assert_eq!(
+                results,
+                vec![
+                    OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                    OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 395 }
+                ]
+            );
+            if *bid_ask == Side::Bid {
+                assert_eq!(ob.min_ask(), Price::MAX);
+                assert_eq!(ob.best_ask(), None);
+                assert_eq!(ob.max_bid(), 395);
+                assert_eq!(ob.best_bid(), Some(395));
+                assert_eq!(ob._asks(), Vec::new());
+                assert_eq!(
+                    ob._bids(),
+                    init_book(vec![(395, 1), (395, 2)])
+                );
+                assert_eq!(ob.spread(), Price::MAX - 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+                assert_eq!(ob.traded_volume(), 0);
+                assert_eq!(
+                    ob.depth(3, false),
+                    BookDepth {
+                        levels: 3,
+                        asks: Vec::new(),
+                        bids: vec![BookLevel {
+                            price: 395,
+                            qty: 14,
+                            cumulative_qty: 14,
+                            cumulative_notional: 5530,
+                            orders: vec![]
+                        }],
+                    }
+                );
+                assert_eq!(ob.last_trade(), None);
+            } else {
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(
+                    ob._asks(),
+                    init_book(vec![(395, 1), (395, 2)])
+                );
+                assert_eq!(ob._bids(), Vec::new());
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
                 assert_eq!(ob.traded_volume(), 0);
                 assert_eq!(
-                    ob.depth(3, false),
-                    BookDepth {
-                        levels: 3,
-                        asks: vec![BookLevel {
+                    ob.depth(3, false),
+                    BookDepth {
+                        levels: 3,
+                        asks: vec![BookLevel {
+                            price: 395,
+                            qty: 14,
+                            cumulative_qty: 14,
+                            cumulative_notional: 5530,
+                            orders: vec![]
+                        }],
+                        bids: Vec::new(),
+                    }
+                );
+                assert_eq!(ob.last_trade(), None);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn two_resting_orders_stacked() {
+        for (bid_ask, _) in &BID_ASK_COMBINATIONS {
+            let (ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *bid_ask,
+                    qty: 2,
+                    price: 398,
+                },
+            ]);
+            assert_eq!(
+                results,
+                vec![
+                    OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                    OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 398 }
+                ]
+            );
+            if *bid_ask == Side::Bid {
+                assert_eq!(ob.min_ask(), Price::MAX);
+                assert_eq!(ob.best_ask(), None);
+                assert_eq!(ob.max_bid(), 398);
+                assert_eq!(ob.best_bid(), Some(398));
+                assert_eq!(ob._asks(), Vec::new());
+                assert_eq!(
+                    ob._bids(),
+                    init_book(vec![(398, 2), (395, 1)])
+                );
+                assert_eq!(ob.spread(), Price::MAX - 398);
+                assert_eq!(ob.bid_ask_spread(), None);
+            } else {
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(
+                    ob._asks(),
+                    init_book(vec![(398, 2), (395, 1)])
+                );
+                assert_eq!(ob._bids(), Vec::new());
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn three_resting_orders_stacked() {
+        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
+            let (ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *ask_bid,
+                    qty: 2,
+                    price: 399,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 3,
+                    side: *bid_ask,
+                    qty: 2,
+                    price: 398,
+                },
+            ]);
+            if *bid_ask == Side::Bid {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 399 },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(ob.min_ask(), 399);
+                assert_eq!(ob.best_ask(), Some(399));
+                assert_eq!(ob.max_bid(), 398);
+                assert_eq!(ob.best_bid(), Some(398));
+                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
+                assert_eq!(
+                    ob._bids(),
+                    init_book(vec![(398, 3), (395, 1)])
+                );
+                assert_eq!(ob.spread(), 1);
+                assert_eq!(ob.bid_ask_spread(), Some(1));
+            } else {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Filled {
+                            id: 2,
+                            filled_qty: 2,
+                            remaining_qty: 0,
+                            fills: fills![FillMetadata {
+                                trade_id: 1,
+                                taker_id: 2,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: false,
+                                maker_remaining_qty: 10,
+                            }],
+                        },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(
+                    ob._asks(),
+                    init_book(vec![(398, 3), (395, 1)])
+                );
+                assert_eq!(ob._bids(), init_book(vec![]));
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn crossing_limit_order_partial() {
+        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
+            let (mut ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *ask_bid,
+                    qty: 2,
+                    price: 399,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 3,
+                    side: *bid_ask,
+                    qty: 2,
+                    price: 398,
+                },
+            ]);
+            let result = ob.execute(OrderType::Limit {
+                user_id: 1,
+                id: 4,
+                side: *ask_bid,
+                qty: 1,
+                price: 397,
+            });
+
+            if *bid_ask == Side::Bid {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 399 },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::Filled {
+                        id: 4,
+                        filled_qty: 1,
+                        remaining_qty: 0,
+                        fills: fills![FillMetadata {
+                            trade_id: 1,
+                            taker_id: 4,
+                            maker_id: 3,
+                            maker_user_id: 1,
+                            maker_client_order_id: None,
+                            qty: 1,
+                            price: 398,
+                            taker_side: *ask_bid,
+                            total_fill: false,
+                            maker_remaining_qty: 1,
+                        }]
+                    }
+                );
+                assert_eq!(ob.min_ask(), 399);
+                assert_eq!(ob.best_ask(), Some(399));
+                assert_eq!(ob.max_bid(), 398);
+                assert_eq!(ob.best_bid(), Some(398));
+                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
+                assert_eq!(
+                    ob._bids(),
+                    init_book(vec![(398, 3), (395, 1)])
+                );
+                assert_eq!(ob.spread(), 1);
+                assert_eq!(ob.bid_ask_spread(), Some(1));
+            } else {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Filled {
+                            id: 2,
+                            filled_qty: 2,
+                            remaining_qty: 0,
+                            fills: fills![FillMetadata {
+                                trade_id: 1,
+                                taker_id: 2,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: false,
+                                maker_remaining_qty: 10,
+                            }],
+                        },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::Filled {
+                        id: 4,
+                        filled_qty: 1,
+                        remaining_qty: 0,
+                        fills: fills![FillMetadata {
+                            trade_id: 2,
+                            taker_id: 4,
+                            maker_id: 1,
+                            maker_user_id: 1,
+                            maker_client_order_id: None,
+                            qty: 1,
+                            price: 395,
+                            taker_side: *ask_bid,
+                            total_fill: false,
+                            maker_remaining_qty: 9,
+                        }]
+                    }
+                );
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(
+                    ob._asks(),
+                    init_book(vec![(398, 3), (395, 1)])
+                );
+                assert_eq!(ob._bids(), init_book(vec![]));
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn crossing_limit_order_matching() {
+        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
+            let (mut ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *ask_bid,
+                    qty: 2,
+                    price: 399,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 3,
+                    side: *bid_ask,
+                    qty: 2,
+                    price: 398,
+                },
+            ]);
+            let result = ob.execute(OrderType::Limit {
+                user_id: 1,
+                id: 4,
+                side: *ask_bid,
+                qty: 2,
+                price: 397,
+            });
+
+            if *bid_ask == Side::Bid {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 399 },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::Filled {
+                        id: 4,
+                        filled_qty: 2,
+                        remaining_qty: 0,
+                        fills: fills![FillMetadata {
+                            trade_id: 1,
+                            taker_id: 4,
+                            maker_id: 3,
+                            maker_user_id: 1,
+                            maker_client_order_id: None,
+                            qty: 2,
+                            price: 398,
+                            taker_side: *ask_bid,
+                            total_fill: true,
+                            maker_remaining_qty: 0,
+                        }]
+                    }
+                );
+                assert_eq!(ob.min_ask(), 399);
+                assert_eq!(ob.best_ask(), Some(399));
+                assert_eq!(ob.max_bid(), 395);
+                assert_eq!(ob.best_bid(), Some(395));
+                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
+                assert_eq!(
+                    ob._bids(),
+                    init_book(vec![(395, 1)])
+                );
+                assert_eq!(ob.spread(), 4);
+                assert_eq!(ob.bid_ask_spread(), Some(4));
+            } else {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Filled {
+                            id: 2,
+                            filled_qty: 2,
+                            remaining_qty: 0,
+                            fills: fills![FillMetadata {
+                                trade_id: 1,
+                                taker_id: 2,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: false,
+                                maker_remaining_qty: 10,
+                            }],
+                        },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::Filled {
+                        id: 4,
+                        filled_qty: 2,
+                        remaining_qty: 0,
+                        fills: fills![FillMetadata {
+                            trade_id: 2,
+                            taker_id: 4,
+                            maker_id: 1,
+                            maker_user_id: 1,
+                            maker_client_order_id: None,
+                            qty: 2,
+                            price: 395,
+                            taker_side: *ask_bid,
+                            total_fill: false,
+                            maker_remaining_qty: 8,
+                        }]
+                    }
+                );
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(
+                    ob._asks(),
+                    init_book(vec![(395, 1), (398, 3)])
+                );
+                assert_eq!(ob._bids(), init_book(vec![]));
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn crossing_limit_order_over() {
+        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
+            let (mut ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *ask_bid,
+                    qty: 2,
+                    price: 399,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 3,
+                    side: *bid_ask,
+                    qty: 2,
+                    price: 398,
+                },
+            ]);
+            let result = ob.execute(OrderType::Limit {
+                user_id: 1,
+                id: 4,
+                side: *ask_bid,
+                qty: 5,
+                price: 397,
+            });
+
+            if *bid_ask == Side::Bid {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 399 },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::PartiallyFilled {
+                        id: 4,
+                        filled_qty: 2,
+                        remaining_qty: 3,
+                        resting_price: Some(397),
+                        fills: fills![FillMetadata {
+                            trade_id: 1,
+                            taker_id: 4,
+                            maker_id: 3,
+                            maker_user_id: 1,
+                            maker_client_order_id: None,
+                            qty: 2,
+                            price: 398,
+                            taker_side: *ask_bid,
+                            total_fill: true,
+                            maker_remaining_qty: 0,
+                        }]
+                    }
+                );
+                assert_eq!(ob.min_ask(), 397);
+                assert_eq!(ob.best_ask(), Some(397));
+                assert_eq!(ob.max_bid(), 395);
+                assert_eq!(ob.best_bid(), Some(395));
+                assert_eq!(
+                    ob._asks(),
+                    init_book(vec![(399, 2), (397, 4)])
+                );
+                assert_eq!(
+                    ob._bids(),
+                    init_book(vec![(395, 1)])
+                );
+                assert_eq!(ob.spread(), 2);
+                assert_eq!(ob.bid_ask_spread(), Some(2));
+            } else {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Filled {
+                            id: 2,
+                            filled_qty: 2,
+                            remaining_qty: 0,
+                            fills: fills![FillMetadata {
+                                trade_id: 1,
+                                taker_id: 2,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: false,
+                                maker_remaining_qty: 10,
+                            }],
+                        },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::Filled {
+                        id: 4,
+                        filled_qty: 5,
+                        remaining_qty: 0,
+                        fills: fills![FillMetadata {
+                            trade_id: 2,
+                            taker_id: 4,
+                            maker_id: 1,
+                            maker_user_id: 1,
+                            maker_client_order_id: None,
+                            qty: 5,
+                            price: 395,
+                            taker_side: *ask_bid,
+                            total_fill: false,
+                            maker_remaining_qty: 5,
+                        }]
+                    }
+                );
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(
+                    ob._asks(),
+                    init_book(vec![(395, 1), (398, 3)])
+                );
+                assert_eq!(ob._bids(), init_book(vec![]));
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn market_order_rejected() {
+        for (_, ask_bid) in &BID_ASK_COMBINATIONS {
+            let (mut ob, _) = init_ob(vec![]);
+            let result = ob.execute(OrderType::Market {
+                user_id: 1,
+                id: 1,
+                side: *ask_bid,
+                qty: 5,
+            });
+
+            assert_eq!(result, OrderEvent::Rejected { id: 1, message: RejectReason::LiquidityNotAvailable });
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn market_order_partially_filled() {
+        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
+            let (mut ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *ask_bid,
+                    qty: 2,
+                    price: 399,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 3,
+                    side: *bid_ask,
+                    qty: 2,
+                    price: 398,
+                },
+            ]);
+            let result = ob.execute(OrderType::Market {
+                user_id: 1,
+                id: 4,
+                side: *ask_bid,
+                qty: 15,
+            });
+
+            if *bid_ask == Side::Bid {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 399 },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::PartiallyFilled {
+                        id: 4,
+                        filled_qty: 14,
+                        remaining_qty: 0,
+                        resting_price: None,
+                        fills: fills![
+                            FillMetadata {
+                                trade_id: 1,
+                                taker_id: 4,
+                                maker_id: 3,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 398,
+                                taker_side: *ask_bid,
+                                total_fill: true,
+                                maker_remaining_qty: 0,
+                            },
+                            FillMetadata {
+                                trade_id: 2,
+                                taker_id: 4,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 12,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: true,
+                                maker_remaining_qty: 0,
+                            }
+                        ]
+                    }
+                );
+                assert_eq!(ob.min_ask(), 399);
+                assert_eq!(ob.best_ask(), Some(399));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
+                assert_eq!(ob._bids(), init_book(vec![]));
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 399);
+                assert_eq!(ob.bid_ask_spread(), None);
+            } else {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Filled {
+                            id: 2,
+                            filled_qty: 2,
+                            remaining_qty: 0,
+                            fills: fills![FillMetadata {
+                                trade_id: 1,
+                                taker_id: 2,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: false,
+                                maker_remaining_qty: 10,
+                            }],
+                        },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::PartiallyFilled {
+                        id: 4,
+                        filled_qty: 12,
+                        remaining_qty: 0,
+                        resting_price: None,
+                        fills: fills![
+                            FillMetadata {
+                                trade_id: 2,
+                                taker_id: 4,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 10,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: true,
+                                maker_remaining_qty: 0,
+                            },
+                            FillMetadata {
+                                trade_id: 3,
+                                taker_id: 4,
+                                maker_id: 3,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 398,
+                                taker_side: *ask_bid,
+                                total_fill: true,
+                                maker_remaining_qty: 0,
+                            }
+                        ]
+                    }
+                );
+                assert_eq!(ob.min_ask(), Price::MAX);
+                assert_eq!(ob.best_ask(), None);
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(ob._asks(), init_book(vec![]));
+                assert_eq!(ob._bids(), init_book(vec![]));
+                assert_eq!(ob.spread(), Price::MAX);
+                assert_eq!(ob.bid_ask_spread(), None);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn market_order_filled() {
+        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
+            let (mut ob, results) = init_ob(vec![
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 1,
+                    side: *bid_ask,
+                    qty: 12,
+                    price: 395,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 2,
+                    side: *ask_bid,
+                    qty: 2,
+                    price: 399,
+                },
+                OrderType::Limit {
+                    user_id: 1,
+                    id: 3,
+                    side: *bid_ask,
+                    qty: 2,
+                    price: 398,
+                },
+            ]);
+            let result = ob.execute(OrderType::Market {
+                user_id: 1,
+                id: 4,
+                side: *ask_bid,
+                qty: 7,
+            });
+
+            if *bid_ask == Side::Bid {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 399 },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::Filled {
+                        id: 4,
+                        filled_qty: 7,
+                        remaining_qty: 0,
+                        fills: fills![
+                            FillMetadata {
+                                trade_id: 1,
+                                taker_id: 4,
+                                maker_id: 3,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 398,
+                                taker_side: *ask_bid,
+                                total_fill: true,
+                                maker_remaining_qty: 0,
+                            },
+                            FillMetadata {
+                                trade_id: 2,
+                                taker_id: 4,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 5,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: false,
+                                maker_remaining_qty: 7,
+                            }
+                        ]
+                    }
+                );
+                assert_eq!(ob.min_ask(), 399);
+                assert_eq!(ob.best_ask(), Some(399));
+                assert_eq!(ob.max_bid(), 395);
+                assert_eq!(ob.best_bid(), Some(395));
+                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
+                assert_eq!(
+                    ob._bids(),
+                    init_book(vec![(395, 1)])
+                );
+                assert_eq!(ob.spread(), 4);
+                assert_eq!(ob.bid_ask_spread(), Some(4));
+                assert_eq!(ob.arena.get(3), None);
+                assert_eq!(ob.arena.get(1), Some(&LimitOrder{ user_id: 1, id: 1, qty: 7, price: 395, created_at: 0 }));
+            } else {
+                assert_eq!(
+                    results,
+                    vec![
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Filled {
+                            id: 2,
+                            filled_qty: 2,
+                            remaining_qty: 0,
+                            fills: fills![FillMetadata {
+                                trade_id: 1,
+                                taker_id: 2,
+                                maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
+                                qty: 2,
+                                price: 395,
+                                taker_side: *ask_bid,
+                                total_fill: false,
+                                maker_remaining_qty: 10,
+                            }],
+                        },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
+                    ]
+                );
+                assert_eq!(
+                    result,
+                    OrderEvent::Filled {
+                        id: 4,
+                        filled_qty: 7,
+                        remaining_qty: 0,
+                        fills: fills![FillMetadata {
+                            trade_id: 2,
+                            taker_id: 4,
+                            maker_id: 1,
+                            maker_user_id: 1,
+                            maker_client_order_id: None,
+                            qty: 7,
                             price: 395,
-                            qty: 14,
-                            orders: vec![]
-                        }],
-                        bids: Vec::new(),
+                            taker_side: *ask_bid,
+                            total_fill: false,
+                            maker_remaining_qty: 3,
+                        }]
                     }
                 );
-                assert_eq!(ob.last_trade(), None);
+                assert_eq!(ob.min_ask(), 395);
+                assert_eq!(ob.best_ask(), Some(395));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
+                assert_eq!(
+                    ob._asks(),
+                    init_book(vec![(395, 1), (398, 3)])
+                );
+                assert_eq!(ob._bids(), init_book(vec![]));
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 395);
+                assert_eq!(ob.bid_ask_spread(), None);
+                assert_eq!(ob.arena.get(3), Some(&LimitOrder { user_id: 1, id: 3, qty: 2, price: 398, created_at: 0 }));
+                assert_eq!(ob.arena.get(1), Some(&LimitOrder{ user_id: 1, id: 1, qty: 3, price: 395, created_at: 0 }));
             }
         }
     }
 
     #[test]
-    fn two_resting_orders_stacked() {
+    fn cancels_are_only_acknowledged_for_resting_orders() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 2, price: 396 },
+            OrderType::Market { user_id: 2, id: 3, side: Side::Bid, qty: 2 },
+        ]);
+        let not_found = |id| OrderEvent::CancelRejected { id, message: RejectReason::OrderNotFound };
+        assert_eq!(ob.execute(OrderType::Cancel { id: 2 }), OrderEvent::Cancelled { id: 2 });
+        assert_eq!(ob.execute(OrderType::Cancel { id: 2 }), not_found(2));
+        // Filled and unknown orders are not resting either.
+        assert_eq!(ob.execute(OrderType::Cancel { id: 1 }), not_found(1));
+        assert_eq!(ob.execute(OrderType::Cancel { id: 9 }), not_found(9));
+        assert_eq!(not_found(9).to_string(), "cancel of order 9 rejected: ORDER_NOT_FOUND");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn cancel_non_existing_order() {
+        let (mut ob, _) = init_ob(vec![]);
+        let result = ob.execute(OrderType::Cancel { id: 0 });
+        assert_eq!(result, OrderEvent::CancelRejected { id: 0, message: RejectReason::OrderNotFound });
+        assert_eq!(ob.min_ask(), Price::MAX);
+        assert_eq!(ob.best_ask(), None);
+        assert_eq!(ob.max_bid(), Price::MIN);
+        assert_eq!(ob.best_bid(), None);
+        assert_eq!(ob._asks(), Vec::new());
+        assert_eq!(ob._bids(), Vec::new());
+        assert_eq!(ob.spread(), Price::MAX);
+        assert_eq!(ob.bid_ask_spread(), None);
+        assert_eq!(ob.arena.get(0), None);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn cancel_resting_order() {
         for (bid_ask, _) in &BID_ASK_COMBINATIONS {
-            let (ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *bid_ask,
-                    qty: 2,
-                    price: 398,
-                },
-            ]);
-            assert_eq!(
-                results,
-                vec![
-                    OrderEvent::Open { id: 1 },
-                    OrderEvent::Open { id: 2 }
-                ]
-            );
+            let (mut ob, results) = init_ob(vec![OrderType::Limit {
+                user_id: 1,
+                id: 1,
+                side: *bid_ask,
+                qty: 12,
+                price: 395,
+            }]);
+            let result = ob.execute(OrderType::Cancel { id: 1 });
+            assert_eq!(results, vec![OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 }]);
+            assert_eq!(result, OrderEvent::Cancelled { id: 1 });
+            assert_eq!(ob.min_ask(), Price::MAX);
+            assert_eq!(ob.best_ask(), None);
+            assert_eq!(ob.max_bid(), Price::MIN);
+            assert_eq!(ob.best_bid(), None);
             if *bid_ask == Side::Bid {
-                assert_eq!(ob.min_ask(), u64::MAX);
-                assert_eq!(ob.max_bid(), 398);
                 assert_eq!(ob._asks(), Vec::new());
-                assert_eq!(
-                    ob._bids(),
-                    init_book(vec![(398, 2), (395, 1)])
-                );
-                assert_eq!(ob.spread(), u64::MAX - 398);
+                assert_eq!(ob._bids(), init_book(vec![]));
             } else {
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0u64);
-                assert_eq!(
-                    ob._asks(),
-                    init_book(vec![(398, 2), (395, 1)])
-                );
+                assert_eq!(ob._asks(), init_book(vec![]));
                 assert_eq!(ob._bids(), Vec::new());
-                assert_eq!(ob.spread(), 395);
             }
+            assert_eq!(ob.spread(), Price::MAX);
+            assert_eq!(ob.bid_ask_spread(), None);
+            assert_eq!(ob.arena.get(1), None);
         }
     }
 
     #[test]
-    fn three_resting_orders_stacked() {
+    #[allow(deprecated)]
+    fn cancel_resting_order_of_many() {
         for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (ob, results) = init_ob(vec![
+            let (mut ob, results) = init_ob(vec![
                 OrderType::Limit {
                     user_id: 1,
                     id: 1,
@@ -1087,779 +4243,1640 @@ mod test {
                     price: 398,
                 },
             ]);
+            let result = ob.execute(OrderType::Cancel { id: 1 });
             if *bid_ask == Side::Bid {
                 assert_eq!(
                     results,
                     vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Open { id: 2 },
-                        OrderEvent::Open { id: 3 }
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
+                        OrderEvent::Open { id: 2, remaining_qty: 2, resting_price: 399 },
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
                     ]
                 );
+                assert_eq!(result, OrderEvent::Cancelled { id: 1 });
                 assert_eq!(ob.min_ask(), 399);
+                assert_eq!(ob.best_ask(), Some(399));
                 assert_eq!(ob.max_bid(), 398);
+                assert_eq!(ob.best_bid(), Some(398));
                 assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
                 assert_eq!(
                     ob._bids(),
-                    init_book(vec![(398, 3), (395, 1)])
+                    init_book(vec![(398, 3)])
                 );
                 assert_eq!(ob.spread(), 1);
+                assert_eq!(ob.bid_ask_spread(), Some(1));
             } else {
                 assert_eq!(
                     results,
                     vec![
-                        OrderEvent::Open { id: 1 },
+                        OrderEvent::Open { id: 1, remaining_qty: 12, resting_price: 395 },
                         OrderEvent::Filled {
                             id: 2,
                             filled_qty: 2,
-                            fills: vec![FillMetadata {
+                            remaining_qty: 0,
+                            fills: fills![FillMetadata {
+                                trade_id: 1,
                                 taker_id: 2,
                                 maker_id: 1,
+                                maker_user_id: 1,
+                                maker_client_order_id: None,
                                 qty: 2,
                                 price: 395,
                                 taker_side: *ask_bid,
                                 total_fill: false,
+                                maker_remaining_qty: 10,
                             }],
                         },
-                        OrderEvent::Open { id: 3 }
+                        OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 398 }
                     ]
                 );
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0);
+                assert_eq!(result, OrderEvent::Cancelled { id: 1 });
+                assert_eq!(ob.min_ask(), 398);
+                assert_eq!(ob.best_ask(), Some(398));
+                assert_eq!(ob.max_bid(), Price::MIN);
+                assert_eq!(ob.best_bid(), None);
                 assert_eq!(
                     ob._asks(),
-                    init_book(vec![(398, 3), (395, 1)])
+                    init_book(vec![(398, 3)])
                 );
                 assert_eq!(ob._bids(), init_book(vec![]));
-                assert_eq!(ob.spread(), 395);
+                #[cfg(not(feature = "signed-price"))]
+                assert_eq!(ob.spread(), 398);
+                assert_eq!(ob.bid_ask_spread(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn cross_trade_reported() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Bid, qty: 12, price: 395 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 2, price: 399 },
+        ]);
+        let result = ob.execute(OrderType::Cross { id: 3, buyer_id: 7, seller_id: 8, qty: 100, price: 400 });
+        assert_eq!(
+            result,
+            OrderEvent::Traded { id: 3, buyer_id: 7, seller_id: 8, qty: 100, price: 400 }
+        );
+        assert_eq!(ob.traded_volume(), 100);
+        assert_eq!(
+            ob.last_trade(),
+            Some(Trade { total_qty: 100, avg_price: 400.0, last_qty: 100, last_price: 400 })
+        );
+        assert_eq!(ob._bids(), init_book(vec![(395, 1)]));
+        assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
+        assert_eq!(ob.last_sequence(), 3);
+    }
+
+    #[test]
+    fn cross_trade_outside_bbo_rejected() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Bid, qty: 12, price: 395 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 2, price: 399 },
+        ]);
+        ob.validate_crosses(true);
+        let result = ob.execute(OrderType::Cross { id: 3, buyer_id: 7, seller_id: 8, qty: 100, price: 400 });
+        assert_eq!(result, OrderEvent::Rejected { id: 3, message: RejectReason::CrossPriceOutsideBbo });
+        assert_eq!(ob.traded_volume(), 0);
+        let result = ob.execute(OrderType::Cross { id: 4, buyer_id: 7, seller_id: 8, qty: 100, price: 399 });
+        assert_eq!(
+            result,
+            OrderEvent::Traded { id: 4, buyer_id: 7, seller_id: 8, qty: 100, price: 399 }
+        );
+        assert_eq!(ob.traded_volume(), 100);
+    }
+
+    #[test]
+    fn rfq_executes_against_best_quotes() {
+        let (mut ob, _) = init_ob(vec![OrderType::Limit {
+            user_id: 1,
+            id: 1,
+            side: Side::Ask,
+            qty: 12,
+            price: 395,
+        }]);
+        ob.request_quote(2, 5, Side::Bid, 10, vec![6, 7], 100).unwrap();
+        ob.submit_quote(2, 3, 6, 6, 401, 10).unwrap();
+        ob.submit_quote(2, 4, 7, 6, 400, 20).unwrap();
+        assert_eq!(ob.submit_quote(2, 5, 8, 6, 390, 30), Err(RfqError::MakerNotInvited));
+        let result = ob.execute_rfq(2).unwrap();
+        assert_eq!(
+            result,
+            OrderEvent::Filled {
+                id: 2,
+                filled_qty: 10,
+                remaining_qty: 0,
+                fills: fills![
+                    FillMetadata {
+                        trade_id: 1,
+                        taker_id: 2,
+                        maker_id: 4,
+                        maker_user_id: 7,
+                        maker_client_order_id: None,
+                        qty: 6,
+                        price: 400,
+                        taker_side: Side::Bid,
+                        total_fill: true,
+                        maker_remaining_qty: 0,
+                    },
+                    FillMetadata {
+                        trade_id: 2,
+                        taker_id: 2,
+                        maker_id: 3,
+                        maker_user_id: 6,
+                        maker_client_order_id: None,
+                        qty: 4,
+                        price: 401,
+                        taker_side: Side::Bid,
+                        total_fill: false,
+                        maker_remaining_qty: 2,
+                    },
+                ],
+            }
+        );
+        assert_eq!(ob.rfq(2), None);
+        assert_eq!(ob.execute_rfq(2), Err(RfqError::UnknownRfq));
+        assert_eq!(ob.traded_volume(), 10);
+        assert_eq!(ob._asks(), init_book(vec![(395, 1)]));
+    }
+
+    #[test]
+    fn rfq_without_quotes_expires() {
+        let (mut ob, _) = init_ob(vec![]);
+        ob.request_quote(1, 5, Side::Ask, 10, vec![6], 100).unwrap();
+        assert_eq!(ob.request_quote(1, 5, Side::Ask, 10, vec![6], 100), Err(RfqError::InvalidOrderNumber));
+        assert_eq!(
+            ob.execute_rfq(1),
+            Ok(OrderEvent::Rejected { id: 1, message: RejectReason::LiquidityNotAvailable })
+        );
+        assert_eq!(ob.rfq(1), None);
+        assert_eq!(ob.execute_rfq(2), Err(RfqError::UnknownRfq));
+    }
+
+    #[test]
+    fn abandoned_rfqs_are_executed_after_their_deadline() {
+        let (mut ob, _) = init_ob(vec![]);
+        assert_eq!(ob.request_quote(1, 5, Side::Bid, 0, vec![6], 100), Err(RfqError::InvalidQuantity));
+        ob.request_quote(2, 5, Side::Bid, 10, vec![6], 100).unwrap();
+        ob.request_quote(3, 5, Side::Bid, 10, vec![6], 200).unwrap();
+        assert_eq!(ob.submit_quote(2, 4, 6, 0, 400, 10), Err(RfqError::InvalidQuantity));
+        ob.submit_quote(2, 5, 6, 4, 400, 10).unwrap();
+        assert_eq!(ob.rfq(2).unwrap().state, RfqState::Open);
+
+        assert_eq!(ob.execute_expired_rfqs(100), vec![]);
+        let events = ob.execute_expired_rfqs(101);
+        assert!(matches!(events[..], [OrderEvent::PartiallyFilled { id: 2, filled_qty: 4, .. }]));
+        assert_eq!((ob.rfq(2), ob.rfq(3).map(|rfq| rfq.id)), (None, Some(3)));
+        assert_eq!(
+            ob.execute_expired_rfqs(201),
+            vec![OrderEvent::Rejected { id: 3, message: RejectReason::LiquidityNotAvailable }]
+        );
+        assert_eq!(ob.rfq(3), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn rfqs_go_through_the_checks_of_orders() {
+        let (mut ob, _) = init_ob(vec![]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        ob.set_gap_sink(Some(Box::new(sender)));
+        ob.set_dedup_window(Some(10));
+        ob.request_quote(2, 5, Side::Bid, 10, vec![6, 7], 100).unwrap();
+        assert_eq!(ob.request_quote(2, 5, Side::Bid, 10, vec![6, 7], 100), Err(RfqError::Duplicate));
+        ob.submit_quote(2, 4, 6, 4, 400, 10).unwrap();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![SequenceGap { first: 3, last: 3 }]);
+
+        ob.set_risk_limits(7, Some(RiskLimits::default().with_max_order_notional(1000)));
+        let rejected = RfqError::Rejected(RejectReason::OrderNotionalLimitExceeded);
+        assert_eq!(ob.submit_quote(2, 5, 7, 3, 400, 10), Err(rejected));
+        ob.submit_quote(2, 6, 7, 2, 400, 10).unwrap();
+
+        ob.set_rate_limiter(Some(RateLimiter::new(1, 100, Box::new(ManualClock::new(0)))));
+        ob.request_quote(7, 5, Side::Ask, 1, vec![6], 100).unwrap();
+        assert_eq!(ob.request_quote(8, 5, Side::Ask, 1, vec![6], 100), Err(RfqError::Rejected(RejectReason::RateLimitExceeded)));
+        assert!(matches!(ob.execute_rfq(2), Ok(OrderEvent::PartiallyFilled { id: 2, filled_qty: 6, .. })));
+    }
+
+    #[test]
+    fn resting_order_reports_side_and_state() {
+        let (mut ob, _) = init_ob(vec![OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 12, price: 395 }]);
+        ob.execute(OrderType::Reduce { id: 1, qty_delta: 2 });
+        let order = LimitOrder { user_id: 1, id: 1, qty: 10, price: 395, created_at: 0 };
+        assert_eq!(ob.resting_order(1), Some((Side::Ask, &order)));
+        ob.execute(OrderType::Cancel { id: 1 });
+        assert_eq!(ob.resting_order(1), None);
+    }
+
+    #[test]
+    fn open_orders_lists_the_remaining_orders_of_a_user() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 12, price: 395 },
+            OrderType::Limit { user_id: 2, id: 2, side: Side::Ask, qty: 2, price: 396 },
+            OrderType::Limit { user_id: 1, id: 3, side: Side::Bid, qty: 3, price: 390 },
+        ]);
+        ob.execute(OrderType::Market { user_id: 2, id: 4, side: Side::Bid, qty: 5 });
+        let order = |id, qty, price| LimitOrder { user_id: 1, id, qty, price, created_at: 0 };
+        assert_eq!(ob.open_orders(1), vec![(Side::Ask, &order(1, 7, 395)), (Side::Bid, &order(3, 3, 390))]);
+        assert!(ob.open_orders(2).iter().all(|(_, order)| order.id == 2));
+        ob.execute(OrderType::Cancel { id: 1 });
+        assert_eq!(ob.open_orders(1).len(), 1);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn cancel_where_side_range_and_user() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Bid, qty: 12, price: 395 },
+            OrderType::Limit { user_id: 2, id: 2, side: Side::Bid, qty: 2, price: 396 },
+            OrderType::Limit { user_id: 1, id: 3, side: Side::Bid, qty: 2, price: 398 },
+            OrderType::Limit { user_id: 1, id: 4, side: Side::Ask, qty: 5, price: 399 },
+        ]);
+        assert_eq!(ob.cancel_where(Some(Side::Bid), 396.., Some(1)), vec![3]);
+        assert_eq!(ob.max_bid(), 396);
+        assert_eq!(ob.best_bid(), Some(396));
+        assert_eq!(ob._bids(), init_book(vec![(395, 1), (396, 2)]));
+        assert_eq!(ob.cancel_where(None, .., Some(1)), vec![1, 4]);
+        assert_eq!(ob._bids(), init_book(vec![(396, 2)]));
+        assert_eq!(ob._asks(), Vec::new());
+        assert_eq!(ob.min_ask(), Price::MAX);
+        assert_eq!(ob.best_ask(), None);
+        assert_eq!(ob.cancel_where(Some(Side::Bid), 390..=396, None), vec![2]);
+        assert_eq!(ob._bids(), Vec::new());
+        assert_eq!(ob.arena.get(2), None);
+    }
+
+    #[test]
+    fn reduce_keeps_priority() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 12, price: 395 },
+            OrderType::Limit { user_id: 2, id: 2, side: Side::Ask, qty: 2, price: 395 },
+        ]);
+        let result = ob.execute(OrderType::Reduce { id: 1, qty_delta: 10 });
+        assert_eq!(result, OrderEvent::Reduced { id: 1, qty: 2 });
+        assert_eq!(ob._asks(), init_book(vec![(395, 1), (395, 2)]));
+        assert_eq!(ob.arena.get(1), Some(&LimitOrder { user_id: 1, id: 1, qty: 2, price: 395, created_at: 0 }));
+        let result = ob.execute(OrderType::Reduce { id: 1, qty_delta: 5 });
+        assert_eq!(result, OrderEvent::Cancelled { id: 1 });
+        assert_eq!(ob._asks(), init_book(vec![(395, 2)]));
+        let result = ob.execute(OrderType::Reduce { id: 1, qty_delta: 5 });
+        assert_eq!(result, OrderEvent::CancelRejected { id: 1, message: RejectReason::OrderNotFound });
+        assert_eq!(ob.last_sequence(), 2);
+    }
+
+    #[test]
+    fn auto_assigned_ids_follow_the_last_sequence() {
+        let mut ob = OrderBook::default();
+        let (id, event) = ob.execute_auto(OrderType::Limit { user_id: 1, id: 0, side: Side::Ask, qty: 2, price: 395 });
+        assert_eq!((id, event), (1, OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 395 }));
+        ob.execute(OrderType::Limit { user_id: 1, id: 10, side: Side::Ask, qty: 2, price: 396 });
+        let (id, event) = ob.execute_auto(OrderType::Market { user_id: 2, id: 0, side: Side::Bid, qty: 1 });
+        assert_eq!(id, 11);
+        assert!(matches!(event, OrderEvent::Filled { id: 11, .. }));
+        assert_eq!(ob.execute_auto(OrderType::Cancel { id: 10 }), (10, OrderEvent::Cancelled { id: 10 }));
+        assert_eq!(ob.last_sequence(), 11);
+    }
+
+    #[test]
+    fn events_report_the_resting_rest_of_orders() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 100 });
+        let slide = ob.execute(OrderType::PostonlySlide { user_id: 2, id: 2, side: Side::Bid, qty: 3, price: 101 });
+        assert_eq!(slide, OrderEvent::Open { id: 2, remaining_qty: 3, resting_price: 99 });
+        let limit = ob.execute(OrderType::Limit { user_id: 2, id: 3, side: Side::Bid, qty: 5, price: 100 });
+        assert!(matches!(
+            limit,
+            OrderEvent::PartiallyFilled { id: 3, filled_qty: 2, remaining_qty: 3, resting_price: Some(100), .. }
+        ));
+        let ioc = ob.execute(OrderType::IOC { user_id: 1, id: 4, side: Side::Ask, qty: 4, price: 100 });
+        assert!(matches!(ioc, OrderEvent::PartiallyFilled { id: 4, filled_qty: 3, remaining_qty: 0, resting_price: None, .. }));
+        let market = ob.execute(OrderType::Market { user_id: 1, id: 5, side: Side::Ask, qty: 1 });
+        assert!(matches!(market, OrderEvent::Filled { id: 5, filled_qty: 1, remaining_qty: 0, .. }));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn orders_executed_at_their_timestamp() {
+        let clock = ManualClock::new(100);
+        let journal = MemoryJournal::new();
+        let mut ob = OrderBook::default();
+        ob.set_clock(Box::new(clock.clone()));
+        ob.set_journal(Some(Box::new(journal.clone())));
+        let order = OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 };
+        assert_eq!(ob.execute_at(order, Some(40)), StampedEvent { timestamp: 40, event: OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 395 } });
+        assert_eq!(ob.order_age(1), Some(60));
+        let stamped = ob.execute_at(OrderType::Market { user_id: 2, id: 2, side: Side::Bid, qty: 1 }, None);
+        assert_eq!(stamped.timestamp, 100);
+        assert_eq!(ob.tape().map(|entry| entry.timestamp).collect::<Vec<_>>(), vec![100]);
+        assert_eq!(journal.entries().iter().map(|entry| entry.timestamp).collect::<Vec<_>>(), vec![40, 100]);
+
+        let mut replica = OrderBook::default();
+        replica.replay_entries(journal.entries());
+        assert_eq!(replica.resting_order(1).map(|(_, order)| order.created_at), Some(40));
+    }
+
+    #[test]
+    fn gtt_orders_expire_with_clock() {
+        let clock = ManualClock::new(100);
+        let mut ob = OrderBook::default();
+        ob.set_clock(Box::new(clock.clone()));
+        ob.execute(OrderType::GTT { user_id: 1, id: 1, side: Side::Bid, qty: 12, price: 395, expires_at: 150 });
+        ob.execute(OrderType::GTT { user_id: 1, id: 2, side: Side::Bid, qty: 2, price: 396, expires_at: 200 });
+        ob.execute(OrderType::Limit { user_id: 1, id: 3, side: Side::Bid, qty: 2, price: 394 });
+        clock.advance(20);
+        assert_eq!(ob.order_age(1), Some(20));
+        assert_eq!(ob.expire_orders(), Vec::new());
+        clock.set(150);
+        assert_eq!(ob.expire_orders(), vec![OrderEvent::Expired { id: 1 }]);
+        assert_eq!(ob._bids(), init_book(vec![(394, 3), (396, 2)]));
+        ob.execute(OrderType::Cancel { id: 2 });
+        clock.set(1000);
+        assert_eq!(ob.expire_orders(), Vec::new());
+        assert_eq!(ob._bids(), init_book(vec![(394, 3)]));
+        assert_eq!(ob.order_age(3), Some(900));
+    }
+
+    #[test]
+    fn positions_follow_fills_and_crosses() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 });
+        ob.execute(OrderType::Market { user_id: 2, id: 2, side: Side::Bid, qty: 1 });
+        assert_eq!(ob.position(2), Position::default());
+
+        ob.track_positions(true);
+        ob.execute(OrderType::Limit { user_id: 1, id: 3, side: Side::Ask, qty: 5, price: 396 });
+        ob.execute(OrderType::Market { user_id: 2, id: 4, side: Side::Bid, qty: 4 });
+        ob.execute(OrderType::Cross { id: 5, buyer_id: 1, seller_id: 3, qty: 10, price: 400 });
+        let taker = ob.position(2);
+        assert_eq!(taker, Position { bought_qty: 4, sold_qty: 0, bought_notional: 395 + 3 * 396, sold_notional: 0 });
+        assert_eq!((taker.net(), taker.volume()), (4, 4));
+        let maker = ob.position(1);
+        assert_eq!(maker, Position { bought_qty: 10, sold_qty: 4, bought_notional: 4000, sold_notional: 1583 });
+        assert_eq!((maker.net(), maker.volume()), (6, 14));
+        assert_eq!(ob.position(3).net(), -10);
+    }
+
+    #[test]
+    fn positions_follow_partial_ioc_fills() {
+        let mut ob = OrderBook::default();
+        ob.track_positions(true);
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Bid, qty: 3, price: 100 });
+        for id in [2, 3] {
+            ob.execute(OrderType::IOC { user_id: 2, id, side: Side::Ask, qty: 2, price: 100 });
+        }
+        assert_eq!(ob.position(1), Position { bought_qty: 3, sold_qty: 0, bought_notional: 300, sold_notional: 0 });
+        assert_eq!(ob.position(2).net(), -3);
+        assert_eq!(ob.available_qty(Side::Ask, 100), 0);
+    }
+
+    #[test]
+    fn clearing_records_only_partial_ioc_fills_that_happened() {
+        let mut ob = OrderBook::default();
+        ob.track_clearing(true);
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 3, price: 100 });
+        for id in [2, 3, 4] {
+            ob.execute(OrderType::IOC { user_id: 2, id, side: Side::Bid, qty: 2, price: 100 });
+        }
+        let records: Vec<_> = ob.take_clearing_records().iter().map(|record| (record.trade_id, record.buy_order_id, record.qty)).collect();
+        assert_eq!(records, vec![(1, 2, 2), (2, 3, 1)]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn clearing_records_stream_and_buffer_trades() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 });
+        ob.execute(OrderType::Market { user_id: 2, id: 2, side: Side::Bid, qty: 1 });
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        ob.set_clearing_sink(Some(Box::new(sender)));
+        ob.track_clearing(true);
+        ob.set_fee_schedule(FeeSchedule::new(-10, 30));
+        ob.execute(OrderType::Limit { user_id: 3, id: 3, side: Side::Bid, qty: 40, price: 390 });
+        ob.execute(OrderType::Limit { user_id: 2, id: 4, side: Side::Ask, qty: 50, price: 390 });
+        ob.execute(OrderType::Cross { id: 5, buyer_id: 1, seller_id: 3, qty: 10, price: 400 });
+        let records = ob.take_clearing_records();
+        assert_eq!(
+            records,
+            vec![
+                ClearingRecord {
+                    trade_id: 2,
+                    sequence: 1,
+                    timestamp: 0,
+                    buy_order_id: 3,
+                    sell_order_id: 4,
+                    buyer_id: 3,
+                    seller_id: 2,
+                    qty: 40,
+                    price: 390,
+                    aggressor_side: Some(Side::Ask),
+                    buyer_fee: -15,
+                    seller_fee: 46,
+                },
+                ClearingRecord {
+                    trade_id: 3,
+                    sequence: 2,
+                    timestamp: 0,
+                    buy_order_id: 5,
+                    sell_order_id: 5,
+                    buyer_id: 1,
+                    seller_id: 3,
+                    qty: 10,
+                    price: 400,
+                    aggressor_side: None,
+                    buyer_fee: -4,
+                    seller_fee: -4,
+                },
+            ]
+        );
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), records);
+        assert!(ob.take_clearing_records().is_empty());
+    }
+
+    #[test]
+    fn fill_history_recorded_for_both_sides() {
+        let mut ob = OrderBook::default();
+        ob.track_fills(true);
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 });
+        ob.execute(OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 5, price: 396 });
+        ob.execute(OrderType::Market { user_id: 2, id: 3, side: Side::Bid, qty: 4 });
+        assert_eq!(
+            ob.fills_for(3),
+            &[
+                FillRecord { counterparty_id: 1, qty: 2, price: 395, sequence: 1 },
+                FillRecord { counterparty_id: 2, qty: 2, price: 396, sequence: 2 },
+            ]
+        );
+        assert_eq!(ob.fills_for(1), &[FillRecord { counterparty_id: 3, qty: 2, price: 395, sequence: 1 }]);
+        assert_eq!(ob.fills_for(2), &[FillRecord { counterparty_id: 3, qty: 2, price: 396, sequence: 2 }]);
+        ob.track_fills(false);
+        ob.execute(OrderType::Market { user_id: 2, id: 4, side: Side::Bid, qty: 1 });
+        assert_eq!(ob.fills_for(4), &[]);
+    }
+
+    #[test]
+    fn client_order_ids_echoed_and_cancelable() {
+        let mut ob = OrderBook::default();
+        let result = ob.execute_with_client_id(
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 },
+            77,
+        );
+        assert_eq!(result, ClientOrderEvent { client_order_id: 77, event: OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 395 } });
+        assert_eq!(ob.client_order_id(1), Some(77));
+        let result = ob.execute_with_client_id(
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 2, price: 396 },
+            77,
+        );
+        assert_eq!(
+            result.event,
+            OrderEvent::Rejected { id: 2, message: RejectReason::DuplicateClientOrderId }
+        );
+        let result = ob.cancel_by_client_id(77);
+        assert_eq!(result, Some(ClientOrderEvent { client_order_id: 77, event: OrderEvent::Cancelled { id: 1 } }));
+        assert_eq!(ob.client_order_id(1), None);
+        assert_eq!(ob.cancel_by_client_id(77), None);
+        assert_eq!(ob.metrics().orders_processed, 2);
+    }
+
+    #[test]
+    fn fills_carry_the_client_order_id_of_the_maker() {
+        let mut ob = OrderBook::default();
+        ob.execute_with_client_id(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 5, price: 100 }, 77);
+        ob.execute(OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 5, price: 101 });
+        let event = ob.execute_with_client_id(OrderType::Market { user_id: 2, id: 3, side: Side::Bid, qty: 7 }, 88).event;
+        let OrderEvent::Filled { fills, .. } = event else {
+            panic!("unexpected event {:?}", event);
+        };
+        let makers: Vec<_> = fills.iter().map(|fill| (fill.maker_id, fill.maker_client_order_id)).collect();
+        assert_eq!(makers, vec![(1, Some(77)), (2, None)]);
+    }
+
+    #[test]
+    fn fills_report_what_is_left_of_a_partially_filled_maker() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Bid, qty: 10, price: 100 });
+        let mut sell = |id, qty| -> Vec<_> {
+            let event = ob.execute(OrderType::Market { user_id: 2, id, side: Side::Ask, qty });
+            let OrderEvent::Filled { fills, .. } = event else {
+                panic!("unexpected event {:?}", event);
+            };
+            fills.iter().map(|fill| (fill.maker_user_id, fill.qty, fill.total_fill, fill.maker_remaining_qty)).collect()
+        };
+        assert_eq!(sell(2, 3), vec![(1, 3, false, 7)]);
+        assert_eq!(sell(3, 4), vec![(1, 4, false, 3)]);
+        assert_eq!(sell(4, 3), vec![(1, 3, true, 0)]);
+    }
+
+    #[test]
+    fn partial_ioc_fills_report_the_applied_maker_quantity() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 4, price: 100 });
+        ob.execute(OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 4, price: 102 });
+        let mut buy = |id, qty| -> Vec<_> {
+            let event = ob.execute(OrderType::IOC { user_id: 2, id, side: Side::Bid, qty, price: 100 });
+            let (OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. }) = event else {
+                panic!("unexpected event {:?}", event);
+            };
+            fills.iter().map(|fill| (fill.maker_id, fill.maker_remaining_qty)).collect()
+        };
+        assert_eq!(buy(3, 3), vec![(1, 1)]);
+        assert_eq!(buy(4, 3), vec![(1, 0)]);
+        assert_eq!(ob.available_qty(Side::Bid, 102), 4);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn cancel_session_pulls_resting_orders() {
+        let mut ob = OrderBook::default();
+        ob.execute_in_session(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 }, 5);
+        ob.execute_in_session(OrderType::Limit { user_id: 1, id: 2, side: Side::Bid, qty: 2, price: 390 }, 5);
+        ob.execute_in_session(OrderType::Limit { user_id: 2, id: 3, side: Side::Ask, qty: 2, price: 396 }, 6);
+        ob.execute_in_session(OrderType::Market { user_id: 2, id: 4, side: Side::Bid, qty: 2 }, 6);
+        assert_eq!(ob.cancel_session(5), vec![OrderEvent::Cancelled { id: 2 }]);
+        assert_eq!(ob._bids(), Vec::new());
+        assert_eq!(ob._asks(), init_book(vec![(396, 3)]));
+        assert_eq!(ob.cancel_session(5), Vec::new());
+        assert_eq!(ob.cancel_session(6), vec![OrderEvent::Cancelled { id: 3 }]);
+        assert_eq!(ob.min_ask(), Price::MAX);
+        assert_eq!(ob.best_ask(), None);
+    }
+
+    #[test]
+    fn duplicate_order_id_rejected() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Bid, qty: 2, price: 390 },
+            OrderType::Market { user_id: 1, id: 3, side: Side::Bid, qty: 2 },
+        ]);
+        let result = ob.execute(OrderType::Limit { user_id: 2, id: 2, side: Side::Bid, qty: 5, price: 391 });
+        assert_eq!(result, OrderEvent::Rejected { id: 2, message: RejectReason::DuplicateOrderId });
+        assert_eq!(ob.arena.get(2), Some(&LimitOrder { user_id: 1, id: 2, qty: 2, price: 390, created_at: 0 }));
+        let result = ob.execute(OrderType::Limit { user_id: 2, id: 1, side: Side::Bid, qty: 5, price: 391 });
+        assert_eq!(result, OrderEvent::Rejected { id: 1, message: RejectReason::InvalidSequence });
+    }
+
+    #[test]
+    fn resent_orders_within_the_dedup_window_are_duplicates() {
+        let (mut ob, _) = init_ob(vec![]);
+        let limit = |id| OrderType::Limit { user_id: 1, id, side: Side::Ask, qty: 2, price: 395 };
+        ob.execute(limit(1));
+        assert_eq!(ob.execute(limit(1)), OrderEvent::Rejected { id: 1, message: RejectReason::DuplicateOrderId });
+
+        ob.set_dedup_window(Some(2));
+        let market = OrderType::Market { user_id: 2, id: 2, side: Side::Bid, qty: 2 };
+        assert!(matches!(ob.execute(market), OrderEvent::Filled { id: 2, .. }));
+        ob.execute(limit(3));
+        assert_eq!(ob.execute(market), OrderEvent::Duplicate { id: 2 });
+        assert_eq!(ob.try_execute(limit(3)), Ok(OrderEvent::Duplicate { id: 3 }));
+        assert_eq!(ob.last_sequence(), 3);
+        assert_eq!(ob._asks(), vec![(395, vec![3])]);
+
+        // Only the last two IDs are remembered.
+        ob.execute(limit(4));
+        assert_eq!(ob.execute(market), OrderEvent::Rejected { id: 2, message: RejectReason::InvalidSequence });
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn skipped_order_ids_are_recorded_as_gaps() {
+        let (mut ob, _) = init_ob(vec![]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        ob.set_gap_sink(Some(Box::new(sender)));
+        let limit = |id| OrderType::Limit { user_id: 1, id, side: Side::Ask, qty: 2, price: 395 };
+        ob.execute(limit(3));
+        ob.execute(limit(4));
+        assert!(ob.gaps().is_empty());
+        ob.track_gaps(true);
+        assert_eq!(ob.execute(limit(7)), OrderEvent::Open { id: 7, remaining_qty: 2, resting_price: 395 });
+
+        // Orders rejected before reaching the book were received.
+        ob.set_risk_limits(1, Some(RiskLimits::default().with_max_order_qty(1)));
+        assert!(matches!(ob.execute(limit(8)), OrderEvent::Rejected { id: 8, .. }));
+        ob.set_risk_limits(1, None);
+        ob.execute(limit(9));
+        ob.execute(limit(12));
+        let gap = |first, last| SequenceGap { first, last };
+        assert_eq!(ob.gaps(), &[gap(5, 6), gap(10, 11)]);
+        assert!(ob.gaps()[1].contains(11) && !ob.gaps()[1].contains(12));
+
+        // Missing orders arriving late do not close their gap.
+        assert_eq!(ob.execute(limit(5)), OrderEvent::Rejected { id: 5, message: RejectReason::InvalidSequence });
+        assert_eq!(ob.gaps(), &[gap(5, 6), gap(10, 11)]);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![gap(5, 6), gap(10, 11)]);
+        assert_eq!(ob.take_gaps().len(), 2);
+        assert!(ob.gaps().is_empty());
+    }
+
+    #[test]
+    fn level_quantity_overflow_rejected() {
+        let (mut ob, _) = init_ob(vec![OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: Qty::MAX - 1, price: 395 }]);
+        let limit = |id, qty, price| OrderType::Limit { user_id: 2, id, side: Side::Ask, qty, price };
+        assert_eq!(ob.try_execute(limit(2, 2, 395)), Err(ExecuteError::QuantityOverflow { id: 2, price: 395 }));
+        assert_eq!(ob.execute(limit(3, 2, 395)), OrderEvent::Rejected { id: 3, message: RejectReason::QuantityOverflow });
+        assert_eq!(ob.execute(limit(4, 1, 395)), OrderEvent::Open { id: 4, remaining_qty: 1, resting_price: 395 });
+        assert_eq!(ob.execute(limit(5, Qty::MAX, 396)), OrderEvent::Open { id: 5, remaining_qty: Qty::MAX, resting_price: 396 });
+
+        // Notionals beyond 64 bits still average correctly, and the volume
+        // saturates when quantities are 64-bit wide.
+        let big = u64::MAX as Qty;
+        let (mut ob, _) = init_ob(vec![limit(1, big, 395), limit(2, big, 397)]);
+        ob.track_stats(true);
+        ob.execute(OrderType::Market { user_id: 3, id: 3, side: Side::Bid, qty: big });
+        assert_eq!(ob.last_trade().map(|trade| trade.avg_price), Some(395.0));
+        ob.execute(OrderType::Market { user_id: 3, id: 4, side: Side::Bid, qty: big });
+        assert_eq!(ob.traded_volume(), big.saturating_add(big));
+    }
+
+    #[test]
+    fn try_execute_separates_validation_failures() {
+        let (mut ob, _) = init_ob(vec![OrderType::Limit { user_id: 1, id: 2, side: Side::Bid, qty: 2, price: 390 }]);
+        let limit = |id| OrderType::Limit { user_id: 2, id, side: Side::Bid, qty: 5, price: 391 };
+        assert_eq!(ob.try_execute(limit(2)), Err(ExecuteError::DuplicateOrderId { id: 2 }));
+        let error = ob.try_execute(limit(1)).unwrap_err();
+        assert_eq!(error, ExecuteError::InvalidSequence { id: 1, last_id: 2 });
+        assert_eq!((error.id(), error.reason()), (1, RejectReason::InvalidSequence));
+        let market = OrderType::Market { user_id: 2, id: 3, side: Side::Bid, qty: 5 };
+        let rejected = OrderEvent::Rejected { id: 3, message: RejectReason::LiquidityNotAvailable };
+        assert_eq!(ob.try_execute(market), Ok(rejected));
+    }
+
+    #[test]
+    fn admin_cancel_emits_reason() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 },
+        ]);
+        assert_eq!(
+            ob.admin_cancel(1, "erroneous price"),
+            OrderEvent::AdminCancelled { id: 1, reason: "erroneous price".to_string() }
+        );
+        assert_eq!(ob._asks(), Vec::new());
+        assert_eq!(
+            ob.admin_cancel(1, "erroneous price"),
+            OrderEvent::CancelRejected { id: 1, message: RejectReason::OrderNotFound }
+        );
+    }
+
+    #[test]
+    fn rate_limited_user_rejected() {
+        let clock = ManualClock::new(0);
+        let mut ob = OrderBook::default();
+        ob.set_clock(Box::new(clock.clone()));
+        ob.set_rate_limiter(Some(RateLimiter::new(2, 100, Box::new(clock.clone()))));
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 });
+        ob.execute(OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 2, price: 396 });
+        let result = ob.execute(OrderType::Cancel { id: 1 });
+        assert_eq!(result, OrderEvent::Rejected { id: 1, message: RejectReason::RateLimitExceeded });
+        let result = ob.execute(OrderType::Limit { user_id: 2, id: 3, side: Side::Ask, qty: 2, price: 397 });
+        assert_eq!(result, OrderEvent::Open { id: 3, remaining_qty: 2, resting_price: 397 });
+        clock.advance(50);
+        assert_eq!(ob.execute(OrderType::Cancel { id: 1 }), OrderEvent::Cancelled { id: 1 });
+        ob.set_rate_limiter(None);
+        assert_eq!(ob.execute(OrderType::Cancel { id: 2 }), OrderEvent::Cancelled { id: 2 });
+    }
+
+    #[test]
+    fn risk_limits_reject_orders_of_limited_users() {
+        let mut ob = OrderBook::default();
+        let limits = RiskLimits::default().with_max_order_qty(10).with_max_order_notional(2_000);
+        ob.set_risk_limits(1, Some(limits.with_max_open_orders(2).with_max_open_notional(1_500)));
+        assert_eq!(ob.risk_limits(1), Some(limits.with_max_open_orders(2).with_max_open_notional(1_500)));
+        let rejected = |id, message| OrderEvent::Rejected { id, message };
+
+        let order = |id, qty, price| OrderType::Limit { user_id: 1, id, side: Side::Ask, qty, price };
+        assert_eq!(ob.execute(order(1, 11, 100)), rejected(1, RejectReason::OrderQtyLimitExceeded));
+        assert_eq!(ob.execute(order(2, 10, 201)), rejected(2, RejectReason::OrderNotionalLimitExceeded));
+        assert_eq!(ob.execute(order(3, 10, 100)), OrderEvent::Open { id: 3, remaining_qty: 10, resting_price: 100 });
+        assert_eq!(ob.execute(order(4, 6, 100)), rejected(4, RejectReason::OpenNotionalLimitExceeded));
+        assert_eq!(ob.execute(order(5, 5, 100)), OrderEvent::Open { id: 5, remaining_qty: 5, resting_price: 100 });
+        assert_eq!(ob.execute(order(6, 1, 1)), rejected(6, RejectReason::OpenOrdersLimitExceeded));
+        // Orders that cannot rest only count against the per-order limits.
+        let ioc = OrderType::IOC { user_id: 1, id: 7, side: Side::Ask, qty: 1, price: 1 };
+        assert_eq!(ob.execute(ioc), OrderEvent::Cancelled { id: 7 });
+        // Market orders are valued at the prices they would sweep.
+        let market = |id, qty| OrderType::Market { user_id: 2, id, side: Side::Bid, qty };
+        ob.set_risk_limits(2, Some(RiskLimits::default().with_max_order_notional(1_200)));
+        assert_eq!(ob.execute(market(8, 13)), rejected(8, RejectReason::OrderNotionalLimitExceeded));
+        assert!(matches!(ob.execute(market(9, 12)), OrderEvent::Filled { id: 9, .. }));
+
+        ob.set_risk_limits(1, None);
+        assert_eq!(ob.risk_limits(1), None);
+        assert_eq!(ob.execute(order(10, 11, 100)), OrderEvent::Open { id: 10, remaining_qty: 11, resting_price: 100 });
+    }
+
+    #[test]
+    fn deltas_report_changed_levels() {
+        let mut ob = OrderBook::default();
+        ob.track_deltas(true);
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 });
+        ob.execute(OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 5, price: 396 });
+        ob.execute(OrderType::Limit { user_id: 1, id: 3, side: Side::Bid, qty: 5, price: 390 });
+        assert_eq!(
+            ob.take_deltas(),
+            vec![
+                BookDelta { side: Side::Ask, price: 395, qty: 2, reason: DeltaReason::Add },
+                BookDelta { side: Side::Ask, price: 396, qty: 5, reason: DeltaReason::Add },
+                BookDelta { side: Side::Bid, price: 390, qty: 5, reason: DeltaReason::Add },
+            ]
+        );
+        ob.execute(OrderType::Market { user_id: 2, id: 4, side: Side::Bid, qty: 3 });
+        ob.execute(OrderType::Reduce { id: 3, qty_delta: 1 });
+        ob.execute(OrderType::Cancel { id: 2 });
+        assert_eq!(
+            ob.take_deltas(),
+            vec![
+                BookDelta { side: Side::Ask, price: 395, qty: 0, reason: DeltaReason::Fill },
+                BookDelta { side: Side::Ask, price: 396, qty: 0, reason: DeltaReason::Cancel },
+                BookDelta { side: Side::Bid, price: 390, qty: 4, reason: DeltaReason::Modify },
+            ]
+        );
+        assert_eq!(ob.take_deltas(), Vec::new());
+    }
+
+    #[test]
+    fn order_updates_report_every_order_change() {
+        let mut ob = OrderBook::default();
+        ob.track_order_updates(true);
+        ob.execute(OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 395 });
+        ob.execute(OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 5, price: 396 });
+        ob.execute(OrderType::Limit { user_id: 2, id: 3, side: Side::Bid, qty: 4, price: 396 });
+        ob.execute(OrderType::Reduce { id: 2, qty_delta: 1 });
+        ob.execute(OrderType::Cancel { id: 2 });
+        assert_eq!(
+            ob.take_order_updates(),
+            vec![
+                OrderUpdate::Add { id: 1, side: Side::Ask, price: 395, qty: 2 },
+                OrderUpdate::Add { id: 2, side: Side::Ask, price: 396, qty: 5 },
+                OrderUpdate::Execute { id: 1, side: Side::Ask, price: 395, qty: 2, remaining_qty: 0 },
+                OrderUpdate::Execute { id: 2, side: Side::Ask, price: 396, qty: 2, remaining_qty: 3 },
+                OrderUpdate::Modify { id: 2, side: Side::Ask, price: 396, qty: 2 },
+                OrderUpdate::Delete { id: 2, side: Side::Ask, price: 396 },
+            ]
+        );
+        assert_eq!(ob.take_order_updates(), Vec::new());
+    }
+
+    #[test]
+    fn depth_limited_to_best_levels() {
+        let (ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 397 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 5, price: 396 },
+            OrderType::Limit { user_id: 1, id: 3, side: Side::Ask, qty: 1, price: 398 },
+            OrderType::Limit { user_id: 1, id: 4, side: Side::Bid, qty: 4, price: 390 },
+            OrderType::Limit { user_id: 1, id: 5, side: Side::Bid, qty: 3, price: 392 },
+            OrderType::Limit { user_id: 1, id: 6, side: Side::Bid, qty: 7, price: 391 },
+        ]);
+        assert_eq!(
+            ob.depth(2, false),
+            BookDepth {
+                levels: 2,
+                asks: vec![
+                    BookLevel { price: 396, qty: 5, cumulative_qty: 5, cumulative_notional: 1980, orders: vec![] },
+                    BookLevel { price: 397, qty: 2, cumulative_qty: 7, cumulative_notional: 2774, orders: vec![] },
+                ],
+                bids: vec![
+                    BookLevel { price: 392, qty: 3, cumulative_qty: 3, cumulative_notional: 1176, orders: vec![] },
+                    BookLevel { price: 391, qty: 7, cumulative_qty: 10, cumulative_notional: 3913, orders: vec![] },
+                ],
             }
+        );
+        assert_eq!(ob.depth(0, false).asks, Vec::new());
+    }
+
+    #[test]
+    fn available_qty_at_or_better() {
+        let (ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 397 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 5, price: 396 },
+            OrderType::Limit { user_id: 1, id: 3, side: Side::Ask, qty: 1, price: 396 },
+            OrderType::Limit { user_id: 1, id: 4, side: Side::Bid, qty: 4, price: 390 },
+            OrderType::Limit { user_id: 1, id: 5, side: Side::Bid, qty: 3, price: 392 },
+        ]);
+        assert_eq!(ob.available_qty(Side::Bid, 395), 0);
+        assert_eq!(ob.available_qty(Side::Bid, 396), 6);
+        assert_eq!(ob.available_qty(Side::Bid, 400), 8);
+        assert_eq!(ob.available_qty(Side::Ask, 392), 3);
+        assert_eq!(ob.available_qty(Side::Ask, 0), 7);
+    }
+
+    #[test]
+    fn available_qty_saturates_near_the_maximum_quantity() {
+        let ask = |id, qty, price| OrderType::Limit { user_id: 1, id, side: Side::Ask, qty, price };
+        let (mut ob, _) = init_ob(vec![ask(1, Qty::MAX - 1, 100), ask(2, 5, 101)]);
+        assert_eq!(ob.available_qty(Side::Bid, 100), Qty::MAX - 1);
+        assert_eq!(ob.available_qty(Side::Bid, 101), Qty::MAX);
+
+        let fok = |id, price| OrderType::FOK { user_id: 2, id, side: Side::Bid, qty: Qty::MAX, price };
+        assert_eq!(ob.execute(fok(3, 100)), OrderEvent::Cancelled { id: 3 });
+        assert!(matches!(ob.execute(fok(4, 101)), OrderEvent::Filled { id: 4, filled_qty: Qty::MAX, .. }));
+        assert_eq!(ob.available_qty(Side::Bid, 101), 4);
+    }
+
+    #[test]
+    fn partial_ioc_fills_are_applied_before_they_are_taped() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 5, price: 101 });
+        let ioc = |id| OrderType::IOC { id, user_id: 2, side: Side::Bid, qty: 4, price: 101 };
+        assert!(matches!(ob.execute(ioc(3)), OrderEvent::Filled { id: 3, filled_qty: 4, .. }));
+        assert!(matches!(ob.execute(ioc(4)), OrderEvent::PartiallyFilled { id: 4, filled_qty: 3, remaining_qty: 0, resting_price: None, .. }));
+        assert!(matches!(ob.execute(ioc(5)), OrderEvent::Cancelled { id: 5 }));
+
+        assert_eq!(ob._asks(), Vec::new());
+        assert_eq!(ob.bbo(), Bbo::default());
+        let tape: Vec<_> = ob.tape().map(|trade| (trade.taker_id, trade.maker_id, trade.qty)).collect();
+        assert_eq!(tape, vec![(3, 1, 2), (3, 2, 2), (4, 2, 3)]);
+    }
+
+    #[test]
+    fn trade_tape_records_fills_and_crosses() {
+        let mut ob = OrderBook::default();
+        ob.set_tape_capacity(Some(10));
+        ob.set_clock(Box::new(ManualClock::new(5)));
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 2, price: 101 });
+        ob.execute(OrderType::Market { id: 3, user_id: 2, side: Side::Bid, qty: 3 });
+        ob.execute(OrderType::Cross { id: 4, buyer_id: 3, seller_id: 4, qty: 7, price: 99 });
+        let tape: Vec<TapeEntry> = ob.tape().copied().collect();
+        assert_eq!(
+            tape,
+            vec![
+                TapeEntry { sequence: 1, trade_id: 1, timestamp: 5, taker_id: 3, maker_id: 1, buyer_id: 2, seller_id: 1, aggressor_side: Some(Side::Bid), qty: 2, price: 100 },
+                TapeEntry { sequence: 2, trade_id: 2, timestamp: 5, taker_id: 3, maker_id: 2, buyer_id: 2, seller_id: 1, aggressor_side: Some(Side::Bid), qty: 1, price: 101 },
+                TapeEntry { sequence: 3, trade_id: 3, timestamp: 5, taker_id: 4, maker_id: 4, buyer_id: 3, seller_id: 4, aggressor_side: None, qty: 7, price: 99 },
+            ]
+        );
+        assert_eq!(ob.tape_page(1, 1), vec![tape[1]]);
+        let recent: Vec<u64> = ob.recent_trades(2).map(|trade| trade.sequence).collect();
+        assert_eq!(recent, vec![3, 2]);
+    }
+
+    #[test]
+    fn recent_trades_come_from_a_bounded_tape() {
+        let mut ob = OrderBook::default();
+        let trades = |ob: &OrderBook, n| ob.recent_trades(n).map(|trade| (trade.trade_id, trade.qty)).collect::<Vec<_>>();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 10, price: 100 });
+        for (id, qty) in [(2, 1), (3, 2)] {
+            ob.execute(OrderType::Market { id, user_id: 2, side: Side::Bid, qty });
+        }
+        // The tape is on by default.
+        assert_eq!(trades(&ob, 5), vec![(2, 2), (1, 1)]);
+
+        ob.set_tape_capacity(Some(2));
+        for (id, qty) in [(4, 3), (5, 1), (6, 2)] {
+            ob.execute(OrderType::Market { id, user_id: 2, side: Side::Bid, qty });
         }
+        assert_eq!(trades(&ob, 5), vec![(5, 2), (4, 1)]);
+        assert_eq!(trades(&ob, 1), vec![(5, 2)]);
+        assert_eq!(trades(&ob, 0), vec![]);
+
+        ob.set_tape_capacity(None);
+        ob.execute(OrderType::Market { id: 7, user_id: 2, side: Side::Bid, qty: 1 });
+        assert_eq!(trades(&ob, 5), vec![]);
     }
 
     #[test]
-    fn crossing_limit_order_partial() {
-        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (mut ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *ask_bid,
-                    qty: 2,
-                    price: 399,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 3,
-                    side: *bid_ask,
-                    qty: 2,
-                    price: 398,
-                },
-            ]);
-            let result = ob.execute(OrderType::Limit {
-                user_id: 1,
-                id: 4,
-                side: *ask_bid,
-                qty: 1,
-                price: 397,
-            });
+    fn trade_ids_increase_across_fills_crosses_and_rfqs() {
+        let mut ob = OrderBook::default();
+        ob.track_clearing(true);
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 1, price: 100 });
+        ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 1, price: 101 });
+        let trade_ids = |event: OrderEvent| match event {
+            OrderEvent::Filled { fills, .. } => fills.iter().map(|fill| fill.trade_id).collect(),
+            event => panic!("unexpected event {:?}", event),
+        };
+        let ids: Vec<u64> = trade_ids(ob.execute(OrderType::Market { id: 3, user_id: 2, side: Side::Bid, qty: 2 }));
+        assert_eq!(ids, vec![1, 2]);
 
-            if *bid_ask == Side::Bid {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Open { id: 2 },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::Filled {
-                        id: 4,
-                        filled_qty: 1,
-                        fills: vec![FillMetadata {
-                            taker_id: 4,
-                            maker_id: 3,
-                            qty: 1,
-                            price: 398,
-                            taker_side: *ask_bid,
-                            total_fill: false,
-                        }]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 399);
-                assert_eq!(ob.max_bid(), 398);
-                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
-                assert_eq!(
-                    ob._bids(),
-                    init_book(vec![(398, 3), (395, 1)])
-                );
-                assert_eq!(ob.spread(), 1);
-            } else {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Filled {
-                            id: 2,
-                            filled_qty: 2,
-                            fills: vec![FillMetadata {
-                                taker_id: 2,
-                                maker_id: 1,
-                                qty: 2,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: false,
-                            }],
-                        },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::Filled {
-                        id: 4,
-                        filled_qty: 1,
-                        fills: vec![FillMetadata {
-                            taker_id: 4,
-                            maker_id: 1,
-                            qty: 1,
-                            price: 395,
-                            taker_side: *ask_bid,
-                            total_fill: false,
-                        }]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0);
-                assert_eq!(
-                    ob._asks(),
-                    init_book(vec![(398, 3), (395, 1)])
-                );
-                assert_eq!(ob._bids(), init_book(vec![]));
-                assert_eq!(ob.spread(), 395);
+        // Orders that do not trade leave the trade IDs alone.
+        ob.execute(OrderType::Limit { id: 4, user_id: 1, side: Side::Ask, qty: 1, price: 102 });
+        assert!(matches!(ob.execute(OrderType::Market { id: 5, user_id: 2, side: Side::Ask, qty: 1 }), OrderEvent::Rejected { .. }));
+        assert_eq!(ob.last_trade_id(), 2);
+
+        ob.execute(OrderType::Cross { id: 6, buyer_id: 3, seller_id: 4, qty: 1, price: 102 });
+        assert_eq!(ob.last_trade_id(), 3);
+        ob.request_quote(7, 2, Side::Bid, 1, vec![5], 10).unwrap();
+        ob.submit_quote(7, 8, 5, 1, 101, 5).unwrap();
+        assert_eq!(trade_ids(ob.execute_rfq(7).unwrap()), vec![4]);
+
+        let mut restored = OrderBook::default();
+        restored.restore(ob.snapshot());
+        let ids: Vec<u64> = trade_ids(restored.execute(OrderType::Market { id: 9, user_id: 2, side: Side::Bid, qty: 1 }));
+        assert_eq!(ids, vec![5]);
+
+        // The tape and the clearing records carry the same IDs.
+        assert_eq!(ob.tape().map(|trade| trade.trade_id).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(ob.take_clearing_records().iter().map(|record| record.trade_id).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn replayed_trades_keep_their_ids() {
+        let journal = MemoryJournal::new();
+        let mut ob = OrderBook::default();
+        ob.set_journal(Some(Box::new(journal.clone())));
+        let mut ids = Vec::new();
+        let record = |ids: &mut Vec<u64>, event: OrderEvent| {
+            if let OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } = event {
+                ids.extend(fills.iter().map(|fill| fill.trade_id));
             }
+        };
+        record(&mut ids, ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 }));
+        record(&mut ids, ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 2, price: 101 }));
+        // The remainder of the IOC and the killed FOK do not take up IDs.
+        record(&mut ids, ob.execute(OrderType::IOC { id: 3, user_id: 2, side: Side::Bid, qty: 5, price: 101 }));
+        record(&mut ids, ob.execute(OrderType::Limit { id: 4, user_id: 1, side: Side::Ask, qty: 2, price: 102 }));
+        record(&mut ids, ob.execute(OrderType::FOK { id: 5, user_id: 2, side: Side::Bid, qty: 3, price: 102 }));
+        ob.execute(OrderType::Cross { id: 6, buyer_id: 3, seller_id: 4, qty: 1, price: 102 });
+        ids.push(ob.last_trade_id());
+        ob.request_quote(7, 2, Side::Bid, 1, vec![5], 10).unwrap();
+        ob.submit_quote(7, 8, 5, 1, 101, 5).unwrap();
+        record(&mut ids, ob.execute_rfq(7).unwrap());
+        ob.set_phase(TradingPhase::PreOpen);
+        record(&mut ids, ob.execute(OrderType::Limit { id: 9, user_id: 2, side: Side::Bid, qty: 3, price: 103 }));
+        for event in ob.set_phase(TradingPhase::Open) {
+            record(&mut ids, event);
         }
+        record(&mut ids, ob.execute(OrderType::Market { id: 10, user_id: 2, side: Side::Ask, qty: 1 }));
+        assert_eq!(ids, (1..=6).collect::<Vec<_>>());
+
+        let mut replayed = OrderBook::default();
+        replayed.replay_entries(journal.entries());
+        assert_eq!(replayed.tape().map(|trade| trade.trade_id).collect::<Vec<_>>(), ids);
+        assert_eq!(replayed.last_trade_id(), ob.last_trade_id());
     }
 
     #[test]
-    fn crossing_limit_order_matching() {
-        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (mut ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *ask_bid,
-                    qty: 2,
-                    price: 399,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 3,
-                    side: *bid_ask,
-                    qty: 2,
-                    price: 398,
-                },
-            ]);
-            let result = ob.execute(OrderType::Limit {
-                user_id: 1,
-                id: 4,
-                side: *ask_bid,
-                qty: 2,
-                price: 397,
-            });
+    fn top_of_book_prices() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 104 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 1, price: 104 },
+            OrderType::Limit { user_id: 1, id: 3, side: Side::Bid, qty: 1, price: 100 },
+        ]);
+        assert_eq!(ob.mid_price(), Some(102.0));
+        assert_eq!(ob.weighted_mid(), Some(103.0));
+        assert_eq!(ob.microprice(), Some(101.0));
+        assert_eq!(ob.bbo(), Bbo { bid: Some((100, 1)), ask: Some((104, 3)) });
+        ob.execute(OrderType::Reduce { id: 1, qty_delta: 1 });
+        ob.execute(OrderType::Market { user_id: 2, id: 4, side: Side::Ask, qty: 1 });
+        assert_eq!(ob.mid_price(), None);
+        assert_eq!(ob.bbo(), Bbo { bid: None, ask: Some((104, 2)) });
+        ob.execute(OrderType::Limit { user_id: 1, id: 5, side: Side::Bid, qty: 2, price: 101 });
+        assert_eq!(ob.microprice(), Some(102.5));
+    }
 
-            if *bid_ask == Side::Bid {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Open { id: 2 },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::Filled {
-                        id: 4,
-                        filled_qty: 2,
-                        fills: vec![FillMetadata {
-                            taker_id: 4,
-                            maker_id: 3,
-                            qty: 2,
-                            price: 398,
-                            taker_side: *ask_bid,
-                            total_fill: true,
-                        }]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 399);
-                assert_eq!(ob.max_bid(), 395);
-                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
-                assert_eq!(
-                    ob._bids(),
-                    init_book_holes(vec![(395, 1)], vec![398])
-                );
-                assert_eq!(ob.spread(), 4);
-            } else {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Filled {
-                            id: 2,
-                            filled_qty: 2,
-                            fills: vec![FillMetadata {
-                                taker_id: 2,
-                                maker_id: 1,
-                                qty: 2,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: false,
-                            }],
-                        },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::Filled {
-                        id: 4,
-                        filled_qty: 2,
-                        fills: vec![FillMetadata {
-                            taker_id: 4,
-                            maker_id: 1,
-                            qty: 2,
-                            price: 395,
-                            taker_side: *ask_bid,
-                            total_fill: false,
-                        }]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0u64);
-                assert_eq!(
-                    ob._asks(),
-                    init_book(vec![(395, 1), (398, 3)])
-                );
-                assert_eq!(ob._bids(), init_book(vec![]));
-                assert_eq!(ob.spread(), 395);
-            }
+    #[test]
+    fn bbo_follows_the_top_levels() {
+        let (mut ob, _) = init_ob(vec![]);
+        assert_eq!(ob.bbo(), Bbo::default());
+        let limit = |id, side, qty, price| OrderType::Limit { user_id: 1, id, side, qty, price };
+        for order in [limit(1, Side::Bid, 2, 99), limit(2, Side::Bid, 3, 100), limit(3, Side::Bid, 4, 100), limit(4, Side::Ask, 5, 102)] {
+            ob.execute(order);
         }
+        assert_eq!(ob.bbo(), Bbo { bid: Some((100, 7)), ask: Some((102, 5)) });
+
+        ob.execute(OrderType::Market { user_id: 2, id: 5, side: Side::Ask, qty: 4 });
+        assert_eq!(ob.bbo().bid, Some((100, 3)));
+        ob.execute(OrderType::Cancel { id: 3 });
+        assert_eq!(ob.bbo().bid, Some((99, 2)));
+        ob.execute(OrderType::Limit { user_id: 2, id: 6, side: Side::Ask, qty: 1, price: 101 });
+        assert_eq!(ob.bbo(), Bbo { bid: Some((99, 2)), ask: Some((101, 1)) });
+        ob.execute(OrderType::Market { user_id: 2, id: 7, side: Side::Ask, qty: 2 });
+        assert_eq!(ob.bbo(), Bbo { bid: None, ask: Some((101, 1)) });
+    }
+
+    #[test]
+    fn checksum_over_top_levels() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 397 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 5, price: 396 },
+            OrderType::Limit { user_id: 1, id: 3, side: Side::Bid, qty: 4, price: 390 },
+            OrderType::Limit { user_id: 1, id: 4, side: Side::Bid, qty: 3, price: 392 },
+        ]);
+        assert_eq!(ob.checksum(1), crc32fast::hash(b"39653923"));
+        assert_eq!(ob.checksum(10), crc32fast::hash(b"3965397239233904"));
+        ob.execute(OrderType::Limit { user_id: 1, id: 5, side: Side::Bid, qty: 1, price: 380 });
+        assert_ne!(ob.checksum(10), crc32fast::hash(b"3965397239233904"));
+        assert_eq!(ob.checksum(2), crc32fast::hash(b"3965397239233904"));
+    }
+
+    #[test]
+    fn iterate_levels_best_first() {
+        let (ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Ask, qty: 2, price: 397 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Ask, qty: 5, price: 396 },
+            OrderType::Limit { user_id: 1, id: 3, side: Side::Ask, qty: 1, price: 396 },
+            OrderType::Limit { user_id: 1, id: 4, side: Side::Bid, qty: 4, price: 390 },
+            OrderType::Limit { user_id: 1, id: 5, side: Side::Bid, qty: 3, price: 392 },
+        ]);
+        let asks: Vec<(Price, Vec<u64>)> = ob.iter_asks().map(|(price, queue)| (price, queue.collect())).collect();
+        assert_eq!(asks, vec![(396, vec![2, 3]), (397, vec![1])]);
+        let bids: Vec<(Price, Vec<u64>)> = ob.iter_bids().map(|(price, queue)| (price, queue.collect())).collect();
+        assert_eq!(bids, vec![(392, vec![5]), (390, vec![4])]);
+        assert_eq!(ob.iter_ask_qtys().collect::<Vec<_>>(), vec![(396, 6), (397, 2)]);
+        assert_eq!(ob.iter_bid_qtys().collect::<Vec<_>>(), vec![(392, 3), (390, 4)]);
+    }
+
+    #[test]
+    fn depth_levels_accumulate_from_the_top_of_book() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { user_id: 1, id: 1, side: Side::Bid, qty: 2, price: 100 },
+            OrderType::Limit { user_id: 1, id: 2, side: Side::Bid, qty: 3, price: 99 },
+            OrderType::Limit { user_id: 1, id: 3, side: Side::Bid, qty: 1, price: 99 },
+            OrderType::Limit { user_id: 1, id: 4, side: Side::Bid, qty: 5, price: 97 },
+        ]);
+        let cumulative = |ob: &OrderBook, levels| {
+            ob.depth(levels, false).bids.iter().map(|level| (level.cumulative_qty, level.cumulative_notional)).collect::<Vec<_>>()
+        };
+        assert_eq!(cumulative(&ob, 5), vec![(2, 200), (6, 596), (11, 1081)]);
+        assert_eq!(cumulative(&ob, 2), vec![(2, 200), (6, 596)]);
+
+        // Filling the top level shifts the accumulation to the next one.
+        ob.execute(OrderType::Market { user_id: 2, id: 5, side: Side::Ask, qty: 3 });
+        assert_eq!(cumulative(&ob, 5), vec![(3, 297), (8, 782)]);
+
+        // Notional saturates rather than overflowing.
+        ob.execute(OrderType::Limit { user_id: 1, id: 6, side: Side::Bid, qty: 3, price: Price::MAX / 2 });
+        assert_eq!(cumulative(&ob, 2), vec![(3, Price::MAX), (6, Price::MAX)]);
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn depth_streamed_to_another_thread() {
+        let mut ob = OrderBook::default();
+        let receiver = ob.stream_depth(1, false);
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 3, price: 99 });
+        drop(ob);
+        let asks: Vec<usize> = std::thread::spawn(move || std::iter::from_fn(|| receiver.recv()).map(|depth| depth.asks.len()).collect())
+            .join()
+            .unwrap();
+        assert_eq!(asks, vec![0, 1, 1]);
+
+        let mut ob = OrderBook::default();
+        let receiver = ob.stream_depth(1, true);
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 3, price: 99 });
+        assert_eq!(receiver.try_recv().map(|depth| depth.asks[0].price), Some(99));
+        assert_eq!(receiver.try_recv(), None);
     }
 
     #[test]
-    fn crossing_limit_order_over() {
-        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (mut ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *ask_bid,
-                    qty: 2,
-                    price: 399,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 3,
-                    side: *bid_ask,
-                    qty: 2,
-                    price: 398,
-                },
-            ]);
-            let result = ob.execute(OrderType::Limit {
-                user_id: 1,
-                id: 4,
-                side: *ask_bid,
-                qty: 5,
-                price: 397,
-            });
+    fn snapshot_restores_identical_book() {
+        let mut ob = OrderBook::default();
+        ob.track_stats(true);
+        ob.set_clock(Box::new(ManualClock::new(10)));
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        ob.execute_with_client_id(OrderType::Limit { id: 2, user_id: 2, side: Side::Ask, qty: 3, price: 100 }, 77);
+        ob.execute_in_session(OrderType::Limit { id: 3, user_id: 3, side: Side::Bid, qty: 4, price: 98 }, 5);
+        ob.execute(OrderType::GTT { id: 4, user_id: 4, side: Side::Bid, qty: 1, price: 97, expires_at: 50 });
+        ob.execute(OrderType::Market { id: 5, user_id: 5, side: Side::Bid, qty: 1 });
 
-            if *bid_ask == Side::Bid {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Open { id: 2 },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::PartiallyFilled {
-                        id: 4,
-                        filled_qty: 2,
-                        fills: vec![FillMetadata {
-                            taker_id: 4,
-                            maker_id: 3,
-                            qty: 2,
-                            price: 398,
-                            taker_side: *ask_bid,
-                            total_fill: true,
-                        }]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 397);
-                assert_eq!(ob.max_bid(), 395);
-                assert_eq!(
-                    ob._asks(),
-                    init_book(vec![(399, 2), (397, 4)])
-                );
-                assert_eq!(
-                    ob._bids(),
-                    init_book_holes(vec![(395, 1)], vec![398])
-                );
-                assert_eq!(ob.spread(), 2);
-            } else {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Filled {
-                            id: 2,
-                            filled_qty: 2,
-                            fills: vec![FillMetadata {
-                                taker_id: 2,
-                                maker_id: 1,
-                                qty: 2,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: false,
-                            }],
-                        },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::Filled {
-                        id: 4,
-                        filled_qty: 5,
-                        fills: vec![FillMetadata {
-                            taker_id: 4,
-                            maker_id: 1,
-                            qty: 5,
-                            price: 395,
-                            taker_side: *ask_bid,
-                            total_fill: false,
-                        }]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0);
-                assert_eq!(
-                    ob._asks(),
-                    init_book(vec![(395, 1), (398, 3)])
-                );
-                assert_eq!(ob._bids(), init_book(vec![]));
-                assert_eq!(ob.spread(), 395);
-            }
+        let mut restored = OrderBook::default();
+        restored.restore(ob.snapshot());
+        assert_eq!(restored.depth(10, true), ob.depth(10, true));
+        assert_eq!(restored.bbo(), ob.bbo());
+        assert_eq!(restored.last_sequence(), 5);
+        assert_eq!(restored.traded_volume(), 1);
+        assert_eq!(restored.last_trade_id(), 1);
+        assert_eq!(restored.client_order_id(2), Some(77));
+        assert_eq!(restored.cancel_session(5), vec![OrderEvent::Cancelled { id: 3 }]);
+        restored.set_clock(Box::new(ManualClock::new(50)));
+        assert_eq!(restored.expire_orders(), vec![OrderEvent::Expired { id: 4 }]);
+
+        let order = OrderType::Market { id: 6, user_id: 6, side: Side::Bid, qty: 3 };
+        assert_eq!(restored.execute(order), ob.execute(order));
+    }
+
+    #[cfg(feature = "std")]
+    #[derive(Debug)]
+    struct BrokenJournal;
+
+    #[cfg(feature = "std")]
+    impl JournalSink for BrokenJournal {
+        fn append(&mut self, _entry: &JournalEntry) -> std::io::Result<()> {
+            Err(std::io::ErrorKind::Other.into())
         }
     }
 
     #[test]
-    fn market_order_rejected() {
-        for (_, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (mut ob, _) = init_ob(vec![]);
-            let result = ob.execute(OrderType::Market {
-                user_id: 1,
-                id: 1,
-                side: *ask_bid,
-                qty: 5,
-            });
+    #[cfg(feature = "std")]
+    fn journal_replay_rebuilds_book() {
+        let clock = ManualClock::new(10);
+        let journal = MemoryJournal::new();
+        let mut ob = OrderBook::default();
+        ob.set_clock(Box::new(clock.clone()));
+        ob.set_journal(Some(Box::new(journal.clone())));
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        ob.execute_with_client_id(OrderType::Limit { id: 2, user_id: 2, side: Side::Ask, qty: 3, price: 101 }, 77);
+        ob.execute_in_session(OrderType::Limit { id: 3, user_id: 3, side: Side::Bid, qty: 4, price: 98 }, 5);
+        ob.execute(OrderType::GTT { id: 4, user_id: 4, side: Side::Bid, qty: 1, price: 97, expires_at: 20 });
+        clock.advance(10);
+        ob.execute(OrderType::Market { id: 5, user_id: 5, side: Side::Bid, qty: 1 });
+        ob.expire_orders();
+        ob.cancel_where(Some(Side::Bid), .., Some(3));
+        let entries = journal.entries();
+        assert_eq!(entries.iter().map(|entry| entry.sequence).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(ob.journal_sequence(), 7);
+
+        let mut replayed = OrderBook::default();
+        assert_eq!(replayed.replay_entries(entries), 7);
+        assert_eq!(replayed.depth(10, true), ob.depth(10, true));
+        assert_eq!(replayed.last_sequence(), 5);
+        assert_eq!(replayed.journal_sequence(), 7);
+        assert_eq!(replayed.client_order_id(2), Some(77));
+
+        ob.set_journal(Some(Box::new(BrokenJournal)));
+        let order = OrderType::Limit { id: 6, user_id: 1, side: Side::Ask, qty: 2, price: 100 };
+        assert_eq!(ob.execute(order), OrderEvent::Rejected { id: 6, message: RejectReason::JournalUnavailable });
+        assert_eq!(ob.admin_cancel(2, "test"), OrderEvent::CancelRejected { id: 2, message: RejectReason::OrderNotFound });
+        assert_eq!(ob.last_sequence(), 5);
+    }
 
-            assert_eq!(result, OrderEvent::Rejected { id: 1, message: LIQUIDITY_NOT_AVAILABLE });
+    #[test]
+    #[cfg(all(feature = "std", feature = "serde"))]
+    fn journal_replay_from_reader() {
+        let mut ob = OrderBook::default();
+        ob.set_journal(Some(Box::new(WriterJournal::new(Vec::new()))));
+        let mut lines = Vec::new();
+        let mut journal = WriterJournal::new(&mut lines);
+        for (sequence, price) in [(1, 100), (2, 101)] {
+            let order = OrderType::Limit { id: sequence, user_id: 1, side: Side::Ask, qty: 2, price };
+            let entry = JournalEntry { sequence, timestamp: 0, order: Some(order), ..Default::default() };
+            journal.append(&entry).unwrap();
         }
+        assert_eq!(ob.replay(lines.as_slice()).unwrap(), 2);
+        assert_eq!(ob.best_ask(), Some(100));
+        assert_eq!(ob.last_sequence(), 2);
     }
 
     #[test]
-    fn market_order_partially_filled() {
-        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (mut ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *ask_bid,
-                    qty: 2,
-                    price: 399,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 3,
-                    side: *bid_ask,
-                    qty: 2,
-                    price: 398,
-                },
-            ]);
-            let result = ob.execute(OrderType::Market {
-                user_id: 1,
-                id: 4,
-                side: *ask_bid,
-                qty: 15,
-            });
+    #[cfg(all(feature = "std", feature = "serde"))]
+    fn load_jsonl_statistics() {
+        let mut ob = OrderBook::default();
+        let lines = r#"{"Limit":{"id":1,"user_id":1,"side":"Ask","qty":5,"price":100}}
+{"Limit":{"id":2,"user_id":1,"side":"Ask","qty":5,"price":101}}
 
-            if *bid_ask == Side::Bid {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Open { id: 2 },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::PartiallyFilled {
-                        id: 4,
-                        filled_qty: 14,
-                        fills: vec![
-                            FillMetadata {
-                                taker_id: 4,
-                                maker_id: 3,
-                                qty: 2,
-                                price: 398,
-                                taker_side: *ask_bid,
-                                total_fill: true,
-                            },
-                            FillMetadata {
-                                taker_id: 4,
-                                maker_id: 1,
-                                qty: 12,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: true,
-                            }
-                        ]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 399);
-                assert_eq!(ob.max_bid(), 0);
-                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
-                assert_eq!(ob._bids(), init_book_holes(vec![], vec![395, 398]));
-                assert_eq!(ob.spread(), 399);
-            } else {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Filled {
-                            id: 2,
-                            filled_qty: 2,
-                            fills: vec![FillMetadata {
-                                taker_id: 2,
-                                maker_id: 1,
-                                qty: 2,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: false,
-                            }],
-                        },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::PartiallyFilled {
-                        id: 4,
-                        filled_qty: 12,
-                        fills: vec![
-                            FillMetadata {
-                                taker_id: 4,
-                                maker_id: 1,
-                                qty: 10,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: true,
-                            },
-                            FillMetadata {
-                                taker_id: 4,
-                                maker_id: 3,
-                                qty: 2,
-                                price: 398,
-                                taker_side: *ask_bid,
-                                total_fill: true,
-                            }
-                        ]
-                    }
-                );
-                assert_eq!(ob.min_ask(), u64::MAX);
-                assert_eq!(ob.max_bid(), 0);
-                assert_eq!(ob._asks(), init_book_holes(vec![], vec![395, 398]));
-                assert_eq!(ob._bids(), init_book(vec![]));
-                assert_eq!(ob.spread(), u64::MAX);
-            }
+{"Market":{"id":3,"user_id":2,"side":"Bid","qty":7}}
+{"Cancel":{"id":9}}
+{"Cancel":{"id":2}}
+"#;
+        let stats = ob.load_jsonl(lines.as_bytes()).unwrap();
+        assert_eq!(
+            stats,
+            crate::LoadStats { orders: 5, opened: 2, filled: 1, partially_filled: 0, rejected: 1, cancelled: 1, duplicates: 0, trades: 2, traded_qty: 7 }
+        );
+        assert_eq!(ob.best_ask(), None);
+        assert!(matches!(ob.load_jsonl("{}".as_bytes()), Err(crate::JournalError::InvalidEntry { line: 1, .. })));
+    }
+
+    #[test]
+    #[cfg(feature = "binary")]
+    fn replicas_share_state_hash() {
+        let journal = MemoryJournal::new();
+        let mut primary = OrderBook::default();
+        primary.set_journal(Some(Box::new(journal.clone())));
+        let empty = primary.state_hash();
+        primary.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 5, price: 100 });
+        primary.execute(OrderType::Limit { id: 2, user_id: 2, side: Side::Ask, qty: 5, price: 100 });
+        primary.execute(OrderType::Market { id: 3, user_id: 3, side: Side::Bid, qty: 2 });
+        assert_ne!(primary.state_hash(), empty);
+
+        let mut backup = OrderBook::default();
+        backup.replay_entries(journal.entries());
+        assert_eq!(backup.state_hash(), primary.state_hash());
+
+        // The owners of the resting orders are part of the state.
+        let mut reordered = OrderBook::default();
+        reordered.execute(OrderType::Limit { id: 2, user_id: 2, side: Side::Ask, qty: 5, price: 100 });
+        reordered.execute(OrderType::Limit { id: 3, user_id: 1, side: Side::Ask, qty: 5, price: 100 });
+        let mut ordered = OrderBook::default();
+        ordered.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 5, price: 100 });
+        ordered.execute(OrderType::Limit { id: 3, user_id: 2, side: Side::Ask, qty: 5, price: 100 });
+        assert_ne!(reordered.state_hash(), ordered.state_hash());
+    }
+
+    #[test]
+    #[cfg(feature = "binary")]
+    fn replay_rebuilds_requests_for_quote() {
+        let clock = ManualClock::new(10);
+        let journal = MemoryJournal::new();
+        let mut primary = OrderBook::default();
+        primary.set_clock(Box::new(clock.clone()));
+        primary.set_journal(Some(Box::new(journal.clone())));
+        primary.track_positions(true);
+        primary.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 5, price: 100 });
+        primary.request_quote(2, 7, Side::Bid, 6, vec![8, 9], 50).unwrap();
+        primary.submit_quote(2, 3, 8, 4, 101, 20).unwrap();
+        primary.submit_quote(2, 4, 9, 4, 102, 30).unwrap();
+        assert_eq!(primary.submit_quote(2, 5, 6, 4, 99, 30), Err(RfqError::MakerNotInvited));
+        primary.request_quote(6, 7, Side::Ask, 2, vec![8], 90).unwrap();
+        clock.advance(5);
+        primary.execute_rfq(2).unwrap();
+        primary.execute(OrderType::Market { id: 7, user_id: 3, side: Side::Bid, qty: 2 });
+        assert_eq!(journal.entries().len(), 7);
+        assert!(journal.entries()[1].order.is_none());
+
+        let mut backup = OrderBook::default();
+        backup.track_positions(true);
+        assert_eq!(backup.replay_entries(journal.entries()), 7);
+        assert_eq!(backup.state_hash(), primary.state_hash());
+        assert_eq!(backup.last_sequence(), 7);
+        assert_eq!(backup.last_trade_id(), primary.last_trade_id());
+        assert_eq!(backup.tape().collect::<Vec<_>>(), primary.tape().collect::<Vec<_>>());
+        assert_eq!(backup.position(7), primary.position(7));
+        assert_eq!(backup.rfq(6), primary.rfq(6));
+
+        // Open requests are part of the snapshot.
+        let mut restored = OrderBook::default();
+        restored.restore(primary.snapshot());
+        assert_eq!(restored.rfq(6), primary.rfq(6));
+    }
+
+    #[test]
+    #[cfg(feature = "binary")]
+    fn replay_runs_journaled_auctions() {
+        let journal = MemoryJournal::new();
+        let mut primary = OrderBook::default();
+        primary.set_journal(Some(Box::new(journal.clone())));
+        primary.set_phase(TradingPhase::PreOpen);
+        primary.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 5, price: 98 });
+        primary.execute(OrderType::Limit { id: 2, user_id: 2, side: Side::Bid, qty: 3, price: 101 });
+
+        // The crossed book and its phase are part of the snapshot.
+        let mut restored = OrderBook::default();
+        restored.restore(primary.snapshot());
+        assert_eq!((restored.phase(), restored.best_bid(), restored.best_ask()), (TradingPhase::PreOpen, Some(101), Some(98)));
+
+        let auction = primary.set_phase(TradingPhase::Open);
+        assert!(matches!(&auction[..], [OrderEvent::Filled { id: 2, filled_qty: 3, .. }]));
+        assert_eq!(restored.set_phase(TradingPhase::Open), auction);
+        primary.execute(OrderType::Market { id: 3, user_id: 3, side: Side::Bid, qty: 1 });
+        primary.set_phase(TradingPhase::PreClose);
+        assert_eq!(journal.entries().iter().filter(|entry| entry.phase.is_some()).count(), 3);
+
+        let mut backup = OrderBook::default();
+        assert_eq!(backup.replay_entries(journal.entries()), 6);
+        assert_eq!(backup.phase(), TradingPhase::PreClose);
+        assert_eq!(backup.state_hash(), primary.state_hash());
+        assert_eq!(backup.tape().collect::<Vec<_>>(), primary.tape().collect::<Vec<_>>());
+
+        primary.set_journal(Some(Box::new(BrokenJournal)));
+        assert_eq!(primary.set_phase(TradingPhase::Closed), vec![]);
+        assert_eq!(primary.phase(), TradingPhase::PreClose);
+    }
+
+    #[test]
+    fn level_sizes_follow_the_queues() {
+        let mut ob = OrderBook::default();
+        for (id, qty) in [(1, 5), (2, 4), (3, 3)] {
+            ob.execute(OrderType::Limit { id, user_id: 1, side: Side::Ask, qty, price: 100 });
+        }
+        ob.execute(OrderType::Limit { id: 4, user_id: 1, side: Side::Ask, qty: 2, price: 101 });
+        ob.execute(OrderType::Market { id: 5, user_id: 2, side: Side::Bid, qty: 6 });
+        ob.execute(OrderType::Reduce { id: 2, qty_delta: 1 });
+        ob.execute(OrderType::Cancel { id: 3 });
+        let depth = ob.depth(5, true);
+        for level in &depth.asks {
+            assert_eq!(level.qty, level.orders.iter().map(|order| order.qty).sum::<Qty>());
+        }
+        assert_eq!(depth.asks.iter().map(|level| (level.price, level.qty)).collect::<Vec<_>>(), vec![(100, 2), (101, 2)]);
+        assert_eq!(ob.available_qty(Side::Bid, 101), 4);
+        assert!(matches!(
+            ob.execute(OrderType::FOK { id: 6, user_id: 2, side: Side::Bid, qty: 5, price: 101 }),
+            OrderEvent::Cancelled { id: 6 }
+        ));
+        assert!(matches!(
+            ob.execute(OrderType::FOK { id: 7, user_id: 2, side: Side::Bid, qty: 4, price: 101 }),
+            OrderEvent::Filled { id: 7, filled_qty: 4, .. }
+        ));
+    }
+
+    #[test]
+    fn execute_all_matches_one_by_one() {
+        let orders = [
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 },
+            OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 3, price: 101 },
+            OrderType::Limit { id: 3, user_id: 1, side: Side::Bid, qty: 4, price: 98 },
+            OrderType::Market { id: 4, user_id: 2, side: Side::Bid, qty: 3 },
+            OrderType::Postonly { id: 5, user_id: 2, side: Side::Bid, qty: 1, price: 100 },
+            OrderType::Cancel { id: 3 },
+            OrderType::Limit { id: 6, user_id: 2, side: Side::Bid, qty: 5, price: 101 },
+            OrderType::FOK { id: 7, user_id: 3, side: Side::Ask, qty: 2, price: 99 },
+        ];
+        let mut single = OrderBook::default();
+        let expected: Vec<OrderEvent> = orders.iter().map(|order| single.execute(*order)).collect();
+        let mut batch = OrderBook::default();
+        assert_eq!(batch.execute_all(&orders), expected);
+        assert_eq!(batch._asks(), single._asks());
+        assert_eq!(batch._bids(), single._bids());
+        assert_eq!((batch.best_ask(), batch.best_bid()), (single.best_ask(), single.best_bid()));
+    }
+
+    #[test]
+    fn price_ladder_matches_tree_storage() {
+        let orders = [
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 },
+            OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 3, price: 110 },
+            OrderType::Limit { id: 3, user_id: 1, side: Side::Bid, qty: 4, price: 90 },
+            OrderType::Postonly { id: 4, user_id: 2, side: Side::Bid, qty: 1, price: 95 },
+            OrderType::Limit { id: 5, user_id: 2, side: Side::Bid, qty: 4, price: 110 },
+            OrderType::Cancel { id: 3 },
+            OrderType::IOC { id: 6, user_id: 3, side: Side::Ask, qty: 1, price: 93 },
+        ];
+        let mut tree = OrderBook::default();
+        let mut ladder = OrderBook::with_storage(16, 4, false, PriceLadder::new(50, 150, 5), PriceLadder::new(50, 150, 5));
+        for order in orders {
+            assert_eq!(ladder.execute(order), tree.execute(order));
+        }
+        #[cfg(feature = "binary")]
+        {
+            assert_eq!(ladder.state_hash(), tree.state_hash());
+        }
+        assert_eq!((ladder.best_ask(), ladder.best_bid()), (tree.best_ask(), tree.best_bid()));
+        assert_eq!(ladder.depth(3, true), tree.depth(3, true));
+
+        // Sliding post-only orders step back by one tick of the ladder.
+        ladder.execute(OrderType::PostonlySlide { id: 7, user_id: 2, side: Side::Bid, qty: 1, price: 120 });
+        assert_eq!(ladder.best_bid(), Some(105));
+
+        for (id, price) in [(103, 103), (155, 155)] {
+            assert_eq!(
+                ladder.execute(OrderType::Limit { id, user_id: 1, side: Side::Ask, qty: 1, price }),
+                OrderEvent::Rejected { id, message: RejectReason::PriceOutOfRange }
+            );
         }
     }
 
     #[test]
-    fn market_order_filled() {
-        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (mut ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *ask_bid,
-                    qty: 2,
-                    price: 399,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 3,
-                    side: *bid_ask,
-                    qty: 2,
-                    price: 398,
-                },
-            ]);
-            let result = ob.execute(OrderType::Market {
-                user_id: 1,
-                id: 4,
-                side: *ask_bid,
-                qty: 7,
-            });
+    fn metrics_count_orders_and_fills() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 2, price: 101 });
+        assert_eq!(ob.metrics().nanos_per_order(), None);
 
-            if *bid_ask == Side::Bid {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Open { id: 2 },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::Filled {
-                        id: 4,
-                        filled_qty: 7,
-                        fills: vec![
-                            FillMetadata {
-                                taker_id: 4,
-                                maker_id: 3,
-                                qty: 2,
-                                price: 398,
-                                taker_side: *ask_bid,
-                                total_fill: true,
-                            },
-                            FillMetadata {
-                                taker_id: 4,
-                                maker_id: 1,
-                                qty: 5,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: false,
-                            }
-                        ]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 399);
-                assert_eq!(ob.max_bid(), 395);
-                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
-                assert_eq!(
-                    ob._bids(),
-                    init_book_holes(vec![(395, 1)], vec![398])
-                );
-                assert_eq!(ob.spread(), 4);
-                assert_eq!(ob.arena.get(3), None);
-                assert_eq!(ob.arena.get(1), Some(&LimitOrder{ user_id: 1, id: 1, qty: 7, price: 395 }));
-            } else {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Filled {
-                            id: 2,
-                            filled_qty: 2,
-                            fills: vec![FillMetadata {
-                                taker_id: 2,
-                                maker_id: 1,
-                                qty: 2,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: false,
-                            }],
-                        },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(
-                    result,
-                    OrderEvent::Filled {
-                        id: 4,
-                        filled_qty: 7,
-                        fills: vec![FillMetadata {
-                            taker_id: 4,
-                            maker_id: 1,
-                            qty: 7,
-                            price: 395,
-                            taker_side: *ask_bid,
-                            total_fill: false,
-                        }]
-                    }
-                );
-                assert_eq!(ob.min_ask(), 395);
-                assert_eq!(ob.max_bid(), 0);
-                assert_eq!(
-                    ob._asks(),
-                    init_book(vec![(395, 1), (398, 3)])
-                );
-                assert_eq!(ob._bids(), init_book(vec![]));
-                assert_eq!(ob.spread(), 395);
-                assert_eq!(ob.arena.get(3), Some(&LimitOrder { user_id: 1, id: 3, qty: 2, price: 398 }));
-                assert_eq!(ob.arena.get(1), Some(&LimitOrder{ user_id: 1, id: 1, qty: 3, price: 395 }));
-            }
+        ob.set_metrics_clock(Some(Box::new(ManualClock::new(5))));
+        ob.execute(OrderType::Market { id: 3, user_id: 2, side: Side::Bid, qty: 3 });
+        ob.execute(OrderType::Cancel { id: 9 });
+        assert_eq!(
+            ob.metrics(),
+            BookMetrics { orders_processed: 4, fills_generated: 2, timed_orders: 2, busy_time: 0 }
+        );
+        assert_eq!(ob.metrics().nanos_per_order(), Some(0.0));
+
+        ob.reset_metrics();
+        assert_eq!(ob.metrics(), BookMetrics::default());
+    }
+
+    #[test]
+    fn depth_limit_rejects_or_evicts_far_orders() {
+        let limit = |id, side, price| OrderType::Limit { id, user_id: 1, side, qty: 1, price };
+        let mut ob = OrderBook::default();
+        ob.track_evictions(true);
+        ob.set_depth_limit(Some(DepthLimit::levels(2, DepthPolicy::Reject)));
+        ob.execute(limit(1, Side::Ask, 101));
+        ob.execute(limit(2, Side::Ask, 102));
+        assert_eq!(ob.execute(limit(3, Side::Ask, 102)), OrderEvent::Open { id: 3, remaining_qty: 1, resting_price: 102 });
+        assert_eq!(
+            ob.execute(limit(4, Side::Ask, 103)),
+            OrderEvent::Rejected { id: 4, message: RejectReason::DepthLimitExceeded }
+        );
+        assert_eq!(ob.execute(limit(5, Side::Bid, 95)), OrderEvent::Open { id: 5, remaining_qty: 1, resting_price: 95 });
+
+        ob.set_depth_limit(Some(DepthLimit::levels(2, DepthPolicy::EvictFarthest)));
+        assert_eq!(ob.execute(limit(6, Side::Ask, 100)), OrderEvent::Open { id: 6, remaining_qty: 1, resting_price: 100 });
+        assert_eq!(ob.take_evicted(), vec![OrderEvent::Cancelled { id: 2 }, OrderEvent::Cancelled { id: 3 }]);
+        assert_eq!(ob._asks(), init_book(vec![(100, 6), (101, 1)]));
+
+        // The ask at 101 is farther from the touch than the bid at 95.
+        ob.set_depth_limit(Some(DepthLimit::orders(2, DepthPolicy::EvictFarthest)));
+        assert_eq!(ob.take_evicted(), vec![OrderEvent::Cancelled { id: 1 }]);
+        ob.execute(limit(7, Side::Bid, 90));
+        assert_eq!(ob.take_evicted(), vec![OrderEvent::Cancelled { id: 7 }]);
+        assert_eq!((ob.best_ask(), ob.best_bid()), (Some(100), Some(95)));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn replay_applies_journaled_depth_limits() {
+        let limit = |id, side, price| OrderType::Limit { id, user_id: 1, side, qty: 1, price };
+        let journal = MemoryJournal::new();
+        let mut primary = OrderBook::default();
+        primary.set_journal(Some(Box::new(journal.clone())));
+        primary.track_evictions(true);
+        for (id, price) in [(1, 101), (2, 102), (3, 103)] {
+            primary.execute(limit(id, Side::Ask, price));
         }
+        assert!(primary.set_depth_limit(Some(DepthLimit::levels(2, DepthPolicy::EvictFarthest))));
+        primary.execute(limit(4, Side::Ask, 100));
+        assert_eq!(primary.take_evicted(), vec![OrderEvent::Cancelled { id: 3 }, OrderEvent::Cancelled { id: 2 }]);
+        assert!(primary.set_depth_limit(None));
+        primary.execute(limit(5, Side::Ask, 105));
+        let limits: Vec<_> = journal.entries().iter().filter_map(|entry| entry.depth_limit).collect();
+        assert_eq!(limits.len(), 2);
+        assert!(!limits[1].is_bounded());
+
+        let mut backup = OrderBook::default();
+        backup.track_evictions(true);
+        assert_eq!(backup.replay_entries(journal.entries()), 7);
+        assert_eq!(backup.depth(10, false), primary.depth(10, false));
+        assert_eq!(backup.take_evicted(), vec![]);
+        assert_eq!(backup.execute(limit(6, Side::Ask, 106)), OrderEvent::Open { id: 6, remaining_qty: 1, resting_price: 106 });
     }
 
     #[test]
-    fn cancel_non_existing_order() {
-        let (mut ob, _) = init_ob(vec![]);
-        let result = ob.execute(OrderType::Cancel { id: 0 });
-        assert_eq!(result, OrderEvent::Cancelled { id: 0 });
-        assert_eq!(ob.min_ask(), u64::MAX);
-        assert_eq!(ob.max_bid(), 0);
-        assert_eq!(ob._asks(), Vec::new());
-        assert_eq!(ob._bids(), Vec::new());
-        assert_eq!(ob.spread(), u64::MAX);
-        assert_eq!(ob.arena.get(0), None);
+    #[cfg(feature = "std")]
+    fn evictions_stream_to_the_sink_without_buffering() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut ob = OrderBook::default();
+        ob.set_eviction_sink(Some(Box::new(sender)));
+        for (id, price) in [(1, 99), (2, 98), (3, 97)] {
+            ob.execute(OrderType::Limit { id, user_id: 1, side: Side::Bid, qty: 1, price });
+        }
+        ob.set_depth_limit(Some(DepthLimit::orders(1, DepthPolicy::EvictFarthest)));
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![OrderEvent::Cancelled { id: 3 }, OrderEvent::Cancelled { id: 2 }]);
+        assert_eq!(ob.take_evicted(), vec![]);
+
+        ob.track_evictions(true);
+        ob.execute(OrderType::Limit { id: 4, user_id: 1, side: Side::Bid, qty: 1, price: 100 });
+        assert_eq!(ob.take_evicted(), vec![OrderEvent::Cancelled { id: 1 }]);
+        assert_eq!(receiver.try_recv(), Ok(OrderEvent::Cancelled { id: 1 }));
     }
 
     #[test]
-    fn cancel_resting_order() {
-        for (bid_ask, _) in &BID_ASK_COMBINATIONS {
-            let (mut ob, results) = init_ob(vec![OrderType::Limit {
-                user_id: 1,
-                id: 1,
-                side: *bid_ask,
-                qty: 12,
-                price: 395,
-            }]);
-            let result = ob.execute(OrderType::Cancel { id: 1 });
-            assert_eq!(results, vec![OrderEvent::Open { id: 1 }]);
-            assert_eq!(result, OrderEvent::Cancelled { id: 1 });
-            assert_eq!(ob.min_ask(), u64::MAX);
-            assert_eq!(ob.max_bid(), 0);
-            if *bid_ask == Side::Bid {
-                assert_eq!(ob._asks(), Vec::new());
-                assert_eq!(ob._bids(), init_book_holes(vec![], vec![395]));
-            } else {
-                assert_eq!(ob._asks(), init_book_holes(vec![], vec![395]));
-                assert_eq!(ob._bids(), Vec::new());
+    fn depth_into_reuses_buffers() {
+        let (ob, _) = init_ob(vec![
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 101 },
+            OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 3, price: 102 },
+            OrderType::Limit { id: 3, user_id: 1, side: Side::Bid, qty: 4, price: 99 },
+        ]);
+        let (mut asks, mut bids) = (Vec::new(), Vec::new());
+        ob.depth_into(&mut asks, &mut bids, 5, true);
+        let expected = ob.depth(5, true);
+        assert_eq!((&asks, &bids), (&expected.asks, &expected.bids));
+
+        let capacity = asks[0].orders.capacity();
+        ob.depth_into(&mut asks, &mut bids, 1, false);
+        assert_eq!(asks, ob.depth(1, false).asks);
+        assert_eq!((asks.len(), asks[0].orders.capacity()), (1, capacity));
+        assert_eq!(bids, ob.depth(1, false).bids);
+    }
+
+    #[test]
+    fn best_prices_follow_the_touch() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 101 },
+            OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 2, price: 101 },
+            OrderType::Limit { id: 3, user_id: 1, side: Side::Ask, qty: 3, price: 104 },
+            OrderType::Limit { id: 4, user_id: 1, side: Side::Bid, qty: 4, price: 99 },
+            OrderType::Limit { id: 5, user_id: 1, side: Side::Bid, qty: 4, price: 97 },
+        ]);
+        ob.execute(OrderType::Cancel { id: 3 });
+        ob.execute(OrderType::Cancel { id: 5 });
+        assert_eq!((ob.best_ask(), ob.best_bid()), (Some(101), Some(99)));
+        ob.execute(OrderType::Cancel { id: 1 });
+        assert_eq!(ob.best_ask(), Some(101));
+        ob.execute(OrderType::Market { id: 6, user_id: 2, side: Side::Bid, qty: 2 });
+        assert_eq!(ob.best_ask(), None);
+        ob.execute(OrderType::Cancel { id: 4 });
+        assert_eq!(ob.best_bid(), None);
+    }
+
+    #[cfg(feature = "signed-price")]
+    #[test]
+    fn negative_prices_trade_and_quote() {
+        let (mut ob, _) = init_ob(vec![
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: -5 },
+            OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 2, price: 3 },
+            OrderType::Limit { id: 3, user_id: 2, side: Side::Bid, qty: 1, price: -12 },
+        ]);
+        assert_eq!((ob.best_bid(), ob.best_ask(), ob.bid_ask_spread()), (Some(-12), Some(-5), Some(7)));
+        assert_eq!(ob.depth(1, false).asks[0].cumulative_notional, -10);
+
+        let event = ob.execute(OrderType::Limit { id: 4, user_id: 3, side: Side::Bid, qty: 3, price: 0 });
+        assert!(matches!(event, OrderEvent::PartiallyFilled { filled_qty: 2, .. }));
+        assert_eq!((ob.best_bid(), ob.best_ask()), (Some(0), Some(3)));
+        assert_eq!(ob.last_trade().map(|trade| trade.avg_price), Some(-5.0));
+
+        let mut ladder = OrderBook::with_storage(16, 4, false, PriceLadder::new(-100, 100, 5), PriceLadder::new(-100, 100, 5));
+        assert_eq!(ladder.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Bid, qty: 1, price: -95 }), OrderEvent::Open { id: 1, remaining_qty: 1, resting_price: -95 });
+        assert_eq!(
+            ladder.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Bid, qty: 1, price: -105 }),
+            OrderEvent::Rejected { id: 2, message: RejectReason::PriceOutOfRange }
+        );
+        assert_eq!(ladder.best_bid(), Some(-95));
+    }
+
+    #[test]
+    fn makers_fill_in_price_time_priority() {
+        // A naive reference keeps the resting asks in arrival order and
+        // scans them for the best price.
+        let mut reference: Vec<(Price, u64, Qty)> = Vec::new();
+        let mut ob = OrderBook::default();
+        let mut next = scripted(7);
+        for id in 1..=2000 {
+            match next(4) {
+                0 => {
+                    let qty = 1 + next(12) as Qty;
+                    let fills: Vec<(u64, Qty)> = match ob.execute(OrderType::Market { id, user_id: 2, side: Side::Bid, qty }) {
+                        OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } => {
+                            fills.iter().map(|fill| (fill.maker_id, fill.qty)).collect()
+                        }
+                        _ => Vec::new(),
+                    };
+                    let mut expected = Vec::new();
+                    let mut left = qty;
+                    while left > 0 {
+                        let Some(best) = reference.iter().enumerate().min_by_key(|(_, (price, _, _))| *price).map(|(index, _)| index) else {
+                            break;
+                        };
+                        let traded = left.min(reference[best].2);
+                        expected.push((reference[best].1, traded));
+                        left -= traded;
+                        reference[best].2 -= traded;
+                        if reference[best].2 == 0 {
+                            reference.remove(best);
+                        }
+                    }
+                    assert_eq!(fills, expected, "market order {}", id);
+                }
+                1 => {
+                    let target = 1 + next(id);
+                    ob.execute(OrderType::Cancel { id: target });
+                    reference.retain(|(_, id, _)| *id != target);
+                }
+                _ => {
+                    let (price, qty) = (100 + next(3) as Price, 1 + next(5) as Qty);
+                    ob.execute(OrderType::Limit { id, user_id: 1, side: Side::Ask, qty, price });
+                    reference.push((price, id, qty));
+                }
             }
-            assert_eq!(ob.spread(), u64::MAX);
-            assert_eq!(ob.arena.get(1), None);
         }
+        let levels: Vec<(Price, Vec<u64>)> = (100..103)
+            .map(|price| (price, reference.iter().filter(|order| order.0 == price).map(|order| order.1).collect::<Vec<_>>()))
+            .filter(|(_, ids)| !ids.is_empty())
+            .collect();
+        assert_eq!(ob._asks(), levels);
+        assert!(reference.iter().all(|&(_, id, qty)| ob.resting_order(id).map(|(_, order)| order.qty) == Some(qty)));
     }
 
     #[test]
-    fn cancel_resting_order_of_many() {
-        for (bid_ask, ask_bid) in &BID_ASK_COMBINATIONS {
-            let (mut ob, results) = init_ob(vec![
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 1,
-                    side: *bid_ask,
-                    qty: 12,
-                    price: 395,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 2,
-                    side: *ask_bid,
-                    qty: 2,
-                    price: 399,
-                },
-                OrderType::Limit {
-                    user_id: 1,
-                    id: 3,
-                    side: *bid_ask,
-                    qty: 2,
-                    price: 398,
-                },
-            ]);
-            let result = ob.execute(OrderType::Cancel { id: 1 });
-            if *bid_ask == Side::Bid {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Open { id: 2 },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(result, OrderEvent::Cancelled { id: 1 });
-                assert_eq!(ob.min_ask(), 399);
-                assert_eq!(ob.max_bid(), 398);
-                assert_eq!(ob._asks(), init_book(vec![(399, 2)]));
-                assert_eq!(
-                    ob._bids(),
-                    init_book_holes(vec![(398, 3)], vec![395])
-                );
-                assert_eq!(ob.spread(), 1);
+    fn levels_are_pruned_as_they_empty() {
+        let mut ob = OrderBook::default();
+        let mut next = scripted(11);
+        for id in 1..=1000 {
+            let side = if next(2) == 0 { Side::Bid } else { Side::Ask };
+            let order = match next(6) {
+                0 => OrderType::Cancel { id: 1 + next(id) },
+                1 => OrderType::Reduce { id: 1 + next(id), qty_delta: 1 + next(3) as Qty },
+                2 => OrderType::Market { id, user_id: 2, side, qty: 1 + next(6) as Qty },
+                _ => OrderType::Limit { id, user_id: 1, side, qty: 1 + next(4) as Qty, price: 95 + next(10) as Price },
+            };
+            ob.execute(order);
+
+            // The levels are exactly the prices orders rest at, on each side.
+            let (mut bids, mut asks) = (Vec::new(), Vec::new());
+            for (side, order) in (1..=id).filter_map(|id| ob.resting_order(id)) {
+                if side == Side::Bid { &mut bids } else { &mut asks }.push(order.price);
+            }
+            for (levels, mut prices) in [(ob._bids(), bids), (ob._asks(), asks)] {
+                prices.sort_unstable();
+                prices.dedup();
+                assert_eq!(levels.iter().map(|(price, _)| *price).collect::<Vec<_>>(), prices, "after order {}", id);
+                assert!(levels.iter().all(|(_, ids)| !ids.is_empty()));
+            }
+            assert_eq!(ob.best_bid(), ob._bids().last().map(|(price, _)| *price));
+            assert_eq!(ob.best_ask(), ob._asks().first().map(|(price, _)| *price));
+            assert_eq!(ob.depth(usize::MAX, false).bids.len(), ob._bids().len());
+        }
+    }
+
+    #[test]
+    fn cancels_remove_only_the_order_from_its_queue() {
+        let without = |levels: Vec<(Price, Vec<u64>)>, id| -> Vec<(Price, Vec<u64>)> {
+            levels
+                .into_iter()
+                .map(|(price, ids)| (price, ids.into_iter().filter(|other| *other != id).collect::<Vec<_>>()))
+                .filter(|(_, ids)| !ids.is_empty())
+                .collect()
+        };
+        let mut ob = OrderBook::default();
+        let mut next = scripted(13);
+        let mut cancels = 0;
+        for id in 1..=3000 {
+            if next(3) == 0 {
+                let target = 1 + next(id);
+                let (bids, asks) = (ob._bids(), ob._asks());
+                let resting = [&bids, &asks].iter().any(|levels| levels.iter().any(|(_, ids)| ids.contains(&target)));
+                let expected = if resting { OrderEvent::Cancelled { id: target } } else { OrderEvent::CancelRejected { id: target, message: RejectReason::OrderNotFound } };
+                assert_eq!(ob.execute(OrderType::Cancel { id: target }), expected);
+                assert_eq!((ob._bids(), ob._asks()), (without(bids, target), without(asks, target)));
+                cancels += usize::from(resting);
             } else {
-                assert_eq!(
-                    results,
-                    vec![
-                        OrderEvent::Open { id: 1 },
-                        OrderEvent::Filled {
-                            id: 2,
-                            filled_qty: 2,
-                            fills: vec![FillMetadata {
-                                taker_id: 2,
-                                maker_id: 1,
-                                qty: 2,
-                                price: 395,
-                                taker_side: *ask_bid,
-                                total_fill: false,
-                            }],
-                        },
-                        OrderEvent::Open { id: 3 }
-                    ]
-                );
-                assert_eq!(result, OrderEvent::Cancelled { id: 1 });
-                assert_eq!(ob.min_ask(), 398);
-                assert_eq!(ob.max_bid(), 0);
-                assert_eq!(
-                    ob._asks(),
-                    init_book_holes(vec![(398, 3)], vec![395])
-                );
-                assert_eq!(ob._bids(), init_book(vec![]));
-                assert_eq!(ob.spread(), 398);
+                // The sides only overlap at 100, so most orders rest.
+                let (side, low) = if next(2) == 0 { (Side::Bid, 95) } else { (Side::Ask, 100) };
+                ob.execute(OrderType::Limit { id, user_id: next(3), side, qty: 1 + next(4) as Qty, price: low + next(6) as Price });
             }
         }
+        assert!(cancels > 300);
     }
 }
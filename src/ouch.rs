@@ -0,0 +1,245 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use thiserror::Error;
+
+use crate::models::{price_from_wire, qty_from_wire, wire_price, wire_qty, OrderEvent, OrderId, OrderType, Price, Qty, Side};
+use crate::rejectmessages::{self, RejectReason};
+
+/// An order entry request decoded by [`decode_ouch`].
+///
+/// [`decode_ouch`]: fn.decode_ouch.html
+#[derive(Debug, Copy, Clone)]
+pub enum OuchRequest {
+    /// An order to execute, from an enter (`O`) or cancel (`X`) message.
+    Order(OrderType),
+    /// A replace (`U`) message. The replacement order should only be executed
+    /// once the existing order was canceled.
+    Replace {
+        /// The ID of the order to replace.
+        existing: OrderId,
+        /// The limit order replacing it.
+        order: OrderType,
+    },
+}
+
+/// Errors returned when decoding an order entry message.
+#[derive(Debug, Error, PartialEq)]
+pub enum OuchError {
+    /// The buffer is shorter than the message type requires.
+    #[error("Message too short: expected {expected} bytes, found {found}")]
+    Truncated {
+        /// The length of the message type.
+        expected: usize,
+        /// The length of the buffer.
+        found: usize,
+    },
+    /// The message type is not an order entry message.
+    #[error("Unknown message type {0:#04x}")]
+    UnknownMessage(u8),
+    /// A field holds a value outside of its enumeration.
+    #[error("Invalid {field} {value:#04x}")]
+    InvalidField {
+        /// The name of the field.
+        field: &'static str,
+        /// The value found.
+        value: u8,
+    },
+    /// A quantity does not fit the book quantity type.
+    #[error("Quantity {0} is out of range")]
+    QtyOutOfRange(u64),
+}
+
+struct Fields<'a> {
+    bytes: &'a [u8],
+}
+
+impl Fields<'_> {
+    fn u8(&mut self) -> u8 {
+        let (value, rest) = self.bytes.split_first().expect("message length was checked");
+        self.bytes = rest;
+        *value
+    }
+
+    fn u64(&mut self) -> u64 {
+        let (value, rest) = self.bytes.split_at(8);
+        self.bytes = rest;
+        u64::from_be_bytes(value.try_into().unwrap())
+    }
+
+    fn qty(&mut self) -> Result<Qty, OuchError> {
+        let qty = self.u64();
+        qty_from_wire(qty).ok_or(OuchError::QtyOutOfRange(qty))
+    }
+
+    fn side(&mut self) -> Result<Side, OuchError> {
+        match self.u8() {
+            b'B' => Ok(Side::Bid),
+            b'S' => Ok(Side::Ask),
+            value => Err(OuchError::InvalidField { field: "side", value }),
+        }
+    }
+}
+
+/// Decode an OUCH style order entry message. All integers are big endian
+/// and 64-bit wide, and order tokens are the book order IDs.
+///
+/// * Enter order, 43 bytes: `O`, token, user ID, side (`B` or `S`),
+///   quantity, price, time in force and expiry. The time in force is one of
+///   `D` (day, a limit order), `I` (IOC), `F` (FOK), `P` (post-only), `S`
+///   (sliding post-only), `M` (market, ignoring the price) or `G`
+///   (good-till-time, expiring at the book clock time given by the expiry).
+///   The expiry is ignored for the other kinds.
+/// * Cancel order, 17 bytes: `X`, token and the quantity to cancel. A zero
+///   quantity cancels the whole order; otherwise the order is reduced.
+/// * Replace order, 42 bytes: `U`, existing token, replacement token, user
+///   ID, side, quantity and price. The replacement is a limit order.
+///
+/// Quantities that do not fit [`Qty`] are refused rather than clamped.
+///
+/// [`Qty`]: type.Qty.html
+pub fn decode_ouch(bytes: &[u8]) -> Result<OuchRequest, OuchError> {
+    let (&message_type, body) = bytes.split_first().ok_or(OuchError::Truncated { expected: 1, found: 0 })?;
+    let expected = match message_type {
+        b'O' => 43,
+        b'X' => 17,
+        b'U' => 42,
+        other => return Err(OuchError::UnknownMessage(other)),
+    };
+    if bytes.len() < expected {
+        return Err(OuchError::Truncated { expected, found: bytes.len() });
+    }
+    let mut fields = Fields { bytes: body };
+    let request = match message_type {
+        b'O' => {
+            let (id, user_id, side, qty, price) = (fields.u64(), fields.u64(), fields.side()?, fields.qty()?, price_from_wire(fields.u64()));
+            let order = match fields.u8() {
+                b'D' => OrderType::Limit { id, user_id, side, qty, price },
+                b'I' => OrderType::IOC { id, user_id, side, qty, price },
+                b'F' => OrderType::FOK { id, user_id, side, qty, price },
+                b'P' => OrderType::Postonly { id, user_id, side, qty, price },
+                b'S' => OrderType::PostonlySlide { id, user_id, side, qty, price },
+                b'M' => OrderType::Market { id, user_id, side, qty },
+                b'G' => OrderType::GTT { id, user_id, side, qty, price, expires_at: fields.u64() },
+                value => return Err(OuchError::InvalidField { field: "time in force", value }),
+            };
+            OuchRequest::Order(order)
+        }
+        b'X' => {
+            let id = fields.u64();
+            match fields.u64() {
+                0 => OuchRequest::Order(OrderType::Cancel { id }),
+                qty_delta => OuchRequest::Order(OrderType::Reduce { id, qty_delta: qty_from_wire(qty_delta).ok_or(OuchError::QtyOutOfRange(qty_delta))? }),
+            }
+        }
+        _ => {
+            let existing = fields.u64();
+            let (id, user_id, side, qty, price) = (fields.u64(), fields.u64(), fields.side()?, fields.qty()?, price_from_wire(fields.u64()));
+            OuchRequest::Replace { existing, order: OrderType::Limit { id, user_id, side, qty, price } }
+        }
+    };
+    Ok(request)
+}
+
+fn reject_code(reason: RejectReason) -> u8 {
+    rejectmessages::ALL.iter().position(|known| *known == reason).map_or(0, |index| index as u8 + 1)
+}
+
+/// Encode an order event into OUCH style response messages, one per fill for
+/// executions. All integers are big endian and 64-bit wide.
+///
+/// * Accepted, 9 bytes: `A` and token, for open orders.
+/// * Canceled, 18 bytes: `C`, token, remaining quantity and reason, which is
+///   `U` (user), `A` (administrator), `E` (expired) or `R` (reduced, leaving
+///   the remaining quantity on the book).
+/// * Executed, 33 bytes: `E`, token, executed quantity, price and match
+///   number. The match number is the maker order ID, or the order ID for
+///   reported crosses.
+/// * Nothing for duplicate orders, whose messages were sent when they were
+///   first executed.
+/// * Rejected, 10 bytes: `J`, token and reject code, and cancel rejected,
+///   10 bytes: `I`, token and reject code. The reject code is the position of
+///   the reject message in the order the messages are declared, starting
+///   from 1, or 0 for an unknown message.
+pub fn encode_ouch_event(event: &OrderEvent) -> Vec<Vec<u8>> {
+    let canceled = |id: OrderId, qty: Qty, reason: u8| [&b"C"[..], &id.to_be_bytes(), &wire_qty(qty).to_be_bytes(), &[reason]].concat();
+    let executed = |id: OrderId, qty: Qty, price: Price, match_number: u64| {
+        [&b"E"[..], &id.to_be_bytes(), &wire_qty(qty).to_be_bytes(), &wire_price(price).to_be_bytes(), &match_number.to_be_bytes()].concat()
+    };
+    match event {
+        OrderEvent::Open { id, .. } => vec![[&b"A"[..], &id.to_be_bytes()].concat()],
+        OrderEvent::Duplicate { .. } => Vec::new(),
+        OrderEvent::Cancelled { id } => vec![canceled(*id, 0, b'U')],
+        OrderEvent::AdminCancelled { id, .. } => vec![canceled(*id, 0, b'A')],
+        OrderEvent::Expired { id } => vec![canceled(*id, 0, b'E')],
+        OrderEvent::Reduced { id, qty } => vec![canceled(*id, *qty, b'R')],
+        OrderEvent::Rejected { id, message } => vec![[&b"J"[..], &id.to_be_bytes(), &[reject_code(*message)]].concat()],
+        OrderEvent::CancelRejected { id, message } => {
+            vec![[&b"I"[..], &id.to_be_bytes(), &[reject_code(*message)]].concat()]
+        }
+        OrderEvent::PartiallyFilled { id, fills, .. } | OrderEvent::Filled { id, fills, .. } => {
+            fills.iter().map(|fill| executed(*id, fill.qty, fill.price, fill.maker_id)).collect()
+        }
+        OrderEvent::Traded { id, qty, price, .. } => vec![executed(*id, *qty, *price, *id)],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_ouch, encode_ouch_event, OuchError, OuchRequest};
+    use crate::{OrderBook, OrderType, Side};
+
+    fn enter(id: u64, side: u8, qty: u64, price: u64, tif: u8) -> Vec<u8> {
+        let fields = [id, 9, qty, price, 0].map(u64::to_be_bytes);
+        [&b"O"[..], &fields[0], &fields[1], &[side], &fields[2], &fields[3], &[tif], &fields[4]].concat()
+    }
+
+    #[test]
+    fn ouch_order_entry() {
+        let mut ob = OrderBook::default();
+        let OuchRequest::Order(order) = decode_ouch(&enter(1, b'S', 5, 100, b'D')).unwrap() else {
+            panic!("expected an order");
+        };
+        assert!(matches!(order, OrderType::Limit { id: 1, user_id: 9, side: Side::Ask, qty: 5, price: 100 }));
+        assert_eq!(encode_ouch_event(&ob.execute(order)), vec![[&b"A"[..], &1u64.to_be_bytes()].concat()]);
+
+        let OuchRequest::Order(order) = decode_ouch(&enter(2, b'B', 2, 0, b'M')).unwrap() else {
+            panic!("expected an order");
+        };
+        let executed = [&b"E"[..], &2u64.to_be_bytes(), &2u64.to_be_bytes(), &100u64.to_be_bytes(), &1u64.to_be_bytes()].concat();
+        assert_eq!(encode_ouch_event(&ob.execute(order)), vec![executed]);
+
+        let reduce = [&b"X"[..], &1u64.to_be_bytes(), &1u64.to_be_bytes()].concat();
+        let OuchRequest::Order(order) = decode_ouch(&reduce).unwrap() else {
+            panic!("expected an order");
+        };
+        let canceled = [&b"C"[..], &1u64.to_be_bytes(), &2u64.to_be_bytes(), b"R"].concat();
+        assert_eq!(encode_ouch_event(&ob.execute(order)), vec![canceled]);
+
+        let cancel = [&b"X"[..], &7u64.to_be_bytes(), &0u64.to_be_bytes()].concat();
+        let OuchRequest::Order(order) = decode_ouch(&cancel).unwrap() else {
+            panic!("expected an order");
+        };
+        assert_eq!(encode_ouch_event(&ob.execute(order)), vec![[&b"I"[..], &7u64.to_be_bytes(), &[3]].concat()]);
+
+        let mut replace = [&b"U"[..], &1u64.to_be_bytes()].concat();
+        replace.extend_from_slice(&enter(3, b'S', 4, 101, b'D')[1..34]);
+        assert!(matches!(
+            decode_ouch(&replace),
+            Ok(OuchRequest::Replace { existing: 1, order: OrderType::Limit { id: 3, side: Side::Ask, qty: 4, price: 101, .. } })
+        ));
+
+        assert!(matches!(decode_ouch(&enter(4, b'Z', 1, 1, b'D')), Err(OuchError::InvalidField { field: "side", value: b'Z' })));
+        assert!(matches!(decode_ouch(&cancel[..5]), Err(OuchError::Truncated { expected: 17, found: 5 })));
+        assert!(matches!(decode_ouch(b"Q"), Err(OuchError::UnknownMessage(b'Q'))));
+    }
+
+    #[test]
+    #[cfg(all(feature = "narrow-qty", not(feature = "wide-qty")))]
+    fn ouch_refuses_quantities_out_of_range() {
+        let qty = u64::from(crate::Qty::MAX) + 1;
+        assert!(matches!(decode_ouch(&enter(1, b'B', qty, 100, b'D')), Err(OuchError::QtyOutOfRange(q)) if q == qty));
+        let reduce = [&b"X"[..], &1u64.to_be_bytes(), &qty.to_be_bytes()].concat();
+        assert!(matches!(decode_ouch(&reduce), Err(OuchError::QtyOutOfRange(q)) if q == qty));
+    }
+}
@@ -0,0 +1,104 @@
+use alloc::boxed::Box;
+
+use crate::clock::Clock;
+use crate::collections::HashMap;
+use crate::models::UserId;
+
+/// A per-user token bucket allowing `max_messages` messages per `interval`,
+/// measured with the timestamps of its own clock. Each user starts with a
+/// full bucket, which refills continuously as time passes.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_messages: u64,
+    interval: u64,
+    clock: Box<dyn Clock>,
+    buckets: HashMap<UserId, Bucket>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Bucket {
+    // Every message costs `interval` units, every elapsed tick refills
+    // `max_messages` units, and the bucket holds at most
+    // `max_messages * interval` units.
+    allowance: u64,
+    last_update: u64,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter allowing `max_messages` messages per `interval`
+    /// ticks of `clock` for every user, e.g. nanoseconds of a [`SystemClock`].
+    /// The clock is required, rather than taken from the order book, whose
+    /// default clock never advances and would never refill the buckets.
+    ///
+    /// [`SystemClock`]: struct.SystemClock.html
+    pub fn new(max_messages: u64, interval: u64, clock: Box<dyn Clock>) -> Self {
+        Self {
+            max_messages,
+            interval: interval.max(1),
+            clock,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Consume one message for `user_id` at the current time of the clock,
+    /// returning whether it is allowed.
+    pub fn allow(&mut self, user_id: UserId) -> bool {
+        let now = self.clock.now();
+        self.check(user_id, now)
+    }
+
+    /// Consume one message for `user_id` at time `now`, returning whether it
+    /// is allowed.
+    pub fn check(&mut self, user_id: UserId, now: u64) -> bool {
+        let capacity = self.max_messages.saturating_mul(self.interval);
+        let bucket = self.buckets.entry(user_id).or_insert(Bucket {
+            allowance: capacity,
+            last_update: now,
+        });
+        let elapsed = now.saturating_sub(bucket.last_update);
+        bucket.allowance = bucket
+            .allowance
+            .saturating_add(elapsed.saturating_mul(self.max_messages))
+            .min(capacity);
+        bucket.last_update = bucket.last_update.max(now);
+        if bucket.allowance < self.interval {
+            return false;
+        }
+        bucket.allowance -= self.interval;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RateLimiter;
+    use crate::ManualClock;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let mut limiter = RateLimiter::new(2, 10, Box::new(ManualClock::default()));
+        assert!(limiter.check(1, 0));
+        assert!(limiter.check(1, 0));
+        assert!(!limiter.check(1, 4));
+        assert!(limiter.check(2, 4));
+        assert!(limiter.check(1, 10));
+        assert!(limiter.check(1, 10));
+        assert!(!limiter.check(1, 10));
+        assert!(limiter.check(1, 100));
+        assert!(limiter.check(1, 100));
+        assert!(!limiter.check(1, 100));
+    }
+
+    #[test]
+    fn limited_users_are_allowed_again_as_the_clock_advances() {
+        let clock = ManualClock::new(1_000);
+        let mut limiter = RateLimiter::new(1, 10, Box::new(clock.clone()));
+        assert!(limiter.allow(1));
+        assert!(!limiter.allow(1));
+        clock.advance(5);
+        assert!(!limiter.allow(1));
+        clock.advance(5);
+        assert!(limiter.allow(1));
+        assert!(!limiter.allow(1));
+    }
+}
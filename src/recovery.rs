@@ -0,0 +1,94 @@
+use std::io::BufRead;
+
+use crate::journal::{read_journal, JournalEntry, JournalError};
+use crate::orderbook::OrderBook;
+use crate::snapshot::BookSnapshot;
+
+/// Orchestrates crash recovery from periodic snapshots and the journal.
+///
+/// While the book runs with a journal, call [`checkpoint`] after each
+/// execution to take a snapshot every `snapshot_interval` journal entries.
+/// On recovery, [`recover`] restores the latest snapshot and replays only the
+/// journal entries appended after it.
+///
+/// [`checkpoint`]: #method.checkpoint
+/// [`recover`]: #method.recover
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    snapshot_interval: u64,
+    latest: Option<BookSnapshot>,
+}
+
+impl Recovery {
+    /// Create a recovery helper taking a snapshot every `snapshot_interval`
+    /// journal entries, starting from `latest`, e.g. a persisted snapshot.
+    pub fn new(snapshot_interval: u64, latest: Option<BookSnapshot>) -> Self {
+        Self { snapshot_interval: snapshot_interval.max(1), latest }
+    }
+
+    /// Return the latest snapshot taken or loaded, if any.
+    pub fn latest_snapshot(&self) -> Option<&BookSnapshot> {
+        self.latest.as_ref()
+    }
+
+    /// Take a snapshot of `book` if at least `snapshot_interval` journal
+    /// entries were appended since the latest one, returning it so it can be
+    /// persisted.
+    pub fn checkpoint(&mut self, book: &OrderBook) -> Option<&BookSnapshot> {
+        let since = self.latest.as_ref().map_or(0, |snapshot| snapshot.journal_sequence);
+        if book.journal_sequence() < since + self.snapshot_interval {
+            return None;
+        }
+        self.latest = Some(book.snapshot());
+        self.latest.as_ref()
+    }
+
+    /// Rebuild `book` from the latest snapshot, if any, and the journal
+    /// `entries` appended after it. Return the number of entries replayed.
+    pub fn recover<I: IntoIterator<Item = JournalEntry>>(&self, book: &mut OrderBook, entries: I) -> usize {
+        let since = match &self.latest {
+            Some(snapshot) => {
+                book.restore(snapshot.clone());
+                snapshot.journal_sequence
+            }
+            None => 0,
+        };
+        book.replay_entries(entries.into_iter().filter(|entry| entry.sequence > since))
+    }
+
+    /// Rebuild `book` like [`recover`], reading the journal entries from
+    /// `reader`.
+    ///
+    /// [`recover`]: #method.recover
+    pub fn recover_from<R: BufRead>(&self, book: &mut OrderBook, reader: R) -> Result<usize, JournalError> {
+        let entries = read_journal(reader).collect::<Result<Vec<_>, _>>()?;
+        Ok(self.recover(book, entries))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Recovery;
+    use crate::{MemoryJournal, OrderBook, OrderType, Price, Side};
+
+    #[test]
+    fn recover_from_snapshot_and_journal_tail() {
+        let journal = MemoryJournal::new();
+        let mut ob = OrderBook::default();
+        ob.set_journal(Some(Box::new(journal.clone())));
+        let mut recovery = Recovery::new(2, None);
+        let mut snapshots = 0;
+        for id in 1..=5 {
+            ob.execute(OrderType::Limit { id, user_id: 1, side: Side::Ask, qty: 2, price: 100 + id as Price });
+            snapshots += recovery.checkpoint(&ob).is_some() as usize;
+        }
+        ob.execute(OrderType::Cancel { id: 2 });
+        assert_eq!(snapshots, 2);
+        assert_eq!(recovery.latest_snapshot().map(|snapshot| snapshot.journal_sequence), Some(4));
+
+        let mut recovered = OrderBook::default();
+        assert_eq!(recovery.recover(&mut recovered, journal.entries()), 2);
+        assert_eq!(recovered.depth(10, true), ob.depth(10, true));
+        assert_eq!(recovered.last_sequence(), 5);
+    }
+}
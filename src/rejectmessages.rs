@@ -1,7 +1,236 @@
+#[cfg(feature = "serde")]
+use alloc::borrow::Cow;
+#[cfg(feature = "serde")]
+use alloc::format;
+use core::fmt;
+#[cfg(feature = "strum")]
+use core::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
 /// Used when the sequence number is not in an increasing order.
-pub const INVALID_ORDER_NUMBER: &'static str = "INVALID_ORDER_NUMBER";
+pub const INVALID_ORDER_NUMBER: &str = "INVALID_ORDER_NUMBER";
 /// Used when there is not enough liquidity for market orders.
-pub const LIQUIDITY_NOT_AVAILABLE: &'static str = "LIQUIDITY_NOT_AVAILABLE";
-// pub const MAX_UNSETTLED_LIMIT_REACHED: &'static str = "MAX_UNSETTLED_LIMIT_REACHED";
-// pub const MAX_ORDER_SIZE: &'static str = "MAX_ORDER_SIZE";
-// pub const MIN_ORDER_SIZE: &'static str = "MIN_ORDER_SIZE";
\ No newline at end of file
+pub const LIQUIDITY_NOT_AVAILABLE: &str = "LIQUIDITY_NOT_AVAILABLE";
+/// Used when a cancel refers to an order that is not on the book.
+pub const ORDER_NOT_FOUND: &str = "ORDER_NOT_FOUND";
+/// Used when a user exceeds the configured message rate.
+pub const RATE_LIMIT_EXCEEDED: &str = "RATE_LIMIT_EXCEEDED";
+/// Used when an order ID collides with an order resting on the book.
+pub const DUPLICATE_ORDER_ID: &str = "DUPLICATE_ORDER_ID";
+/// Used when a client order ID is already in use by a resting order.
+pub const DUPLICATE_CLIENT_ORDER_ID: &str = "DUPLICATE_CLIENT_ORDER_ID";
+/// Used when a spread order cannot be filled within its limit net price.
+pub const SPREAD_PRICE_NOT_AVAILABLE: &str = "SPREAD_PRICE_NOT_AVAILABLE";
+/// Used when a cross is reported at a price outside the best bid and offer.
+pub const CROSS_PRICE_OUTSIDE_BBO: &str = "CROSS_PRICE_OUTSIDE_BBO";
+/// Used when an order cannot be appended to the journal.
+pub const JOURNAL_UNAVAILABLE: &str = "JOURNAL_UNAVAILABLE";
+/// Used when an order would rest at a price the book storage cannot hold.
+pub const PRICE_OUT_OF_RANGE: &str = "PRICE_OUT_OF_RANGE";
+/// Used when an order would rest beyond the depth limit of the book.
+pub const DEPTH_LIMIT_EXCEEDED: &str = "DEPTH_LIMIT_EXCEEDED";
+/// Used when an order is routed to a symbol without an order book.
+pub const UNKNOWN_SYMBOL: &str = "UNKNOWN_SYMBOL";
+/// Used when an order is sent to a book that is closed for trading.
+pub const MARKET_CLOSED: &str = "MARKET_CLOSED";
+/// Used when an order would trade while the book collects orders for an
+/// auction, in its pre-open or closing call phase.
+pub const WOULD_TRADE_BEFORE_OPEN: &str = "WOULD_TRADE_BEFORE_OPEN";
+/// Used when an order would overflow the resting quantity of its level.
+pub const QUANTITY_OVERFLOW: &str = "QUANTITY_OVERFLOW";
+/// Used when an order is larger than the maximum order quantity of its user.
+pub const ORDER_QTY_LIMIT_EXCEEDED: &str = "ORDER_QTY_LIMIT_EXCEEDED";
+/// Used when an order is worth more than the maximum order notional of its user.
+pub const ORDER_NOTIONAL_LIMIT_EXCEEDED: &str = "ORDER_NOTIONAL_LIMIT_EXCEEDED";
+/// Used when an order would exceed the maximum open orders of its user.
+pub const OPEN_ORDERS_LIMIT_EXCEEDED: &str = "OPEN_ORDERS_LIMIT_EXCEEDED";
+/// Used when an order would exceed the maximum open notional of its user.
+pub const OPEN_NOTIONAL_LIMIT_EXCEEDED: &str = "OPEN_NOTIONAL_LIMIT_EXCEEDED";
+
+/// The reason an order or a cancel was rejected. A reason converts to and
+/// from its message constant, e.g. `LIQUIDITY_NOT_AVAILABLE`, which is also
+/// how it is serialized, so encoded events are unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RejectReason {
+    /// The sequence number is not in an increasing order.
+    InvalidSequence,
+    /// There is not enough liquidity for a market order.
+    LiquidityNotAvailable,
+    /// A cancel refers to an order that is not on the book.
+    OrderNotFound,
+    /// A user exceeds the configured message rate.
+    RateLimitExceeded,
+    /// An order ID collides with an order resting on the book.
+    DuplicateOrderId,
+    /// A client order ID is already in use by a resting order.
+    DuplicateClientOrderId,
+    /// A spread order cannot be filled within its limit net price.
+    SpreadPriceNotAvailable,
+    /// A cross is reported at a price outside the best bid and offer.
+    CrossPriceOutsideBbo,
+    /// An order cannot be appended to the journal.
+    JournalUnavailable,
+    /// An order would rest at a price the book storage cannot hold.
+    PriceOutOfRange,
+    /// An order would rest beyond the depth limit of the book.
+    DepthLimitExceeded,
+    /// An order is routed to a symbol without an order book.
+    UnknownSymbol,
+    /// An order is sent to a book that is closed for trading.
+    MarketClosed,
+    /// An order would trade while the book collects orders for an auction,
+    /// in its pre-open or closing call phase.
+    WouldTradeBeforeOpen,
+    /// An order would overflow the resting quantity of its level.
+    QuantityOverflow,
+    /// An order is larger than the maximum order quantity of its user.
+    OrderQtyLimitExceeded,
+    /// An order is worth more than the maximum order notional of its user.
+    OrderNotionalLimitExceeded,
+    /// An order would exceed the maximum open orders of its user.
+    OpenOrdersLimitExceeded,
+    /// An order would exceed the maximum open notional of its user.
+    OpenNotionalLimitExceeded,
+}
+
+impl RejectReason {
+    /// Return the message constant of the reason.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            RejectReason::InvalidSequence => INVALID_ORDER_NUMBER,
+            RejectReason::LiquidityNotAvailable => LIQUIDITY_NOT_AVAILABLE,
+            RejectReason::OrderNotFound => ORDER_NOT_FOUND,
+            RejectReason::RateLimitExceeded => RATE_LIMIT_EXCEEDED,
+            RejectReason::DuplicateOrderId => DUPLICATE_ORDER_ID,
+            RejectReason::DuplicateClientOrderId => DUPLICATE_CLIENT_ORDER_ID,
+            RejectReason::SpreadPriceNotAvailable => SPREAD_PRICE_NOT_AVAILABLE,
+            RejectReason::CrossPriceOutsideBbo => CROSS_PRICE_OUTSIDE_BBO,
+            RejectReason::JournalUnavailable => JOURNAL_UNAVAILABLE,
+            RejectReason::PriceOutOfRange => PRICE_OUT_OF_RANGE,
+            RejectReason::DepthLimitExceeded => DEPTH_LIMIT_EXCEEDED,
+            RejectReason::UnknownSymbol => UNKNOWN_SYMBOL,
+            RejectReason::MarketClosed => MARKET_CLOSED,
+            RejectReason::WouldTradeBeforeOpen => WOULD_TRADE_BEFORE_OPEN,
+            RejectReason::QuantityOverflow => QUANTITY_OVERFLOW,
+            RejectReason::OrderQtyLimitExceeded => ORDER_QTY_LIMIT_EXCEEDED,
+            RejectReason::OrderNotionalLimitExceeded => ORDER_NOTIONAL_LIMIT_EXCEEDED,
+            RejectReason::OpenOrdersLimitExceeded => OPEN_ORDERS_LIMIT_EXCEEDED,
+            RejectReason::OpenNotionalLimitExceeded => OPEN_NOTIONAL_LIMIT_EXCEEDED,
+        }
+    }
+
+    /// Return the reason of a message constant, if any.
+    #[cfg(any(feature = "serde", feature = "strum"))]
+    fn from_message(message: &str) -> Option<Self> {
+        ALL.into_iter().find(|reason| reason.as_str() == message)
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "strum")]
+impl FromStr for RejectReason {
+    type Err = strum::ParseError;
+
+    /// Parse a message constant into its reason.
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        RejectReason::from_message(message).ok_or(strum::ParseError::VariantNotFound)
+    }
+}
+
+impl From<RejectReason> for &'static str {
+    fn from(reason: RejectReason) -> Self {
+        reason.as_str()
+    }
+}
+
+impl PartialEq<&str> for RejectReason {
+    fn eq(&self, message: &&str) -> bool {
+        self.as_str() == *message
+    }
+}
+
+impl PartialEq<RejectReason> for &str {
+    fn eq(&self, reason: &RejectReason) -> bool {
+        *self == reason.as_str()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for RejectReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for RejectReason {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let message = Cow::<'de, str>::deserialize(deserializer)?;
+        RejectReason::from_message(&message)
+            .ok_or_else(|| de::Error::custom(format!("unknown reject message `{}`", message)))
+    }
+}
+
+/// Every reason, in the order the messages are declared.
+pub(crate) const ALL: [RejectReason; 19] = [
+    RejectReason::InvalidSequence,
+    RejectReason::LiquidityNotAvailable,
+    RejectReason::OrderNotFound,
+    RejectReason::RateLimitExceeded,
+    RejectReason::DuplicateOrderId,
+    RejectReason::DuplicateClientOrderId,
+    RejectReason::SpreadPriceNotAvailable,
+    RejectReason::CrossPriceOutsideBbo,
+    RejectReason::JournalUnavailable,
+    RejectReason::PriceOutOfRange,
+    RejectReason::DepthLimitExceeded,
+    RejectReason::UnknownSymbol,
+    RejectReason::MarketClosed,
+    RejectReason::WouldTradeBeforeOpen,
+    RejectReason::QuantityOverflow,
+    RejectReason::OrderQtyLimitExceeded,
+    RejectReason::OrderNotionalLimitExceeded,
+    RejectReason::OpenOrdersLimitExceeded,
+    RejectReason::OpenNotionalLimitExceeded,
+];
+
+// pub const MAX_UNSETTLED_LIMIT_REACHED: &str = "MAX_UNSETTLED_LIMIT_REACHED";
+// pub const MIN_ORDER_SIZE: &str = "MIN_ORDER_SIZE";
+
+#[cfg(test)]
+mod test {
+    use super::{RejectReason, ALL};
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "strum"))]
+    fn reject_reasons_round_trip_through_their_messages() {
+        for reason in ALL {
+            assert_eq!(reason.to_string().parse::<RejectReason>(), Ok(reason));
+            let json = serde_json::to_string(&reason).unwrap();
+            assert_eq!(json, format!("\"{}\"", reason));
+            assert_eq!(serde_json::from_str::<RejectReason>(&json).unwrap(), reason);
+        }
+        assert_eq!(RejectReason::InvalidSequence, "INVALID_ORDER_NUMBER");
+        assert!("UNKNOWN".parse::<RejectReason>().is_err());
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "serde", feature = "strum")))]
+    fn reject_reasons_keep_their_messages_without_serde_or_strum() {
+        use alloc::string::ToString;
+
+        for (index, reason) in ALL.into_iter().enumerate() {
+            let message: &str = reason.into();
+            assert_eq!(reason.to_string(), message);
+            assert!(ALL[..index].iter().all(|other| *other != message));
+        }
+        assert_eq!(RejectReason::OrderNotFound, "ORDER_NOT_FOUND");
+    }
+}
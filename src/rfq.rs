@@ -0,0 +1,208 @@
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::models::{OrderId, Price, Qty, Side, UserId};
+use crate::rejectmessages::RejectReason;
+
+/// The lifecycle state of a request for quote. Requests are removed from the
+/// book once closed, so the requests it holds are open.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RfqState {
+    /// The request is accepting quotes from the invited makers.
+    Open,
+    /// The request was executed against one or more quotes.
+    Filled,
+    /// The request was closed without any quote to execute against.
+    Expired,
+}
+
+/// A quote submitted by an invited maker in response to a request for quote.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RfqQuote {
+    /// The unique ID of this quote.
+    pub id: OrderId,
+    /// User id of the quoting maker.
+    pub maker_id: UserId,
+    /// The quoted quantity.
+    pub qty: Qty,
+    /// The quoted price.
+    pub price: Price,
+}
+
+/// A request for quote submitted by a taker. Invited makers respond with
+/// quotes until the deadline, after which the request is executed against the
+/// best responses.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rfq {
+    /// The unique ID of this request.
+    pub id: OrderId,
+    /// User id of the requesting taker.
+    pub user_id: UserId,
+    /// The side of the taker.
+    pub side: Side,
+    /// The requested quantity.
+    pub qty: Qty,
+    /// The makers invited to respond.
+    pub makers: Vec<UserId>,
+    /// The last timestamp at which quotes are accepted.
+    pub deadline: u64,
+    /// The quotes received so far, in arrival order.
+    pub quotes: Vec<RfqQuote>,
+    /// The current state of the request.
+    pub state: RfqState,
+}
+
+/// A step of the request for quote flow, as appended to the journal of an
+/// [`OrderBook`] so that replaying it rebuilds the requests and their trades.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RfqAction {
+    /// A request for quote opened with [`OrderBook::request_quote`].
+    ///
+    /// [`OrderBook::request_quote`]: struct.OrderBook.html#method.request_quote
+    Request {
+        /// The unique ID of the request.
+        id: OrderId,
+        /// User id of the requesting taker.
+        user_id: UserId,
+        /// The side of the taker.
+        side: Side,
+        /// The requested quantity.
+        qty: Qty,
+        /// The makers invited to respond.
+        makers: Vec<UserId>,
+        /// The last timestamp at which quotes are accepted.
+        deadline: u64,
+    },
+    /// A quote submitted with [`OrderBook::submit_quote`].
+    ///
+    /// [`OrderBook::submit_quote`]: struct.OrderBook.html#method.submit_quote
+    Quote {
+        /// The ID of the request the quote responds to.
+        rfq_id: OrderId,
+        /// The unique ID of the quote.
+        id: OrderId,
+        /// User id of the quoting maker.
+        maker_id: UserId,
+        /// The quoted quantity.
+        qty: Qty,
+        /// The quoted price.
+        price: Price,
+        /// The time the quote was submitted at.
+        now: u64,
+    },
+    /// The execution of a request with [`OrderBook::execute_rfq`].
+    ///
+    /// [`OrderBook::execute_rfq`]: struct.OrderBook.html#method.execute_rfq
+    Execute {
+        /// The ID of the executed request.
+        rfq_id: OrderId,
+    },
+}
+
+/// Errors returned by the request for quote flow.
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum RfqError {
+    /// The request or quote id is not greater than the last sequence.
+    #[error("Order id is not in an increasing order")]
+    InvalidOrderNumber,
+    /// No request for quote exists with the given id.
+    #[error("Unknown request for quote")]
+    UnknownRfq,
+    /// The request for quote was already executed or expired.
+    #[error("Request for quote is not open")]
+    NotOpen,
+    /// The quote arrived after the request deadline.
+    #[error("Quote received after the deadline")]
+    DeadlinePassed,
+    /// The quoting user was not invited by the taker.
+    #[error("Maker was not invited to quote")]
+    MakerNotInvited,
+    /// The requested or quoted quantity is zero.
+    #[error("Quantity must be positive")]
+    InvalidQuantity,
+    /// The request or quote id was already processed, e.g. when it is sent
+    /// again by a retrying gateway.
+    #[error("Request or quote was already processed")]
+    Duplicate,
+    /// The request or quote was refused by the checks orders go through,
+    /// such as rate limits and risk limits.
+    #[error("Rejected: {0}")]
+    Rejected(RejectReason),
+}
+
+impl Rfq {
+    pub(crate) fn new(id: OrderId, user_id: UserId, side: Side, qty: Qty, makers: Vec<UserId>, deadline: u64) -> Self {
+        Self { id, user_id, side, qty, makers, deadline, quotes: Vec::new(), state: RfqState::Open }
+    }
+
+    pub(crate) fn add_quote(&mut self, quote: RfqQuote, now: u64) -> Result<(), RfqError> {
+        self.check_quote(&quote, now)?;
+        self.quotes.push(quote);
+        Ok(())
+    }
+
+    /// Return why `quote` would be refused at time `now`, if it would.
+    pub(crate) fn check_quote(&self, quote: &RfqQuote, now: u64) -> Result<(), RfqError> {
+        if self.state != RfqState::Open {
+            return Err(RfqError::NotOpen);
+        }
+        if now > self.deadline {
+            return Err(RfqError::DeadlinePassed);
+        }
+        if !self.makers.contains(&quote.maker_id) {
+            return Err(RfqError::MakerNotInvited);
+        }
+        if quote.qty == 0 {
+            return Err(RfqError::InvalidQuantity);
+        }
+        Ok(())
+    }
+
+    /// Return the received quotes ordered from best to worst for the taker.
+    /// Quotes at the same price keep their arrival order.
+    pub fn ranked_quotes(&self) -> Vec<RfqQuote> {
+        let mut quotes = self.quotes.clone();
+        match self.side {
+            Side::Bid => quotes.sort_by_key(|quote| quote.price),
+            Side::Ask => quotes.sort_by_key(|quote| core::cmp::Reverse(quote.price)),
+        }
+        quotes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Rfq, RfqError, RfqQuote};
+    use crate::Side;
+
+    #[test]
+    fn quotes_ranked_for_taker() {
+        let mut rfq = Rfq::new(1, 1, Side::Ask, 10, vec![2, 3], 100);
+        rfq.add_quote(RfqQuote { id: 2, maker_id: 2, qty: 5, price: 99 }, 10).unwrap();
+        rfq.add_quote(RfqQuote { id: 3, maker_id: 3, qty: 5, price: 101 }, 20).unwrap();
+        let ranked: Vec<u64> = rfq.ranked_quotes().iter().map(|quote| quote.id).collect();
+        assert_eq!(ranked, vec![3, 2]);
+    }
+
+    #[test]
+    fn quote_rejections() {
+        let mut rfq = Rfq::new(1, 1, Side::Bid, 10, vec![2], 100);
+        let quote = RfqQuote { id: 2, maker_id: 2, qty: 5, price: 99 };
+        assert_eq!(rfq.add_quote(quote, 101), Err(RfqError::DeadlinePassed));
+        assert_eq!(
+            rfq.add_quote(RfqQuote { maker_id: 9, ..quote }, 10),
+            Err(RfqError::MakerNotInvited)
+        );
+        assert_eq!(rfq.add_quote(RfqQuote { qty: 0, ..quote }, 10), Err(RfqError::InvalidQuantity));
+        assert_eq!(rfq.add_quote(quote, 100), Ok(()));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::models::{wide_qty, Price, Qty};
+use crate::rejectmessages::RejectReason;
+
+/// Pre-trade risk limits of a user, checked by [`OrderBook::execute`] before
+/// an order of the user is matched. Notionals are absolute values, the price
+/// times the quantity, so that negative prices count against the limits as
+/// well. See [`OrderBook::set_risk_limits`].
+///
+/// [`OrderBook::execute`]: struct.OrderBook.html#method.execute
+/// [`OrderBook::set_risk_limits`]: struct.OrderBook.html#method.set_risk_limits
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RiskLimits {
+    /// The maximum quantity of a single order, if any.
+    pub max_order_qty: Option<Qty>,
+    /// The maximum notional of a single order, if any. The notional of a
+    /// market order is the cost of sweeping the opposite side of the book.
+    pub max_order_notional: Option<u128>,
+    /// The maximum number of resting orders of the user, if any.
+    pub max_open_orders: Option<usize>,
+    /// The maximum notional of the resting orders of the user, if any.
+    pub max_open_notional: Option<u128>,
+}
+
+impl RiskLimits {
+    /// Limit the quantity of every order to `max_order_qty`.
+    pub fn with_max_order_qty(self, max_order_qty: Qty) -> Self {
+        Self { max_order_qty: Some(max_order_qty), ..self }
+    }
+
+    /// Limit the notional of every order to `max_order_notional`.
+    pub fn with_max_order_notional(self, max_order_notional: u128) -> Self {
+        Self { max_order_notional: Some(max_order_notional), ..self }
+    }
+
+    /// Limit the number of resting orders to `max_open_orders`.
+    pub fn with_max_open_orders(self, max_open_orders: usize) -> Self {
+        Self { max_open_orders: Some(max_open_orders), ..self }
+    }
+
+    /// Limit the notional of the resting orders to `max_open_notional`.
+    pub fn with_max_open_notional(self, max_open_notional: u128) -> Self {
+        Self { max_open_notional: Some(max_open_notional), ..self }
+    }
+
+    /// Return the reason to reject an order of `qty` and `notional`, or
+    /// `None` if it is within the limits. `open` holds the number and
+    /// notional of the resting orders of the user for orders that may rest,
+    /// which count in full toward the open limits, whether or not part of
+    /// them trades on arrival.
+    pub(crate) fn check(&self, qty: Qty, notional: u128, open: Option<(usize, u128)>) -> Option<RejectReason> {
+        if self.max_order_qty.is_some_and(|max| qty > max) {
+            return Some(RejectReason::OrderQtyLimitExceeded);
+        }
+        if self.max_order_notional.is_some_and(|max| notional > max) {
+            return Some(RejectReason::OrderNotionalLimitExceeded);
+        }
+        let (open_orders, open_notional) = open?;
+        if self.max_open_orders.is_some_and(|max| open_orders >= max) {
+            return Some(RejectReason::OpenOrdersLimitExceeded);
+        }
+        if self.max_open_notional.is_some_and(|max| open_notional.saturating_add(notional) > max) {
+            return Some(RejectReason::OpenNotionalLimitExceeded);
+        }
+        None
+    }
+}
+
+/// The absolute notional of `qty` at `price`, saturating at `u128::MAX`.
+pub(crate) fn abs_notional(price: Price, qty: Qty) -> u128 {
+    i128::from(price).unsigned_abs().saturating_mul(wide_qty(qty))
+}
+
+/// The absolute notional of trading `qty` against `levels`, best first.
+pub(crate) fn sweep_notional(levels: impl Iterator<Item = (Price, Qty)>, qty: Qty) -> u128 {
+    let mut remaining = qty;
+    let mut notional = 0u128;
+    for (price, level_qty) in levels {
+        if remaining == 0 {
+            break;
+        }
+        let traded = remaining.min(level_qty);
+        remaining -= traded;
+        notional = notional.saturating_add(abs_notional(price, traded));
+    }
+    notional
+}
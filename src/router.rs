@@ -0,0 +1,209 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{EngineEvent, MatchingEngine, SymbolOrder};
+use crate::models::{self, Notional, OrderEvent, OrderId, OrderType, Price, Qty, Side, UserId};
+use crate::session::TradingPhase;
+use crate::storage::BookStorage;
+
+/// An order taking liquidity from the books of a [`SmartRouter`].
+///
+/// [`SmartRouter`]: struct.SmartRouter.html
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RouteOrder {
+    /// The unique ID of this order. It is used as the child order id in every
+    /// book, so it must be in sequence for all of them.
+    pub id: OrderId,
+    /// User id for this order
+    pub user_id: UserId,
+    /// The side of the order.
+    pub side: Side,
+    /// The quantity to take across all books.
+    pub qty: Qty,
+    /// The worst price to trade at, if any. Bids pay at most this price,
+    /// asks receive at least this price.
+    pub limit_price: Option<Price>,
+}
+
+/// The aggregated result of a [`RouteOrder`].
+///
+/// [`RouteOrder`]: struct.RouteOrder.html
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RouteReport {
+    /// The ID of the order this report is referring to.
+    pub id: OrderId,
+    /// The quantity requested.
+    pub qty: Qty,
+    /// The quantity filled across all books.
+    pub filled_qty: Qty,
+    /// The sum of price times quantity over all fills.
+    pub notional: Notional,
+    /// The events of the child orders, in routing order.
+    pub children: Vec<EngineEvent>,
+}
+
+impl RouteReport {
+    /// Return the average price of the fills, if any.
+    pub fn avg_price(&self) -> Option<f64> {
+        (self.filled_qty > 0).then(|| self.notional as f64 / self.filled_qty as f64)
+    }
+}
+
+/// Routes orders across the books of fungible instruments in a
+/// [`MatchingEngine`]. Orders are split to take the best prices of the
+/// consolidated depth first, ties going to the book listed first, and each
+/// book receives one immediate-or-cancel child order. Quantity that cannot
+/// be filled is not rested.
+///
+/// [`MatchingEngine`]: struct.MatchingEngine.html
+#[derive(Debug, Clone)]
+pub struct SmartRouter {
+    symbols: Vec<String>,
+}
+
+impl SmartRouter {
+    /// Create a router over the books of `symbols`, in tie-break order.
+    pub fn new<I: IntoIterator<Item = T>, T: Into<String>>(symbols: I) -> Self {
+        Self { symbols: symbols.into_iter().map(Into::into).collect() }
+    }
+
+    /// Return the child orders `order` would be split into, without executing
+    /// them. Books that are missing or not open are skipped.
+    pub fn plan<S: BookStorage>(&self, engine: &MatchingEngine<S>, order: &RouteOrder) -> Vec<SymbolOrder> {
+        let RouteOrder { id, user_id, side, qty, limit_price } = *order;
+        let within_limit = |price: Price| match (side, limit_price) {
+            (_, None) => true,
+            (Side::Bid, Some(limit)) => price <= limit,
+            (Side::Ask, Some(limit)) => price >= limit,
+        };
+
+        // Each book can contribute at most `qty`, so only that much of its
+        // depth is collected.
+        let mut levels = Vec::new();
+        for (index, symbol) in self.symbols.iter().enumerate() {
+            let Some(book) = engine.book(symbol).filter(|_| engine.phase(symbol) == Some(TradingPhase::Open)) else {
+                continue;
+            };
+            let depth: Box<dyn Iterator<Item = (Price, Qty)>> = match side {
+                Side::Bid => Box::new(book.iter_ask_qtys()),
+                Side::Ask => Box::new(book.iter_bid_qtys()),
+            };
+            let mut collected = 0;
+            for (price, level_qty) in depth.take_while(|(price, _)| within_limit(*price)) {
+                if collected >= qty {
+                    break;
+                }
+                levels.push((price, index, level_qty));
+                collected = collected.saturating_add(level_qty);
+            }
+        }
+        match side {
+            Side::Bid => levels.sort_by_key(|&(price, index, _)| (price, index)),
+            Side::Ask => levels.sort_by_key(|&(price, index, _)| (core::cmp::Reverse(price), index)),
+        }
+
+        // Allocate the quantity level by level, keeping the worst price taken
+        // on each book as the limit of its child order.
+        let mut allocations: Vec<Option<(Qty, Price)>> = vec![None; self.symbols.len()];
+        let mut remaining = qty;
+        for (price, index, level_qty) in levels {
+            if remaining == 0 {
+                break;
+            }
+            let take = level_qty.min(remaining);
+            remaining -= take;
+            let allocation = allocations[index].get_or_insert((0, price));
+            allocation.0 += take;
+            allocation.1 = price;
+        }
+        allocations
+            .into_iter()
+            .zip(&self.symbols)
+            .filter_map(|(allocation, symbol)| {
+                let (qty, price) = allocation?;
+                let order = OrderType::IOC { id, user_id, side, qty, price };
+                Some(SymbolOrder { symbol: symbol.clone(), order })
+            })
+            .collect()
+    }
+
+    /// Split `order` across the books and execute the child orders,
+    /// aggregating their events into one report.
+    pub fn route<S: BookStorage>(&self, engine: &mut MatchingEngine<S>, order: RouteOrder) -> RouteReport {
+        let children = engine.execute_all(self.plan(engine, &order));
+        let (mut filled_qty, mut notional): (Qty, Notional) = (0, 0);
+        for child in &children {
+            if let OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } = &child.event {
+                for fill in fills.iter() {
+                    filled_qty = filled_qty.saturating_add(fill.qty);
+                    notional = notional.saturating_add(models::notional(fill.price, fill.qty));
+                }
+            }
+        }
+        RouteReport { id: order.id, qty: order.qty, filled_qty, notional, children }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RouteOrder, SmartRouter};
+    use crate::{MatchingEngine, OrderBook, OrderType, Qty, Side, SymbolOrder};
+
+    #[test]
+    fn router_takes_the_cheapest_consolidated_depth() {
+        let mut engine = MatchingEngine::new();
+        for symbol in ["X", "Y", "Z"] {
+            engine.add_book(symbol, OrderBook::default());
+        }
+        let ask = |symbol: &str, id, qty, price| SymbolOrder {
+            symbol: symbol.to_string(),
+            order: OrderType::Limit { id, user_id: 1, side: Side::Ask, qty, price },
+        };
+        engine.execute_all([
+            ask("X", 1, 5, 100),
+            ask("X", 2, 5, 103),
+            ask("Y", 1, 3, 101),
+            ask("Y", 2, 10, 102),
+            ask("Z", 1, 4, 100),
+        ]);
+        let router = SmartRouter::new(["X", "Y", "Z"]);
+        let order = RouteOrder { id: 10, user_id: 2, side: Side::Bid, qty: 14, limit_price: Some(102) };
+        let plan: Vec<_> = router.plan(&engine, &order).into_iter().map(|child| (child.symbol, child.order)).collect();
+        assert_eq!(plan.len(), 3);
+        assert!(matches!(plan[0], (ref s, OrderType::IOC { qty: 5, price: 100, .. }) if s == "X"));
+        assert!(matches!(plan[1], (ref s, OrderType::IOC { qty: 5, price: 102, .. }) if s == "Y"));
+        assert!(matches!(plan[2], (ref s, OrderType::IOC { qty: 4, price: 100, .. }) if s == "Z"));
+
+        let report = router.route(&mut engine, order);
+        assert_eq!(report.filled_qty, 14);
+        assert_eq!(report.notional, 9 * 100 + 3 * 101 + 2 * 102);
+        assert_eq!(report.children.iter().map(|child| child.trade_ids.len()).sum::<usize>(), 4);
+        assert_eq!(engine.book("Y").unwrap().bbo().ask, Some((102, 8)));
+
+        let order = RouteOrder { id: 11, user_id: 2, side: Side::Bid, qty: 20, limit_price: Some(102) };
+        let report = router.route(&mut engine, order);
+        assert_eq!((report.filled_qty, report.avg_price()), (8, Some(102.0)));
+    }
+
+    #[test]
+    fn router_collects_depth_near_the_maximum_quantity() {
+        let mut engine = MatchingEngine::new();
+        engine.add_book("X", OrderBook::default());
+        for (id, qty, price) in [(1, Qty::MAX - 1, 100), (2, 5, 101)] {
+            let order = OrderType::Limit { id, user_id: 1, side: Side::Ask, qty, price };
+            engine.execute(SymbolOrder { symbol: "X".to_string(), order });
+        }
+        let router = SmartRouter::new(["X"]);
+        let order = RouteOrder { id: 3, user_id: 2, side: Side::Bid, qty: Qty::MAX, limit_price: None };
+        let report = router.route(&mut engine, order);
+        assert_eq!(report.filled_qty, Qty::MAX);
+        assert_eq!(engine.book("X").unwrap().bbo().ask, Some((101, 4)));
+    }
+}
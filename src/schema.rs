@@ -0,0 +1,182 @@
+use alloc::string::{String, ToString};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::models::{BookDepth, OrderEvent};
+use crate::snapshot::BookSnapshot;
+
+/// The schema version written by [`encode_versioned`]. Payloads written
+/// before versioning was introduced have no envelope and are read as
+/// version 1.
+///
+/// [`encode_versioned`]: fn.encode_versioned.html
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// A type stored as JSON in a versioned envelope, such as
+/// `{"schema":"OrderEvent","version":2,"payload":{"Cancelled":{"id":1}}}`,
+/// so that payloads kept across releases of the crate can still be read.
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// The name identifying the type in the envelope.
+    const SCHEMA: &'static str;
+
+    /// Turn a payload written with an older schema version into the current
+    /// layout. By default the payload is kept as is, for types whose older
+    /// layouts are read through serde defaults and aliases.
+    fn upgrade(payload: Value, _version: u32) -> Value {
+        payload
+    }
+}
+
+impl Versioned for OrderEvent {
+    const SCHEMA: &'static str = "OrderEvent";
+
+    /// Version 1 named the order IDs of a fill `order_1` and `order_2`.
+    fn upgrade(mut payload: Value, version: u32) -> Value {
+        if version < 2 {
+            let fills = payload.as_object_mut().into_iter().flat_map(|event| event.values_mut()).filter_map(|fields| fields.get_mut("fills"));
+            for fill in fills.filter_map(Value::as_array_mut).flatten().filter_map(Value::as_object_mut) {
+                for (former, current) in [("order_1", "taker_id"), ("order_2", "maker_id")] {
+                    if let Some(id) = fill.remove(former) {
+                        fill.insert(current.to_string(), id);
+                    }
+                }
+            }
+        }
+        payload
+    }
+}
+
+impl Versioned for BookDepth {
+    const SCHEMA: &'static str = "BookDepth";
+}
+
+impl Versioned for BookSnapshot {
+    const SCHEMA: &'static str = "BookSnapshot";
+}
+
+/// Errors returned when decoding a versioned payload.
+#[derive(Debug, Error, PartialEq)]
+pub enum SchemaError {
+    /// The payload was written with a newer schema version than this release
+    /// of the crate reads.
+    #[error("Unsupported schema version {0}")]
+    UnsupportedVersion(u32),
+    /// The envelope holds another type than the one requested.
+    #[error("Expected schema `{expected}`, found `{found}`")]
+    UnexpectedSchema {
+        /// The schema of the requested type.
+        expected: &'static str,
+        /// The schema found in the envelope.
+        found: String,
+    },
+    /// The text is not valid JSON, or not a valid payload of the requested
+    /// type.
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(String),
+}
+
+#[derive(Serialize)]
+struct EnvelopeRef<'a, T> {
+    schema: &'static str,
+    version: u32,
+    payload: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    schema: String,
+    version: u32,
+    payload: Value,
+}
+
+/// Encode a value as JSON in an envelope tagged with its schema and the
+/// current [`SCHEMA_VERSION`].
+///
+/// [`SCHEMA_VERSION`]: constant.SCHEMA_VERSION.html
+pub fn encode_versioned<T: Versioned>(value: &T) -> String {
+    let envelope = EnvelopeRef { schema: T::SCHEMA, version: SCHEMA_VERSION, payload: value };
+    // Writing to a string only fails for types serde cannot represent.
+    serde_json::to_string(&envelope).expect("versioned payloads are serializable")
+}
+
+/// Decode a value written by [`encode_versioned`] with the current or an
+/// older schema version. A payload without an envelope, as written before
+/// versioning was introduced, is read as version 1.
+///
+/// [`encode_versioned`]: fn.encode_versioned.html
+pub fn decode_versioned<T: Versioned>(text: &str) -> Result<T, SchemaError> {
+    let invalid = |error: serde_json::Error| SchemaError::InvalidPayload(error.to_string());
+    let value: Value = serde_json::from_str(text).map_err(invalid)?;
+    let is_envelope = value.get("schema").is_some() && value.get("version").is_some();
+    let (version, payload) = if is_envelope {
+        let envelope = Envelope::deserialize(value).map_err(invalid)?;
+        if envelope.schema != T::SCHEMA {
+            return Err(SchemaError::UnexpectedSchema { expected: T::SCHEMA, found: envelope.schema });
+        }
+        (envelope.version, envelope.payload)
+    } else {
+        (1, value)
+    };
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(SchemaError::UnsupportedVersion(version));
+    }
+    let payload = if version < SCHEMA_VERSION { T::upgrade(payload, version) } else { payload };
+    T::deserialize(payload).map_err(invalid)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_versioned, encode_versioned, SchemaError, SCHEMA_VERSION};
+    use crate::{BookDepth, OrderBook, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn versioned_round_trip_and_older_payloads() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 });
+        let event = ob.execute(OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: 1 });
+
+        let text = encode_versioned(&event);
+        assert!(text.starts_with(&format!(r#"{{"schema":"OrderEvent","version":{},"payload":"#, SCHEMA_VERSION)));
+        assert_eq!(decode_versioned::<OrderEvent>(&text).unwrap(), event);
+        assert_eq!(decode_versioned::<BookDepth>(&encode_versioned(&ob.depth(5, true))).unwrap(), ob.depth(5, true));
+        let mut restored = OrderBook::default();
+        restored.restore(decode_versioned(&encode_versioned(&ob.snapshot())).unwrap());
+        assert_eq!(restored.depth(5, true), ob.depth(5, true));
+
+        let unversioned = r#"{"Filled":{"id":2,"filled_qty":1,"fills":[{"order_1":2,"order_2":1,"maker_user_id":1,"qty":1,"price":100,"taker_side":"Bid","total_fill":false,"maker_remaining_qty":1}]}}"#;
+        match decode_versioned::<OrderEvent>(unversioned).unwrap() {
+            OrderEvent::Filled { id: 2, remaining_qty: 0, fills, .. } => assert_eq!((fills[0].taker_id, fills[0].maker_id), (2, 1)),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        let upgraded = <OrderEvent as super::Versioned>::upgrade(serde_json::from_str(unversioned).unwrap(), 1);
+        let fill = &upgraded["Filled"]["fills"][0];
+        assert_eq!((&fill["taker_id"], &fill["maker_id"]), (&2.into(), &1.into()));
+        assert!(fill.get("order_1").is_none() && fill.get("order_2").is_none());
+
+        let newer = text.replace(&format!(r#""version":{}"#, SCHEMA_VERSION), r#""version":99"#);
+        assert_eq!(decode_versioned::<OrderEvent>(&newer), Err(SchemaError::UnsupportedVersion(99)));
+        assert_eq!(
+            decode_versioned::<BookDepth>(&text),
+            Err(SchemaError::UnexpectedSchema { expected: "BookDepth", found: "OrderEvent".to_string() })
+        );
+        assert!(matches!(decode_versioned::<OrderEvent>("{"), Err(SchemaError::InvalidPayload(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "wide-qty")]
+    fn wide_quantities_round_trip_through_envelopes() {
+        let mut ob = OrderBook::default();
+        let qty = u64::MAX as crate::Qty + 1;
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty, price: 100 });
+        let event = ob.execute(OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: 1 });
+        assert_eq!(decode_versioned::<OrderEvent>(&encode_versioned(&event)).unwrap(), event);
+        assert_eq!(decode_versioned::<BookDepth>(&encode_versioned(&ob.depth(5, true))).unwrap(), ob.depth(5, true));
+        let mut restored = OrderBook::default();
+        restored.restore(decode_versioned(&encode_versioned(&ob.snapshot())).unwrap());
+        assert_eq!(restored.depth(5, true), ob.depth(5, true));
+    }
+}
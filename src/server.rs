@@ -0,0 +1,382 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::models::{BookDepth, FillMetadata, OrderEvent, OrderType};
+use crate::orderbook::OrderBook;
+use crate::storage::BookStorage;
+use crate::utils::TaggedFields;
+
+// How many client messages can wait for the book before connections block.
+const REQUEST_QUEUE: usize = 1024;
+
+// How many messages can wait for a client before it is disconnected.
+const CLIENT_QUEUE: usize = 1024;
+
+/// A market data stream a WebSocket client can subscribe to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamKind {
+    /// The depth of the book after every change.
+    Depth,
+    /// Every fill.
+    Trades,
+}
+
+/// A JSON message sent by a client of an [`ExchangeServer`], e.g.
+/// `{"type":"order","order":{"Cancel":{"id":1}}}` or
+/// `{"type":"subscribe","stream":"depth"}`.
+///
+/// [`ExchangeServer`]: struct.ExchangeServer.html
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Execute an order, answered with its event.
+    Order {
+        /// The order to execute.
+        order: OrderType,
+    },
+    /// Subscribe to a stream. Depth subscribers receive the current depth
+    /// right away.
+    Subscribe {
+        /// The stream to subscribe to.
+        stream: StreamKind,
+    },
+}
+
+/// A JSON message sent by an [`ExchangeServer`] to its clients.
+///
+/// [`ExchangeServer`]: struct.ExchangeServer.html
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The event of an order sent by this client.
+    Event {
+        /// The event of the order.
+        event: OrderEvent,
+    },
+    /// The depth of the book, for depth subscribers.
+    Depth {
+        /// The depth of the book.
+        depth: BookDepth,
+    },
+    /// A fill, for trade subscribers.
+    Trade {
+        /// The fill.
+        fill: FillMetadata,
+    },
+    /// A client message that could not be parsed.
+    Error {
+        /// The reason the message was refused.
+        message: String,
+    },
+}
+
+impl<'de> Deserialize<'de> for ClientMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut message = TaggedFields::deserialize(deserializer)?;
+        match message.kind() {
+            "order" => Ok(Self::Order { order: message.take("order")? }),
+            "subscribe" => Ok(Self::Subscribe { stream: message.take("stream")? }),
+            _ => Err(message.unknown_variant(&["order", "subscribe"])),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ServerMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut message = TaggedFields::deserialize(deserializer)?;
+        match message.kind() {
+            "event" => Ok(Self::Event { event: message.take("event")? }),
+            "depth" => Ok(Self::Depth { depth: message.take("depth")? }),
+            "trade" => Ok(Self::Trade { fill: message.take("fill")? }),
+            "error" => Ok(Self::Error { message: message.take("message")? }),
+            _ => Err(message.unknown_variant(&["event", "depth", "trade", "error"])),
+        }
+    }
+}
+
+struct Request {
+    message: ClientMessage,
+    client: Client,
+}
+
+/// The queue of messages to a connected client, shared by its connection and
+/// the book.
+#[derive(Clone)]
+struct Client {
+    outgoing: SyncSender<Message>,
+    disconnect: Arc<AtomicBool>,
+}
+
+impl Client {
+    /// Queue `message`, returning whether the client is still connected.
+    fn send(&self, message: &ServerMessage) -> bool {
+        serde_json::to_string(message).map_or(true, |text| self.queue(Message::text(text)))
+    }
+
+    /// Queue a WebSocket message, returning whether the client is still
+    /// connected. A client whose queue is full is too slow to keep up and is
+    /// disconnected instead.
+    fn queue(&self, message: Message) -> bool {
+        match self.outgoing.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.disconnect.store(true, Ordering::Relaxed);
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// The reading half of a connection. Once the handshake is done, it drops
+/// what tungstenite writes on its own, e.g. pongs, which the writing half
+/// sends instead so that frames never interleave.
+struct Incoming {
+    stream: TcpStream,
+    handshaking: bool,
+}
+
+impl Read for Incoming {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Incoming {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.handshaking {
+            self.stream.write(buf)
+        } else {
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// A small WebSocket exchange around an [`OrderBook`], available with the
+/// `server` feature. Clients send [`ClientMessage`]s as JSON text, receive
+/// the events of their own orders, and can subscribe to the depth and trade
+/// streams. The book runs on its own thread and every connection on two
+/// others, one reading and one writing, until the process exits. A client
+/// that falls more than a thousand messages behind, e.g. a subscriber not
+/// reading its streams, is disconnected.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`ClientMessage`]: enum.ClientMessage.html
+#[derive(Debug)]
+pub struct ExchangeServer {
+    local_addr: SocketAddr,
+}
+
+impl ExchangeServer {
+    /// Listen on `addr` and start serving `book`, publishing the top
+    /// `depth_levels` levels to depth subscribers.
+    pub fn bind<A, S>(addr: A, book: OrderBook<S>, depth_levels: usize) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+        S: BookStorage + Send + 'static,
+    {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let (requests, receiver) = mpsc::sync_channel(REQUEST_QUEUE);
+        thread::spawn(move || run_book(book, depth_levels, receiver));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let requests = requests.clone();
+                thread::spawn(move || serve_client(stream, requests));
+            }
+        });
+        Ok(Self { local_addr })
+    }
+
+    /// Return the address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+fn run_book<S: BookStorage>(mut book: OrderBook<S>, depth_levels: usize, requests: Receiver<Request>) {
+    let mut depth_subscribers: Vec<Client> = Vec::new();
+    let mut trade_subscribers: Vec<Client> = Vec::new();
+    for Request { message, client } in requests {
+        match message {
+            ClientMessage::Order { order } => {
+                let event = book.execute(order);
+                if let OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } = &event {
+                    for fill in fills.iter() {
+                        broadcast(&mut trade_subscribers, &ServerMessage::Trade { fill: *fill });
+                    }
+                }
+                client.send(&ServerMessage::Event { event });
+                if !depth_subscribers.is_empty() {
+                    broadcast(&mut depth_subscribers, &ServerMessage::Depth { depth: book.depth(depth_levels, false) });
+                }
+            }
+            ClientMessage::Subscribe { stream: StreamKind::Depth } => {
+                if client.send(&ServerMessage::Depth { depth: book.depth(depth_levels, false) }) {
+                    depth_subscribers.push(client);
+                }
+            }
+            ClientMessage::Subscribe { stream: StreamKind::Trades } => trade_subscribers.push(client),
+        }
+    }
+}
+
+/// Send `message` to every subscriber, serialized once, and drop the
+/// subscribers that are gone.
+fn broadcast(subscribers: &mut Vec<Client>, message: &ServerMessage) {
+    let Ok(text) = serde_json::to_string(message) else {
+        return;
+    };
+    let message = Message::text(text);
+    subscribers.retain(|subscriber| subscriber.queue(message.clone()));
+}
+
+fn serve_client(stream: TcpStream, requests: SyncSender<Request>) {
+    let Ok(writer) = stream.try_clone() else {
+        return;
+    };
+    let Ok(mut socket) = tungstenite::accept(Incoming { stream, handshaking: true }) else {
+        return;
+    };
+    socket.get_mut().handshaking = false;
+    let (outgoing, receiver) = mpsc::sync_channel(CLIENT_QUEUE);
+    let client = Client { outgoing, disconnect: Arc::new(AtomicBool::new(false)) };
+    let disconnect = client.disconnect.clone();
+    thread::spawn(move || write_client(WebSocket::from_raw_socket(writer, Role::Server, None), receiver, &disconnect));
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let reply = match serde_json::from_str(&text) {
+                    Ok(message) => requests.send(Request { message, client: client.clone() }).err().map(|_| "The book has stopped".to_string()),
+                    Err(error) => Some(error.to_string()),
+                };
+                if let Some(message) = reply {
+                    client.send(&ServerMessage::Error { message });
+                }
+            }
+            Ok(Message::Ping(payload)) => {
+                client.queue(Message::Pong(payload));
+            }
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+    client.queue(Message::Close(None));
+}
+
+/// Send the queued messages to the client until it is gone, closed or
+/// disconnected for being too slow, then shut the connection down, which
+/// also stops the reading half.
+fn write_client(mut socket: WebSocket<TcpStream>, outgoing: Receiver<Message>, disconnect: &AtomicBool) {
+    for message in outgoing {
+        let closing = matches!(message, Message::Close(_));
+        if disconnect.load(Ordering::Relaxed) || socket.send(message).is_err() || closing {
+            break;
+        }
+    }
+    let _ = socket.get_ref().shutdown(Shutdown::Both);
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpStream;
+
+    use tungstenite::{Message, WebSocket};
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+
+    use super::{Client, ClientMessage, ExchangeServer, ServerMessage, StreamKind};
+    use crate::{OrderBook, OrderEvent, OrderType, Side};
+
+    fn connect(server: &ExchangeServer) -> WebSocket<TcpStream> {
+        let stream = TcpStream::connect(server.local_addr()).unwrap();
+        let url = format!("ws://{}/", server.local_addr());
+        tungstenite::client(url, stream).unwrap().0
+    }
+
+    fn send(socket: &mut WebSocket<TcpStream>, message: &ClientMessage) {
+        socket.send(Message::text(serde_json::to_string(message).unwrap())).unwrap();
+    }
+
+    fn receive(socket: &mut WebSocket<TcpStream>) -> ServerMessage {
+        loop {
+            if let Message::Text(text) = socket.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "wide-qty")]
+    fn wide_quantities_round_trip_through_messages() {
+        let qty = u64::MAX as crate::Qty + 1;
+        let order = ClientMessage::Order { order: OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty, price: 100 } };
+        let json = serde_json::to_string(&order).unwrap();
+        assert!(json.contains("18446744073709551616"));
+        assert!(matches!(serde_json::from_str(&json).unwrap(), ClientMessage::Order { order: OrderType::Limit { qty: parsed, .. } } if parsed == qty));
+
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty, price: 100 });
+        let depth = ServerMessage::Depth { depth: ob.depth(1, false) };
+        assert_eq!(serde_json::from_str::<ServerMessage>(&serde_json::to_string(&depth).unwrap()).unwrap(), depth);
+    }
+
+    #[test]
+    fn server_executes_orders_and_streams_market_data() {
+        let server = ExchangeServer::bind("127.0.0.1:0", OrderBook::default(), 5).unwrap();
+        let mut watcher = connect(&server);
+        send(&mut watcher, &ClientMessage::Subscribe { stream: StreamKind::Depth });
+        assert!(matches!(receive(&mut watcher), ServerMessage::Depth { depth } if depth.asks.is_empty()));
+        send(&mut watcher, &ClientMessage::Subscribe { stream: StreamKind::Trades });
+
+        let mut trader = connect(&server);
+        send(&mut trader, &ClientMessage::Order { order: OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 100 } });
+        assert_eq!(receive(&mut trader), ServerMessage::Event { event: OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 100 } });
+        assert!(matches!(receive(&mut watcher), ServerMessage::Depth { depth } if depth.asks[0].qty == 2));
+
+        send(&mut trader, &ClientMessage::Order { order: OrderType::Market { id: 2, user_id: 2, side: Side::Bid, qty: 1 } });
+        assert!(matches!(receive(&mut trader), ServerMessage::Event { event: OrderEvent::Filled { id: 2, .. } }));
+        assert!(matches!(receive(&mut watcher), ServerMessage::Trade { fill } if fill.maker_id == 1 && fill.qty == 1));
+        assert!(matches!(receive(&mut watcher), ServerMessage::Depth { depth } if depth.asks[0].qty == 1));
+
+        trader.send(Message::text("not json")).unwrap();
+        assert!(matches!(receive(&mut trader), ServerMessage::Error { .. }));
+
+        trader.send(Message::Ping(b"still there?".to_vec().into())).unwrap();
+        assert!(matches!(trader.read().unwrap(), Message::Pong(payload) if payload == b"still there?".as_slice()));
+        trader.close(None).unwrap();
+        assert!(matches!(trader.read().unwrap(), Message::Close(_)));
+    }
+
+    #[test]
+    fn slow_clients_are_disconnected() {
+        let (outgoing, receiver) = mpsc::sync_channel(1);
+        let client = Client { outgoing, disconnect: Arc::new(AtomicBool::new(false)) };
+        let message = ServerMessage::Error { message: "lagging".to_string() };
+        assert!(client.send(&message));
+        assert!(!client.disconnect.load(Ordering::Relaxed));
+        assert!(!client.send(&message));
+        assert!(client.disconnect.load(Ordering::Relaxed));
+        drop(receiver);
+
+        // Clients that are already gone are just dropped.
+        let gone = Client { outgoing: mpsc::sync_channel(1).0, disconnect: Arc::new(AtomicBool::new(false)) };
+        assert!(!gone.send(&message));
+        assert!(!gone.disconnect.load(Ordering::Relaxed));
+    }
+}
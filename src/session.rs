@@ -0,0 +1,303 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::collections::BTreeMap;
+use crate::engine::{EngineEvent, MatchingEngine};
+use crate::storage::BookStorage;
+
+/// The trading phase of an [`OrderBook`]. See [`OrderBook::set_phase`].
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`OrderBook::set_phase`]: struct.OrderBook.html#method.set_phase
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TradingPhase {
+    /// New orders are rejected; cancels and reduces are accepted.
+    Closed,
+    /// Orders are collected for the opening auction: limit orders rest on
+    /// the book without being matched, even when they cross, and other
+    /// orders that would trade are rejected.
+    PreOpen,
+    /// Orders are matched continuously.
+    #[default]
+    Open,
+    /// Orders are collected for the closing auction, as during the pre-open
+    /// phase.
+    PreClose,
+}
+
+impl TradingPhase {
+    /// Return whether orders are collected for an auction during the phase,
+    /// instead of being matched. The auction is run when the book leaves
+    /// the phase.
+    pub fn is_call(self) -> bool {
+        matches!(self, TradingPhase::PreOpen | TradingPhase::PreClose)
+    }
+}
+
+/// The daily session of an instrument. The times are offsets within a cycle
+/// of `period` clock units, e.g. a day, with
+/// `pre_open <= open <= pre_close <= close <= period`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SessionSchedule {
+    /// The start of the pre-open phase.
+    pub pre_open: u64,
+    /// The start of continuous trading. The opening auction is run then.
+    pub open: u64,
+    /// The start of the closing call phase, if the session ends with a
+    /// closing auction.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pre_close: Option<u64>,
+    /// The end of continuous trading, or of the closing call phase, after
+    /// which the book is closed until the next pre-open.
+    pub close: u64,
+    /// The length of the cycle the times repeat with.
+    pub period: u64,
+}
+
+impl SessionSchedule {
+    /// Return the phase of the session at the clock time `now`.
+    pub fn phase_at(&self, now: u64) -> TradingPhase {
+        let offset = now % self.period.max(1);
+        if offset >= self.open && offset < self.close {
+            if self.pre_close.is_some_and(|pre_close| offset >= pre_close) {
+                TradingPhase::PreClose
+            } else {
+                TradingPhase::Open
+            }
+        } else if offset >= self.pre_open && offset < self.open {
+            TradingPhase::PreOpen
+        } else {
+            TradingPhase::Closed
+        }
+    }
+}
+
+/// A phase transition applied to a book by a [`SessionScheduler`].
+///
+/// [`SessionScheduler`]: struct.SessionScheduler.html
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SessionEvent {
+    /// The symbol of the book.
+    pub symbol: String,
+    /// The clock time the transition was applied at.
+    pub timestamp: u64,
+    /// The phase before the transition.
+    pub from: TradingPhase,
+    /// The phase after the transition.
+    pub to: TradingPhase,
+    /// The events of the auction run by the transition, when the book left a
+    /// call phase.
+    pub auction: Vec<EngineEvent>,
+}
+
+/// Moves the books of a [`MatchingEngine`] through their daily sessions,
+/// reading the time from the injected clock. Call [`poll`] regularly, e.g.
+/// before routing each order, to apply the transitions that are due. Leaving
+/// the pre-open phase runs the opening auction, and leaving the closing call
+/// phase runs the closing auction. See [`OrderBook::set_phase`].
+///
+/// [`MatchingEngine`]: struct.MatchingEngine.html
+/// [`poll`]: #method.poll
+/// [`OrderBook::set_phase`]: struct.OrderBook.html#method.set_phase
+#[derive(Debug)]
+pub struct SessionScheduler {
+    clock: Box<dyn Clock>,
+    schedules: BTreeMap<String, SessionSchedule>,
+}
+
+impl SessionScheduler {
+    /// Create a scheduler without sessions reading the time from `clock`.
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        Self { clock, schedules: BTreeMap::new() }
+    }
+
+    /// Set the session of `symbol`, returning the session it replaces, if
+    /// any.
+    pub fn set_schedule(&mut self, symbol: impl Into<String>, schedule: SessionSchedule) -> Option<SessionSchedule> {
+        self.schedules.insert(symbol.into(), schedule)
+    }
+
+    /// Remove the session of `symbol`, leaving its book in its current phase.
+    pub fn remove_schedule(&mut self, symbol: &str) -> Option<SessionSchedule> {
+        self.schedules.remove(symbol)
+    }
+
+    /// Move every scheduled book of `engine` to the phase its session is in
+    /// at the current time, returning the transitions applied in symbol
+    /// order. Sessions of symbols without a book are skipped, as are
+    /// transitions the book could not journal, which are retried by the next
+    /// poll.
+    pub fn poll<S: BookStorage>(&mut self, engine: &mut MatchingEngine<S>) -> Vec<SessionEvent> {
+        let now = self.clock.now();
+        let mut events = Vec::new();
+        for (symbol, schedule) in &self.schedules {
+            let (Some(from), to) = (engine.phase(symbol), schedule.phase_at(now)) else {
+                continue;
+            };
+            if from != to {
+                let auction = engine.set_phase(symbol, to);
+                if engine.phase(symbol) == Some(to) {
+                    events.push(SessionEvent { symbol: symbol.clone(), timestamp: now, from, to, auction });
+                }
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SessionEvent, SessionSchedule, SessionScheduler, TradingPhase};
+    use crate::rejectmessages::RejectReason;
+    use crate::{ManualClock, MatchingEngine, OrderBook, OrderEvent, OrderType, Price, Qty, RfqError, Side, SymbolOrder};
+
+    #[test]
+    fn scheduler_moves_books_through_sessions() {
+        let clock = ManualClock::new(5);
+        let mut scheduler = SessionScheduler::new(Box::new(clock.clone()));
+        scheduler.set_schedule("AAA", SessionSchedule { pre_open: 8, open: 10, pre_close: None, close: 16, period: 24 });
+        let mut engine = MatchingEngine::new();
+        engine.add_book("AAA", OrderBook::default());
+        let execute = |engine: &mut MatchingEngine, order| engine.execute(SymbolOrder { symbol: "AAA".to_string(), order }).event;
+
+        let transition = |timestamp, from, to| SessionEvent { symbol: "AAA".to_string(), timestamp, from, to, auction: vec![] };
+        assert_eq!(scheduler.poll(&mut engine), vec![transition(5, TradingPhase::Open, TradingPhase::Closed)]);
+        assert_eq!(
+            execute(&mut engine, OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 1, price: 100 }),
+            OrderEvent::Rejected { id: 1, message: RejectReason::MarketClosed }
+        );
+
+        clock.set(8);
+        assert_eq!(scheduler.poll(&mut engine), vec![transition(8, TradingPhase::Closed, TradingPhase::PreOpen)]);
+        assert_eq!(scheduler.poll(&mut engine), vec![]);
+        assert_eq!(
+            execute(&mut engine, OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 1, price: 100 }),
+            OrderEvent::Open { id: 2, remaining_qty: 1, resting_price: 100 }
+        );
+        assert_eq!(
+            execute(&mut engine, OrderType::IOC { id: 3, user_id: 2, side: Side::Bid, qty: 1, price: 100 }),
+            OrderEvent::Rejected { id: 3, message: RejectReason::WouldTradeBeforeOpen }
+        );
+
+        clock.set(24 + 12);
+        assert_eq!(scheduler.poll(&mut engine), vec![transition(36, TradingPhase::PreOpen, TradingPhase::Open)]);
+        assert!(matches!(
+            execute(&mut engine, OrderType::Market { id: 4, user_id: 2, side: Side::Bid, qty: 1 }),
+            OrderEvent::Filled { id: 4, .. }
+        ));
+    }
+
+    #[test]
+    fn books_enforce_their_phase_without_an_engine() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 1, price: 100 });
+        assert_eq!(ob.set_phase(TradingPhase::PreOpen), vec![]);
+        assert_eq!(
+            ob.execute(OrderType::IOC { id: 2, user_id: 2, side: Side::Bid, qty: 1, price: 100 }),
+            OrderEvent::Rejected { id: 2, message: RejectReason::WouldTradeBeforeOpen }
+        );
+        assert_eq!(
+            ob.execute(OrderType::Limit { id: 3, user_id: 2, side: Side::Bid, qty: 1, price: 99 }),
+            OrderEvent::Open { id: 3, remaining_qty: 1, resting_price: 99 }
+        );
+
+        ob.set_phase(TradingPhase::Closed);
+        assert_eq!(
+            ob.execute(OrderType::Market { id: 4, user_id: 2, side: Side::Bid, qty: 1 }),
+            OrderEvent::Rejected { id: 4, message: RejectReason::MarketClosed }
+        );
+        assert!(matches!(ob.execute(OrderType::Cancel { id: 3 }), OrderEvent::Cancelled { id: 3 }));
+        assert_eq!((ob.phase(), ob.best_ask(), ob.best_bid()), (TradingPhase::Closed, Some(100), None));
+    }
+
+    #[test]
+    fn requests_for_quote_follow_the_phase() {
+        let mut ob = OrderBook::default();
+        ob.set_phase(TradingPhase::Closed);
+        let closed = RfqError::Rejected(RejectReason::MarketClosed);
+        assert_eq!(ob.request_quote(1, 1, Side::Bid, 5, vec![2], 100), Err(closed));
+        assert_eq!(ob.last_sequence(), 0);
+
+        ob.set_phase(TradingPhase::PreOpen);
+        ob.request_quote(1, 1, Side::Bid, 5, vec![2], 100).unwrap();
+        ob.submit_quote(1, 2, 2, 5, 100, 10).unwrap();
+        assert_eq!(ob.execute_rfq(1), Err(RfqError::Rejected(RejectReason::WouldTradeBeforeOpen)));
+        assert_eq!(ob.execute_expired_rfqs(200), vec![]);
+
+        ob.set_phase(TradingPhase::Closed);
+        assert_eq!(ob.submit_quote(1, 3, 2, 5, 99, 10), Err(closed));
+        assert_eq!(ob.execute_rfq(1), Err(closed));
+        assert_eq!(ob.rfq(1).map(|rfq| rfq.quotes.len()), Some(1));
+
+        ob.set_phase(TradingPhase::Open);
+        assert!(matches!(ob.execute_rfq(1), Ok(OrderEvent::Filled { id: 1, filled_qty: 5, .. })));
+    }
+
+    #[test]
+    fn scheduler_runs_opening_and_closing_auctions() {
+        let clock = ManualClock::new(8);
+        let mut scheduler = SessionScheduler::new(Box::new(clock.clone()));
+        scheduler.set_schedule("AAA", SessionSchedule { pre_open: 8, open: 10, pre_close: Some(14), close: 16, period: 24 });
+        let mut engine = MatchingEngine::new();
+        engine.add_book("AAA", OrderBook::default());
+        let execute = |engine: &mut MatchingEngine, order| engine.execute(SymbolOrder { symbol: "AAA".to_string(), order }).event;
+        // The fills of the auctions, as (taker, maker, quantity, price).
+        let auction = |events: &[SessionEvent]| -> Vec<(u64, u64, Qty, Price)> {
+            let trades = events.iter().flat_map(|event| event.auction.iter());
+            trades
+                .flat_map(|trade| match &trade.event {
+                    OrderEvent::Filled { fills, .. } | OrderEvent::PartiallyFilled { fills, .. } => fills.to_vec(),
+                    event => panic!("unexpected auction event {:?}", event),
+                })
+                .map(|fill| (fill.taker_id, fill.maker_id, fill.qty, fill.price))
+                .collect()
+        };
+
+        assert_eq!(scheduler.poll(&mut engine)[0].to, TradingPhase::PreOpen);
+        for order in [
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 5, price: 98 },
+            OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 5, price: 102 },
+            OrderType::Limit { id: 3, user_id: 2, side: Side::Bid, qty: 4, price: 103 },
+            OrderType::Limit { id: 4, user_id: 3, side: Side::Bid, qty: 4, price: 100 },
+            OrderType::Limit { id: 5, user_id: 3, side: Side::Bid, qty: 3, price: 99 },
+        ] {
+            assert!(matches!(execute(&mut engine, order), OrderEvent::Open { .. }));
+        }
+        assert_eq!(engine.book("AAA").map(|book| (book.best_bid(), book.best_ask())), Some((Some(103), Some(98))));
+
+        // 5 trade at every price from 98 to 100, and 100 leaves the smallest
+        // imbalance.
+        clock.set(10);
+        let events = scheduler.poll(&mut engine);
+        assert_eq!(auction(&events), vec![(3, 1, 4, 100), (4, 1, 1, 100)]);
+        assert_eq!(events[0].auction.iter().map(|event| event.trade_ids.clone()).collect::<Vec<_>>(), vec![vec![1], vec![2]]);
+        let book = engine.book("AAA").unwrap();
+        assert_eq!((book.best_bid(), book.best_ask(), book.resting_order(4).map(|(_, order)| order.qty)), (Some(100), Some(102), Some(3)));
+
+        clock.set(14);
+        assert_eq!(scheduler.poll(&mut engine)[0].to, TradingPhase::PreClose);
+        assert!(matches!(
+            execute(&mut engine, OrderType::Limit { id: 6, user_id: 4, side: Side::Ask, qty: 3, price: 99 }),
+            OrderEvent::Open { id: 6, .. }
+        ));
+        assert_eq!(
+            execute(&mut engine, OrderType::Market { id: 7, user_id: 4, side: Side::Ask, qty: 1 }),
+            OrderEvent::Rejected { id: 7, message: RejectReason::WouldTradeBeforeOpen }
+        );
+
+        clock.set(16);
+        let events = scheduler.poll(&mut engine);
+        assert_eq!((events[0].from, events[0].to), (TradingPhase::PreClose, TradingPhase::Closed));
+        assert_eq!(auction(&events), vec![(4, 6, 3, 100)]);
+        let book = engine.book("AAA").unwrap();
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(99), Some(102)));
+    }
+}
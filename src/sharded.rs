@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::engine::{EngineEvent, MatchingEngine, SymbolOrder};
+use crate::models::Price;
+use crate::orderbook::OrderBook;
+use crate::storage::{BookStorage, PriceQueue};
+
+/// An event of a [`ShardedEngine`], tagged with the shard that produced it.
+/// Trade IDs are numbered per shard, so `(shard, trade_id)` is unique across
+/// the engine.
+///
+/// [`ShardedEngine`]: struct.ShardedEngine.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardEvent {
+    /// The index of the shard.
+    pub shard: usize,
+    /// The event of the shard's engine.
+    pub event: EngineEvent,
+}
+
+#[derive(Debug)]
+enum ShardCommand<S> {
+    AddBook(String, Box<OrderBook<S>>),
+    Execute(SymbolOrder),
+}
+
+#[derive(Debug)]
+struct Shard<S> {
+    commands: Sender<ShardCommand<S>>,
+    handle: JoinHandle<MatchingEngine<S>>,
+}
+
+/// A multi-instrument engine splitting its books over worker threads. Each
+/// shard runs its own single-threaded [`MatchingEngine`], and each symbol is
+/// pinned to one shard, so the events of a symbol keep the order its orders
+/// were submitted in. The events of all shards are merged into one stream.
+///
+/// [`MatchingEngine`]: struct.MatchingEngine.html
+#[derive(Debug)]
+pub struct ShardedEngine<S = BTreeMap<Price, PriceQueue>> {
+    shards: Vec<Shard<S>>,
+    assignments: BTreeMap<String, usize>,
+    events: Receiver<ShardEvent>,
+}
+
+impl<S: BookStorage + Send + 'static> ShardedEngine<S> {
+    /// Start `shards` worker threads without books.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "a sharded engine needs at least one shard");
+        let (event_sender, events) = mpsc::channel();
+        let shards = (0..shards)
+            .map(|shard| {
+                let (commands, receiver) = mpsc::channel();
+                let event_sender = event_sender.clone();
+                let handle = thread::spawn(move || {
+                    let mut engine = MatchingEngine::new();
+                    for command in receiver {
+                        match command {
+                            ShardCommand::AddBook(symbol, book) => {
+                                engine.add_book(symbol, *book);
+                            }
+                            ShardCommand::Execute(order) => {
+                                let _ = event_sender.send(ShardEvent { shard, event: engine.execute(order) });
+                            }
+                        }
+                    }
+                    engine
+                });
+                Shard { commands, handle }
+            })
+            .collect();
+        Self { shards, assignments: BTreeMap::new(), events }
+    }
+
+    /// Add the book of `symbol` to the shard with the fewest symbols, the
+    /// lowest index first, returning the shard. A symbol already assigned
+    /// keeps its shard, and its book is replaced.
+    pub fn add_book(&mut self, symbol: impl Into<String>, book: OrderBook<S>) -> usize {
+        let symbol = symbol.into();
+        let shard = match self.assignments.get(&symbol) {
+            Some(shard) => *shard,
+            None => {
+                let mut counts = vec![0; self.shards.len()];
+                for shard in self.assignments.values() {
+                    counts[*shard] += 1;
+                }
+                (0..counts.len()).min_by_key(|shard| counts[*shard]).unwrap_or_default()
+            }
+        };
+        self.add_book_to(shard, symbol, book);
+        shard
+    }
+
+    /// Pin the book of `symbol` to `shard`, e.g. to group related symbols.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard` is out of range, or if the symbol is already pinned
+    /// to another shard.
+    pub fn add_book_to(&mut self, shard: usize, symbol: impl Into<String>, book: OrderBook<S>) {
+        let symbol = symbol.into();
+        let pinned = *self.assignments.entry(symbol.clone()).or_insert(shard);
+        assert_eq!(pinned, shard, "{} is pinned to shard {}", symbol, pinned);
+        self.send(shard, ShardCommand::AddBook(symbol, Box::new(book)));
+    }
+
+    /// Return the shard the book of `symbol` is pinned to.
+    pub fn shard_of(&self, symbol: &str) -> Option<usize> {
+        self.assignments.get(symbol).copied()
+    }
+
+    /// Return the number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Queue `order` on the shard of its symbol. Orders for a symbol without
+    /// a book go to the first shard, which rejects them.
+    pub fn submit(&self, order: SymbolOrder) {
+        let shard = self.shard_of(&order.symbol).unwrap_or_default();
+        self.send(shard, ShardCommand::Execute(order));
+    }
+
+    /// Return the merged stream of events, with one event per submitted
+    /// order.
+    pub fn events(&self) -> &Receiver<ShardEvent> {
+        &self.events
+    }
+
+    /// Stop the shards once their queued orders are processed, and return
+    /// their engines in shard order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a shard thread panicked.
+    pub fn join(self) -> Vec<MatchingEngine<S>> {
+        self.shards
+            .into_iter()
+            .map(|Shard { commands, handle }| {
+                drop(commands);
+                handle.join().expect("a shard thread panicked")
+            })
+            .collect()
+    }
+
+    fn send(&self, shard: usize, command: ShardCommand<S>) {
+        // A shard only stops after its sender is dropped in `join`, or after
+        // panicking, which `join` reports.
+        let _ = self.shards[shard].commands.send(command);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ShardedEngine;
+    use crate::{OrderBook, OrderEvent, OrderType, Side, SymbolOrder};
+
+    #[test]
+    fn shards_keep_per_symbol_order() {
+        let mut engine = ShardedEngine::new(2);
+        assert_eq!(engine.add_book("AAA", OrderBook::default()), 0);
+        assert_eq!(engine.add_book("BBB", OrderBook::default()), 1);
+        engine.add_book_to(0, "CCC", OrderBook::default());
+        assert_eq!(engine.add_book("DDD", OrderBook::default()), 1);
+        assert_eq!(engine.shard_of("CCC"), Some(0));
+
+        let symbols = ["AAA", "BBB", "CCC", "DDD"];
+        for id in 1..=10 {
+            for symbol in symbols {
+                let side = if id % 2 == 1 { Side::Ask } else { Side::Bid };
+                let order = OrderType::Limit { id, user_id: 1, side, qty: 1, price: 100 };
+                engine.submit(SymbolOrder { symbol: symbol.to_string(), order });
+            }
+        }
+        let events: Vec<_> = engine.events().iter().take(40).collect();
+        for symbol in symbols {
+            let symbol_events: Vec<_> = events
+                .iter()
+                .filter(|event| event.event.symbol == symbol)
+                .map(|event| {
+                    assert_eq!(Some(event.shard), engine.shard_of(symbol));
+                    event.event.event.clone()
+                })
+                .collect();
+            assert_eq!(symbol_events.len(), 10);
+            assert_eq!(symbol_events[0], OrderEvent::Open { id: 1, remaining_qty: 1, resting_price: 100 });
+            assert!(matches!(symbol_events[1], OrderEvent::Filled { id: 2, .. }));
+        }
+
+        let engines = engine.join();
+        assert_eq!(engines.len(), 2);
+        assert_eq!(engines[1].books().map(|(symbol, _)| symbol).collect::<Vec<_>>(), vec!["BBB", "DDD"]);
+        assert_eq!(engines[0].next_trade_id(), 11);
+    }
+}
@@ -0,0 +1,282 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::models::{price_from_wire, saturating_qty_from_wire, wire_price, wire_qty, Price, Qty};
+use crate::orderbook::OrderBook;
+use crate::storage::BookStorage;
+
+// The region is a sequence of 64-bit words:
+//
+// | word | content |
+// | --- | --- |
+// | 0 | `MAGIC` |
+// | 1 | the seqlock sequence, odd while a write is in progress |
+// | 2 | the number of levels of each side |
+// | 3, 4 | the number of asks and bids present |
+// | 5 | 1 if a last trade is present |
+// | 6, 7 | the last trade price and quantity |
+// | 8.. | the asks, then the bids, as price and quantity pairs |
+const MAGIC: u64 = u64::from_le_bytes(*b"LEGNSHM1");
+const SEQUENCE: usize = 1;
+const LEVELS: usize = 2;
+const ASK_COUNT: usize = 3;
+const BID_COUNT: usize = 4;
+const HAS_TRADE: usize = 5;
+const TRADE_PRICE: usize = 6;
+const TRADE_QTY: usize = 7;
+const HEADER_WORDS: usize = 8;
+
+fn region_len(levels: usize) -> usize {
+    (HEADER_WORDS + 4 * levels) * 8
+}
+
+/// View a mapped region as words. The words are only accessed atomically,
+/// as other processes share them.
+///
+/// # Safety
+///
+/// `ptr` must be 8-byte aligned and valid for `len` bytes for the lifetime
+/// of the returned slice.
+unsafe fn words<'a>(ptr: *const u8, len: usize) -> &'a [AtomicU64] {
+    std::slice::from_raw_parts(ptr as *const AtomicU64, len / 8)
+}
+
+/// The market data read from a shared-memory region, see [`ShmReader`].
+///
+/// [`ShmReader`]: struct.ShmReader.html
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShmSnapshot {
+    /// The number of updates published so far.
+    pub sequence: u64,
+    /// The best ask levels as `(price, qty)` pairs, best first.
+    pub asks: Vec<(Price, Qty)>,
+    /// The best bid levels as `(price, qty)` pairs, best first.
+    pub bids: Vec<(Price, Qty)>,
+    /// The price and quantity of the last fill, if any.
+    pub last_trade: Option<(Price, Qty)>,
+}
+
+/// Publishes the top of a book and its last trade to a memory-mapped file,
+/// available with the `shm` feature. Co-located processes read it with a
+/// [`ShmReader`] without syscalls or deserialization; a seqlock lets them
+/// detect and retry reads racing a publish. There must be a single publisher
+/// per file.
+///
+/// [`ShmReader`]: struct.ShmReader.html
+#[derive(Debug)]
+pub struct ShmPublisher {
+    map: MmapMut,
+    levels: usize,
+    sequence: u64,
+}
+
+impl ShmPublisher {
+    /// Create the file at `path`, sized for `levels` levels of each side, and
+    /// publish an empty book. The region a previous publisher left in the
+    /// file is reused, its sequence carrying on, since readers may still
+    /// have it mapped: the file is never truncated, and a file of another
+    /// size is refused.
+    pub fn create<P: AsRef<Path>>(path: P, levels: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let len = region_len(levels) as u64;
+        match file.metadata()?.len() {
+            0 => file.set_len(len)?,
+            found if found != len => {
+                return Err(io::Error::new(ErrorKind::InvalidInput, "the file is not sized for this number of levels"));
+            }
+            _ => {}
+        }
+        // SAFETY: the file is sized for the region; other processes only
+        // read it through atomic word accesses.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        // SAFETY: mappings are page aligned.
+        let words = unsafe { words(map.as_mut_ptr(), map.len()) };
+        if words[0].load(Ordering::Acquire) == MAGIC {
+            // A torn sequence is left odd by a publisher that died mid-write.
+            let sequence = words[SEQUENCE].load(Ordering::Relaxed).div_ceil(2);
+            let mut publisher = Self { map, levels, sequence };
+            publisher.write(|words| {
+                for count in [ASK_COUNT, BID_COUNT, HAS_TRADE] {
+                    words[count].store(0, Ordering::Relaxed);
+                }
+            });
+            return Ok(publisher);
+        }
+        // Readers refuse the region until the magic number is stored.
+        for word in words.iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+        words[LEVELS].store(levels as u64, Ordering::Relaxed);
+        words[0].store(MAGIC, Ordering::Release);
+        Ok(Self { map, levels, sequence: 0 })
+    }
+
+    /// Publish the top levels of `book` and its last trade. Readers see
+    /// either the previous or the new state, never a mix of both.
+    pub fn publish<S: BookStorage>(&mut self, book: &OrderBook<S>) {
+        let levels = self.levels;
+        self.write(|words| {
+            let asks = &words[HEADER_WORDS..HEADER_WORDS + 2 * levels];
+            let bids = &words[HEADER_WORDS + 2 * levels..HEADER_WORDS + 4 * levels];
+            words[ASK_COUNT].store(write_levels(asks, book.iter_ask_qtys()), Ordering::Relaxed);
+            words[BID_COUNT].store(write_levels(bids, book.iter_bid_qtys()), Ordering::Relaxed);
+            let trade = book.last_trade();
+            words[HAS_TRADE].store(trade.is_some() as u64, Ordering::Relaxed);
+            words[TRADE_PRICE].store(trade.map_or(0, |trade| wire_price(trade.last_price)), Ordering::Relaxed);
+            words[TRADE_QTY].store(trade.map_or(0, |trade| wire_qty(trade.last_qty)), Ordering::Relaxed);
+        });
+    }
+
+    /// Run `update` on the words of the region under the seqlock.
+    fn write(&mut self, update: impl FnOnce(&[AtomicU64])) {
+        // SAFETY: mappings are page aligned.
+        let words = unsafe { words(self.map.as_mut_ptr(), self.map.len()) };
+        let sequence = &words[SEQUENCE];
+        sequence.store(2 * self.sequence + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        update(words);
+        self.sequence += 1;
+        sequence.store(2 * self.sequence, Ordering::Release);
+    }
+}
+
+fn write_levels(words: &[AtomicU64], levels: impl Iterator<Item = (Price, Qty)>) -> u64 {
+    let mut count = 0;
+    for (pair, (price, qty)) in words.chunks_exact(2).zip(levels) {
+        pair[0].store(wire_price(price), Ordering::Relaxed);
+        pair[1].store(wire_qty(qty), Ordering::Relaxed);
+        count += 1;
+    }
+    count
+}
+
+/// Reads the market data published by a [`ShmPublisher`], possibly from
+/// another process.
+///
+/// [`ShmPublisher`]: struct.ShmPublisher.html
+#[derive(Debug)]
+pub struct ShmReader {
+    map: Mmap,
+    levels: usize,
+}
+
+impl ShmReader {
+    /// Map the file at `path` created by a publisher.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the region is only read through atomic word accesses.
+        let map = unsafe { Mmap::map(&file)? };
+        let invalid = || io::Error::new(ErrorKind::InvalidData, "not a shared-memory book region");
+        if map.len() < HEADER_WORDS * 8 {
+            return Err(invalid());
+        }
+        // SAFETY: mappings are page aligned.
+        let header = unsafe { words(map.as_ptr(), map.len()) };
+        if header[0].load(Ordering::Acquire) != MAGIC {
+            return Err(invalid());
+        }
+        let levels = header[LEVELS].load(Ordering::Relaxed) as usize;
+        if map.len() < region_len(levels) {
+            return Err(invalid());
+        }
+        Ok(Self { map, levels })
+    }
+
+    /// Read a consistent copy of the published state, retrying while a
+    /// publish is in progress.
+    pub fn read(&self) -> ShmSnapshot {
+        let mut snapshot = ShmSnapshot::default();
+        self.read_into(&mut snapshot);
+        snapshot
+    }
+
+    /// Like [`read`], reusing the buffers of `snapshot`.
+    ///
+    /// [`read`]: #method.read
+    pub fn read_into(&self, snapshot: &mut ShmSnapshot) {
+        // SAFETY: mappings are page aligned.
+        let words = unsafe { words(self.map.as_ptr(), self.map.len()) };
+        loop {
+            let before = words[SEQUENCE].load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let asks = &words[HEADER_WORDS..HEADER_WORDS + 2 * self.levels];
+            let bids = &words[HEADER_WORDS + 2 * self.levels..HEADER_WORDS + 4 * self.levels];
+            read_levels(asks, words[ASK_COUNT].load(Ordering::Relaxed), &mut snapshot.asks);
+            read_levels(bids, words[BID_COUNT].load(Ordering::Relaxed), &mut snapshot.bids);
+            snapshot.last_trade = (words[HAS_TRADE].load(Ordering::Relaxed) == 1)
+                .then(|| (price_from_wire(words[TRADE_PRICE].load(Ordering::Relaxed)), saturating_qty_from_wire(words[TRADE_QTY].load(Ordering::Relaxed))));
+            fence(Ordering::Acquire);
+            if words[SEQUENCE].load(Ordering::Relaxed) == before {
+                snapshot.sequence = before / 2;
+                return;
+            }
+        }
+    }
+}
+
+fn read_levels(words: &[AtomicU64], count: u64, out: &mut Vec<(Price, Qty)>) {
+    out.clear();
+    // A torn count is caught by the sequence check, but must not overrun.
+    out.extend(
+        words
+            .chunks_exact(2)
+            .take(count as usize)
+            .map(|pair| (price_from_wire(pair[0].load(Ordering::Relaxed)), saturating_qty_from_wire(pair[1].load(Ordering::Relaxed)))),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ShmPublisher, ShmReader, ShmSnapshot};
+    use crate::{OrderBook, OrderType, Side};
+
+    #[test]
+    fn shm_region_mirrors_the_top_of_book() {
+        let path = std::env::temp_dir().join(format!("legion-shm-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut publisher = ShmPublisher::create(&path, 2).unwrap();
+        let reader = ShmReader::open(&path).unwrap();
+        assert_eq!(reader.read().sequence, 0);
+        assert!(reader.read().asks.is_empty());
+
+        let mut ob = OrderBook::default();
+        ob.track_stats(true);
+        for order in [
+            OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 2, price: 101 },
+            OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 3, price: 102 },
+            OrderType::Limit { id: 3, user_id: 1, side: Side::Ask, qty: 4, price: 103 },
+            OrderType::Limit { id: 4, user_id: 2, side: Side::Bid, qty: 5, price: 99 },
+            OrderType::Market { id: 5, user_id: 2, side: Side::Bid, qty: 1 },
+        ] {
+            ob.execute(order);
+            publisher.publish(&ob);
+        }
+        let snapshot = reader.read();
+        assert_eq!(snapshot.sequence, 5);
+        assert_eq!(snapshot.asks, vec![(101, 1), (102, 3)]);
+        assert_eq!(snapshot.bids, vec![(99, 5)]);
+        assert_eq!(snapshot.last_trade, Some((101, 1)));
+
+        // A new publisher reuses the region while the reader still maps it.
+        drop(publisher);
+        let mut publisher = ShmPublisher::create(&path, 2).unwrap();
+        assert_eq!(reader.read(), ShmSnapshot { sequence: 6, ..ShmSnapshot::default() });
+        publisher.publish(&ob);
+        assert_eq!(reader.read().sequence, 7);
+        assert_eq!(reader.read().asks, vec![(101, 1), (102, 3)]);
+        assert_eq!(ShmPublisher::create(&path, 3).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(reader.read().sequence, 7);
+
+        drop((publisher, reader));
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(ShmReader::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use alloc::vec::Vec;
+#[cfg(feature = "binary")]
+use std::io::{self, Write};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::collections::BTreeMap;
+use crate::models::{ClientOrderId, LimitOrder, OrderId, Price, Qty, SessionId, Side, Trade};
+use crate::rfq::Rfq;
+use crate::session::TradingPhase;
+
+/// A serializable copy of the state of an [`OrderBook`], taken with
+/// [`OrderBook::snapshot`] and loaded back with [`OrderBook::restore`].
+///
+/// Histories kept for reporting (fills, trade tape, candles and rolling
+/// statistics) and the book configuration are not part of the snapshot.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`OrderBook::snapshot`]: struct.OrderBook.html#method.snapshot
+/// [`OrderBook::restore`]: struct.OrderBook.html#method.restore
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BookSnapshot {
+    /// The last sequence processed by the book.
+    pub last_sequence: u64,
+    /// The sequence of the last journal entry applied to the book. Recovery
+    /// replays the journal entries after it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub journal_sequence: u64,
+    /// The ID of the last trade of the book, so a restored book keeps its
+    /// trade IDs unique.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub last_trade_id: u64,
+    /// The resting asks, best price first and in time priority within a
+    /// price level.
+    pub asks: Vec<LimitOrder>,
+    /// The resting bids, best price first and in time priority within a
+    /// price level.
+    pub bids: Vec<LimitOrder>,
+    /// The expiry time of the resting good-till-time orders, as
+    /// `(expires_at, id)` pairs.
+    pub expiries: Vec<(u64, OrderId)>,
+    /// The client order IDs of the resting orders that have one.
+    pub client_order_ids: Vec<(OrderId, ClientOrderId)>,
+    /// The sessions of the resting orders placed within one.
+    pub sessions: Vec<(OrderId, SessionId)>,
+    /// The last trade recorded by the stats tracking.
+    pub last_trade: Option<Trade>,
+    /// The traded volume recorded by the stats tracking.
+    pub traded_volume: Qty,
+    /// The open requests for quote, in request ID order.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rfqs: Vec<Rfq>,
+    /// The trading phase of the book. During a call phase the resting orders
+    /// may cross, waiting for the auction.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub phase: TradingPhase,
+}
+
+impl BookSnapshot {
+    /// Return a 64-bit FNV-1a hash of the fixed width, little endian binary
+    /// encoding of the snapshot. The hash only depends on the book state, so
+    /// replicas that applied the same orders return the same hash, across
+    /// platforms and releases of the crate that keep the snapshot layout.
+    #[cfg(feature = "binary")]
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = Fnv1a(0xcbf2_9ce4_8422_2325);
+        // Writing to the hasher cannot fail.
+        bincode::serialize_into(&mut hasher, self).expect("snapshots are serializable");
+        hasher.0
+    }
+
+    /// Compute the changes that turn this snapshot into `newer`, e.g. to
+    /// reconcile a mirrored book against the authoritative one.
+    pub fn diff(&self, newer: &BookSnapshot) -> SnapshotDiff {
+        let before = self.orders();
+        let after = newer.orders();
+        let mut diff = SnapshotDiff::default();
+        for (id, (side, order)) in &after {
+            match before.get(id) {
+                None => diff.added.push((*side, (*order).clone())),
+                Some((_, previous)) if previous != order => diff.modified.push((*side, (*order).clone())),
+                Some(_) => {}
+            }
+        }
+        for (id, (side, order)) in &before {
+            if !after.contains_key(id) {
+                diff.removed.push((*side, (*order).clone()));
+            }
+        }
+
+        let (before, after) = (self.level_sizes(), newer.level_sizes());
+        for (key, qty) in &after {
+            if before.get(key) != Some(qty) {
+                let before = before.get(key).copied().unwrap_or(0);
+                diff.levels.push(LevelDiff { side: level_side(key.0), price: key.1, before, after: *qty });
+            }
+        }
+        for (key, qty) in &before {
+            if !after.contains_key(key) {
+                diff.levels.push(LevelDiff { side: level_side(key.0), price: key.1, before: *qty, after: 0 });
+            }
+        }
+        diff.levels.sort_by_key(|level| (level.side == Side::Bid, level.price));
+        diff
+    }
+
+    fn orders(&self) -> BTreeMap<OrderId, (Side, &LimitOrder)> {
+        let asks = self.asks.iter().map(|order| (order.id, (Side::Ask, order)));
+        asks.chain(self.bids.iter().map(|order| (order.id, (Side::Bid, order)))).collect()
+    }
+
+    /// Return the total quantity of each level, keyed by whether it is a bid
+    /// level and its price.
+    fn level_sizes(&self) -> BTreeMap<(bool, Price), Qty> {
+        let mut sizes = BTreeMap::new();
+        for (is_bid, orders) in [(false, &self.asks), (true, &self.bids)] {
+            for order in orders {
+                *sizes.entry((is_bid, order.price)).or_insert(0) += order.qty;
+            }
+        }
+        sizes
+    }
+}
+
+fn level_side(is_bid: bool) -> Side {
+    if is_bid {
+        Side::Bid
+    } else {
+        Side::Ask
+    }
+}
+
+/// The changes between two [`BookSnapshot`]s, as returned by
+/// [`BookSnapshot::diff`]. Orders are listed by ID.
+///
+/// [`BookSnapshot`]: struct.BookSnapshot.html
+/// [`BookSnapshot::diff`]: struct.BookSnapshot.html#method.diff
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SnapshotDiff {
+    /// The orders only resting in the newer snapshot.
+    pub added: Vec<(Side, LimitOrder)>,
+    /// The orders only resting in the older snapshot.
+    pub removed: Vec<(Side, LimitOrder)>,
+    /// The orders resting in both snapshots with different content, as found
+    /// in the newer snapshot.
+    pub modified: Vec<(Side, LimitOrder)>,
+    /// The price levels whose total quantity changed, asks first, by price.
+    pub levels: Vec<LevelDiff>,
+}
+
+impl SnapshotDiff {
+    /// Return whether the snapshots hold the same resting orders.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// A change of the total quantity resting at a price level.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LevelDiff {
+    /// The side of the level.
+    pub side: Side,
+    /// The price of the level.
+    pub price: Price,
+    /// The quantity in the older snapshot, zero if the level was empty.
+    pub before: Qty,
+    /// The quantity in the newer snapshot, zero if the level is empty.
+    pub after: Qty,
+}
+
+/// A 64-bit FNV-1a hasher fed through `Write`.
+#[cfg(feature = "binary")]
+struct Fnv1a(u64);
+
+#[cfg(feature = "binary")]
+impl Write for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LevelDiff;
+    use crate::{OrderBook, OrderType, Side};
+
+    #[test]
+    fn snapshot_diff() {
+        let mut ob = OrderBook::default();
+        ob.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Ask, qty: 5, price: 100 });
+        ob.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Bid, qty: 5, price: 90 });
+        let before = ob.snapshot();
+        assert!(before.diff(&before).is_empty());
+
+        ob.execute(OrderType::Market { id: 3, user_id: 2, side: Side::Bid, qty: 2 });
+        ob.execute(OrderType::Cancel { id: 2 });
+        ob.execute(OrderType::Limit { id: 4, user_id: 2, side: Side::Ask, qty: 1, price: 101 });
+        let diff = before.diff(&ob.snapshot());
+        let ids = |orders: &[(Side, crate::LimitOrder)]| orders.iter().map(|(side, order)| (*side, order.id, order.qty)).collect::<Vec<_>>();
+        assert_eq!(ids(&diff.added), vec![(Side::Ask, 4, 1)]);
+        assert_eq!(ids(&diff.removed), vec![(Side::Bid, 2, 5)]);
+        assert_eq!(ids(&diff.modified), vec![(Side::Ask, 1, 3)]);
+        assert_eq!(
+            diff.levels,
+            vec![
+                LevelDiff { side: Side::Ask, price: 100, before: 5, after: 3 },
+                LevelDiff { side: Side::Ask, price: 101, before: 0, after: 1 },
+                LevelDiff { side: Side::Bid, price: 90, before: 5, after: 0 },
+            ]
+        );
+    }
+}
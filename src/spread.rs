@@ -0,0 +1,267 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::models::{FillMetadata, OrderEvent, OrderId, OrderType, Price, Qty, Side, UserId};
+use crate::orderbook::OrderBook;
+use crate::rejectmessages::RejectReason;
+
+/// A calendar spread order across two books. Buying the spread (bid) buys the
+/// front leg and sells the back leg; selling the spread (ask) does the
+/// opposite. Both legs are filled for the same quantity, or not at all.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SpreadOrder {
+    /// The unique ID of this order. It is used as the leg order id in both
+    /// books, so it must be in sequence for both of them.
+    pub id: OrderId,
+    /// User id for this order
+    pub user_id: UserId,
+    /// The spread side.
+    pub side: Side,
+    /// The quantity of each leg.
+    pub qty: Qty,
+    /// The limit net price per unit (front leg price minus back leg price).
+    /// Bids pay at most this price, asks receive at least this price.
+    pub net_price: i64,
+}
+
+/// An event resulting from the execution of a [`SpreadOrder`].
+///
+/// [`SpreadOrder`]: struct.SpreadOrder.html
+// Filled spreads are the common case, so the inline leg fills are kept
+// rather than boxed.
+#[cfg_attr(feature = "smallvec", allow(clippy::large_enum_variant))]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SpreadEvent {
+    /// Both legs were filled completely.
+    Filled {
+        /// The ID of the order this event is referring to.
+        id: OrderId,
+        /// The average net price per unit achieved across both legs.
+        net_price: f64,
+        /// The event of the front leg.
+        front: OrderEvent,
+        /// The event of the back leg.
+        back: OrderEvent,
+    },
+    /// Neither leg was executed.
+    Rejected {
+        /// The ID of the order this event is referring to.
+        id: OrderId,
+        /// The reason of the rejection.
+        message: RejectReason,
+    },
+}
+
+/// Return the spread bid and ask implied by the outright books, as
+/// `(bid, ask)`. A side is `None` when one of the outright sides it depends on
+/// is empty.
+pub fn implied_spread(front: &OrderBook, back: &OrderBook) -> (Option<i64>, Option<i64>) {
+    // Prices may be unsigned, so the difference is taken wider and saturated.
+    let difference = |a: Price, b: Price| (i128::from(a) - i128::from(b)).clamp(i64::MIN.into(), i64::MAX.into()) as i64;
+    let bid = match (best_price(front, Side::Bid), best_price(back, Side::Ask)) {
+        (Some(front_bid), Some(back_ask)) => Some(difference(front_bid, back_ask)),
+        _ => None,
+    };
+    let ask = match (best_price(front, Side::Ask), best_price(back, Side::Bid)) {
+        (Some(front_ask), Some(back_bid)) => Some(difference(front_ask, back_bid)),
+        _ => None,
+    };
+    (bid, ask)
+}
+
+fn best_price(book: &OrderBook, side: Side) -> Option<Price> {
+    match side {
+        Side::Bid => book.best_bid(),
+        Side::Ask => book.best_ask(),
+    }
+}
+
+/// Execute a spread order atomically against the `front` and `back` books.
+/// Both legs go through the checks of [`OrderBook::execute`], such as rate
+/// and risk limits, and are simulated first; they are only journaled and
+/// applied to the books when both pass and can be filled completely within
+/// the limit net price. Journal appends cannot be taken back, so when the
+/// back journal fails after the front one accepted its leg, the spread is
+/// rejected with that leg left in the front journal.
+///
+/// [`OrderBook::execute`]: struct.OrderBook.html#method.execute
+pub fn execute_spread(front: &mut OrderBook, back: &mut OrderBook, order: SpreadOrder) -> SpreadEvent {
+    let SpreadOrder { id, user_id, side, qty, net_price } = order;
+    if front.last_sequence() >= id || back.last_sequence() >= id {
+        return SpreadEvent::Rejected { id, message: RejectReason::InvalidSequence };
+    }
+    let front_leg = OrderType::Market { id, user_id, side, qty };
+    let back_leg = OrderType::Market { id, user_id, side: !side, qty };
+    if let Some(message) = front.admit_leg(&front_leg).or_else(|| back.admit_leg(&back_leg)) {
+        return SpreadEvent::Rejected { id, message };
+    }
+
+    let (front_fills, front_remaining) = front.simulate(id, side, qty, None);
+    let (back_fills, back_remaining) = back.simulate(id, !side, qty, None);
+    if qty == 0 || front_remaining > 0 || back_remaining > 0 {
+        return SpreadEvent::Rejected { id, message: RejectReason::LiquidityNotAvailable };
+    }
+
+    // Quantities may be 128 bits wide, so the notionals saturate.
+    let times_qty = |price: i128, qty: Qty| price.saturating_mul(i128::try_from(qty).unwrap_or(i128::MAX));
+    let notional = |fills: &[FillMetadata]| -> i128 {
+        fills.iter().map(|fill| times_qty(fill.price.into(), fill.qty)).fold(0, i128::saturating_add)
+    };
+    let net_notional = notional(&front_fills).saturating_sub(notional(&back_fills));
+    let limit_notional = times_qty(net_price.into(), qty);
+    let within_limit = match side {
+        Side::Bid => net_notional <= limit_notional,
+        Side::Ask => net_notional >= limit_notional,
+    };
+    if !within_limit {
+        return SpreadEvent::Rejected { id, message: RejectReason::SpreadPriceNotAvailable };
+    }
+
+    if !front.journal_leg(front_leg) || !back.journal_leg(back_leg) {
+        return SpreadEvent::Rejected { id, message: RejectReason::JournalUnavailable };
+    }
+    let front_event = front.apply_leg(front_leg);
+    let back_event = back.apply_leg(back_leg);
+    SpreadEvent::Filled {
+        id,
+        net_price: net_notional as f64 / qty as f64,
+        front: front_event,
+        back: back_event,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{execute_spread, implied_spread, SpreadEvent, SpreadOrder};
+    #[cfg(feature = "std")]
+    use crate::{JournalEntry, JournalSink};
+    #[cfg(feature = "binary")]
+    use crate::MemoryJournal;
+    use crate::{OrderBook, OrderEvent, OrderType, RejectReason, RiskLimits, Side};
+
+    fn init_books() -> (OrderBook, OrderBook) {
+        let mut front = OrderBook::default();
+        front.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Bid, qty: 10, price: 100 });
+        front.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 10, price: 102 });
+        let mut back = OrderBook::default();
+        back.execute(OrderType::Limit { id: 1, user_id: 2, side: Side::Bid, qty: 10, price: 104 });
+        back.execute(OrderType::Limit { id: 2, user_id: 2, side: Side::Ask, qty: 10, price: 105 });
+        (front, back)
+    }
+
+    #[test]
+    fn implied_prices() {
+        let (front, back) = init_books();
+        assert_eq!(implied_spread(&front, &back), (Some(-5), Some(-2)));
+        assert_eq!(implied_spread(&OrderBook::default(), &back), (None, None));
+    }
+
+    #[test]
+    fn spread_filled_on_both_legs() {
+        let (mut front, mut back) = init_books();
+        let event = execute_spread(
+            &mut front,
+            &mut back,
+            SpreadOrder { id: 3, user_id: 9, side: Side::Bid, qty: 4, net_price: -2 },
+        );
+        match event {
+            SpreadEvent::Filled { id, net_price, front: front_event, back: back_event } => {
+                assert_eq!(id, 3);
+                assert!((net_price + 2.0).abs() < 1.0e-6);
+                assert!(matches!(front_event, OrderEvent::Filled { filled_qty: 4, .. }));
+                assert!(matches!(back_event, OrderEvent::Filled { filled_qty: 4, .. }));
+            }
+            _ => panic!("spread should be filled"),
+        }
+        assert_eq!(front.depth(1, false).asks[0].qty, 6);
+        assert_eq!(back.depth(1, false).bids[0].qty, 6);
+    }
+
+    #[test]
+    fn spread_rejected_leaves_books_untouched() {
+        let (mut front, mut back) = init_books();
+        let event = execute_spread(
+            &mut front,
+            &mut back,
+            SpreadOrder { id: 3, user_id: 9, side: Side::Bid, qty: 4, net_price: -3 },
+        );
+        assert_eq!(event, SpreadEvent::Rejected { id: 3, message: RejectReason::SpreadPriceNotAvailable });
+        assert_eq!(front.depth(1, false).asks[0].qty, 10);
+        assert_eq!(back.depth(1, false).bids[0].qty, 10);
+        assert_eq!(front.last_sequence(), 2);
+    }
+
+    #[test]
+    fn spread_rejected_by_a_back_leg_gate_leaves_both_books_untouched() {
+        let (mut front, mut back) = init_books();
+        back.set_risk_limits(9, Some(RiskLimits::default().with_max_order_qty(3)));
+        let order = SpreadOrder { id: 3, user_id: 9, side: Side::Bid, qty: 4, net_price: -2 };
+        let event = execute_spread(&mut front, &mut back, order);
+        assert!(matches!(event, SpreadEvent::Rejected { id: 3, .. }));
+        assert_eq!(front.depth(1, false).asks[0].qty, 10);
+        assert_eq!(back.depth(1, false).bids[0].qty, 10);
+        assert_eq!((front.last_sequence(), back.last_sequence()), (2, 2));
+
+        back.set_risk_limits(9, None);
+        assert!(matches!(execute_spread(&mut front, &mut back, order), SpreadEvent::Filled { id: 3, .. }));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn spread_rejected_by_a_failing_back_journal_fills_neither_leg() {
+        #[derive(Debug)]
+        struct BrokenJournal;
+
+        impl JournalSink for BrokenJournal {
+            fn append(&mut self, _entry: &JournalEntry) -> std::io::Result<()> {
+                Err(std::io::ErrorKind::Other.into())
+            }
+        }
+
+        let (mut front, mut back) = init_books();
+        back.set_journal(Some(Box::new(BrokenJournal)));
+        let order = SpreadOrder { id: 3, user_id: 9, side: Side::Bid, qty: 4, net_price: -2 };
+        let event = execute_spread(&mut front, &mut back, order);
+        assert_eq!(event, SpreadEvent::Rejected { id: 3, message: RejectReason::JournalUnavailable });
+        assert_eq!(front.depth(1, false).asks[0].qty, 10);
+        assert_eq!(back.depth(1, false).bids[0].qty, 10);
+    }
+
+    #[test]
+    #[cfg(feature = "binary")]
+    fn replay_rebuilds_books_after_spread_ioc_and_rfq_traffic() {
+        let (front_journal, back_journal) = (MemoryJournal::new(), MemoryJournal::new());
+        let (mut front, mut back) = (OrderBook::default(), OrderBook::default());
+        front.set_journal(Some(Box::new(front_journal.clone())));
+        back.set_journal(Some(Box::new(back_journal.clone())));
+        front.execute(OrderType::Limit { id: 1, user_id: 1, side: Side::Bid, qty: 10, price: 100 });
+        front.execute(OrderType::Limit { id: 2, user_id: 1, side: Side::Ask, qty: 10, price: 102 });
+        assert!(matches!(
+            front.execute(OrderType::IOC { id: 3, user_id: 3, side: Side::Bid, qty: 15, price: 102 }),
+            OrderEvent::PartiallyFilled { filled_qty: 10, .. }
+        ));
+        front.execute(OrderType::Limit { id: 4, user_id: 1, side: Side::Ask, qty: 10, price: 103 });
+        front.request_quote(5, 4, Side::Bid, 3, vec![5], 100).unwrap();
+        front.submit_quote(5, 6, 5, 3, 101, 10).unwrap();
+        assert!(matches!(front.execute_rfq(5), Ok(OrderEvent::Filled { filled_qty: 3, .. })));
+        back.execute(OrderType::Limit { id: 1, user_id: 2, side: Side::Bid, qty: 10, price: 104 });
+        back.execute(OrderType::Limit { id: 2, user_id: 2, side: Side::Ask, qty: 10, price: 105 });
+
+        let order = SpreadOrder { id: 7, user_id: 9, side: Side::Bid, qty: 4, net_price: -1 };
+        assert!(matches!(execute_spread(&mut front, &mut back, order), SpreadEvent::Filled { id: 7, .. }));
+        assert!(matches!(
+            back.execute(OrderType::IOC { id: 8, user_id: 3, side: Side::Ask, qty: 20, price: 104 }),
+            OrderEvent::PartiallyFilled { filled_qty: 6, .. }
+        ));
+
+        for (book, journal) in [(&front, &front_journal), (&back, &back_journal)] {
+            let mut replayed = OrderBook::default();
+            replayed.replay_entries(journal.entries());
+            assert_eq!(replayed.state_hash(), book.state_hash());
+            assert_eq!(replayed.last_trade_id(), book.last_trade_id());
+            assert_eq!(replayed.tape().collect::<Vec<_>>(), book.tape().collect::<Vec<_>>());
+        }
+    }
+}
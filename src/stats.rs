@@ -0,0 +1,234 @@
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::collections::VecDeque;
+use crate::models::{self, wide_qty, Notional, Price, Qty};
+
+/// The span of recent trades a rolling statistic is computed over.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StatsWindow {
+    /// The last `n` trades.
+    Trades(usize),
+    /// The trades that happened within the given duration before the current
+    /// clock time.
+    Duration(u64),
+}
+
+/// The width of the buckets trades are aggregated into by the candle
+/// aggregator.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CandleInterval {
+    /// Buckets spanning the given number of order sequence numbers.
+    Sequence(u64),
+    /// Buckets spanning the given duration of clock time.
+    Duration(u64),
+}
+
+/// The open, high, low, close and volume of the trades in a candle bucket.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Candle {
+    /// The first sequence number or timestamp covered by the bucket.
+    pub start: u64,
+    /// The price of the first trade.
+    pub open: Price,
+    /// The highest trade price.
+    pub high: Price,
+    /// The lowest trade price.
+    pub low: Price,
+    /// The price of the last trade.
+    pub close: Price,
+    /// The traded quantity.
+    pub volume: Qty,
+}
+
+/// Throughput counters of an order book, returned by
+/// [`OrderBook::metrics`].
+///
+/// [`OrderBook::metrics`]: struct.OrderBook.html#method.metrics
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BookMetrics {
+    /// The number of orders executed, including rejected ones.
+    pub orders_processed: u64,
+    /// The number of fills generated.
+    pub fills_generated: u64,
+    /// The number of orders executed while a metrics clock was installed.
+    pub timed_orders: u64,
+    /// The time spent executing the timed orders, in the unit of the metrics
+    /// clock.
+    pub busy_time: u64,
+}
+
+impl BookMetrics {
+    /// Return the average execution time of the timed orders, in
+    /// nanoseconds for a [`SystemClock`], or `None` if no order was timed.
+    ///
+    /// [`SystemClock`]: struct.SystemClock.html
+    pub fn nanos_per_order(&self) -> Option<f64> {
+        (self.timed_orders > 0).then(|| self.busy_time as f64 / self.timed_orders as f64)
+    }
+}
+
+/// Aggregates trades into candles, keeping the finished ones until they are
+/// taken.
+#[derive(Debug, Clone)]
+pub(crate) struct CandleAggregator {
+    interval: CandleInterval,
+    current: Option<Candle>,
+    finished: Vec<Candle>,
+}
+
+impl CandleAggregator {
+    pub(crate) fn new(interval: CandleInterval) -> Self {
+        Self { interval, current: None, finished: Vec::new() }
+    }
+
+    pub(crate) fn push(&mut self, sequence: u64, timestamp: u64, price: Price, qty: Qty) {
+        let (key, width) = match self.interval {
+            CandleInterval::Sequence(width) => (sequence, width),
+            CandleInterval::Duration(width) => (timestamp, width),
+        };
+        let start = key - key % width.max(1);
+        match &mut self.current {
+            Some(candle) if start <= candle.start => {
+                candle.high = candle.high.max(price);
+                candle.low = candle.low.min(price);
+                candle.close = price;
+                candle.volume = candle.volume.saturating_add(qty);
+            }
+            current => {
+                if let Some(candle) = current.take() {
+                    self.finished.push(candle);
+                }
+                *current = Some(Candle { start, open: price, high: price, low: price, close: price, volume: qty });
+            }
+        }
+    }
+
+    pub(crate) fn current(&self) -> Option<Candle> {
+        self.current
+    }
+
+    pub(crate) fn take_finished(&mut self) -> Vec<Candle> {
+        core::mem::take(&mut self.finished)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct TradePrint {
+    price: Price,
+    qty: Qty,
+    timestamp: u64,
+}
+
+/// A bounded history of the most recent trade prints, used to compute rolling
+/// statistics.
+#[derive(Debug, Clone)]
+pub(crate) struct TradeWindow {
+    capacity: usize,
+    prints: VecDeque<TradePrint>,
+}
+
+impl TradeWindow {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity, prints: VecDeque::with_capacity(capacity) }
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.prints.len() > capacity {
+            self.prints.pop_front();
+        }
+    }
+
+    pub(crate) fn push(&mut self, price: Price, qty: Qty, timestamp: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.prints.len() == self.capacity {
+            self.prints.pop_front();
+        }
+        self.prints.push_back(TradePrint { price, qty, timestamp });
+    }
+
+    fn window(&self, window: StatsWindow, now: u64) -> impl Iterator<Item = &TradePrint> + Clone {
+        let skip = match window {
+            StatsWindow::Trades(n) => self.prints.len().saturating_sub(n),
+            StatsWindow::Duration(duration) => {
+                let since = now.saturating_sub(duration);
+                self.prints.iter().take_while(|print| print.timestamp < since).count()
+            }
+        };
+        self.prints.iter().skip(skip)
+    }
+
+    pub(crate) fn vwap(&self, window: StatsWindow, now: u64) -> Option<f64> {
+        let (notional, qty) = self.window(window, now).fold((0, 0u128), |(notional, qty): (Notional, u128), print| {
+            (notional + models::notional(print.price, print.qty), qty + wide_qty(print.qty))
+        });
+        (qty > 0).then(|| notional as f64 / qty as f64)
+    }
+
+    pub(crate) fn twap(&self, window: StatsWindow, now: u64) -> Option<f64> {
+        let prints: Vec<&TradePrint> = self.window(window, now).collect();
+        let first = prints.first()?;
+        let duration = now.saturating_sub(first.timestamp);
+        if duration == 0 {
+            let sum: Notional = prints.iter().map(|print| Notional::from(print.price)).sum();
+            return Some(sum as f64 / prints.len() as f64);
+        }
+        // Every price is weighted by the time it stayed the last traded price.
+        let weighted: Notional = prints
+            .iter()
+            .enumerate()
+            .map(|(index, print)| {
+                let until = prints.get(index + 1).map_or(now, |next| next.timestamp);
+                Notional::from(print.price) * Notional::from(until.saturating_sub(print.timestamp))
+            })
+            .sum();
+        Some(weighted as f64 / duration as f64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Candle, CandleAggregator, CandleInterval, StatsWindow, TradeWindow};
+
+    #[test]
+    fn rolling_vwap_and_twap() {
+        let mut window = TradeWindow::new(3);
+        assert_eq!(window.vwap(StatsWindow::Trades(3), 0), None);
+        window.push(90, 10, 0);
+        window.push(100, 1, 0);
+        window.push(100, 3, 10);
+        window.push(110, 1, 30);
+        assert_eq!(window.vwap(StatsWindow::Trades(10), 40), Some(102.0));
+        assert_eq!(window.vwap(StatsWindow::Trades(1), 40), Some(110.0));
+        assert_eq!(window.vwap(StatsWindow::Duration(30), 40), Some(102.5));
+        assert_eq!(window.twap(StatsWindow::Trades(3), 40), Some(102.5));
+        assert_eq!(window.twap(StatsWindow::Trades(1), 30), Some(110.0));
+    }
+
+    #[test]
+    fn candles_roll_over_buckets() {
+        let mut candles = CandleAggregator::new(CandleInterval::Duration(60));
+        candles.push(1, 5, 100, 2);
+        candles.push(2, 30, 104, 1);
+        candles.push(3, 59, 98, 3);
+        assert!(candles.take_finished().is_empty());
+        candles.push(4, 130, 101, 4);
+        assert_eq!(
+            candles.take_finished(),
+            vec![Candle { start: 0, open: 100, high: 104, low: 98, close: 98, volume: 6 }]
+        );
+        assert_eq!(
+            candles.current(),
+            Some(Candle { start: 120, open: 101, high: 101, low: 101, close: 101, volume: 4 })
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::iter::Map;
+use core::ops::{Bound, RangeBounds};
+
+use crate::arena::OrderHandle;
+use crate::collections::{btree_map, BTreeMap, VecDeque};
+use crate::models::{price_distance, Price};
+
+/// The orders resting at a price, in time priority.
+pub type PriceQueue = VecDeque<OrderHandle>;
+
+/// The price levels of one side of an [`OrderBook`], selected when the book
+/// is created with [`OrderBook::with_storage`].
+///
+/// A level exists from the moment it is inserted until it is removed, and
+/// may be empty in between. The default storage is a `BTreeMap`, which
+/// accepts any price; [`PriceLadder`] trades a bounded price range for
+/// constant time level access.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`OrderBook::with_storage`]: struct.OrderBook.html#method.with_storage
+/// [`PriceLadder`]: struct.PriceLadder.html
+pub trait BookStorage: Debug {
+    /// Iterator over the levels of a price range, in ascending price order.
+    type Levels<'a>: DoubleEndedIterator<Item = (Price, &'a PriceQueue)>
+    where
+        Self: 'a;
+
+    /// Return whether orders can rest at `price`.
+    fn accepts(&self, _price: Price) -> bool {
+        true
+    }
+
+    /// Return the smallest price increment, by which post-only orders slide
+    /// away from the opposite side.
+    fn tick_size(&self) -> Price {
+        1
+    }
+
+    /// Return the level at `price`, if it exists.
+    fn get(&self, price: Price) -> Option<&PriceQueue>;
+
+    /// Return the level at `price` mutably, if it exists.
+    fn get_mut(&mut self, price: Price) -> Option<&mut PriceQueue>;
+
+    /// Return the level at `price`, inserting the empty queue returned by
+    /// `new_queue` if it does not exist. The price must be accepted.
+    fn get_or_insert_with<F: FnOnce() -> PriceQueue>(&mut self, price: Price, new_queue: F) -> &mut PriceQueue;
+
+    /// Remove the level at `price`, if it exists, returning its queue for
+    /// reuse unless the storage keeps it.
+    fn remove(&mut self, price: Price) -> Option<PriceQueue>;
+
+    /// Remove every level.
+    fn clear(&mut self);
+
+    /// Remove the empty levels.
+    fn remove_empty(&mut self);
+
+    /// Iterate over the levels with a price in `range`.
+    fn range<R: RangeBounds<Price>>(&self, range: R) -> Self::Levels<'_>;
+
+    /// Iterate over every level.
+    fn levels(&self) -> Self::Levels<'_> {
+        self.range(..)
+    }
+
+    /// Return the lowest price of a non-empty level.
+    fn lowest(&self) -> Option<Price> {
+        self.levels().find(|(_, queue)| !queue.is_empty()).map(|(price, _)| price)
+    }
+
+    /// Return the highest price of a non-empty level.
+    fn highest(&self) -> Option<Price> {
+        self.levels().rev().find(|(_, queue)| !queue.is_empty()).map(|(price, _)| price)
+    }
+}
+
+type TreeLevel<'a> = (&'a Price, &'a PriceQueue);
+
+impl BookStorage for BTreeMap<Price, PriceQueue> {
+    type Levels<'a> = Map<btree_map::Range<'a, Price, PriceQueue>, fn(TreeLevel<'a>) -> (Price, &'a PriceQueue)>;
+
+    fn get(&self, price: Price) -> Option<&PriceQueue> {
+        BTreeMap::get(self, &price)
+    }
+
+    fn get_mut(&mut self, price: Price) -> Option<&mut PriceQueue> {
+        BTreeMap::get_mut(self, &price)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> PriceQueue>(&mut self, price: Price, new_queue: F) -> &mut PriceQueue {
+        self.entry(price).or_insert_with(new_queue)
+    }
+
+    fn remove(&mut self, price: Price) -> Option<PriceQueue> {
+        BTreeMap::remove(self, &price)
+    }
+
+    fn clear(&mut self) {
+        BTreeMap::clear(self);
+    }
+
+    fn remove_empty(&mut self) {
+        self.retain(|_, queue| !queue.is_empty());
+    }
+
+    fn range<R: RangeBounds<Price>>(&self, range: R) -> Self::Levels<'_> {
+        BTreeMap::range(self, range).map(|(price, queue)| (*price, queue))
+    }
+}
+
+/// A dense price ladder for instruments with a known price range and tick:
+/// one queue per tick in a flat vector, with a bitset of the existing levels
+/// to find the best prices with a few word scans. Prices outside the range or
+/// off the tick grid are rejected by the book.
+///
+/// The queues of removed levels keep their allocation for the next orders at
+/// that price, so queues are only allocated the first time a price is used.
+#[derive(Debug, Clone)]
+pub struct PriceLadder {
+    min_price: Price,
+    tick_size: Price,
+    queues: Vec<PriceQueue>,
+    occupied: Vec<u64>,
+}
+
+impl PriceLadder {
+    /// Create a ladder for the prices from `min_price` to `max_price`
+    /// inclusive, in increments of `tick_size`.
+    ///
+    /// Panics if `tick_size` is zero or `max_price` is lower than
+    /// `min_price`.
+    pub fn new(min_price: Price, max_price: Price, tick_size: Price) -> Self {
+        assert!(tick_size > 0, "the tick size must be positive");
+        let span = price_distance(min_price, max_price).expect("the price range is empty");
+        let tick = price_distance(0, tick_size).unwrap_or(1);
+        let levels = (span / tick) as usize + 1;
+        Self {
+            min_price,
+            tick_size,
+            queues: (0..levels).map(|_| VecDeque::new()).collect(),
+            occupied: vec![0; levels.div_ceil(64)],
+        }
+    }
+
+    fn index(&self, price: Price) -> Option<usize> {
+        let offset = price_distance(self.min_price, price)?;
+        let tick = self.tick();
+        let index = (offset / tick) as usize;
+        (offset % tick == 0 && index < self.queues.len()).then_some(index)
+    }
+
+    /// The tick size, as a number of price points.
+    fn tick(&self) -> u64 {
+        price_distance(0, self.tick_size).unwrap_or(1)
+    }
+
+    fn is_occupied(&self, index: usize) -> bool {
+        self.occupied[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Map a price bound to the first index at or above it.
+    fn lower_index(&self, bound: Bound<&Price>) -> usize {
+        let (price, exclusive) = match bound {
+            Bound::Unbounded => return 0,
+            Bound::Included(price) => (*price, false),
+            Bound::Excluded(price) => (*price, true),
+        };
+        let Some(offset) = price_distance(self.min_price, price) else {
+            return 0;
+        };
+        let tick = self.tick();
+        let index = offset / tick;
+        let on_tick = offset % tick == 0;
+        let index = if on_tick && !exclusive { index } else { index.saturating_add(1) };
+        index.min(self.queues.len() as u64) as usize
+    }
+}
+
+impl BookStorage for PriceLadder {
+    type Levels<'a> = LadderLevels<'a>;
+
+    fn accepts(&self, price: Price) -> bool {
+        self.index(price).is_some()
+    }
+
+    fn tick_size(&self) -> Price {
+        self.tick_size
+    }
+
+    fn get(&self, price: Price) -> Option<&PriceQueue> {
+        let index = self.index(price)?;
+        self.is_occupied(index).then(|| &self.queues[index])
+    }
+
+    fn get_mut(&mut self, price: Price) -> Option<&mut PriceQueue> {
+        let index = self.index(price)?;
+        self.is_occupied(index).then(|| &mut self.queues[index])
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> PriceQueue>(&mut self, price: Price, new_queue: F) -> &mut PriceQueue {
+        let index = self.index(price).expect("the price is outside the ladder");
+        self.occupied[index / 64] |= 1 << (index % 64);
+        let queue = &mut self.queues[index];
+        if queue.capacity() == 0 {
+            *queue = new_queue();
+        }
+        queue
+    }
+
+    fn remove(&mut self, price: Price) -> Option<PriceQueue> {
+        if let Some(index) = self.index(price) {
+            self.occupied[index / 64] &= !(1 << (index % 64));
+            self.queues[index].clear();
+        }
+        None
+    }
+
+    fn clear(&mut self) {
+        for (word, bits) in self.occupied.iter_mut().enumerate() {
+            while *bits != 0 {
+                self.queues[word * 64 + bits.trailing_zeros() as usize].clear();
+                *bits &= *bits - 1;
+            }
+        }
+    }
+
+    fn remove_empty(&mut self) {
+        for (word, bits) in self.occupied.iter_mut().enumerate() {
+            let mut remaining = *bits;
+            while remaining != 0 {
+                let bit = remaining.trailing_zeros() as usize;
+                if self.queues[word * 64 + bit].is_empty() {
+                    *bits &= !(1 << bit);
+                }
+                remaining &= remaining - 1;
+            }
+        }
+    }
+
+    fn range<R: RangeBounds<Price>>(&self, range: R) -> Self::Levels<'_> {
+        let front = self.lower_index(range.start_bound());
+        let back = match range.end_bound() {
+            Bound::Unbounded => self.queues.len(),
+            Bound::Included(price) => self.lower_index(Bound::Excluded(price)),
+            Bound::Excluded(price) => self.lower_index(Bound::Included(price)),
+        };
+        LadderLevels { ladder: self, front, back: back.max(front) }
+    }
+}
+
+/// A bounded pool of empty queues, recycling the queues of removed levels
+/// for new levels instead of reallocating them.
+#[derive(Debug)]
+pub(crate) struct QueuePool {
+    queues: Vec<PriceQueue>,
+    queue_capacity: usize,
+    max_queues: usize,
+}
+
+impl QueuePool {
+    pub(crate) fn new(queue_capacity: usize, max_queues: usize) -> Self {
+        Self { queues: Vec::new(), queue_capacity, max_queues }
+    }
+
+    /// Return an empty queue, reusing a recycled one if any.
+    pub(crate) fn take(&mut self) -> PriceQueue {
+        self.queues.pop().unwrap_or_else(|| VecDeque::with_capacity(self.queue_capacity))
+    }
+
+    /// Keep `queue` for reuse if the pool is not full.
+    pub(crate) fn recycle(&mut self, mut queue: PriceQueue) {
+        if self.queues.len() < self.max_queues {
+            queue.clear();
+            self.queues.push(queue);
+        }
+    }
+
+    pub(crate) fn set_max_queues(&mut self, max_queues: usize) {
+        self.max_queues = max_queues;
+        self.queues.truncate(max_queues);
+    }
+}
+
+/// Iterator over the existing levels of a [`PriceLadder`] in a price range,
+/// skipping 64 missing levels per bitset word.
+///
+/// [`PriceLadder`]: struct.PriceLadder.html
+#[derive(Debug, Clone)]
+pub struct LadderLevels<'a> {
+    ladder: &'a PriceLadder,
+    front: usize,
+    back: usize,
+}
+
+impl<'a> LadderLevels<'a> {
+    fn level(&self, index: usize) -> (Price, &'a PriceQueue) {
+        (self.ladder.min_price + index as Price * self.ladder.tick_size, &self.ladder.queues[index])
+    }
+}
+
+impl<'a> Iterator for LadderLevels<'a> {
+    type Item = (Price, &'a PriceQueue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            let word = self.front / 64;
+            let bits = self.ladder.occupied[word] & (u64::MAX << (self.front % 64));
+            if bits == 0 {
+                self.front = (word + 1) * 64;
+                continue;
+            }
+            let index = word * 64 + bits.trailing_zeros() as usize;
+            if index >= self.back {
+                break;
+            }
+            self.front = index + 1;
+            return Some(self.level(index));
+        }
+        self.front = self.back;
+        None
+    }
+}
+
+impl DoubleEndedIterator for LadderLevels<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            let last = self.back - 1;
+            let word = last / 64;
+            let bits = self.ladder.occupied[word] & (u64::MAX >> (63 - last % 64));
+            if bits == 0 {
+                self.back = word * 64;
+                continue;
+            }
+            let index = word * 64 + 63 - bits.leading_zeros() as usize;
+            if index < self.front {
+                break;
+            }
+            self.back = index;
+            return Some(self.level(index));
+        }
+        self.back = self.front;
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{BookStorage, PriceLadder, PriceQueue, QueuePool};
+    use crate::models::Price;
+
+    fn prices<'a>(levels: impl Iterator<Item = (Price, &'a PriceQueue)>) -> Vec<Price> {
+        levels.map(|(price, _)| price).collect()
+    }
+
+    #[test]
+    fn ladder_matches_tree() {
+        let mut ladder = PriceLadder::new(100, 1_100, 5);
+        let mut tree = BTreeMap::<Price, PriceQueue>::new();
+        for price in [100, 135, 420, 425, 1_100, 740] {
+            ladder.get_or_insert_with(price, || PriceQueue::with_capacity(4));
+            tree.get_or_insert_with(price, || PriceQueue::with_capacity(4));
+        }
+        assert!(ladder.remove(425).is_none());
+        assert!(BookStorage::remove(&mut tree, 425).is_some());
+        assert!(ladder.accepts(105) && !ladder.accepts(106) && !ladder.accepts(95) && !ladder.accepts(1_105));
+        assert_eq!(ladder.tick_size(), 5);
+        assert!(ladder.get(425).is_none() && ladder.get(420).is_some());
+
+        for range in [(0, 2_000), (101, 740), (135, 739), (136, 1_100), (421, 424)] {
+            assert_eq!(prices(ladder.range(range.0..=range.1)), prices(BookStorage::range(&tree, range.0..=range.1)));
+            assert_eq!(prices(ladder.range(range.0..range.1).rev()), prices(BookStorage::range(&tree, range.0..range.1).rev()));
+        }
+        let mut both_ends = ladder.levels();
+        assert_eq!(both_ends.next().map(|(price, _)| price), Some(100));
+        assert_eq!(both_ends.next_back().map(|(price, _)| price), Some(1_100));
+        assert_eq!(prices(both_ends), vec![135, 420, 740]);
+
+        // Levels are only reported as best prices while they hold orders.
+        assert_eq!((ladder.lowest(), ladder.highest()), (None, None));
+        ladder.clear();
+        assert_eq!(prices(ladder.levels()), Vec::<Price>::new());
+    }
+
+    #[test]
+    fn pool_recycles_queues() {
+        let mut pool = QueuePool::new(8, 1);
+        let mut queue = pool.take();
+        assert!(queue.capacity() >= 8);
+        queue.reserve(100);
+        let capacity = queue.capacity();
+        pool.recycle(queue);
+        pool.recycle(PriceQueue::new());
+        assert_eq!(pool.take().capacity(), capacity);
+        assert!(pool.take().capacity() >= 8);
+    }
+}
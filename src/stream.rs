@@ -0,0 +1,120 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::models::BookDepth;
+
+/// The receiving end of a depth stream opened with
+/// [`OrderBook::stream_depth`]. It can be moved to another thread to serve
+/// market data without borrowing the book.
+///
+/// [`OrderBook::stream_depth`]: struct.OrderBook.html#method.stream_depth
+#[derive(Debug)]
+pub struct DepthReceiver {
+    inner: ReceiverInner,
+}
+
+#[derive(Debug)]
+enum ReceiverInner {
+    Queued(Receiver<BookDepth>),
+    Conflated(Arc<LatestSlot>),
+}
+
+/// The latest published snapshot, replacing any snapshot not yet received.
+#[derive(Debug, Default)]
+struct LatestSlot {
+    state: Mutex<SlotState>,
+    ready: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct SlotState {
+    latest: Option<BookDepth>,
+    closed: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct DepthPublisher {
+    levels: usize,
+    inner: PublisherInner,
+}
+
+#[derive(Debug)]
+enum PublisherInner {
+    Queued(Sender<BookDepth>),
+    Conflated(Arc<LatestSlot>),
+}
+
+/// Create a connected publisher and receiver of snapshots of `levels`
+/// levels. When `conflate` is set, only the latest snapshot is kept until it
+/// is received.
+pub(crate) fn depth_channel(levels: usize, conflate: bool) -> (DepthPublisher, DepthReceiver) {
+    let (publisher, receiver) = if conflate {
+        let slot = Arc::new(LatestSlot::default());
+        (PublisherInner::Conflated(slot.clone()), ReceiverInner::Conflated(slot))
+    } else {
+        let (sender, receiver) = mpsc::channel();
+        (PublisherInner::Queued(sender), ReceiverInner::Queued(receiver))
+    };
+    (DepthPublisher { levels, inner: publisher }, DepthReceiver { inner: receiver })
+}
+
+impl DepthPublisher {
+    pub(crate) fn levels(&self) -> usize {
+        self.levels
+    }
+
+    /// Publish a snapshot, returning whether the receiver is still connected.
+    pub(crate) fn publish(&self, depth: BookDepth) -> bool {
+        match &self.inner {
+            PublisherInner::Queued(sender) => sender.send(depth).is_ok(),
+            PublisherInner::Conflated(slot) => {
+                // The slot is shared with the receiver only.
+                if Arc::strong_count(slot) < 2 {
+                    return false;
+                }
+                slot.state.lock().unwrap().latest = Some(depth);
+                slot.ready.notify_one();
+                true
+            }
+        }
+    }
+}
+
+impl Drop for DepthPublisher {
+    fn drop(&mut self) {
+        if let PublisherInner::Conflated(slot) = &self.inner {
+            slot.state.lock().unwrap().closed = true;
+            slot.ready.notify_one();
+        }
+    }
+}
+
+impl DepthReceiver {
+    /// Block until a snapshot is available and return it, or return `None`
+    /// once the stream is closed and every published snapshot was received.
+    pub fn recv(&self) -> Option<BookDepth> {
+        match &self.inner {
+            ReceiverInner::Queued(receiver) => receiver.recv().ok(),
+            ReceiverInner::Conflated(slot) => {
+                let mut state = slot.state.lock().unwrap();
+                loop {
+                    if let Some(depth) = state.latest.take() {
+                        return Some(depth);
+                    }
+                    if state.closed {
+                        return None;
+                    }
+                    state = slot.ready.wait(state).unwrap();
+                }
+            }
+        }
+    }
+
+    /// Return a snapshot if one is available, without blocking.
+    pub fn try_recv(&self) -> Option<BookDepth> {
+        match &self.inner {
+            ReceiverInner::Queued(receiver) => receiver.try_recv().ok(),
+            ReceiverInner::Conflated(slot) => slot.state.lock().unwrap().latest.take(),
+        }
+    }
+}
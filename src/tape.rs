@@ -0,0 +1,114 @@
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::collections::VecDeque;
+use crate::models::{OrderId, Price, Qty, Side, UserId};
+
+/// A single trade printed on the [`OrderBook`] trade tape.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TapeEntry {
+    /// The position of this trade on the tape, starting from 1.
+    pub sequence: u64,
+    /// The unique ID of the trade within the order book.
+    pub trade_id: u64,
+    /// The book clock time at which the trade happened.
+    pub timestamp: u64,
+    /// The ID of the order that triggered the trade (taker).
+    pub taker_id: OrderId,
+    /// The ID of the matching order. For cross trades, it is the ID of the
+    /// cross order, like the taker ID; the counterparties are told apart by
+    /// their user IDs.
+    pub maker_id: OrderId,
+    /// User id of the buyer.
+    pub buyer_id: UserId,
+    /// User id of the seller.
+    pub seller_id: UserId,
+    /// The side of the taker order, or `None` for cross trades, which have no
+    /// aggressor.
+    pub aggressor_side: Option<Side>,
+    /// The quantity that was traded.
+    pub qty: Qty,
+    /// The price at which the trade happened.
+    pub price: Price,
+}
+
+/// The most recent trades, bounded to a fixed capacity, oldest first.
+#[derive(Debug, Clone)]
+pub(crate) struct TradeTape {
+    capacity: usize,
+    next_sequence: u64,
+    entries: VecDeque<TapeEntry>,
+}
+
+impl TradeTape {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity, next_sequence: 1, entries: VecDeque::new() }
+    }
+
+    pub(crate) fn push(&mut self, mut entry: TapeEntry) {
+        entry.sequence = self.next_sequence;
+        self.next_sequence += 1;
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = &TapeEntry> {
+        self.entries.iter()
+    }
+
+    pub(crate) fn page(&self, after: u64, limit: usize) -> Vec<TapeEntry> {
+        // Sequences on the tape are contiguous, so the first entry after
+        // `after` can be located by offset.
+        let skip = self
+            .entries
+            .front()
+            .map_or(0, |first| after.saturating_sub(first.sequence - 1) as usize);
+        self.entries.iter().skip(skip).take(limit).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TapeEntry, TradeTape};
+    use crate::{Price, Side};
+
+    fn entry(price: Price) -> TapeEntry {
+        TapeEntry {
+            sequence: 0,
+            trade_id: 0,
+            timestamp: 0,
+            taker_id: 2,
+            maker_id: 1,
+            buyer_id: 4,
+            seller_id: 3,
+            aggressor_side: Some(Side::Bid),
+            qty: 1,
+            price,
+        }
+    }
+
+    #[test]
+    fn bounded_tape_pagination() {
+        let mut tape = TradeTape::new(3);
+        for price in 100..105 {
+            tape.push(entry(price));
+        }
+        let sequences: Vec<u64> = tape.iter().map(|trade| trade.sequence).collect();
+        assert_eq!(sequences, vec![3, 4, 5]);
+        let prices: Vec<Price> = tape.page(0, 2).iter().map(|trade| trade.price).collect();
+        assert_eq!(prices, vec![102, 103]);
+        let prices: Vec<Price> = tape.page(3, 10).iter().map(|trade| trade.price).collect();
+        assert_eq!(prices, vec![103, 104]);
+        assert!(tape.page(5, 10).is_empty());
+    }
+}
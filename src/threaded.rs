@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::models::{BookDepth, OrderEvent, OrderType, Price};
+use crate::orderbook::OrderBook;
+use crate::snapshot::BookSnapshot;
+use crate::storage::{BookStorage, PriceQueue};
+
+/// A command sent to a book running on its own thread with
+/// [`BookThread::spawn`].
+///
+/// [`BookThread::spawn`]: struct.BookThread.html#method.spawn
+#[derive(Debug, Clone)]
+pub enum BookCommand {
+    /// Execute an order, answered with [`BookResponse::Event`].
+    ///
+    /// [`BookResponse::Event`]: enum.BookResponse.html#variant.Event
+    Execute(OrderType),
+    /// Render the depth, answered with [`BookResponse::Depth`].
+    ///
+    /// [`BookResponse::Depth`]: enum.BookResponse.html#variant.Depth
+    Depth {
+        /// The number of levels of each side.
+        levels: usize,
+        /// Whether the orders of each level are included.
+        include_orders: bool,
+    },
+    /// Take a snapshot, answered with [`BookResponse::Snapshot`].
+    ///
+    /// [`BookResponse::Snapshot`]: enum.BookResponse.html#variant.Snapshot
+    Snapshot,
+    /// Stop the thread. Commands sent after it are not processed.
+    Shutdown,
+}
+
+/// The answer of a book thread to a [`BookCommand`]. Every command except
+/// `Shutdown` is answered exactly once, in the order the commands were
+/// received.
+///
+/// [`BookCommand`]: enum.BookCommand.html
+#[derive(Debug, Clone)]
+pub enum BookResponse {
+    /// The event of an executed order.
+    Event(OrderEvent),
+    /// The rendered depth.
+    Depth(BookDepth),
+    /// The snapshot of the book.
+    Snapshot(BookSnapshot),
+}
+
+/// An [`OrderBook`] running on a dedicated thread, driven through a command
+/// channel and answering on a response channel. The book stays
+/// single-threaded, so multi-threaded applications share it without a
+/// `Mutex`: clone the [`sender`] for every producer, and read the responses
+/// from one consumer.
+///
+/// [`OrderBook`]: struct.OrderBook.html
+/// [`sender`]: #method.sender
+#[derive(Debug)]
+pub struct BookThread<S = BTreeMap<Price, PriceQueue>> {
+    commands: Sender<BookCommand>,
+    responses: Receiver<BookResponse>,
+    handle: JoinHandle<OrderBook<S>>,
+}
+
+impl<S: BookStorage + Send + 'static> BookThread<S> {
+    /// Move `book` to a new thread and start processing commands.
+    pub fn spawn(mut book: OrderBook<S>) -> Self {
+        let (commands, command_receiver) = mpsc::channel();
+        let (response_sender, responses) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            for command in command_receiver {
+                let response = match command {
+                    BookCommand::Execute(order) => BookResponse::Event(book.execute(order)),
+                    BookCommand::Depth { levels, include_orders } => BookResponse::Depth(book.depth(levels, include_orders)),
+                    BookCommand::Snapshot => BookResponse::Snapshot(book.snapshot()),
+                    BookCommand::Shutdown => break,
+                };
+                // Keep processing when nobody listens, so fire-and-forget
+                // producers still reach the book.
+                let _ = response_sender.send(response);
+            }
+            book
+        });
+        Self { commands, responses, handle }
+    }
+}
+
+impl<S> BookThread<S> {
+    /// Return a sender of commands, which can be cloned and moved to other
+    /// threads.
+    pub fn sender(&self) -> Sender<BookCommand> {
+        self.commands.clone()
+    }
+
+    /// Return the receiver of the responses.
+    pub fn responses(&self) -> &Receiver<BookResponse> {
+        &self.responses
+    }
+
+    /// Split the handle into its command sender, response receiver and the
+    /// thread, which returns the book once it stops.
+    pub fn into_parts(self) -> (Sender<BookCommand>, Receiver<BookResponse>, JoinHandle<OrderBook<S>>) {
+        (self.commands, self.responses, self.handle)
+    }
+
+    /// Stop the thread once the commands already sent are processed, and
+    /// return the book.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the book thread panicked.
+    pub fn join(self) -> OrderBook<S> {
+        let _ = self.commands.send(BookCommand::Shutdown);
+        self.handle.join().expect("the book thread panicked")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BookCommand, BookResponse, BookThread};
+    use crate::{OrderBook, OrderEvent, OrderType, Price, Side};
+
+    #[test]
+    fn book_thread_answers_in_order() {
+        let book = BookThread::spawn(OrderBook::default());
+        let sender = book.sender();
+        let producer = std::thread::spawn(move || {
+            for id in 1..=3 {
+                let order = OrderType::Limit { id, user_id: 1, side: Side::Ask, qty: 1, price: 100 + id as Price };
+                sender.send(BookCommand::Execute(order)).unwrap();
+            }
+        });
+        producer.join().unwrap();
+        book.sender().send(BookCommand::Execute(OrderType::Cancel { id: 2 })).unwrap();
+        book.sender().send(BookCommand::Depth { levels: 5, include_orders: false }).unwrap();
+
+        let responses: Vec<_> = book.responses().iter().take(5).collect();
+        for (id, response) in (1..=3).zip(&responses) {
+            assert!(matches!(response, BookResponse::Event(OrderEvent::Open { id: open, .. }) if *open == id));
+        }
+        assert!(matches!(responses[3], BookResponse::Event(OrderEvent::Cancelled { id: 2 })));
+        let BookResponse::Depth(depth) = &responses[4] else { panic!("expected the depth") };
+        assert_eq!(depth.asks.iter().map(|level| level.price).collect::<Vec<_>>(), vec![101, 103]);
+
+        let book = book.join();
+        assert_eq!(book.best_ask(), Some(101));
+    }
+}
@@ -9,3 +9,46 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+/// The fields of a JSON message tagged by its `"type"`, for the messages that
+/// deserialize their variants by hand: serde buffers the fields of an
+/// internally tagged enum in a way that cannot hold 128-bit integers, which
+/// quantities are with the `wide-qty` feature. The fields are buffered as
+/// JSON values instead, which hold them because `wide-qty` enables the
+/// `arbitrary_precision` feature of `serde_json`.
+#[cfg(any(feature = "feed", feature = "server"))]
+pub(crate) struct TaggedFields {
+    kind: String,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+#[cfg(any(feature = "feed", feature = "server"))]
+impl TaggedFields {
+    pub(crate) fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        use serde::Deserialize;
+
+        let mut fields = serde_json::Map::deserialize(deserializer)?;
+        match fields.remove("type") {
+            Some(serde_json::Value::String(kind)) => Ok(Self { kind, fields }),
+            Some(_) => Err(D::Error::invalid_type(serde::de::Unexpected::Other("non-string type"), &"a string")),
+            None => Err(D::Error::missing_field("type")),
+        }
+    }
+
+    /// The `"type"` of the message.
+    pub(crate) fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Deserialize the field `name` of the message.
+    pub(crate) fn take<T: serde::de::DeserializeOwned, E: serde::de::Error>(&mut self, name: &'static str) -> Result<T, E> {
+        let value = self.fields.remove(name).ok_or_else(|| E::missing_field(name))?;
+        T::deserialize(value).map_err(E::custom)
+    }
+
+    /// The error for a `"type"` outside of `variants`.
+    pub(crate) fn unknown_variant<E: serde::de::Error>(&self, variants: &'static [&'static str]) -> E {
+        E::unknown_variant(&self.kind, variants)
+    }
+}
@@ -1,121 +1,1179 @@
-use std::{cell::RefCell, str::FromStr, vec};
+use std::{cell::RefCell, str::FromStr};
 
+use js_sys::{Array, Function, Object, Reflect, Uint8Array};
+use serde::Serialize;
+use strum_macros::{AsRefStr, EnumString};
+use thiserror::Error;
 use wasm_bindgen::prelude::*;
 
-use crate::{Side, OrderType, OrderBook};
+use crate::models::{price_from_wire, qty_from_wire, Price, Qty};
+use crate::{decode, encode, BookDepth, BookLevel, BookSnapshot, DecodeError, Side, Bbo, EngineEvent, OrderEvent, OrderParseError, OrderType, OrderBook, MatchingEngine, SymbolOrder, TapeEntry};
 
 #[wasm_bindgen]
 extern {
-    fn alert(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = error)]
+    fn console_error(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = warn)]
+    fn console_warn(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = info)]
+    fn console_info(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = debug)]
+    fn console_debug(s: &str);
+}
+
+/// The TypeScript definitions of the values returned to JS, added to the
+/// generated `.d.ts` file. They mirror the serde encoding of the models.
+#[allow(dead_code)]
+const TYPESCRIPT: &str = r#"
+/** The value returned by every function of the module. */
+export type Reply<T> =
+    | { status: "ok"; value: T }
+    | { status: "error"; code: string; message: string };
+
+export type Side = "Bid" | "Ask";
+
+export interface FillMetadata {
+    trade_id: number;
+    taker_id: number;
+    maker_id: number;
+    /** @deprecated Use `taker_id`, removed in the next release. */
+    order_1: number;
+    /** @deprecated Use `maker_id`, removed in the next release. */
+    order_2: number;
+    maker_user_id: number;
+    maker_client_order_id: number | null;
+    qty: number;
+    price: number;
+    taker_side: Side;
+    total_fill: boolean;
+    maker_remaining_qty: number;
+}
+
+export type OrderEvent =
+    | { Rejected: { id: number; message: string } }
+    | { Open: { id: number; remaining_qty: number; resting_price: number } }
+    | { Cancelled: { id: number } }
+    | { AdminCancelled: { id: number; reason: string } }
+    | { CancelRejected: { id: number; message: string } }
+    | { Expired: { id: number } }
+    | { Reduced: { id: number; qty: number } }
+    | { PartiallyFilled: { id: number; filled_qty: number; remaining_qty: number; resting_price: number | null; fills: FillMetadata[] } }
+    | { Filled: { id: number; filled_qty: number; remaining_qty: number; fills: FillMetadata[] } }
+    | { Traded: { id: number; buyer_id: number; seller_id: number; qty: number; price: number } }
+    | { Duplicate: { id: number } };
+
+export interface LimitOrder {
+    user_id: number;
+    id: number;
+    qty: number;
+    price: number;
+    created_at: number;
+}
+
+export interface BookLevel {
+    price: number;
+    qty: number;
+    cumulative_qty: number;
+    cumulative_notional: number;
+    orders: LimitOrder[];
+}
+
+export interface BookDepth {
+    levels: number;
+    asks: BookLevel[];
+    bids: BookLevel[];
+}
+
+export interface EngineEvent {
+    symbol: string;
+    event: OrderEvent;
+    trade_ids: number[];
+}
+
+export interface Quote {
+    price: number;
+    qty: number;
+}
+
+export interface Bbo {
+    bid: Quote | null;
+    ask: Quote | null;
+}
+
+export interface OpenOrder {
+    symbol: string;
+    id: number;
+    side: Side;
+    price: number;
+    qty: number;
+}
+
+export interface BboUpdate {
+    symbol: string;
+    bbo: Bbo;
+}
+
+export interface TapeEntry {
+    sequence: number;
+    trade_id: number;
+    timestamp: number;
+    taker_id: number;
+    maker_id: number;
+    buyer_id: number;
+    seller_id: number;
+    aggressor_side: Side | null;
+    qty: number;
+    price: number;
+}
+"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &str = TYPESCRIPT;
+
+// The TypeScript types of the values returned to JS.
+#[wasm_bindgen]
+extern {
+    #[wasm_bindgen(typescript_type = "Reply<void>")]
+    pub type UnitReply;
+    #[wasm_bindgen(typescript_type = "Reply<number>")]
+    pub type NumberReply;
+    #[wasm_bindgen(typescript_type = "Reply<Bbo>")]
+    pub type BboReply;
+    #[wasm_bindgen(typescript_type = "Reply<OpenOrder[]>")]
+    pub type OpenOrdersReply;
+    #[wasm_bindgen(typescript_type = "Reply<string[]>")]
+    pub type SymbolsReply;
+    #[wasm_bindgen(typescript_type = "Reply<OrderEvent>")]
+    pub type EventReply;
+    #[wasm_bindgen(typescript_type = "Reply<OrderEvent[]>")]
+    pub type EventsReply;
+    #[wasm_bindgen(typescript_type = "Reply<BookDepth>")]
+    pub type DepthReply;
+    #[wasm_bindgen(typescript_type = "Reply<TapeEntry[]>")]
+    pub type TradesReply;
+    #[wasm_bindgen(typescript_type = "Reply<Uint8Array>")]
+    pub type BytesReply;
 }
 
 thread_local! {
-    static ORDER_BOOK:RefCell<crate::OrderBook> = RefCell::new(crate::OrderBook::default())
+    static ENGINE:RefCell<MatchingEngine> = RefCell::new(MatchingEngine::default());
+    static CALLBACKS:RefCell<Callbacks> = RefCell::new(Callbacks::default());
+    static LOGGER:RefCell<Logger> = RefCell::new(Logger::default());
+}
+
+/// The verbosity of the module, from nothing to every executed order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumString, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+enum LogLevel {
+    #[default]
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+/// Where log messages go: the log callback if one is set, or the console.
+#[derive(Debug, Default)]
+struct Logger {
+    level: LogLevel,
+    callback: Option<Function>,
+}
+
+/// Return whether messages of `level` are logged.
+fn log_enabled(level: LogLevel) -> bool {
+    LOGGER.with(|logger| level != LogLevel::Off && level <= logger.borrow().level)
+}
+
+/// Log the message built by `message` if `level` is enabled. The engine must
+/// not be borrowed, as the callback may call back into the module.
+fn log(level: LogLevel, message: impl FnOnce() -> String) {
+    if !log_enabled(level) {
+        return;
+    }
+    let message = message();
+    match LOGGER.with(|logger| logger.borrow().callback.clone()) {
+        Some(callback) => {
+            let _ = callback.call2(&JsValue::NULL, &JsValue::from_str(level.as_ref()), &JsValue::from_str(&message));
+        }
+        None => match level {
+            LogLevel::Error => console_error(&message),
+            LogLevel::Warn => console_warn(&message),
+            LogLevel::Info => console_info(&message),
+            LogLevel::Debug | LogLevel::Off => console_debug(&message),
+        },
+    }
+}
+
+/// The JS functions called as the books change.
+#[derive(Debug, Default)]
+struct Callbacks {
+    event: Option<Function>,
+    bbo: Option<Function>,
+}
+
+/// The price of a side of the top of a book and the quantity resting at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct Quote {
+    price: Price,
+    qty: Qty,
+}
+
+/// The best bid and offer of a book, with `null` for an empty side.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+struct Quotes {
+    bid: Option<Quote>,
+    ask: Option<Quote>,
+}
+
+impl From<Bbo> for Quotes {
+    fn from(bbo: Bbo) -> Self {
+        let quote = |(price, qty)| Quote { price, qty };
+        Self { bid: bbo.bid.map(quote), ask: bbo.ask.map(quote) }
+    }
+}
+
+/// A change of the top of a book, pushed to the BBO callback.
+#[derive(Debug, Serialize)]
+struct BboUpdate {
+    symbol: String,
+    bbo: Quotes,
+}
+
+/// Return the changes of the top of the books since the last call.
+fn take_bbo_updates() -> Vec<BboUpdate> {
+    let updates = ENGINE.with(|engine| engine.borrow_mut().take_bbo_updates());
+    updates.into_iter().map(|(symbol, bbo)| BboUpdate { symbol, bbo: bbo.into() }).collect()
+}
+
+/// Push `events`, then the BBO changes, to the callbacks. The engine must
+/// not be borrowed, as the callbacks may call back into the module.
+fn notify(events: &[EngineEvent]) {
+    let (event_callback, bbo_callback) = CALLBACKS.with(|callbacks| {
+        let callbacks = callbacks.borrow();
+        (callbacks.event.clone(), callbacks.bbo.clone())
+    });
+    // Errors thrown by a callback are its own, and do not undo the orders.
+    if let Some(callback) = event_callback {
+        for event in events {
+            if let Ok(event) = to_js(event) {
+                if let Err(error) = callback.call1(&JsValue::NULL, &event) {
+                    log(LogLevel::Error, || format!("The event callback threw {:?}", error));
+                }
+            }
+        }
+    }
+    if let Some(callback) = bbo_callback {
+        for update in take_bbo_updates() {
+            if let Ok(update) = to_js(&update) {
+                if let Err(error) = callback.call1(&JsValue::NULL, &update) {
+                    log(LogLevel::Error, || format!("The BBO callback threw {:?}", error));
+                }
+            }
+        }
+    }
+}
+
+/// An error returned to JS instead of aborting the wasm instance.
+#[derive(Debug, Error)]
+enum WasmError {
+    #[error("No book for symbol `{0}`")]
+    UnknownSymbol(String),
+    #[error("A book for symbol `{0}` already exists")]
+    BookExists(String),
+    #[error(transparent)]
+    InvalidOrder(#[from] OrderParseError),
+    #[error("Order {index} of the batch: {source}")]
+    InvalidBatchOrder { index: usize, source: OrderParseError },
+    #[error("Packed orders hold {0} words, which is not a multiple of {PACKED_ORDER_WORDS}")]
+    PackedLength(usize),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(&'static str),
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(#[from] DecodeError),
+    #[error("Cannot convert the result to JS: {0}")]
+    Serialization(String),
+}
+
+impl WasmError {
+    /// Return a code JS callers can match on.
+    fn code(&self) -> &'static str {
+        match self {
+            WasmError::UnknownSymbol(_) => "UNKNOWN_SYMBOL",
+            WasmError::BookExists(_) => "BOOK_EXISTS",
+            WasmError::InvalidOrder(_) | WasmError::InvalidBatchOrder { .. } | WasmError::PackedLength(_) => "INVALID_ORDER",
+            WasmError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            WasmError::InvalidSnapshot(_) => "INVALID_SNAPSHOT",
+            WasmError::Serialization(_) => "SERIALIZATION_FAILED",
+        }
+    }
+}
+
+/// The value returned by every exported function, either
+/// `{"status":"ok","value":...}` or
+/// `{"status":"error","code":...,"message":...}`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Reply<T> {
+    Ok { value: T },
+    Error { code: &'static str, message: String },
 }
 
+impl<T> From<Result<T, WasmError>> for Reply<T> {
+    fn from(result: Result<T, WasmError>) -> Self {
+        match result {
+            Ok(value) => Reply::Ok { value },
+            Err(error) => Reply::Error { code: error.code(), message: error.to_string() },
+        }
+    }
+}
+
+/// Bytes converted to a `Uint8Array` rather than an array of numbers.
+#[derive(Debug)]
+struct Bytes(Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+/// Convert `value` to JS, with `null` for missing values such as an empty
+/// side of the BBO.
+fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, serde_wasm_bindgen::Error> {
+    let value = value.serialize(&serde_wasm_bindgen::Serializer::new().serialize_missing_as_null(true))?;
+    add_former_fill_names(&value);
+    Ok(value)
+}
+
+/// Copy the `taker_id` and `maker_id` of the fills within `value` to
+/// `order_1` and `order_2`, their names in earlier releases, so that scripts
+/// written against them keep working for one more release.
+fn add_former_fill_names(value: &JsValue) {
+    if value.is_instance_of::<Uint8Array>() {
+        return;
+    }
+    if Array::is_array(value) {
+        Array::from(value).for_each(&mut |item, _, _| add_former_fill_names(&item));
+    } else if value.is_object() {
+        for key in Object::keys(value.unchecked_ref::<Object>()) {
+            let Ok(field) = Reflect::get(value, &key) else {
+                continue;
+            };
+            if key == "fills" && Array::is_array(&field) {
+                Array::from(&field).for_each(&mut |fill, _, _| {
+                    for (name, former) in [("taker_id", "order_1"), ("maker_id", "order_2")] {
+                        if let Ok(id) = Reflect::get(&fill, &name.into()) {
+                            let _ = Reflect::set(&fill, &former.into(), &id);
+                        }
+                    }
+                });
+            } else {
+                add_former_fill_names(&field);
+            }
+        }
+    }
+}
+
+/// Convert `result` to a reply, typed as `R` for TypeScript.
+fn reply<T: Serialize, R: JsCast>(result: Result<T, WasmError>) -> R {
+    if let Err(error) = &result {
+        log(LogLevel::Warn, || format!("{}: {}", error.code(), error));
+    }
+    to_js(&Reply::from(result))
+        .unwrap_or_else(|error| {
+            let reply: Reply<()> = Reply::from(Err(WasmError::Serialization(error.to_string())));
+            to_js(&reply).unwrap_or(JsValue::NULL)
+        })
+        .unchecked_into()
+}
+
+/// Run `f` on the book of `symbol`.
+fn with_book<R>(symbol: &str, f: impl FnOnce(&mut OrderBook) -> R) -> Result<R, WasmError> {
+    ENGINE.with(|engine| engine.borrow_mut().book_mut(symbol).map(f)).ok_or_else(|| WasmError::UnknownSymbol(symbol.to_string()))
+}
+
+/// Execute `order` on the book of `symbol`.
+fn execute(symbol: &str, order: OrderType) -> Result<OrderEvent, WasmError> {
+    execute_all(symbol, vec![order]).map(|mut events| events.remove(0))
+}
+
+/// Execute `orders` in order on the book of `symbol`, and push their events
+/// to the callbacks.
+fn execute_all(symbol: &str, orders: Vec<OrderType>) -> Result<Vec<OrderEvent>, WasmError> {
+    let events = ENGINE.with(|engine| {
+        let mut engine = engine.borrow_mut();
+        if engine.book(symbol).is_none() {
+            return Err(WasmError::UnknownSymbol(symbol.to_string()));
+        }
+        let orders = orders.into_iter().map(|order| SymbolOrder { symbol: symbol.to_string(), order });
+        Ok(engine.execute_all(orders))
+    })?;
+    for event in &events {
+        log(LogLevel::Debug, || format!("{}: {:?}", event.symbol, event.event));
+    }
+    notify(&events);
+    Ok(events.into_iter().map(|event| event.event).collect())
+}
+
+/// The number of words of an order packed by JS for
+/// `execute_orders_packed`.
+const PACKED_ORDER_WORDS: usize = 6;
+
+/// Unpack orders laid out as `[kind, id, user_id, side, qty, price]` words,
+/// where `kind` is 0 for market, 1 limit, 2 cancel, 3 IOC, 4 FOK, 5 post-only
+/// and 6 post-only slide orders, and `side` is 0 for bids and 1 for asks.
+/// Words a kind does not use are ignored.
+fn unpack_orders(words: &[u64]) -> Result<Vec<OrderType>, WasmError> {
+    if !words.len().is_multiple_of(PACKED_ORDER_WORDS) {
+        return Err(WasmError::PackedLength(words.len()));
+    }
+    let unpack = |index: usize, order: &[u64]| {
+        let &[kind, id, user_id, side, qty, price] = order else {
+            unreachable!("orders are split into chunks of {} words", PACKED_ORDER_WORDS);
+        };
+        let invalid = |source| WasmError::InvalidBatchOrder { index, source };
+        let qty = order_qty(qty).map_err(invalid)?;
+        let price = price_from_wire(price);
+        let side = || {
+            u8::try_from(side).ok().and_then(Side::from_repr).ok_or_else(|| invalid(OrderParseError::InvalidSide(side.to_string())))
+        };
+        Ok(match kind {
+            0 => OrderType::Market { id, user_id, side: side()?, qty },
+            1 => OrderType::Limit { id, user_id, side: side()?, qty, price },
+            2 => OrderType::Cancel { id },
+            3 => OrderType::IOC { id, user_id, side: side()?, qty, price },
+            4 => OrderType::FOK { id, user_id, side: side()?, qty, price },
+            5 => OrderType::Postonly { id, user_id, side: side()?, qty, price },
+            6 => OrderType::PostonlySlide { id, user_id, side: side()?, qty, price },
+            _ => return Err(invalid(OrderParseError::UnknownOrderType(kind.to_string()))),
+        })
+    };
+    words.chunks(PACKED_ORDER_WORDS).enumerate().map(|(index, order)| unpack(index, order)).collect()
+}
+
+/// Convert the quantity of an incoming order, refusing quantities that do
+/// not fit `Qty`.
+fn order_qty(qty: u64) -> Result<Qty, OrderParseError> {
+    qty_from_wire(qty).ok_or_else(|| OrderParseError::InvalidInteger { field: "qty", value: qty.to_string() })
+}
+
+fn parse_side(side: &str) -> Result<Side, WasmError> {
+    Side::from_str(&side.to_lowercase()).map_err(|_| OrderParseError::InvalidSide(side.to_string()).into())
+}
+
+fn create(symbol: String) -> Result<(), WasmError> {
+    ENGINE.with(|engine| {
+        let mut engine = engine.borrow_mut();
+        if engine.book(&symbol).is_some() {
+            return Err(WasmError::BookExists(symbol.clone()));
+        }
+        engine.add_book(symbol.clone(), OrderBook::default());
+        Ok(())
+    })?;
+    log(LogLevel::Info, || format!("Created the book of {}", symbol));
+    Ok(())
+}
+
+/// Log messages up to `level`: `off` (the default), `error`, `warn` (error
+/// replies), `info` (book changes) or `debug` (every executed order).
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn get_book_state() -> JsValue {
-    let state = ORDER_BOOK.with(|book| {
-        let book_state = book.borrow_mut();
-        return book_state.depth(199, true);
-    });
-    serde_wasm_bindgen::to_value(&state).unwrap()
+pub fn set_log_level(level: String) -> UnitReply {
+    reply(set_level(&level))
 }
 
+fn set_level(level: &str) -> Result<(), WasmError> {
+    let level = LogLevel::from_str(&level.to_lowercase()).map_err(|_| WasmError::InvalidArgument("unknown log level"))?;
+    LOGGER.with(|logger| logger.borrow_mut().level = level);
+    Ok(())
+}
+
+/// Send log messages to `callback`, called with the level and the message,
+/// or to the console with `undefined`.
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn place_market(id:u64, user_id: u64, side: String, qty: u64) -> JsValue{
-    let event = ORDER_BOOK.with(|book| {
-        return book.borrow_mut().execute(OrderType::Market{
-            id, 
-            user_id,
-            side: if side.to_uppercase() == "BID" { Side::Bid } else { Side::Ask }, 
-            qty
-        });
-    });
-    serde_wasm_bindgen::to_value(&event).unwrap()
+pub fn set_log_callback(callback: Option<Function>) {
+    LOGGER.with(|logger| logger.borrow_mut().callback = callback);
 }
 
+/// Call `callback` with every event executed from now on, as an
+/// `EngineEvent`, or stop calling it with `undefined`.
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn set_event_callback(callback: Option<Function>) {
+    CALLBACKS.with(|callbacks| callbacks.borrow_mut().event = callback);
+}
 
+/// Call `callback` with a `BboUpdate` whenever the top of a book changes,
+/// or stop calling it with `undefined`.
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn place_limit(id:u64, user_id: u64, side: String, qty: u64, price: u64) -> JsValue{
-    alert(&side);
-    let event = ORDER_BOOK.with(|book| {
-        return book.borrow_mut().execute(OrderType::Limit{
-            id, 
-            user_id,
-            side: if side.to_uppercase() == "BID" { Side::Bid } else { Side::Ask } , 
-            qty,
-            price
-        });
-    });
-    serde_wasm_bindgen::to_value(&event).unwrap()
+pub fn set_bbo_callback(callback: Option<Function>) {
+    // Report changes from now on only.
+    take_bbo_updates();
+    CALLBACKS.with(|callbacks| callbacks.borrow_mut().bbo = callback);
 }
 
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn place_cancel(id:u64) -> JsValue{
-    let event = ORDER_BOOK.with(|book| {
-        return book.borrow_mut().execute(OrderType::Cancel{
-            id
-        });
-    });
-    serde_wasm_bindgen::to_value(&event).unwrap()
+pub fn create_book(symbol: String) -> UnitReply {
+    reply(create(symbol))
 }
 
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn execute_order_text(order:String) -> JsValue {
-    let event = ORDER_BOOK.with(|book| {
-        let mut book_state = book.borrow_mut();
-        let result = book_state.execute(OrderType::from_str(&order).unwrap());
-        return result
-    });
-    serde_wasm_bindgen::to_value(&event).unwrap()
+pub fn remove_book(symbol: String) -> UnitReply {
+    let removed = ENGINE.with(|engine| engine.borrow_mut().remove_book(&symbol));
+    if removed.is_some() {
+        log(LogLevel::Info, || format!("Removed the book of {}", symbol));
+    }
+    notify(&[]);
+    reply(removed.map(drop).ok_or(WasmError::UnknownSymbol(symbol)))
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn get_symbols() -> SymbolsReply {
+    let symbols: Vec<String> = ENGINE.with(|engine| engine.borrow().books().map(|(symbol, _)| symbol.to_string()).collect());
+    reply(Ok(symbols))
+}
+
+/// A resting order of a user, with its remaining quantity.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct OpenOrder {
+    symbol: String,
+    id: u64,
+    side: Side,
+    price: Price,
+    qty: Qty,
 }
 
+/// Return the resting orders of `user_id` in every book, by symbol then ID.
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn clear_book() -> () {
-    return ORDER_BOOK.with(|book| {
-        let mut bookref = book.borrow_mut();
-        *bookref = OrderBook::default();
+pub fn get_open_orders(user_id: u64) -> OpenOrdersReply {
+    reply(Ok(open_orders(user_id)))
+}
+
+fn open_orders(user_id: u64) -> Vec<OpenOrder> {
+    ENGINE.with(|engine| {
+        let engine = engine.borrow();
+        let orders = engine.books().flat_map(|(symbol, book)| {
+            book.open_orders(user_id).into_iter().map(move |(side, order)| OpenOrder {
+                symbol: symbol.to_string(),
+                id: order.id,
+                side,
+                price: order.price,
+                qty: order.qty,
+            })
+        });
+        orders.collect()
     })
 }
 
+/// Return the top `levels` levels of each side of the book of `symbol`,
+/// listing their orders with `include_orders` set. With a `bucket` size,
+/// levels are merged into buckets of that many price points, bids rounded
+/// down and asks up, and `levels` counts buckets.
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn get_bbo() -> Vec<u64> {
-    return ORDER_BOOK.with(|book| {
-        let depth = book.borrow().depth(1, false);
-        let bid:Vec<u64> = depth.bids.iter().map(|level| vec![level.qty.clone(), level.price.clone()]).flatten().collect();
-        let ask:Vec<u64> = depth.asks.iter().map(|level| vec![level.qty.clone(), level.price.clone()]).flatten().collect();
-        let all:Vec<u64> = bid.into_iter().chain(ask.into_iter()).collect();
-        return all;
-    });
+pub fn get_book_state(symbol: String, levels: usize, include_orders: bool, bucket: Option<u64>) -> DepthReply {
+    reply(with_book(&symbol, |book| book_state(book, levels, include_orders, bucket)))
+}
+
+fn book_state(book: &OrderBook, levels: usize, include_orders: bool, bucket: Option<u64>) -> BookDepth {
+    let Some(bucket) = bucket.filter(|bucket| *bucket > 1) else {
+        return book.depth(levels, include_orders);
+    };
+    let bucket = Price::try_from(bucket).unwrap_or(Price::MAX);
+    // Render enough levels to fill the buckets of both sides.
+    let asks = book.iter_ask_qtys().map(|(price, _)| bucket_price(Side::Ask, price, bucket));
+    let bids = book.iter_bid_qtys().map(|(price, _)| bucket_price(Side::Bid, price, bucket));
+    let raw = levels_spanning(asks, levels).max(levels_spanning(bids, levels));
+    let depth = book.depth(raw, include_orders);
+    BookDepth {
+        levels,
+        asks: aggregate(depth.asks, Side::Ask, bucket, levels),
+        bids: aggregate(depth.bids, Side::Bid, bucket, levels),
+    }
+}
+
+/// Return the price of the bucket of `bucket` price points holding `price`.
+fn bucket_price(side: Side, price: Price, bucket: Price) -> Price {
+    let floor = price.div_euclid(bucket) * bucket;
+    match side {
+        Side::Ask if floor != price => floor.saturating_add(bucket),
+        _ => floor,
+    }
+}
+
+/// Return the number of levels whose bucket prices, best first, fall in the
+/// first `count` buckets.
+fn levels_spanning(buckets: impl Iterator<Item = Price>, count: usize) -> usize {
+    let mut seen = 0;
+    let mut last = None;
+    let mut spanned = 0;
+    for price in buckets {
+        if last != Some(price) {
+            if seen == count {
+                break;
+            }
+            seen += 1;
+            last = Some(price);
+        }
+        spanned += 1;
+    }
+    spanned
+}
+
+/// Merge `levels`, best first, into at most `count` buckets.
+fn aggregate(levels: Vec<BookLevel>, side: Side, bucket: Price, count: usize) -> Vec<BookLevel> {
+    let mut merged: Vec<BookLevel> = Vec::new();
+    for level in levels {
+        let price = bucket_price(side, level.price, bucket);
+        if let Some(last) = merged.last_mut().filter(|last| last.price == price) {
+            last.qty += level.qty;
+            // The cumulative figures already count from the top.
+            last.cumulative_qty = level.cumulative_qty;
+            last.cumulative_notional = level.cumulative_notional;
+            last.orders.extend(level.orders);
+        } else if merged.len() < count {
+            merged.push(BookLevel { price, ..level });
+        } else {
+            break;
+        }
+    }
+    merged
 }
 
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn get_last_sequence() -> u64 {
-    return ORDER_BOOK.with(|book| {
-        book.borrow().last_sequence()
-    })
+pub fn place_market(symbol: String, id:u64, user_id: u64, side: String, qty: u64) -> EventReply{
+    reply(parse_side(&side).and_then(|side| execute(&symbol, OrderType::Market{
+        id,
+        user_id,
+        side,
+        qty: order_qty(qty)?
+    })))
+}
+
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn place_limit(symbol: String, id:u64, user_id: u64, side: String, qty: u64, price: Price) -> EventReply{
+    reply(parse_side(&side).and_then(|side| execute(&symbol, OrderType::Limit{
+        id,
+        user_id,
+        side,
+        qty: order_qty(qty)?,
+        price
+    })))
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn place_ioc(symbol: String, id: u64, user_id: u64, side: String, qty: u64, price: Price) -> EventReply {
+    reply(parse_side(&side).and_then(|side| execute(&symbol, OrderType::IOC { id, user_id, side, qty: order_qty(qty)?, price })))
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn place_fok(symbol: String, id: u64, user_id: u64, side: String, qty: u64, price: Price) -> EventReply {
+    reply(parse_side(&side).and_then(|side| execute(&symbol, OrderType::FOK { id, user_id, side, qty: order_qty(qty)?, price })))
+}
+
+/// Change the quantity and price of the resting order `id`. Lowering only
+/// the quantity reduces the order in place, keeping its priority; any other
+/// change cancels it and places the remainder as a new limit order `new_id`,
+/// which may trade. Returns the events of the orders executed.
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn amend_order(symbol: String, id: u64, new_id: u64, qty: u64, price: Price) -> EventsReply {
+    reply(order_qty(qty).map_err(WasmError::from).and_then(|qty| amend(&symbol, id, new_id, qty, price)))
+}
+
+fn amend(symbol: &str, id: u64, new_id: u64, qty: Qty, price: Price) -> Result<Vec<OrderEvent>, WasmError> {
+    let resting = with_book(symbol, |book| book.resting_order(id).map(|(side, order)| (side, order.clone())))?;
+    let orders = match resting {
+        None => vec![OrderType::Cancel { id }],
+        Some((_, order)) if order.price == price && order.qty >= qty => {
+            vec![OrderType::Reduce { id, qty_delta: order.qty - qty }]
+        }
+        Some((side, order)) => {
+            vec![OrderType::Cancel { id }, OrderType::Limit { id: new_id, user_id: order.user_id, side, qty, price }]
+        }
+    };
+    execute_all(symbol, orders)
+}
+
+/// Cancel every resting order of `user_id`, returning a cancel event for
+/// each of them.
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn cancel_all_for_user(symbol: String, user_id: u64) -> EventsReply {
+    reply(cancel_all(&symbol, user_id))
+}
+
+fn cancel_all(symbol: &str, user_id: u64) -> Result<Vec<OrderEvent>, WasmError> {
+    let canceled = with_book(symbol, |book| book.cancel_where(None, .., Some(user_id)))?;
+    let events: Vec<EngineEvent> = canceled
+        .into_iter()
+        .map(|id| EngineEvent { symbol: symbol.to_string(), event: OrderEvent::Cancelled { id }, trade_ids: Vec::new() })
+        .collect();
+    notify(&events);
+    Ok(events.into_iter().map(|event| event.event).collect())
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn place_cancel(symbol: String, id:u64) -> EventReply{
+    reply(execute(&symbol, OrderType::Cancel{
+        id
+    }))
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn execute_order_text(symbol: String, order:String) -> EventReply {
+    reply(OrderType::from_str(&order).map_err(WasmError::from).and_then(|order| execute(&symbol, order)))
+}
+
+/// Execute many orders in one call, returning their events in order. Every
+/// order is parsed before any is executed, so a batch holding an invalid
+/// order executes nothing.
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn execute_orders_text(symbol: String, orders: Vec<String>) -> EventsReply {
+    let orders: Result<Vec<OrderType>, WasmError> = orders
+        .iter()
+        .enumerate()
+        .map(|(index, order)| OrderType::from_str(order).map_err(|source| WasmError::InvalidBatchOrder { index, source }))
+        .collect();
+    reply(orders.and_then(|orders| execute_all(&symbol, orders)))
+}
+
+/// Like `execute_orders_text`, with the orders packed in a `BigUint64Array`
+/// of six words each, `[kind, id, user_id, side, qty, price]`. `kind` is 0
+/// for market, 1 limit, 2 cancel, 3 IOC, 4 FOK, 5 post-only and 6 post-only
+/// slide orders; `side` is 0 for bids and 1 for asks.
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn execute_orders_packed(symbol: String, orders: Vec<u64>) -> EventsReply {
+    reply(unpack_orders(&orders).and_then(|orders| execute_all(&symbol, orders)))
 }
 
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn add_random_orders() -> JsValue{
-    let orders:Vec<String> = Vec::new();
-    let mut events = Vec::new();
-    ORDER_BOOK.with(|book| {
-        let mut book_state = book.borrow_mut();
-        for order in orders.iter() {
-            let result = book_state.execute(OrderType::from_str(order).unwrap());
-            events.push(result);
+pub fn clear_book(symbol: String) -> UnitReply {
+    let cleared = with_book(&symbol, |book| *book = OrderBook::default());
+    if cleared.is_ok() {
+        log(LogLevel::Info, || format!("Cleared the book of {}", symbol));
+    }
+    notify(&[]);
+    reply(cleared)
+}
+
+/// Return the state of the book of `symbol` in the binary snapshot format,
+/// e.g. to persist it to IndexedDB.
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn export_snapshot(symbol: String) -> BytesReply {
+    reply(with_book(&symbol, |book| Bytes(encode(&book.snapshot()))))
+}
+
+/// Replace the state of the book of `symbol`, created if needed, with a
+/// snapshot returned by `export_snapshot`.
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn import_snapshot(symbol: String, snapshot: Vec<u8>) -> UnitReply {
+    reply(import(symbol, &snapshot))
+}
+
+fn import(symbol: String, snapshot: &[u8]) -> Result<(), WasmError> {
+    let snapshot: BookSnapshot = decode(snapshot)?;
+    ENGINE.with(|engine| {
+        let mut engine = engine.borrow_mut();
+        match engine.book_mut(&symbol) {
+            Some(book) => book.restore(snapshot),
+            None => {
+                let mut book = OrderBook::default();
+                book.restore(snapshot);
+                engine.add_book(symbol.clone(), book);
+            }
         }
     });
-    serde_wasm_bindgen::to_value(&events).unwrap()
+    log(LogLevel::Info, || format!("Imported a snapshot into the book of {}", symbol));
+    notify(&[]);
+    Ok(())
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn get_bbo(symbol: String) -> BboReply {
+    reply(with_book(&symbol, |book| Quotes::from(book.bbo())))
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn get_last_sequence(symbol: String) -> NumberReply {
+    reply(with_book(&symbol, |book| book.last_sequence()))
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn get_checksum(symbol: String, levels: usize) -> NumberReply {
+    reply(with_book(&symbol, |book| book.checksum(levels)))
+}
+
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn get_recent_trades(symbol: String, n: usize) -> TradesReply {
+    reply(with_book(&symbol, |book| book.recent_trades(n).copied().collect::<Vec<TapeEntry>>()))
+}
+
+/// Generate `count` orders from `seed` and execute them on the book of
+/// `symbol`, e.g. to fill a demo book. Bids rest in the lower half of the
+/// price range and asks in the upper half; one order in ten is a market
+/// order and one in ten cancels an earlier generated order. IDs follow the
+/// last sequence of the book, and the same seed generates the same orders.
+#[wasm_bindgen]
+#[allow(dead_code)]
+pub fn add_random_orders(symbol: String, count: u32, min_price: u64, max_price: u64, min_qty: u64, max_qty: u64, seed: u64) -> EventsReply {
+    reply(add_random(&symbol, count, RandomRanges { min_price, max_price, min_qty, max_qty }, seed))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RandomRanges {
+    min_price: u64,
+    max_price: u64,
+    min_qty: u64,
+    max_qty: u64,
+}
+
+/// A small deterministic generator, so a seed always replays the same flow.
+#[derive(Debug)]
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 = self.0.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 33) % bound.max(1)
+    }
+
+    /// Return a number in `min..=max`.
+    fn between(&mut self, min: u64, max: u64) -> u64 {
+        min + self.next((max - min).saturating_add(1))
+    }
+
+    fn side(&mut self) -> Side {
+        if self.next(2) == 0 {
+            Side::Bid
+        } else {
+            Side::Ask
+        }
+    }
+}
+
+fn random_orders(first_id: u64, count: u32, ranges: RandomRanges, seed: u64) -> Vec<OrderType> {
+    let RandomRanges { min_price, max_price, min_qty, max_qty } = ranges;
+    let mid = min_price + (max_price - min_price) / 2;
+    let mut rng = Lcg(seed);
+    let qty = |rng: &mut Lcg| qty_from_wire(rng.between(min_qty, max_qty)).expect("add_random checks the quantity range");
+    let mut orders = Vec::with_capacity(count as usize);
+    for id in (first_id..).take(count as usize) {
+        let order = match rng.next(10) {
+            0 if id > first_id => OrderType::Cancel { id: rng.between(first_id, id - 1) },
+            1 => OrderType::Market { id, user_id: rng.between(1, 10), side: rng.side(), qty: qty(&mut rng) },
+            _ => {
+                let side = rng.side();
+                let price = if side == Side::Bid { rng.between(min_price, mid) } else { rng.between(mid, max_price) } as Price;
+                OrderType::Limit { id, user_id: rng.between(1, 10), side, qty: qty(&mut rng), price }
+            }
+        };
+        orders.push(order);
+    }
+    orders
+}
+
+fn add_random(symbol: &str, count: u32, ranges: RandomRanges, seed: u64) -> Result<Vec<OrderEvent>, WasmError> {
+    if ranges.min_price > ranges.max_price || ranges.min_qty > ranges.max_qty || ranges.min_qty == 0 {
+        return Err(WasmError::InvalidArgument("the price and quantity ranges must be non-empty, with quantities above 0"));
+    }
+    order_qty(ranges.max_qty)?;
+    let first_id = with_book(symbol, |book| book.last_sequence() + 1)?;
+    execute_all(symbol, random_orders(first_id, count, ranges, seed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        add_random, amend, book_state, log_enabled, set_level, LogLevel, cancel_all, create, execute, execute_all, import, parse_side, open_orders, take_bbo_updates, unpack_orders, with_book, BboUpdate, OpenOrder, Quote, Quotes, RandomRanges, Reply, WasmError, TYPESCRIPT,
+    };
+    use crate::{encode, BookDepth, BookLevel, EngineEvent, FillMetadata, LimitOrder, OrderEvent, OrderType, RejectReason, Side, TapeEntry};
+
+    fn json<T: serde::Serialize>(result: Result<T, WasmError>) -> String {
+        serde_json::to_string(&Reply::from(result)).unwrap()
+    }
+
+    #[test]
+    fn wasm_books_are_independent() {
+        create("AAA".to_string()).unwrap();
+        create("BBB".to_string()).unwrap();
+
+        let limit = |id, price| OrderType::Limit { id, user_id: 1, side: Side::Bid, qty: 2, price };
+        assert_eq!(execute("AAA", limit(1, 100)).unwrap(), OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 100 });
+        assert_eq!(execute("BBB", limit(1, 200)).unwrap(), OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 200 });
+        assert_eq!(with_book("AAA", |book| book.bbo().bid).unwrap(), Some((100, 2)));
+        assert_eq!(with_book("BBB", |book| book.bbo().bid).unwrap(), Some((200, 2)));
+    }
+
+    #[test]
+    fn wasm_errors_are_returned_as_replies() {
+        create("AAA".to_string()).unwrap();
+        assert_eq!(
+            json(create("AAA".to_string())),
+            r#"{"status":"error","code":"BOOK_EXISTS","message":"A book for symbol `AAA` already exists"}"#
+        );
+        let market = OrderType::Market { id: 1, user_id: 1, side: Side::Bid, qty: 1 };
+        assert_eq!(
+            json(execute("CCC", market)),
+            r#"{"status":"error","code":"UNKNOWN_SYMBOL","message":"No book for symbol `CCC`"}"#
+        );
+        assert_eq!(json(parse_side("BUY")), r#"{"status":"error","code":"INVALID_ORDER","message":"Invalid side `BUY`"}"#);
+        assert!(json("AAA".parse::<OrderType>().map_err(WasmError::from)).contains(r#""code":"INVALID_ORDER""#));
+        assert_eq!(parse_side("Bid").unwrap(), Side::Bid);
+        assert_eq!(json(with_book("AAA", |book| book.last_sequence())), r#"{"status":"ok","value":0}"#);
+    }
+
+    #[test]
+    fn wasm_batches_unpack_orders() {
+        create("AAA".to_string()).unwrap();
+        let orders = unpack_orders(&[1, 1, 7, 1, 5, 100, 0, 2, 8, 0, 2, 0, 2, 1, 0, 0, 0, 0]).unwrap();
+        let events = execute_all("AAA", orders).unwrap();
+        assert_eq!(events[0], OrderEvent::Open { id: 1, remaining_qty: 5, resting_price: 100 });
+        assert!(matches!(events[1], OrderEvent::Filled { id: 2, filled_qty: 2, .. }));
+        assert_eq!(events[2], OrderEvent::Cancelled { id: 1 });
+
+        assert_eq!(json(unpack_orders(&[1, 2, 3])), r#"{"status":"error","code":"INVALID_ORDER","message":"Packed orders hold 3 words, which is not a multiple of 6"}"#);
+        let message = r#"{"status":"error","code":"INVALID_ORDER","message":"Order 1 of the batch: Invalid side `2`"}"#;
+        assert_eq!(json(unpack_orders(&[2, 1, 0, 0, 0, 0, 0, 1, 1, 2, 1, 0])), message);
+        assert!(json(unpack_orders(&[9, 1, 0, 0, 0, 0])).contains("Unknown order type `9`"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "narrow-qty", not(feature = "wide-qty")))]
+    fn wasm_refuses_quantities_out_of_range() {
+        let qty = u64::from(crate::Qty::MAX) + 1;
+        let message = format!(r#"{{"status":"error","code":"INVALID_ORDER","message":"Order 0 of the batch: Invalid integer `{}` for qty"}}"#, qty);
+        assert_eq!(json(unpack_orders(&[1, 1, 7, 1, qty, 100])), message);
+        create("AAA".to_string()).unwrap();
+        let ranges = RandomRanges { min_price: 90, max_price: 110, min_qty: 1, max_qty: qty };
+        assert!(json(add_random("AAA", 1, ranges, 7)).contains("INVALID_ORDER"));
+        assert_eq!(with_book("AAA", |book| book.last_sequence()).unwrap(), 0);
+    }
+
+    /// Return the field names of `value` serialized as a JSON object.
+    fn fields<T: serde::Serialize>(value: &T) -> Vec<String> {
+        match serde_json::to_value(value).unwrap() {
+            serde_json::Value::Object(fields) => fields.keys().cloned().collect(),
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn wasm_typescript_types_match_the_models() {
+        let interface = |name: &str| {
+            let start = TYPESCRIPT.find(&format!("export interface {} {{", name)).unwrap();
+            TYPESCRIPT[start..].split_once('}').unwrap().0
+        };
+        let fill = FillMetadata {
+            trade_id: 1, taker_id: 1, maker_id: 2, maker_user_id: 3, maker_client_order_id: None, qty: 4, price: 5, taker_side: Side::Bid, total_fill: true, maker_remaining_qty: 0,
+        };
+        let order = LimitOrder { user_id: 1, id: 2, qty: 3, price: 4, created_at: 5 };
+        let level = BookLevel { price: 1, qty: 2, cumulative_qty: 3, cumulative_notional: 4, orders: vec![] };
+        let depth = BookDepth { levels: 1, asks: vec![], bids: vec![] };
+        let entry = TapeEntry { sequence: 1, trade_id: 1, timestamp: 2, taker_id: 3, maker_id: 4, buyer_id: 7, seller_id: 8, aggressor_side: None, qty: 5, price: 6 };
+        let engine_event = EngineEvent { symbol: String::new(), event: OrderEvent::Open { id: 1, remaining_qty: 2, resting_price: 3 }, trade_ids: vec![] };
+        let open = OpenOrder { symbol: String::new(), id: 1, side: Side::Bid, price: 2, qty: 3 };
+        let update = BboUpdate { symbol: String::new(), bbo: Quotes { bid: Some(Quote { price: 1, qty: 2 }), ask: None } };
+        for (name, fields) in [
+            ("FillMetadata", [fields(&fill), vec!["order_1".to_string(), "order_2".to_string()]].concat()),
+            ("LimitOrder", fields(&order)),
+            ("BookLevel", fields(&level)),
+            ("BookDepth", fields(&depth)),
+            ("TapeEntry", fields(&entry)),
+            ("EngineEvent", fields(&engine_event)),
+            ("OpenOrder", fields(&open)),
+            ("Quote", fields(&update.bbo.bid)),
+            ("Bbo", fields(&update.bbo)),
+            ("BboUpdate", fields(&update)),
+        ] {
+            let interface = interface(name);
+            assert_eq!(interface.matches(';').count(), fields.len(), "{}", name);
+            for field in fields {
+                assert!(interface.contains(&format!(" {}: ", field)), "{}.{}", name, field);
+            }
+        }
+
+        let fills = || vec![fill].into_iter().collect();
+        for event in [
+            OrderEvent::Rejected { id: 1, message: RejectReason::LiquidityNotAvailable },
+            OrderEvent::Open { id: 1, remaining_qty: 1, resting_price: 1 },
+            OrderEvent::Cancelled { id: 1 },
+            OrderEvent::AdminCancelled { id: 1, reason: String::new() },
+            OrderEvent::CancelRejected { id: 1, message: RejectReason::OrderNotFound },
+            OrderEvent::Expired { id: 1 },
+            OrderEvent::Reduced { id: 1, qty: 1 },
+            OrderEvent::PartiallyFilled { id: 1, filled_qty: 1, remaining_qty: 1, resting_price: Some(1), fills: fills() },
+            OrderEvent::Filled { id: 1, filled_qty: 1, remaining_qty: 0, fills: fills() },
+            OrderEvent::Traded { id: 1, buyer_id: 1, seller_id: 2, qty: 1, price: 1 },
+            OrderEvent::Duplicate { id: 1 },
+        ] {
+            let variant = fields(&event).remove(0);
+            let line = TYPESCRIPT.lines().find(|line| line.contains(&format!("{{ {}: {{", variant))).unwrap();
+            let inner = serde_json::to_value(&event).unwrap()[&variant].clone();
+            for field in fields(&inner) {
+                assert!(line.contains(&format!(" {}: ", field)), "{}.{}", variant, field);
+            }
+        }
+    }
+
+    #[test]
+    fn wasm_reports_bbo_changes() {
+        create("AAA".to_string()).unwrap();
+        create("BBB".to_string()).unwrap();
+        take_bbo_updates();
+        let limit = |id, qty| OrderType::Limit { id, user_id: 1, side: Side::Ask, qty, price: 100 };
+        execute_all("AAA", vec![limit(1, 2), limit(2, 3)]).unwrap();
+        execute("BBB", limit(1, 1)).unwrap();
+        execute("BBB", OrderType::Cancel { id: 1 }).unwrap();
+
+        let updates: Vec<_> = take_bbo_updates().into_iter().map(|update| (update.symbol, update.bbo.ask)).collect();
+        assert_eq!(updates, vec![("AAA".to_string(), Some(Quote { price: 100, qty: 5 }))]);
+        assert!(take_bbo_updates().is_empty());
+
+        let bbo = with_book("AAA", |book| Quotes::from(book.bbo())).unwrap();
+        assert_eq!(serde_json::to_string(&bbo).unwrap(), r#"{"bid":null,"ask":{"price":100,"qty":5}}"#);
+    }
+
+    #[test]
+    fn wasm_bbo_has_null_for_empty_sides() {
+        create("AAA".to_string()).unwrap();
+        let bbo = || json(with_book("AAA", |book| Quotes::from(book.bbo())));
+        assert_eq!(bbo(), r#"{"status":"ok","value":{"bid":null,"ask":null}}"#);
+
+        let limit = |id, side, qty, price| OrderType::Limit { id, user_id: 1, side, qty, price };
+        execute_all("AAA", vec![limit(1, Side::Bid, 2, 99), limit(2, Side::Bid, 3, 99)]).unwrap();
+        assert_eq!(bbo(), r#"{"status":"ok","value":{"bid":{"price":99,"qty":5},"ask":null}}"#);
+        execute("AAA", limit(3, Side::Ask, 4, 101)).unwrap();
+        assert_eq!(bbo(), r#"{"status":"ok","value":{"bid":{"price":99,"qty":5},"ask":{"price":101,"qty":4}}}"#);
+    }
+
+    #[test]
+    fn wasm_snapshots_round_trip() {
+        create("AAA".to_string()).unwrap();
+        let limit = |id, side, price| OrderType::Limit { id, user_id: 1, side, qty: 2, price };
+        execute_all("AAA", vec![limit(1, Side::Ask, 101), limit(2, Side::Bid, 99)]).unwrap();
+        let snapshot = with_book("AAA", |book| encode(&book.snapshot())).unwrap();
+
+        import("BBB".to_string(), &snapshot).unwrap();
+        let hash = |symbol| with_book(symbol, |book| book.state_hash()).unwrap();
+        assert_eq!(hash("BBB"), hash("AAA"));
+        assert_eq!(with_book("BBB", |book| book.bbo().bid).unwrap(), Some((99, 2)));
+
+        let error = json(import("BBB".to_string(), &snapshot[..3]));
+        assert!(error.starts_with(r#"{"status":"error","code":"INVALID_SNAPSHOT""#), "{}", error);
+        assert_eq!(hash("BBB"), hash("AAA"));
+    }
+
+    #[test]
+    fn wasm_amends_and_cancels_all() {
+        create("AAA".to_string()).unwrap();
+        let limit = |id, user_id, price| OrderType::Limit { id, user_id, side: Side::Ask, qty: 5, price };
+        execute_all("AAA", vec![limit(1, 1, 100), limit(2, 2, 100), limit(3, 1, 101)]).unwrap();
+
+        assert_eq!(amend("AAA", 1, 0, 3, 100).unwrap(), vec![OrderEvent::Reduced { id: 1, qty: 3 }]);
+        assert_eq!(with_book("AAA", |book| book.depth(1, true).asks[0].orders[0].id).unwrap(), 1);
+        let events = amend("AAA", 2, 4, 5, 99).unwrap();
+        assert_eq!(events, vec![OrderEvent::Cancelled { id: 2 }, OrderEvent::Open { id: 4, remaining_qty: 5, resting_price: 99 }]);
+        assert_eq!(with_book("AAA", |book| book.bbo().ask).unwrap(), Some((99, 5)));
+        assert!(matches!(amend("AAA", 9, 10, 1, 1).unwrap()[..], [OrderEvent::CancelRejected { id: 9, .. }]));
+
+        let events = cancel_all("AAA", 1).unwrap();
+        assert_eq!(events, vec![OrderEvent::Cancelled { id: 1 }, OrderEvent::Cancelled { id: 3 }]);
+        assert_eq!(with_book("AAA", |book| book.depth(5, false).asks.len()).unwrap(), 1);
+        assert!(json(cancel_all("CCC", 1)).contains("UNKNOWN_SYMBOL"));
+    }
+
+    #[test]
+    fn wasm_random_orders_replay_from_a_seed() {
+        let ranges = RandomRanges { min_price: 90, max_price: 110, min_qty: 1, max_qty: 10 };
+        create("AAA".to_string()).unwrap();
+        create("BBB".to_string()).unwrap();
+        let events = add_random("AAA", 200, ranges, 7).unwrap();
+        assert_eq!(events.len(), 200);
+        assert_eq!(add_random("BBB", 200, ranges, 7).unwrap(), events);
+        assert_ne!(add_random("BBB", 200, ranges, 8).unwrap(), events);
+
+        let count = |matches: fn(&OrderEvent) -> bool| events.iter().filter(|event| matches(event)).count();
+        assert!(count(|event| matches!(event, OrderEvent::Open { .. })) > 100);
+        assert!(count(|event| matches!(event, OrderEvent::Filled { .. } | OrderEvent::PartiallyFilled { .. })) > 0);
+        assert!(count(|event| matches!(event, OrderEvent::Cancelled { .. })) > 0);
+        assert_eq!(count(|event| matches!(event, OrderEvent::Rejected { message: RejectReason::InvalidSequence, .. })), 0);
+        let (bid, ask) = with_book("AAA", |book| (book.best_bid().unwrap(), book.best_ask().unwrap())).unwrap();
+        assert!(90 <= bid && bid <= ask && ask <= 110);
+
+        // More orders continue the sequence of the book.
+        assert!(add_random("AAA", 10, ranges, 7).is_ok());
+        assert!(with_book("AAA", |book| book.last_sequence()).unwrap() > 200);
+        let empty = RandomRanges { min_qty: 0, ..ranges };
+        assert!(json(add_random("AAA", 1, empty, 7)).contains("INVALID_ARGUMENT"));
+    }
+
+    #[test]
+    fn wasm_book_state_aggregates_buckets() {
+        let mut ob = crate::OrderBook::default();
+        for (id, side, price) in [(1, Side::Ask, 101), (2, Side::Ask, 104), (3, Side::Ask, 105), (4, Side::Ask, 111), (5, Side::Bid, 99), (6, Side::Bid, 91)] {
+            ob.execute(OrderType::Limit { id, user_id: 1, side, qty: 2, price });
+        }
+        assert_eq!(book_state(&ob, 2, false, None), ob.depth(2, false));
+        assert_eq!(book_state(&ob, 2, true, Some(1)), ob.depth(2, true));
+
+        let depth = book_state(&ob, 2, true, Some(5));
+        let levels = |levels: &[crate::BookLevel]| {
+            levels.iter().map(|level| (level.price, level.qty, level.cumulative_qty, level.orders.len())).collect::<Vec<_>>()
+        };
+        assert_eq!(depth.levels, 2);
+        assert_eq!(levels(&depth.asks), vec![(105, 6, 6, 3), (115, 2, 8, 1)]);
+        assert_eq!(levels(&depth.bids), vec![(95, 2, 2, 1), (90, 2, 4, 1)]);
+        assert_eq!(depth.asks[0].cumulative_notional, 2 * (101 + 104 + 105));
+        assert_eq!(levels(&book_state(&ob, 1, false, Some(5)).asks), vec![(105, 6, 6, 0)]);
+    }
+
+    #[test]
+    fn wasm_log_level_filters_messages() {
+        assert!(!log_enabled(LogLevel::Error));
+        set_level("WARN").unwrap();
+        assert!(log_enabled(LogLevel::Error) && log_enabled(LogLevel::Warn));
+        assert!(!log_enabled(LogLevel::Info));
+        assert!(json(set_level("verbose")).contains("INVALID_ARGUMENT"));
+        set_level("off").unwrap();
+        assert!(!log_enabled(LogLevel::Error));
+    }
+
+    #[test]
+    fn wasm_lists_open_orders_of_a_user() {
+        create("AAA".to_string()).unwrap();
+        create("BBB".to_string()).unwrap();
+        let limit = |id, user_id, side, price| OrderType::Limit { id, user_id, side, qty: 5, price };
+        execute_all("BBB", vec![limit(1, 1, Side::Bid, 99), limit(2, 2, Side::Ask, 101)]).unwrap();
+        execute_all("AAA", vec![limit(1, 1, Side::Ask, 101), limit(2, 2, Side::Bid, 101)]).unwrap();
+        execute("AAA", limit(3, 1, Side::Ask, 102)).unwrap();
+        execute("AAA", OrderType::Market { id: 4, user_id: 2, side: Side::Bid, qty: 1 }).unwrap();
+
+        let order = |symbol: &str, id, side, price, qty| OpenOrder { symbol: symbol.to_string(), id, side, price, qty };
+        assert_eq!(open_orders(1), vec![order("AAA", 3, Side::Ask, 102, 4), order("BBB", 1, Side::Bid, 99, 5)]);
+        assert!(open_orders(3).is_empty());
+    }
 }
@@ -9,7 +9,7 @@ console.log(plainText);
 const config: UserConfig = {
 	plugins: [
 		// (plainText as any).default(/\/tests\/test_*/), 
-		wasmPack('../../legion'),  sveltekit()
+		wasmPack('../../legion/wasm'),  sveltekit()
 	]
 };
 
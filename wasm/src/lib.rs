@@ -0,0 +1,5 @@
+//! The WebAssembly bindings of Legion, packaged as a cdylib. The bindings are
+//! defined in the `legion` crate behind its `wasm` feature; linking it here
+//! exports them from the module.
+
+pub use legion::*;